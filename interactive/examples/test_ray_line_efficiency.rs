@@ -27,7 +27,7 @@ fn main() -> anyhow::Result<()> {
 
     let mut agent = Agent2D::default();
 
-    let lidar = Lidar2D::regular(6000);
+    let mut lidar = Lidar2D::regular(6000);
     let mut start = Instant::now();
 
     let mut rng = rand::rng();
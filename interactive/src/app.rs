@@ -1,23 +1,70 @@
 use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::track_file::{LidarFile, TrackFile};
+use crate::track_file::{AgentFile, LidarFile, MapFile, ReplayPointFile, SensorTiming, TrackFile};
+use crate::templates::Template;
 use crate::track_state::{TrackLoadError, TrackRenderState, TrackState};
 use eframe::egui::Color32;
 use eframe::{CreationContext, egui};
 use egui_file_dialog::FileDialog;
-use sim::Agent2D;
-use sim::math::Box2D;
+use sim::agent::AgentLabel;
+use sim::config::{self, Validate};
+use sim::scene::history::SceneHistory;
+use sim::scene::AgentId;
+use sim::sensors::{Sensor2D, SensorClock};
+use sim::{Agent2D, Lidar2D, Scene2D, SimConfig};
+use sim::env::ObservationConfig;
+use sim::plugin::PluginRegistry;
+use sim::replay::Trajectory;
+use sim::safety::SafetySupervisor;
+use sim::scene::occupancy_map::OccupancyMap;
+
+const RELOAD_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// Longest frame time simulated, so a stall or a dragged window doesn't make the scene jump ahead.
+const MAX_FRAME_DT: f32 = 0.25;
 
 pub struct App {
     durations: VecDeque<f32>,
     track_file: String,
     track_load_error: String,
+    /// Parts of the scenario that were skipped while loading the rest.
+    problems: Vec<String>,
     track_file_dialog: FileDialog,
+    /// Picks where to save SVG snapshots of the plot.
+    export_dialog: FileDialog,
     lidar_count: usize,
     track_state: Option<TrackState>,
     last_time: std::time::Instant,
+    last_reload_check: std::time::Instant,
     paused: bool,
+    history: SceneHistory,
+    reversing: bool,
+    rewind_seconds: f32,
+    /// Frame time not yet simulated, less than a [step](sim::SimConfig::dt).
+    accumulator: f32,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        App {
+            durations: VecDeque::new(),
+            track_file: String::new(),
+            track_load_error: String::new(),
+            problems: Vec::new(),
+            track_file_dialog: FileDialog::new(),
+            export_dialog: FileDialog::new(),
+            lidar_count: 60,
+            track_state: Default::default(),
+            last_time: std::time::Instant::now(),
+            last_reload_check: std::time::Instant::now(),
+            paused: false,
+            history: SceneHistory::default(),
+            reversing: false,
+            rewind_seconds: 5.,
+            accumulator: 0.,
+        }
+    }
 }
 
 impl App {
@@ -30,64 +77,58 @@ impl App {
         cc.egui_ctx
             .style_mut(|s| s.drag_value_text_style = egui::TextStyle::Monospace);
 
-        let app = App {
-            durations: VecDeque::new(),
-            track_file: String::new(),
-            track_load_error: String::new(),
-            track_file_dialog: FileDialog::new(),
-            lidar_count: 60,
-            track_state: Default::default(),
-            last_time: std::time::Instant::now(),
-            paused: false,
-        };
-
-        Ok(app)
+        Ok(App::default())
     }
 
     pub fn reset_track(&mut self) {
         log::info!("Resetting TrackState");
         self.track_state = None;
+        self.problems.clear();
+        self.history.clear();
+        self.reversing = false;
     }
     pub fn load_track_state(
         &mut self,
         track_render_state: TrackRenderState,
         ctx: &egui::Context,
     ) -> Result<(), TrackLoadError> {
-        let path = &self.track_file;
+        let path = PathBuf::from(&self.track_file);
         log::debug!("Loading {path:?}");
-        let file = std::fs::File::open(path)?;
-
-        let track_file: TrackFile = serde_norway::from_reader(file)?;
-
-        let agents = track_file
-            .agents
-            .iter()
-            .map(|f| {
-                let mut agent = Agent2D::with_scale(f.scale);
-                agent.state.position = f.position;
-                agent.state.heading = f.heading;
-
-                match f.lidar {
-                    LidarFile::Count { count } => {
-                        self.lidar_count = count;
-                        agent.sensors.lidar.write_arc().set_regular(count);
-                    }
-                }
-
-                agent
-            })
-            .collect::<Vec<_>>();
+        let file = std::fs::File::open(&path)?;
+
+        let track_file = TrackFile::parse(file)?;
+        let path = path.canonicalize()?;
+        let track_dir = path.parent().unwrap_or(std::path::Path::new("/"));
+
+        let plugins = sim::plugin::PLUGINS.read();
+        let mut agents = Vec::new();
+        for (i, value) in track_file.agents.iter().enumerate() {
+            let agent = serde_norway::from_value::<AgentFile>(value.clone())
+                .map_err(TrackLoadError::from)
+                .and_then(|f| self.load_agent(i, &f, &plugins, track_dir, &track_file.map));
+
+            match agent {
+                Ok(agent) => agents.push(agent),
+                Err(e) => self.problems.push(format!("Skipped agents[{i}]: {e}")),
+            }
+        }
+        drop(plugins);
+        for problem in &self.problems {
+            log::warn!("{problem}");
+        }
 
-        let path = PathBuf::from(path).canonicalize()?;
-        let image_path = if let Some(parent) = path.parent() {
-            parent.join(&track_file.track)
-        } else {
-            PathBuf::from("/").join(&track_file.track)
+        let image_path = track_dir.join(&track_file.map.image);
+        let config = SimConfig {
+            boundary: track_file.map.boundary_policy(),
+            ..SimConfig::from_params(&track_file.sim_params())
+                .map_err(|e| TrackLoadError::from(e.in_field("sim")))?
         };
+        self.history = SceneHistory::new(config.history);
 
         let mut track_state = TrackState::load(
             image_path,
-            track_file.threshold,
+            track_file.map.threshold,
+            config,
             track_render_state,
             agents,
             ctx,
@@ -102,6 +143,294 @@ impl App {
 
         Ok(())
     }
+
+    /// Builds one agent. Problems with optional items such as sensors or controllers skip just that item and are
+    /// recorded in `problems`; problems with the agent itself are returned.
+    fn load_agent(
+        &mut self,
+        i: usize,
+        f: &AgentFile,
+        plugins: &PluginRegistry,
+        track_dir: &std::path::Path,
+        map: &MapFile,
+    ) -> Result<Agent2D, TrackLoadError> {
+        let field = |name: &str| format!("agents[{i}].{name}");
+
+        config::positive(&field("scale"), f.scale)?;
+        config::finite(&field("position.x"), f.position.x)?;
+        config::finite(&field("position.y"), f.position.y)?;
+        config::direction(&field("heading"), f.heading)?;
+
+        let mut agent = Agent2D::with_scale(f.scale);
+        agent.state.position = f.position;
+        agent.state.heading = f.heading.normalize();
+        agent.label = AgentLabel {
+            name: f.name.clone(),
+            color: f.color,
+            metadata: f.metadata.clone(),
+        };
+        agent
+            .config
+            .validate()
+            .map_err(|e| e.in_field(&field("scale")))?;
+
+        let mut problems = Vec::new();
+        let mut skip = |field: String, result: Result<(), TrackLoadError>| {
+            if let Err(e) = result {
+                problems.push(format!("Skipped {field}: {e}"));
+            }
+        };
+
+        match f.lidar {
+            LidarFile::Count { count, .. } => {
+                self.lidar_count = count;
+                if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+                    lidar.set_regular(count);
+                }
+            }
+        }
+        let timing = f.lidar.timing();
+        let result = validate_timing(&field("lidar"), timing);
+        if result.is_ok() {
+            set_sensor_timing(&mut agent, Lidar2D::TOPIC, timing);
+        }
+        skip(field("lidar timing"), result);
+
+        for (j, sensor) in f.sensors.iter().enumerate() {
+            let field = field(&format!("sensors[{j}]"));
+            let name = sensor.name.clone().unwrap_or_else(|| sensor.kind.clone());
+
+            let result = validate_timing(&field, sensor.timing())
+                .and_then(|()| {
+                    plugins
+                        .create_sensor(&sensor.kind, &sensor.params())
+                        .map_err(|e| e.in_field(&field).into())
+                })
+                .map(|created| {
+                    agent.sensors.insert_dyn(name.clone(), created);
+                    set_sensor_timing(&mut agent, &name, sensor.timing());
+                });
+            skip(field, result);
+        }
+
+        if let Some(controller) = &f.controller {
+            let result = plugins
+                .create_controller(&controller.kind, &controller.params())
+                .map(|controller| agent.controller = Some(controller))
+                .map_err(|e| e.in_field(&field("controller")).into());
+            skip(field("controller"), result);
+        }
+
+        if let Some(localizer) = &f.localizer {
+            let result = plugins
+                .create_localizer(&localizer.kind, &localizer.params())
+                .map(|localizer| agent.localizer = Some(localizer))
+                .map_err(|e| e.in_field(&field("localizer")).into());
+            skip(field("localizer"), result);
+        }
+        agent.pose_source = f.pose_source.into();
+
+        if let Some(params) = f.observation_params() {
+            let result = ObservationConfig::from_params(&params)
+                .map(|observation| agent.observation = Some(observation))
+                .map_err(|e| e.in_field(&field("observation")).into());
+            skip(field("observation"), result);
+        }
+
+        if let Some(prior_map) = &f.prior_map {
+            let result = load_prior_map(&track_dir.join(prior_map), map)
+                .map(|prior_map| agent.prior_map = Some(Arc::new(prior_map)));
+            skip(field("prior_map"), result);
+        }
+
+        if let Some(safety) = &f.safety {
+            let safety = SafetySupervisor {
+                horizon: safety.horizon,
+                steps: safety.steps,
+                margin: safety.margin,
+                ..Default::default()
+            };
+            let result = safety
+                .validate()
+                .map(|()| agent.safety = Some(safety))
+                .map_err(|e| e.in_field(&field("safety")).into());
+            skip(field("safety"), result);
+        }
+
+        if !f.replay.is_empty() {
+            let points = f.replay.iter().map(ReplayPointFile::point).collect();
+            let result = Trajectory::new(points)
+                .map(|trajectory| agent.start_replay(trajectory))
+                .map_err(Into::into);
+            skip(field("replay"), result);
+        }
+
+        for (j, trailer) in f.trailers.iter().enumerate() {
+            let field = field(&format!("trailers[{j}]"));
+            let config = trailer.config();
+            let result = config
+                .validate()
+                .map(|()| agent.hitch(config))
+                .map_err(|e| e.in_field(&field).into());
+            skip(field, result);
+        }
+
+        self.problems.extend(problems);
+
+        Ok(agent)
+    }
+
+    pub fn load_template(&mut self, template: Template, ctx: &egui::Context) {
+        self.reset_track();
+        self.track_file.clear();
+        self.track_load_error.clear();
+
+        let (image, agents) = template.build(self.lidar_count);
+        let config = SimConfig::default();
+        self.history = SceneHistory::new(config.history);
+        let mut track_state = match TrackState::new(
+            &image,
+            127,
+            config,
+            TrackRenderState::default(),
+            agents,
+            ctx,
+        ) {
+            Ok(track_state) => track_state,
+            Err(e) => {
+                self.track_load_error = format!("{e}");
+                return;
+            }
+        };
+        track_state.track_render_state.active = track_state.scene.agents.keys().next().copied();
+
+        self.track_state = Some(track_state);
+        self.last_time = std::time::Instant::now();
+    }
+}
+
+/// Arrow keys held this frame, applied to the active agent on every simulation step so driving doesn't depend on the
+/// frame rate.
+#[derive(Clone, Copy, Default)]
+struct DriveInput {
+    /// -1, 0 or 1 for down, neither or up.
+    throttle: f32,
+    /// -1, 0 or 1 for right, neither or left.
+    steer: f32,
+}
+
+impl DriveInput {
+    fn from_keys(ctx: &egui::Context) -> Self {
+        ctx.input(|i| {
+            let axis = |positive, negative| {
+                i.key_down(positive) as u8 as f32 - i.key_down(negative) as u8 as f32
+            };
+
+            Self {
+                throttle: axis(egui::Key::ArrowUp, egui::Key::ArrowDown),
+                steer: axis(egui::Key::ArrowLeft, egui::Key::ArrowRight),
+            }
+        })
+    }
+
+    /// Holding a key sweeps the whole torque or steering range in five seconds.
+    fn apply(&self, agent: &mut Agent2D, dt: f32) {
+        let Agent2D { config, state, .. } = agent;
+        let (torque_range, beta_range) = (config.torque_range, config.beta_range);
+
+        state.torque = (state.torque
+            + (torque_range.1 - torque_range.0) * dt * 0.2 * self.throttle)
+            .clamp(torque_range.0, torque_range.1);
+        state.beta = (state.beta + (beta_range.1 - beta_range.0) * dt * 0.2 * self.steer)
+            .clamp(beta_range.0, beta_range.1);
+    }
+}
+
+/// Simulates `elapsed` seconds, along with the time `accumulator` banked from earlier frames, in whole
+/// [steps](SimConfig::dt), driving `active` with `drive` on each. Returns how many steps were taken.
+fn step_fixed(
+    scene: &mut Scene2D,
+    history: &mut SceneHistory,
+    active: Option<AgentId>,
+    drive: DriveInput,
+    accumulator: &mut f32,
+    elapsed: f32,
+) -> usize {
+    *accumulator += elapsed;
+    let sim_dt = scene.config().dt;
+    let mut steps = 0;
+    while *accumulator >= sim_dt {
+        *accumulator -= sim_dt;
+
+        if let Some(agent) = active.and_then(|active| scene.agents.get_mut(&active)) {
+            drive.apply(agent, sim_dt);
+        }
+        history.record(scene);
+        scene.step();
+        steps += 1;
+    }
+
+    steps
+}
+
+fn validate_timing(field: &str, timing: SensorTiming) -> Result<(), TrackLoadError> {
+    if let Some(rate) = timing.rate {
+        config::positive(&format!("{field}.rate"), rate)?;
+    }
+    for (name, value) in [("latency", timing.latency), ("jitter", timing.jitter)] {
+        if let Some(value) = value {
+            config::non_negative(&format!("{field}.{name}"), value)?;
+            config::finite(&format!("{field}.{name}"), value)?;
+        }
+    }
+    if let Some(offset) = timing.clock_offset {
+        config::finite(&format!("{field}.clock_offset"), offset)?;
+    }
+    if let Some(drift) = timing.clock_drift {
+        config::within(
+            &format!("{field}.clock_drift"),
+            drift,
+            drift > -1. && drift.is_finite(),
+            "(-1, ∞)",
+        )?;
+    }
+
+    Ok(())
+}
+
+fn set_sensor_timing(agent: &mut Agent2D, name: &str, timing: SensorTiming) {
+    let Some(entry) = agent.sensors.get_mut(name) else {
+        return;
+    };
+
+    if timing.rate.is_some() {
+        entry.rate = timing.rate;
+    }
+    if let Some(latency) = timing.latency {
+        entry.latency = latency;
+    }
+    if let Some(jitter) = timing.jitter {
+        entry.jitter = jitter;
+    }
+    if timing.clock_offset.is_some() || timing.clock_drift.is_some() {
+        entry.clock = SensorClock {
+            offset: timing.clock_offset.unwrap_or_default(),
+            drift: timing.clock_drift.unwrap_or_default(),
+        };
+    }
+}
+
+/// Reads a map image the way the track's own image is read.
+fn load_prior_map(path: &std::path::Path, map: &MapFile) -> Result<OccupancyMap, TrackLoadError> {
+    let image = image::ImageReader::open(path)?.decode()?.to_luma8();
+    let size = glam::uvec2(image.width(), image.height()).as_usizevec2();
+    let pixels = image.pixels().map(|p| p.0[0] <= map.threshold).collect();
+
+    Ok(OccupancyMap::from_pixels_with_boundary(
+        size,
+        pixels,
+        map.boundary_policy(),
+    )?)
 }
 
 impl eframe::App for App {
@@ -118,6 +447,15 @@ impl eframe::App for App {
                         self.track_file_dialog.pick_file();
                     }
 
+                    ui.menu_button("New from template", |ui| {
+                        for template in Template::ALL {
+                            if ui.button(template.name()).clicked() {
+                                self.load_template(template, ctx);
+                                ui.close();
+                            }
+                        }
+                    });
+
                     self.track_file_dialog.update(ctx);
 
                     if let Some(path) = self.track_file_dialog.take_picked() {
@@ -155,8 +493,38 @@ impl eframe::App for App {
                 if let Some(track_state) = &mut self.track_state
                     && let Some(agent) = &track_state.track_render_state.active
                 {
+                    let agent = *agent;
                     ui.separator();
 
+                    let label = &track_state.scene.agents[&agent].label;
+                    ui.strong(match &label.name {
+                        Some(name) => format!("{name} ({agent:?})"),
+                        None => format!("{agent:?}"),
+                    });
+                    for (key, value) in &label.metadata {
+                        ui.label(format!("{key}: {value}"));
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Traversability");
+                        ui.add_space(10.);
+                        if ui.button("Check").clicked() {
+                            track_state.check_traversability(agent, ctx);
+                        }
+                        if let Some(overlay) = &track_state.traversability
+                            && overlay.agent == agent
+                        {
+                            ui.label(format!(
+                                "{} regions, {} narrow passage samples",
+                                overlay.region_count,
+                                overlay.narrow_passages.len()
+                            ));
+                            if ui.button("Hide").clicked() {
+                                track_state.traversability = None;
+                            }
+                        }
+                    });
+
                     ui.horizontal(|ui| {
                         ui.label("Position");
                         ui.add_space(10.);
@@ -166,7 +534,7 @@ impl eframe::App for App {
                                 &mut track_state
                                     .scene
                                     .agents
-                                    .get_mut(agent)
+                                    .get_mut(&agent)
                                     .unwrap()
                                     .state
                                     .position
@@ -181,7 +549,7 @@ impl eframe::App for App {
                                 &mut track_state
                                     .scene
                                     .agents
-                                    .get_mut(agent)
+                                    .get_mut(&agent)
                                     .unwrap()
                                     .state
                                     .position
@@ -201,7 +569,7 @@ impl eframe::App for App {
                                 &mut track_state
                                     .scene
                                     .agents
-                                    .get_mut(agent)
+                                    .get_mut(&agent)
                                     .unwrap()
                                     .state
                                     .velocity,
@@ -215,7 +583,7 @@ impl eframe::App for App {
                                 &mut track_state
                                     .scene
                                     .agents
-                                    .get_mut(agent)
+                                    .get_mut(&agent)
                                     .unwrap()
                                     .state
                                     .torque,
@@ -226,12 +594,31 @@ impl eframe::App for App {
                         ui.add_sized(
                             [70., 20.],
                             egui::DragValue::new(
-                                &mut track_state.scene.agents.get_mut(agent).unwrap().state.beta,
+                                &mut track_state.scene.agents.get_mut(&agent).unwrap().state.beta,
                             )
                             .prefix("← beta: ")
                             .suffix(" →"),
                         );
                     });
+
+                    let cost = track_state.scene.agents[&agent].sensing_cost;
+                    ui.horizontal(|ui| {
+                        ui.label("Sensing");
+                        ui.add_space(10.);
+                        ui.label(format!(
+                            "{:.0} this step, {:.0} total",
+                            cost.step, cost.total
+                        ));
+                    });
+
+                    let agent = &track_state.scene.agents[&agent];
+                    if agent.config.battery.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.label("Battery");
+                            ui.add_space(10.);
+                            ui.label(format!("{:.0}%", agent.state.state_of_charge * 100.));
+                        });
+                    }
                 }
             });
 
@@ -249,6 +636,40 @@ impl eframe::App for App {
                 }
                 ui.add_space(5.);
 
+                ui.toggle_value(&mut self.reversing, "Reverse")
+                    .on_hover_text("Play the recorded history backwards");
+                if ui.button("Rewind").clicked()
+                    && let Some(track_state) = &mut self.track_state
+                {
+                    self.history
+                        .rewind(&mut track_state.scene, self.rewind_seconds);
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.rewind_seconds)
+                        .range(0.0..=self.history.duration)
+                        .speed(0.1)
+                        .suffix(" s"),
+                );
+                ui.add_space(5.);
+
+                if ui
+                    .add_enabled(self.track_state.is_some(), egui::Button::new("Export SVG"))
+                    .on_hover_text("Save the map, trajectories and scans as a vector figure")
+                    .clicked()
+                {
+                    self.export_dialog.save_file();
+                }
+                self.export_dialog.update(ctx);
+                if let Some(path) = self.export_dialog.take_picked()
+                    && let Some(track_state) = &self.track_state
+                {
+                    match std::fs::write(&path, track_state.to_svg(&self.history)) {
+                        Ok(()) => log::info!("Exported {path:?}"),
+                        Err(e) => log::error!("Failed to export {path:?}: {e}"),
+                    }
+                }
+                ui.add_space(5.);
+
                 ui.label("FPS:");
 
                 let fps = if self.durations.len() > 5 {
@@ -265,6 +686,23 @@ impl eframe::App for App {
             });
         });
 
+        if !self.problems.is_empty() {
+            let mut open = true;
+            egui::Window::new("Problems")
+                .open(&mut open)
+                .default_width(320.)
+                .show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for problem in &self.problems {
+                            ui.colored_label(Color32::YELLOW, problem);
+                        }
+                    });
+                });
+            if !open {
+                self.problems.clear();
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.style_mut().visuals.override_text_color = Some(Color32::from_white_alpha(70));
             let resp = egui_plot::Plot::new("main_plot")
@@ -289,17 +727,7 @@ impl eframe::App for App {
                     track_state.track_render_state.active = None;
 
                     for (&id, agent) in &track_state.scene.agents {
-                        let mut heading = agent.state.heading;
-                        let agent_pos = agent.state.position;
-                        let agent_size = glam::vec2(agent.config.length, agent.config.width);
-
-                        heading.y *= -heading.y;
-                        let body_view_pos = heading.rotate(pos - agent_pos);
-                        let bbox = Box2D {
-                            min: -agent_size / 2.,
-                            max: agent_size / 2.,
-                        };
-                        if bbox.contains(body_view_pos) {
+                        if agent.footprint_polygon().contains(pos) {
                             track_state.track_render_state.active = Some(id);
                             break;
                         }
@@ -310,42 +738,44 @@ impl eframe::App for App {
 
         ctx.request_repaint();
         if let Some(track_state) = &mut self.track_state {
-            let dt = ctx.input(|i| i.unstable_dt);
-            if !self.paused {
-                track_state.scene.update(dt);
+            let dt = ctx.input(|i| i.unstable_dt).min(MAX_FRAME_DT);
+            let drive = DriveInput::from_keys(ctx);
+            if self.paused {
+                self.accumulator = 0.;
+            } else if self.reversing {
+                self.history.rewind(&mut track_state.scene, dt);
+                // Stop once we've run out of history.
+                self.reversing = self.history.len() > 1;
+            } else {
+                step_fixed(
+                    &mut track_state.scene,
+                    &mut self.history,
+                    track_state.track_render_state.active,
+                    drive,
+                    &mut self.accumulator,
+                    dt,
+                );
             }
 
-            if ctx.input(|i| i.key_pressed(egui::Key::Space)) {
-                self.paused = !self.paused;
+            // Agents may be removed by the boundary policy.
+            if let Some(active) = track_state.track_render_state.active
+                && !track_state.scene.agents.contains_key(&active)
+            {
+                track_state.track_render_state.active = None;
             }
 
-            if let Some(active) = &track_state.track_render_state.active {
-                let Agent2D { config, state, .. } =
-                    track_state.scene.agents.get_mut(active).unwrap();
-                let config = &*config;
-
-                let keys = ctx.input(|i| i.keys_down.clone());
+            if self.last_reload_check.elapsed() > RELOAD_CHECK_INTERVAL {
+                self.last_reload_check = std::time::Instant::now();
 
-                if keys.contains(&egui::Key::ArrowUp) {
-                    state.torque += (config.torque_range.1 - config.torque_range.0) * dt * 0.2;
-                }
-
-                if keys.contains(&egui::Key::ArrowDown) {
-                    state.torque -= (config.torque_range.1 - config.torque_range.0) * dt * 0.2;
-                }
-
-                if keys.contains(&egui::Key::ArrowLeft) {
-                    state.beta += (config.beta_range.1 - config.beta_range.0) * dt * 0.2;
-                }
-
-                if keys.contains(&egui::Key::ArrowRight) {
-                    state.beta -= (config.beta_range.1 - config.beta_range.0) * dt * 0.2;
+                for agent in track_state.scene.agents.values() {
+                    if let Some(controller) = &agent.controller {
+                        controller.lock().hot_reload();
+                    }
                 }
+            }
 
-                state.torque = state
-                    .torque
-                    .clamp(config.torque_range.0, config.torque_range.1);
-                state.beta = state.beta.clamp(config.beta_range.0, config.beta_range.1);
+            if ctx.input(|i| i.key_pressed(egui::Key::Space)) {
+                self.paused = !self.paused;
             }
         }
 
@@ -359,3 +789,130 @@ impl eframe::App for App {
         self.last_time = std::time::Instant::now();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use sim::{
+        Agent2D, Lidar2D, Scene2D,
+        localization::PoseSource,
+        plugin::PluginRegistry,
+        scene::{history::SceneHistory, occupancy_map::BoundaryPolicy},
+        sensors::{Sensor2D, bumper::Bumper2D},
+    };
+
+    use crate::{
+        app::{App, DriveInput, step_fixed},
+        track_file::{AgentFile, TrackFile},
+    };
+
+    /// One bare agent, one with items that can't be built and one that can't be built at all.
+    const PARTIAL: &str = "
+version: 2
+map: {image: track.png, threshold: 128}
+agents:
+  - {scale: 1.0, position: [1, 2], heading: [0, 2]}
+  - scale: 0.5
+    position: [0, 0]
+    heading: [1, 0]
+    name: scout
+    sensors:
+      - {kind: sonarr}
+      - {kind: bumper, name: front, rate: 10}
+    controller: {kind: pid}
+    safety: {horizon: -1}
+  - {scale: -1, position: [0, 0], heading: [1, 0]}
+";
+
+    #[test]
+    fn test_partial_track() {
+        let track = TrackFile::parse(PARTIAL.as_bytes()).unwrap();
+        assert_eq!(track.map.boundary_policy(), BoundaryPolicy::Solid);
+        assert!(track.sim_params().0.is_empty());
+
+        let mut app = App::default();
+        let plugins = PluginRegistry::with_builtins();
+        let agents = track
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let f = serde_norway::from_value::<AgentFile>(value.clone()).unwrap();
+                app.load_agent(i, &f, &plugins, std::path::Path::new("."), &track.map)
+            })
+            .collect::<Vec<_>>();
+
+        // Everything left out of the bare agent falls back to its default.
+        let bare = agents[0].as_ref().unwrap();
+        assert_eq!(bare.state.position, glam::vec2(1., 2.));
+        assert_eq!(bare.state.heading, glam::Vec2::Y);
+        assert_eq!(bare.sensors.len(), 1);
+        let lidar = bare.sensors.read_as::<Lidar2D>(Lidar2D::TOPIC).unwrap();
+        assert_eq!(lidar.directions.len(), 60);
+        assert_eq!(bare.sensors.get(Lidar2D::TOPIC).unwrap().rate, None);
+        assert!(bare.controller.is_none());
+        assert!(bare.localizer.is_none());
+        assert!(bare.safety.is_none());
+        assert!(bare.observation.is_none());
+        assert_eq!(bare.pose_source, PoseSource::GroundTruth);
+        assert_eq!(bare.label.name, None);
+
+        // The items that can't be built are left out and the rest of the agent kept.
+        let scout = agents[1].as_ref().unwrap();
+        assert_eq!(scout.label.name.as_deref(), Some("scout"));
+        assert_eq!(scout.sensors.len(), 2);
+        assert!(scout.sensors.read_as::<Bumper2D>("front").is_some());
+        assert_eq!(scout.sensors.get("front").unwrap().rate, Some(10.));
+        assert!(scout.controller.is_none());
+        assert!(scout.safety.is_none());
+
+        assert!(agents[2].is_err());
+
+        let skipped = app
+            .problems
+            .iter()
+            .map(|p| p.split(':').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                "Skipped agents[1].sensors[0]",
+                "Skipped agents[1].controller",
+                "Skipped agents[1].safety"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixed_steps() {
+        let mut scene = Scene2D::from_pixels([8, 8], &[255; 64]).unwrap();
+        let id = scene.add_agent(Agent2D::default());
+        let mut history = SceneHistory::default();
+        let dt = scene.config().dt;
+        let drive = DriveInput {
+            throttle: 1.,
+            steer: 0.,
+        };
+
+        // Uneven frames adding up to 12 steps' worth of time.
+        let mut accumulator = 0.;
+        let mut steps = Vec::new();
+        for frame in [0.5, 1.5, 0.25, 3.75, 0.3, 0.7, 2., 3.] {
+            steps.push(step_fixed(
+                &mut scene,
+                &mut history,
+                Some(id),
+                drive,
+                &mut accumulator,
+                frame * dt,
+            ));
+        }
+
+        assert_eq!(steps, [0, 2, 0, 4, 0, 1, 2, 3]);
+        assert_eq!(steps.iter().sum::<usize>(), 12);
+        assert!((scene.time.0 - 12. * dt).abs() < 1e-5);
+        assert_eq!(history.len(), 12);
+        assert!(scene.agents[&id].state.velocity > 0.);
+        // What's left over waits for the next frame.
+        assert!(accumulator.abs() < 1e-3 * dt);
+    }
+}
@@ -1,6 +1,7 @@
 mod app;
 mod track_state;
 mod track_file;
+mod templates;
 
 use eframe::run_native;
 
@@ -0,0 +1,46 @@
+use sim::{Agent2D, Lidar2D, math::Pose2D, scene::generate, sensors::Sensor2D};
+
+/// Generated scenes offered under "New from template", so the app can be tried without a track file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    Stadium,
+    Rooms,
+}
+
+impl Template {
+    pub const ALL: [Template; 2] = [Template::Stadium, Template::Rooms];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::Stadium => "Stadium track",
+            Template::Rooms => "Grid of rooms",
+        }
+    }
+
+    /// The map as a grayscale image, and the agents to place in it.
+    pub fn build(self, lidar_count: usize) -> (image::DynamicImage, Vec<Agent2D>) {
+        let (size, pixels, pose) = match self {
+            Template::Stadium => {
+                let size = [48, 32];
+                let (pixels, radius) = generate::stadium_track(size, 4.);
+                (size, pixels, Pose2D::new(glam::vec2(0., -radius), 0.))
+            }
+            Template::Rooms => {
+                let size = [48, 48];
+                let pixels = generate::rooms(size, [3, 3], 1, 4);
+                (size, pixels, Pose2D::new(glam::Vec2::ZERO, 0.))
+            }
+        };
+
+        let image = image::GrayImage::from_raw(size[0] as u32, size[1] as u32, pixels)
+            .expect("Generated maps match their size");
+
+        let mut agent = Agent2D::with_scale(2.);
+        agent.state = agent.state.with_pose(pose);
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(lidar_count);
+        }
+
+        (image::DynamicImage::ImageLuma8(image), vec![agent])
+    }
+}
@@ -1,9 +1,118 @@
+use sim::{
+    localization::PoseSource,
+    math::Pose2D,
+    plugin::{ParamValue, PluginParams},
+    replay::TrajectoryPoint,
+    scene::occupancy_map::{BoundaryPolicy, OutOfBoundsAction},
+    safety::SafetySupervisor,
+    trailer::TrailerConfig,
+};
+
+use crate::track_state::TrackLoadError;
+
+/// Version of the track file format read by this build. Older files are migrated when loaded.
+pub const TRACK_FILE_VERSION: u64 = 2;
+
+/// `MIGRATIONS[i]` upgrades a file from version `i + 1` to version `i + 2`.
+const MIGRATIONS: [fn(&mut serde_norway::Mapping); TRACK_FILE_VERSION as usize - 1] = [migrate_v1];
+
+/// A track file in the current format. Use [TrackFile::parse], which migrates older versions, rather than
+/// deserializing directly.
 #[derive(serde::Deserialize)]
 pub struct TrackFile {
-    pub track: std::path::PathBuf,
+    pub map: MapFile,
+    /// Deserialized one at a time into [AgentFile]s, so a bad entry doesn't fail the whole file.
+    #[serde(default)]
+    pub agents: Vec<serde_norway::Value>,
+    /// Whole-simulation settings, see [sim::SimConfig]. The boundary policy comes from `map`.
+    #[serde(default)]
+    pub sim: Option<serde_norway::Mapping>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct MapFile {
+    /// Relative to the track file.
+    pub image: std::path::PathBuf,
     pub threshold: u8,
     #[serde(default)]
-    pub agents: Vec<AgentFile>,
+    pub boundary: BoundaryFile,
+    #[serde(default)]
+    pub out_of_bounds: OutOfBoundsFile,
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryFile {
+    #[default]
+    Solid,
+    Wrap,
+    Open,
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfBoundsFile {
+    #[default]
+    Report,
+    Freeze,
+    Remove,
+}
+
+impl TrackFile {
+    /// Reads a track file of this or any earlier version. Files without a `version` are version 1.
+    pub fn parse(reader: impl std::io::Read) -> Result<Self, TrackLoadError> {
+        let mut file: serde_norway::Value = serde_norway::from_reader(reader)?;
+
+        if let Some(file) = file.as_mapping_mut() {
+            let version = match file.get("version") {
+                None => 1,
+                Some(version) => version
+                    .as_u64()
+                    .filter(|v| *v >= 1)
+                    .ok_or(TrackLoadError::InvalidVersion)?,
+            };
+            if version > TRACK_FILE_VERSION {
+                return Err(TrackLoadError::UnsupportedVersion(version));
+            }
+
+            for migrate in &MIGRATIONS[version as usize - 1..] {
+                migrate(file);
+            }
+        }
+
+        Ok(serde_norway::from_value(file)?)
+    }
+}
+
+/// Version 2 gathers the map settings under `map`, with `track` renamed to `image`.
+fn migrate_v1(file: &mut serde_norway::Mapping) {
+    let mut map = serde_norway::Mapping::new();
+    for (old, new) in [
+        ("track", "image"),
+        ("threshold", "threshold"),
+        ("boundary", "boundary"),
+        ("out_of_bounds", "out_of_bounds"),
+    ] {
+        if let Some(value) = file.remove(old) {
+            map.insert(new.into(), value);
+        }
+    }
+
+    file.insert("map".into(), serde_norway::Value::Mapping(map));
+}
+
+impl MapFile {
+    pub fn boundary_policy(&self) -> BoundaryPolicy {
+        match self.boundary {
+            BoundaryFile::Solid => BoundaryPolicy::Solid,
+            BoundaryFile::Wrap => BoundaryPolicy::Wrap,
+            BoundaryFile::Open => BoundaryPolicy::Open(match self.out_of_bounds {
+                OutOfBoundsFile::Report => OutOfBoundsAction::Report,
+                OutOfBoundsFile::Freeze => OutOfBoundsAction::Freeze,
+                OutOfBoundsFile::Remove => OutOfBoundsAction::Remove,
+            }),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -15,6 +124,38 @@ pub struct AgentFile {
     pub heading: glam::Vec2,
     #[serde(default)]
     pub lidar: LidarFile,
+    #[serde(default)]
+    pub sensors: Vec<PluginFile>,
+    #[serde(default)]
+    pub controller: Option<PluginFile>,
+    #[serde(default)]
+    pub safety: Option<SafetyFile>,
+    #[serde(default)]
+    pub localizer: Option<PluginFile>,
+    /// Whether the controller sees the true pose or the localizer's estimate.
+    #[serde(default)]
+    pub pose_source: PoseSourceFile,
+    /// Observation space for training, see [sim::env::ObservationConfig].
+    #[serde(default)]
+    pub observation: Option<serde_norway::Mapping>,
+    /// Image of the map given to the localizer instead of the track, e.g. an outdated one. Relative to the track file
+    /// and read with the track's threshold.
+    #[serde(default)]
+    pub prior_map: Option<std::path::PathBuf>,
+    /// Towed in order behind the agent, unscaled.
+    #[serde(default)]
+    pub trailers: Vec<TrailerFile>,
+    /// Shown next to the agent instead of its id.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Body colour as `[r, g, b]`.
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+    #[serde(default)]
+    pub metadata: std::collections::BTreeMap<String, String>,
+    /// Poses the agent replays instead of driving, ignoring its controller. Empty to drive as usual.
+    #[serde(default)]
+    pub replay: Vec<ReplayPointFile>,
 }
 
 impl Default for AgentFile {
@@ -24,6 +165,18 @@ impl Default for AgentFile {
             position: glam::Vec2::ZERO,
             heading: glam::Vec2::X,
             lidar: Default::default(),
+            sensors: Vec::new(),
+            controller: None,
+            safety: None,
+            localizer: None,
+            pose_source: Default::default(),
+            observation: None,
+            prior_map: None,
+            trailers: Vec::new(),
+            name: None,
+            color: None,
+            metadata: Default::default(),
+            replay: Vec::new(),
         }
     }
 }
@@ -95,11 +248,486 @@ where
 #[derive(serde::Deserialize)]
 #[serde(untagged)]
 pub enum LidarFile {
-    Count { count: usize },
+    Count {
+        count: usize,
+        #[serde(default)]
+        rate: Option<f32>,
+        #[serde(default)]
+        latency: Option<f32>,
+        #[serde(default)]
+        jitter: Option<f32>,
+        #[serde(default)]
+        clock_offset: Option<f32>,
+        #[serde(default)]
+        clock_drift: Option<f32>,
+    },
 }
 
 impl Default for LidarFile {
     fn default() -> Self {
-        Self::Count { count: 60 }
+        Self::Count {
+            count: 60,
+            rate: None,
+            latency: None,
+            jitter: None,
+            clock_offset: None,
+            clock_drift: None,
+        }
+    }
+}
+
+impl LidarFile {
+    pub fn timing(&self) -> SensorTiming {
+        match *self {
+            Self::Count {
+                rate,
+                latency,
+                jitter,
+                clock_offset,
+                clock_drift,
+                ..
+            } => SensorTiming {
+                rate,
+                latency,
+                jitter,
+                clock_offset,
+                clock_drift,
+            },
+        }
+    }
+}
+
+/// Timing settings accepted by every sensor, each left as configured when missing.
+#[derive(Clone, Copy, Default)]
+pub struct SensorTiming {
+    pub rate: Option<f32>,
+    pub latency: Option<f32>,
+    pub jitter: Option<f32>,
+    pub clock_offset: Option<f32>,
+    pub clock_drift: Option<f32>,
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PoseSourceFile {
+    #[default]
+    GroundTruth,
+    Estimated,
+}
+
+impl From<PoseSourceFile> for PoseSource {
+    fn from(value: PoseSourceFile) -> Self {
+        match value {
+            PoseSourceFile::GroundTruth => PoseSource::GroundTruth,
+            PoseSourceFile::Estimated => PoseSource::Estimated,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SafetyFile {
+    #[serde(default = "SafetyFile::default_horizon")]
+    pub horizon: f32,
+    #[serde(default = "SafetyFile::default_steps")]
+    pub steps: usize,
+    #[serde(default)]
+    pub margin: f32,
+}
+
+impl SafetyFile {
+    fn default_horizon() -> f32 {
+        SafetySupervisor::default().horizon
+    }
+
+    fn default_steps() -> usize {
+        SafetySupervisor::default().steps
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ReplayPointFile {
+    /// Seconds of scene time.
+    pub time: f32,
+    #[serde(deserialize_with = "glam_map")]
+    pub position: glam::Vec2,
+    #[serde(deserialize_with = "glam_map")]
+    pub heading: glam::Vec2,
+}
+
+impl ReplayPointFile {
+    pub fn point(&self) -> TrajectoryPoint {
+        TrajectoryPoint {
+            time: self.time,
+            pose: Pose2D {
+                position: self.position,
+                heading: self.heading.normalize_or_zero(),
+            },
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TrailerFile {
+    #[serde(default = "TrailerFile::default_length")]
+    pub length: f32,
+    #[serde(default = "TrailerFile::default_width")]
+    pub width: f32,
+    #[serde(default = "TrailerFile::default_hitch_offset")]
+    pub hitch_offset: f32,
+}
+
+impl TrailerFile {
+    fn default_length() -> f32 {
+        TrailerConfig::default().length
+    }
+
+    fn default_width() -> f32 {
+        TrailerConfig::default().width
+    }
+
+    fn default_hitch_offset() -> f32 {
+        TrailerConfig::default().hitch_offset
+    }
+
+    pub fn config(&self) -> TrailerConfig {
+        TrailerConfig {
+            length: self.length,
+            width: self.width,
+            hitch_offset: self.hitch_offset,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PluginFile {
+    pub kind: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Sensing rate in Hz. Only used for sensors.
+    #[serde(default)]
+    pub rate: Option<f32>,
+    /// Delivery delay in seconds. Only used for sensors.
+    #[serde(default)]
+    pub latency: Option<f32>,
+    /// Standard deviation of the timestamp error in seconds. Only used for sensors.
+    #[serde(default)]
+    pub jitter: Option<f32>,
+    /// Reading of the sensor's clock at time zero in seconds. Only used for sensors.
+    #[serde(default)]
+    pub clock_offset: Option<f32>,
+    /// Fraction by which the sensor's clock runs fast. Only used for sensors.
+    #[serde(default)]
+    pub clock_drift: Option<f32>,
+    #[serde(flatten)]
+    pub params: serde_norway::Mapping,
+}
+
+impl TrackFile {
+    pub fn sim_params(&self) -> PluginParams {
+        self.sim.as_ref().map(mapping_params).unwrap_or_default()
+    }
+}
+
+impl AgentFile {
+    pub fn observation_params(&self) -> Option<PluginParams> {
+        self.observation.as_ref().map(mapping_params)
+    }
+}
+
+impl PluginFile {
+    pub fn timing(&self) -> SensorTiming {
+        SensorTiming {
+            rate: self.rate,
+            latency: self.latency,
+            jitter: self.jitter,
+            clock_offset: self.clock_offset,
+            clock_drift: self.clock_drift,
+        }
+    }
+
+    pub fn params(&self) -> PluginParams {
+        mapping_params(&self.params)
+    }
+}
+
+fn mapping_params(mapping: &serde_norway::Mapping) -> PluginParams {
+    PluginParams(
+        mapping
+            .iter()
+            .filter_map(|(k, v)| Some((k.as_str()?.to_string(), param_value(v)?)))
+            .collect(),
+    )
+}
+
+fn param_value(value: &serde_norway::Value) -> Option<ParamValue> {
+    use serde_norway::Value;
+
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(ParamValue::Bool(*b)),
+        Value::Number(n) => Some(ParamValue::Number(n.as_f64()?)),
+        Value::String(s) => Some(ParamValue::String(s.clone())),
+        Value::Sequence(seq) => Some(ParamValue::List(
+            seq.iter().filter_map(param_value).collect(),
+        )),
+        Value::Mapping(mapping) => Some(ParamValue::Map(mapping_params(mapping))),
+        Value::Tagged(tagged) => param_value(&tagged.value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::{Mutex, RwLock};
+    use sim::{
+        agent::{Agent2DConfig, Agent2DState},
+        config::{ConfigError, Validate},
+        controller::{AgentController, ControlContext, ControlInput},
+        plugin::{PluginError, PluginParams, PluginRegistry},
+        scene::{
+            Scene2DState,
+            occupancy_map::{BoundaryPolicy, OutOfBoundsAction},
+        },
+        sensors::{Sensor2D, TimeStamped},
+    };
+
+    use crate::{
+        track_file::{AgentFile, TRACK_FILE_VERSION, TrackFile, migrate_v1},
+        track_state::TrackLoadError,
+    };
+
+    const V1: &str = "
+track: track.png
+threshold: 128
+boundary: open
+out_of_bounds: freeze
+agents:
+  - scale: 1.0
+    position: [1, 2]
+    heading: [1, 0]
+";
+
+    fn parse(yaml: &str) -> Result<TrackFile, TrackLoadError> {
+        TrackFile::parse(yaml.as_bytes())
+    }
+
+    #[test]
+    fn test_migrations() {
+        // Version 1 had the map settings at the top level.
+        let mut file: serde_norway::Mapping = serde_norway::from_str(V1).unwrap();
+        migrate_v1(&mut file);
+        for key in ["track", "threshold", "boundary", "out_of_bounds"] {
+            assert!(!file.contains_key(key), "{key}");
+        }
+        let map = file["map"].as_mapping().unwrap();
+        assert_eq!(map["image"].as_str(), Some("track.png"));
+        assert!(!map.contains_key("track"));
+        assert_eq!(map["threshold"].as_u64(), Some(128));
+        assert_eq!(map["boundary"].as_str(), Some("open"));
+        assert_eq!(map["out_of_bounds"].as_str(), Some("freeze"));
+
+        let check = |track: TrackFile| {
+            assert_eq!(track.map.image, std::path::Path::new("track.png"));
+            assert_eq!(track.map.threshold, 128);
+            assert_eq!(
+                track.map.boundary_policy(),
+                BoundaryPolicy::Open(OutOfBoundsAction::Freeze)
+            );
+            assert_eq!(track.agents.len(), 1);
+        };
+        check(parse(V1).unwrap());
+
+        // What the migration writes reads back unchanged as the current version.
+        file.insert("version".into(), TRACK_FILE_VERSION.into());
+        let current = serde_norway::to_string(&file).unwrap();
+        check(parse(&current).unwrap());
+
+        assert!(matches!(
+            parse("version: 3\nmap: {image: track.png, threshold: 128}"),
+            Err(TrackLoadError::UnsupportedVersion(3))
+        ));
+        for version in ["0", "-1", "1.5", "two"] {
+            assert!(
+                matches!(
+                    parse(&format!("version: {version}\n{V1}")),
+                    Err(TrackLoadError::InvalidVersion)
+                ),
+                "{version}"
+            );
+        }
+    }
+
+    /// Reads out its period under a frame name, just to have parameters to load.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Tick {
+        frame: String,
+        period: f32,
+    }
+
+    impl Sensor2D for Tick {
+        type SensorType = f32;
+
+        const TOPIC: &'static str = "tick";
+
+        fn sense(
+            &mut self,
+            _agent_config: Agent2DConfig,
+            _agent_state: Agent2DState,
+            scene: Scene2DState,
+        ) -> Option<TimeStamped<f32>> {
+            Some(TimeStamped {
+                time: scene.time,
+                state: self.period,
+                meta: Default::default(),
+            })
+        }
+    }
+
+    impl Validate for Tick {
+        fn validate(&self) -> Result<(), ConfigError> {
+            sim::config::positive("period", self.period)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Constant(ControlInput);
+
+    impl AgentController for Constant {
+        fn control(&mut self, _: &ControlContext) -> ControlInput {
+            self.0
+        }
+    }
+
+    fn registry() -> PluginRegistry {
+        let mut registry = PluginRegistry::default();
+        registry.register_sensor(Tick::TOPIC, |params: &PluginParams| {
+            let tick = Tick {
+                frame: params.str("frame")?.to_string(),
+                period: params.f32_or("period", 1.)?,
+            };
+            tick.validate()?;
+            Ok(Arc::new(RwLock::new(tick)))
+        });
+        registry.register_controller("constant", |params: &PluginParams| {
+            Ok(Arc::new(Mutex::new(Constant(ControlInput {
+                torque: params.f32_or("torque", 0.)?,
+                beta: params.f32_or("beta", 0.)?,
+            }))))
+        });
+
+        registry
+    }
+
+    /// The one agent of a track, with `agent` added to it.
+    fn agent_file(agent: &str) -> AgentFile {
+        let yaml = format!(
+            "
+version: {TRACK_FILE_VERSION}
+map: {{image: track.png, threshold: 128}}
+agents:
+  - scale: 1.0
+    position: [0, 0]
+    heading: [1, 0]
+{agent}"
+        );
+        let track = parse(&yaml).unwrap();
+        serde_norway::from_value(track.agents[0].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_plugins() {
+        let agent = agent_file(
+            "
+    sensors:
+      - kind: tick
+        name: ticker
+        rate: 10
+        frame: base
+        period: 0.5
+    controller:
+      kind: constant
+      torque: 5
+      beta: 0.25
+",
+        );
+        let registry = registry();
+
+        // The fields every sensor has are taken out, the rest are left for the factory.
+        let sensor = &agent.sensors[0];
+        assert_eq!(sensor.name.as_deref(), Some("ticker"));
+        assert_eq!(sensor.rate, Some(10.));
+        let mut params = sensor.params().0.into_keys().collect::<Vec<_>>();
+        params.sort();
+        assert_eq!(params, ["frame", "period"]);
+
+        let tick = registry
+            .create_sensor(&sensor.kind, &sensor.params())
+            .unwrap();
+        assert_eq!(
+            tick.read().as_any().downcast_ref::<Tick>(),
+            Some(&Tick {
+                frame: "base".to_string(),
+                period: 0.5,
+            })
+        );
+
+        let controller = agent.controller.as_ref().unwrap();
+        let constant = registry
+            .create_controller(&controller.kind, &controller.params())
+            .unwrap();
+        assert_eq!(
+            format!("{:?}", constant.lock()),
+            format!(
+                "{:?}",
+                Constant(ControlInput {
+                    torque: 5.,
+                    beta: 0.25,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn test_plugin_errors() {
+        let registry = registry();
+        let sensor = |yaml: &str| {
+            let agent = agent_file(&format!("    sensors:\n      - {yaml}\n"));
+            let sensor = &agent.sensors[0];
+            registry
+                .create_sensor(&sensor.kind, &sensor.params())
+                .map(|_| ())
+        };
+
+        assert!(matches!(
+            sensor("{kind: tock, frame: base}"),
+            Err(PluginError::UnknownSensor(kind)) if kind == "tock"
+        ));
+        assert!(matches!(
+            sensor("{kind: tick}"),
+            Err(PluginError::MissingParam(param)) if param == "frame"
+        ));
+        assert!(matches!(
+            sensor("{kind: tick, frame: base, period: fast}"),
+            Err(PluginError::InvalidParam(param, "number")) if param == "period"
+        ));
+        assert!(matches!(
+            sensor("{kind: tick, frame: base, period: -1}"),
+            Err(PluginError::Config(_))
+        ));
+        assert!(sensor("{kind: tick, frame: base}").is_ok());
+
+        let agent = agent_file("    controller: {kind: pid, torque: 5}\n");
+        let controller = agent.controller.as_ref().unwrap();
+        assert!(matches!(
+            registry.create_controller(&controller.kind, &controller.params()),
+            Err(PluginError::UnknownController(kind)) if kind == "pid"
+        ));
+        let agent = agent_file("    controller: {kind: constant, torque: [5]}\n");
+        let controller = agent.controller.as_ref().unwrap();
+        assert!(matches!(
+            registry.create_controller(&controller.kind, &controller.params()),
+            Err(PluginError::InvalidParam(param, "number")) if param == "torque"
+        ));
     }
 }
@@ -0,0 +1,167 @@
+use std::fmt::Write;
+
+use sim::{
+    Lidar2D, Scene2D,
+    scene::history::SceneHistory,
+    sensors::{Sensor2D, lidar::Lidar2DSensed},
+};
+
+use crate::track_state::TrackState;
+
+/// Colours for each agent without its own, cycled through.
+const TRAJECTORY_COLORS: [&str; 6] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#9467bd", "#ff7f0e", "#17becf",
+];
+
+/// Flips y so the figure is the right way up, as SVG's y axis points down.
+fn point(p: glam::Vec2) -> String {
+    format!("{:.3},{:.3}", p.x, -p.y)
+}
+
+impl TrackState {
+    /// The scene as a vector figure in metres, see [scene_svg].
+    pub fn to_svg(&self, history: &SceneHistory) -> String {
+        scene_svg(&self.scene, history)
+    }
+}
+
+/// `scene` as a vector figure in metres: the outlines of the map's obstacles, each agent's trajectory over `history`,
+/// its body and heading, and the points of its latest lidar scan.
+fn scene_svg(scene: &Scene2D, history: &SceneHistory) -> String {
+    let bounds = scene.occupancy_map.bounds();
+    let size = bounds.size();
+    let mut svg = String::new();
+
+    // Writing to a String can't fail.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}mm" height="{}mm">"#,
+        bounds.min.x,
+        -bounds.max.y,
+        size.x,
+        size.y,
+        size.x * 10.,
+        size.y * 10.,
+    );
+
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke="black" stroke-width="0.1" stroke-linejoin="round">"#
+    );
+    for outline in scene.occupancy_map.outlines(0.) {
+        let corners = outline.points.into_iter().map(point).collect::<Vec<_>>();
+        let _ = writeln!(svg, r#"<path d="M{}Z"/>"#, corners.join("L"));
+    }
+    let _ = writeln!(svg, "</g>");
+
+    let mut ids = scene.agents.keys().copied().collect::<Vec<_>>();
+    ids.sort();
+    for (i, &id) in ids.iter().enumerate() {
+        let agent = &scene.agents[&id];
+        let color = match agent.label.color {
+            Some([r, g, b]) => format!("#{r:02x}{g:02x}{b:02x}"),
+            None => TRAJECTORY_COLORS[i % TRAJECTORY_COLORS.len()].to_owned(),
+        };
+
+        let trajectory = history
+            .iter()
+            .filter_map(|s| s.agent(id))
+            .map(|a| point(a.state.position))
+            .chain([point(agent.state.position)])
+            .collect::<Vec<_>>();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="0.05" stroke-linejoin="round"/>"#,
+            trajectory.join(" ")
+        );
+
+        if let Some(scan) = scene
+            .scene_loop
+            .query_topic_as::<Lidar2DSensed>(id, Lidar2D::TOPIC)
+        {
+            let _ = writeln!(svg, r#"<g fill="{color}" fill-opacity="0.6">"#);
+            for p in &scan.state.points {
+                let _ = writeln!(
+                    svg,
+                    r#"<circle cx="{:.3}" cy="{:.3}" r="0.06"/>"#,
+                    p.x, -p.y
+                );
+            }
+            let _ = writeln!(svg, "</g>");
+        }
+
+        for trailer in &agent.trailers {
+            let body = trailer.footprint().corners().map(point).join(" ");
+            let _ = writeln!(
+                svg,
+                r#"<polygon points="{body}" fill="{color}" fill-opacity="0.25" stroke="{color}" stroke-width="0.03"/>"#
+            );
+        }
+
+        let body = agent
+            .footprint_polygon()
+            .corners()
+            .iter()
+            .map(|&c| point(c))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let nose = agent.state.position + agent.config.length * agent.state.heading;
+        let _ = writeln!(
+            svg,
+            r#"<polygon points="{body}" fill="{color}" fill-opacity="0.4" stroke="{color}" stroke-width="0.03"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<path d="M{}L{}" stroke="{color}" stroke-width="0.05"/>"#,
+            point(agent.state.position),
+            point(nose)
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod test {
+    use sim::{Agent2D, Scene2D, scene::history::SceneHistory};
+
+    use crate::track_state::export::scene_svg;
+
+    #[test]
+    fn test_svg_elements() {
+        // Two separate walls in an 8 by 8 room.
+        let mut pixels = [255; 64];
+        for i in [9, 10, 11, 45, 53] {
+            pixels[i] = 0;
+        }
+        let mut scene = Scene2D::from_pixels([8, 8], &pixels).unwrap();
+        for x in [-2., 2.] {
+            let mut agent = Agent2D::default();
+            agent.state.position = glam::vec2(x, 0.);
+            scene.add_agent(agent);
+        }
+        let mut history = SceneHistory::default();
+        for _ in 0..3 {
+            history.record(&scene);
+            scene.step();
+        }
+
+        let svg = scene_svg(&scene, &history);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        let count = |element: &str| svg.matches(element).count();
+        assert_eq!(
+            count("<path d=\"M"),
+            2 + 2,
+            "one outline per wall, one heading per agent"
+        );
+        assert_eq!(count("<polyline "), 2);
+        assert_eq!(count("fill-opacity=\"0.4\""), 2);
+        // Each trajectory has a point for every recorded step and the current one.
+        for line in svg.lines().filter(|l| l.starts_with("<polyline ")) {
+            let points = line.split('"').nth(1).unwrap();
+            assert_eq!(points.split(' ').count(), 4);
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use eframe::egui;
 use egui_plot::PlotItemBase;
 use rayon::prelude::*;
-use sim::{Agent2D, Scene2D, scene::AgentId};
+use sim::{Agent2D, Scene2D, SimConfig, scene::AgentId};
 use std::time::Instant;
 
+mod export;
 mod render;
 
 #[derive(Default, Debug, Copy, Clone)]
@@ -11,22 +12,33 @@ pub struct TrackRenderState {
     pub active: Option<AgentId>,
 }
 
+/// Where one agent can drive, tinted over the track.
+#[derive(Clone)]
+pub struct TraversabilityOverlay {
+    pub agent: AgentId,
+    pub texture: egui::TextureHandle,
+    pub region_count: usize,
+    pub narrow_passages: Vec<glam::Vec2>,
+}
+
 #[derive(Clone)]
 pub struct TrackState {
     base: PlotItemBase,
     pub(crate) track_texture: egui::TextureHandle,
     pub(crate) track_render_state: TrackRenderState,
     pub(crate) scene: Scene2D,
+    pub(crate) traversability: Option<TraversabilityOverlay>,
 }
 
 impl TrackState {
     pub fn new(
         image: &image::DynamicImage,
         threshold: u8,
+        config: SimConfig,
         track_render_state: TrackRenderState,
         agents: Vec<Agent2D>,
         ctx: &egui::Context,
-    ) -> Self {
+    ) -> Result<Self, TrackLoadError> {
         let start = Instant::now();
 
         let image = image.to_luma8();
@@ -44,7 +56,7 @@ impl TrackState {
 
         log::info!("Image: Width: {}, Height: {}", size[0], size[1],);
 
-        let mut scene = Scene2D::from_pixels([size[0] as _, size[1] as _], &data).unwrap();
+        let mut scene = Scene2D::new([size[0] as _, size[1] as _], &data, config)?;
         for agent in agents {
             scene.add_agent(agent);
         }
@@ -74,12 +86,58 @@ impl TrackState {
             start.elapsed().as_millis()
         );
 
-        TrackState {
+        Ok(TrackState {
             base: PlotItemBase::new("TrackState".into()),
             track_texture: texture_handle,
             track_render_state,
             scene,
-        }
+            traversability: None,
+        })
+    }
+
+    /// Tints free space `agent` is too wide for in red, and space it fits but can't reach from where it is in orange.
+    pub fn check_traversability(&mut self, agent: AgentId, ctx: &egui::Context) {
+        let Some(traversability) = self.scene.traversability(agent) else {
+            self.traversability = None;
+            return;
+        };
+        let own = traversability.region(self.scene.agents[&agent].state.position);
+
+        let size = self.scene.occupancy_map.size;
+        let pixels = self
+            .scene
+            .occupancy_map
+            .pixels
+            .iter()
+            .zip(traversability.regions())
+            .flat_map(|(&occupied, region)| match (occupied, region) {
+                (true, _) => [0, 0, 0, 0],
+                (false, None) => [230, 60, 60, 110],
+                (false, Some(r)) if own == Some(*r as usize) => [0, 0, 0, 0],
+                (false, Some(_)) => [240, 160, 40, 90],
+            })
+            .collect::<Vec<_>>();
+        let texture = ctx.load_texture(
+            "traversability_texture",
+            egui::ColorImage::from_rgba_unmultiplied([size.x, size.y], &pixels),
+            egui::TextureOptions::NEAREST,
+        );
+
+        log::info!(
+            "{agent:?} fits in {} regions, with {} narrow passage samples",
+            traversability.region_count,
+            traversability.narrow_passages.len()
+        );
+        self.traversability = Some(TraversabilityOverlay {
+            agent,
+            texture,
+            region_count: traversability.region_count,
+            narrow_passages: traversability
+                .narrow_passages
+                .iter()
+                .map(|p| p.center)
+                .collect(),
+        });
     }
 }
 
@@ -93,12 +151,34 @@ pub enum TrackLoadError {
 
     #[error("Deserialize: {0}")]
     Deserialize(#[from] serde_norway::Error),
+
+    #[error("Plugin: {0}")]
+    Plugin(#[from] sim::plugin::PluginError),
+
+    #[error("Map: {0}")]
+    Map(#[from] sim::scene::Scene2DError),
+
+    #[error("Invalid scenario: {0}")]
+    Config(#[from] sim::config::ConfigError),
+
+    #[error("Replay: {0}")]
+    Replay(#[from] sim::replay::TrajectoryError),
+
+    #[error("Track file version must be a positive integer")]
+    InvalidVersion,
+
+    #[error(
+        "Track file version {0} is newer than this build supports ({supported}); update the simulator",
+        supported = crate::track_file::TRACK_FILE_VERSION
+    )]
+    UnsupportedVersion(u64),
 }
 
 impl TrackState {
     pub fn load(
         path: impl AsRef<std::path::Path>,
         threshold: u8,
+        config: SimConfig,
         track_render_state: TrackRenderState,
         agents: Vec<Agent2D>,
         ctx: &egui::Context,
@@ -117,12 +197,6 @@ impl TrackState {
             start.elapsed().as_millis()
         );
 
-        Ok(TrackState::new(
-            &image,
-            threshold,
-            track_render_state,
-            agents,
-            ctx,
-        ))
+        TrackState::new(&image, threshold, config, track_render_state, agents, ctx)
     }
 }
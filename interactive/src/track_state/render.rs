@@ -2,7 +2,7 @@ use std::ops::RangeInclusive;
 
 use egui::{Color32, Rect, Shape, Ui};
 use egui_plot::{PlotBounds, PlotGeometry, PlotItem, PlotItemBase, PlotPoint, PlotTransform};
-use sim::agent::Agent2DMeasurements;
+use sim::{Lidar2D, sensors::{Sensor2D, lidar::Lidar2DSensed}};
 
 use crate::track_state::TrackState;
 
@@ -14,9 +14,9 @@ impl PlotItem for TrackState {
     fn shapes(&self, ui: &Ui, transform: &PlotTransform, shapes: &mut Vec<Shape>) {
         // Track Image
         let image_screen_rect = {
-            let size = self.track_texture.size_vec2();
-            let left_top = PlotPoint::new(-0.5 * size.x as f64, -0.5 * size.y as f64);
-            let right_bottom = PlotPoint::new(0.5 * size.x as f64, 0.5 * size.y as f64);
+            let bounds = self.scene.occupancy_map.bounds();
+            let left_top = vec2_to_plotpoint(bounds.min);
+            let right_bottom = vec2_to_plotpoint(bounds.max);
 
             let left_top_screen = transform.position_from_point(&left_top);
             let right_bottom_screen = transform.position_from_point(&right_bottom);
@@ -35,10 +35,60 @@ impl PlotItem for TrackState {
             &(self.track_texture.id(), image_screen_rect.size()).into(),
         );
 
+        if let Some(overlay) = &self.traversability {
+            egui::paint_texture_at(
+                ui.painter(),
+                image_screen_rect,
+                &egui::ImageOptions {
+                    uv: Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.)),
+                    bg_fill: Color32::TRANSPARENT,
+                    tint: Color32::WHITE,
+                    rotation: None,
+                    corner_radius: egui::CornerRadius::ZERO,
+                },
+                &(overlay.texture.id(), image_screen_rect.size()).into(),
+            );
+
+            for &center in &overlay.narrow_passages {
+                shapes.push(Shape::circle_filled(
+                    transform.position_from_point(&vec2_to_plotpoint(center)),
+                    2.0,
+                    Color32::RED,
+                ));
+            }
+        }
+
         for (id, agent) in &self.scene.agents {
             let agent_pos = transform
                 .position_from_point(&PlotPoint::from(agent.state.position.as_dvec2().to_array()));
 
+            // Trailers, each with a drawbar to the body towing it
+            {
+                let mut tow = agent.state.pose();
+                for trailer in &agent.trailers {
+                    let corners = trailer
+                        .footprint()
+                        .corners()
+                        .map(|c| transform.position_from_point(&vec2_to_plotpoint(c)));
+                    shapes.push(Shape::convex_polygon(
+                        corners.to_vec(),
+                        Color32::DARK_BLUE.gamma_multiply(0.6),
+                        (0.0, Color32::TRANSPARENT),
+                    ));
+
+                    let drawbar = [
+                        tow.position,
+                        trailer.pose.position + trailer.pose.heading * trailer.config.length,
+                    ]
+                    .map(|p| transform.position_from_point(&vec2_to_plotpoint(p)));
+                    shapes.push(Shape::line_segment(
+                        drawbar,
+                        egui::Stroke::new(1.5, Color32::GRAY),
+                    ));
+                    tow = trailer.pose;
+                }
+            }
+
             // Agent direction
             {
                 let agent_heading = transform.position_from_point(&PlotPoint::from(
@@ -55,29 +105,21 @@ impl PlotItem for TrackState {
 
             // Agent Body
             {
-                let transform_scale = egui::vec2(
-                    transform.dpos_dvalue_x() as _,
-                    transform.dpos_dvalue_y() as _,
-                )
-                .abs();
-
-                let flip_y = egui::vec2(1., -1.);
-                let front: egui::Vec2 =
-                    egui::Vec2::from(mint::Vector2::<f32>::from(agent.state.heading))
-                        * transform_scale;
-                let left = front.rot90();
-
-                let center = agent_pos;
-                let half_extent = egui::Vec2::new(agent.config.length, agent.config.width) * 0.5;
+                let corners = agent
+                    .footprint_polygon()
+                    .corners()
+                    .iter()
+                    .map(|&c| transform.position_from_point(&vec2_to_plotpoint(c)))
+                    .collect();
+
+                let color = agent
+                    .label
+                    .color
+                    .map_or(Color32::DARK_BLUE, |[r, g, b]| Color32::from_rgb(r, g, b));
 
                 shapes.push(Shape::convex_polygon(
-                    vec![
-                        center + (front * half_extent.x - left * half_extent.y) * flip_y,
-                        center + (front * half_extent.x + left * half_extent.y) * flip_y,
-                        center + (-front * half_extent.x + left * half_extent.y) * flip_y,
-                        center + (-front * half_extent.x - left * half_extent.y) * flip_y,
-                    ],
-                    Color32::DARK_BLUE,
+                    corners,
+                    color,
                     if self.track_render_state.active == Some(*id) {
                         (1.0, Color32::from_white_alpha(80))
                     } else {
@@ -86,12 +128,48 @@ impl PlotItem for TrackState {
                 ));
             }
 
+            // Agent name
+            if let Some(name) = &agent.label.name {
+                let text = ui.fonts_mut(|fonts| {
+                    Shape::text(
+                        fonts,
+                        agent_pos - egui::vec2(0., 12.),
+                        egui::Align2::CENTER_BOTTOM,
+                        name,
+                        egui::FontId::proportional(12.),
+                        ui.style().visuals.text_color(),
+                    )
+                });
+                shapes.push(text);
+            }
+
+            // Estimated pose
+            if let Some(estimate) = &agent.estimate {
+                let position =
+                    transform.position_from_point(&vec2_to_plotpoint(estimate.pose.position));
+                let heading = transform.position_from_point(&vec2_to_plotpoint(
+                    estimate.pose.position + agent.config.length * estimate.pose.heading,
+                ));
+
+                shapes.push(Shape::circle_stroke(
+                    position,
+                    4.0,
+                    egui::Stroke::new(1.5, Color32::GOLD),
+                ));
+                shapes.push(Shape::line_segment(
+                    [position, heading],
+                    egui::Stroke::new(1.5, Color32::GOLD),
+                ));
+            }
+
             // Lidar Measurements
             {
-                if let Some(Agent2DMeasurements { lidar: Some(lidar) }) =
-                    &self.scene.scene_loop.query(*id)
+                if let Some(lidar) = self
+                    .scene
+                    .scene_loop
+                    .query_topic_as::<Lidar2DSensed>(*id, Lidar2D::TOPIC)
                 {
-                    for &point in &lidar.state.0 {
+                    for &point in &lidar.state.points {
                         let agent_heading =
                             transform.position_from_point(&vec2_to_plotpoint(point));
                         shapes.push(Shape::circle_filled(
@@ -137,12 +215,10 @@ impl PlotItem for TrackState {
 
     fn bounds(&self) -> PlotBounds {
         let mut bounds = PlotBounds::NOTHING;
-        let size = self.track_texture.size_vec2();
-        let left_top = PlotPoint::new(-0.5 * size.x as f64, -0.5 * size.y as f64);
-        let right_bottom = PlotPoint::new(0.5 * size.x as f64, 0.5 * size.y as f64);
+        let map_bounds = self.scene.occupancy_map.bounds();
 
-        bounds.extend_with(&left_top);
-        bounds.extend_with(&right_bottom);
+        bounds.extend_with(&vec2_to_plotpoint(map_bounds.min));
+        bounds.extend_with(&vec2_to_plotpoint(map_bounds.max));
         bounds
     }
 
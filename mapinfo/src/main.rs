@@ -0,0 +1,177 @@
+//! Prints statistics about a map and optionally renders them over it, to check a map before running long simulations
+//! on it.
+//!
+//! ```text
+//! mapinfo <map.png|map.pgm|map.svg|map.yaml> [--threshold 127] [--resolution 0.25] [--overlay overlay.png]
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sim::scene::{analysis::MapAnalysis, occupancy_map::OccupancyMap, ros_map::RosMap};
+
+const USAGE: &str = "Usage: mapinfo <map.png|map.pgm|map.svg|map.yaml> [--threshold 127] [--resolution 0.25] [--overlay overlay.png]";
+
+struct Args {
+    map: PathBuf,
+    /// Pixels at most this bright are occupied, as in the simulator. ROS maps use their own thresholds.
+    threshold: u8,
+    resolution: f32,
+    overlay: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut map = None;
+        let mut parsed = Self {
+            map: PathBuf::new(),
+            threshold: 127,
+            resolution: 0.25,
+            overlay: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--threshold" => parsed.threshold = value()?.parse().context("--threshold")?,
+                "--resolution" => parsed.resolution = value()?.parse().context("--resolution")?,
+                "--overlay" => parsed.overlay = Some(value()?.into()),
+                "-h" | "--help" => anyhow::bail!("{USAGE}"),
+                _ if map.is_none() && !arg.starts_with("--") => map = Some(arg.into()),
+                _ => anyhow::bail!("Unexpected argument {arg:?}\n{USAGE}"),
+            }
+        }
+
+        parsed.map = map.context(USAGE)?;
+        anyhow::ensure!(
+            parsed.resolution.is_finite() && parsed.resolution > 0.,
+            "--resolution must be positive"
+        );
+
+        Ok(parsed)
+    }
+}
+
+fn load_image(path: &Path) -> anyhow::Result<image::GrayImage> {
+    let is_svg = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("svg"));
+    if !is_svg {
+        return Ok(image::open(path)?.to_luma8());
+    }
+
+    let tree = resvg::usvg::Tree::from_data(&std::fs::read(path)?, &Default::default())?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+        .context("The SVG has no area")?;
+    // Whatever the drawing leaves transparent is free space.
+    pixmap.fill(resvg::tiny_skia::Color::WHITE);
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::default(),
+        &mut pixmap.as_mut(),
+    );
+
+    let rgba = image::RgbaImage::from_raw(size.width(), size.height(), pixmap.take())
+        .context("The rendered SVG doesn't match its size")?;
+    Ok(image::DynamicImage::ImageRgba8(rgba).to_luma8())
+}
+
+fn load_map(args: &Args) -> anyhow::Result<OccupancyMap> {
+    let is_yaml = args
+        .map
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+    if is_yaml {
+        return Ok(RosMap::load(&args.map)?.map);
+    }
+
+    let image = load_image(&args.map)?;
+    let size = glam::uvec2(image.width(), image.height()).as_usizevec2();
+    let pixels = image.pixels().map(|p| p.0[0] <= args.threshold).collect();
+    Ok(OccupancyMap::from_pixels(size, pixels)?)
+}
+
+/// One pixel per analysis sample: walls in black, free space shaded by clearance, passage centre lines in green and the
+/// narrowest passage marked in red.
+fn render_overlay(map: &OccupancyMap, analysis: &MapAnalysis) -> image::RgbImage {
+    let resolution = analysis.resolution();
+    let size = (map.extent() / resolution).ceil().as_uvec2();
+    let bounds = map.bounds();
+    let top_left = glam::vec2(bounds.min.x, bounds.max.y);
+    let to_world = |x: u32, y: u32| {
+        let offset = glam::vec2(x as f32 + 0.5, y as f32 + 0.5) * resolution;
+        glam::vec2(offset.x, -offset.y) + top_left
+    };
+    let to_pixel = |loc: glam::Vec2| {
+        let offset = (loc - top_left) / resolution;
+        glam::vec2(offset.x, -offset.y).floor().as_ivec2()
+    };
+
+    let mut overlay = image::RgbImage::from_fn(size.x, size.y, |x, y| {
+        let loc = to_world(x, y);
+        if map.is_occupied_vec2(loc) {
+            return image::Rgb([0, 0, 0]);
+        }
+
+        let shade = (analysis.clearance(loc) / 5.).min(1.);
+        image::Rgb([
+            (120. + 135. * shade) as u8,
+            (140. + 115. * shade) as u8,
+            255,
+        ])
+    });
+    let mut plot = |loc: glam::Vec2, color: [u8; 3]| {
+        let pixel = to_pixel(loc);
+        if pixel.cmpge(glam::IVec2::ZERO).all() && pixel.as_uvec2().cmplt(size).all() {
+            overlay.put_pixel(pixel.x as u32, pixel.y as u32, image::Rgb(color));
+        }
+    };
+
+    for passage in &analysis.passages {
+        plot(passage.center, [40, 160, 40]);
+    }
+    if let Some(narrowest) = analysis.narrowest_passage() {
+        let arm = narrowest.width / 2.;
+        for step in -8..=8 {
+            let t = step as f32 / 8. * arm;
+            plot(narrowest.center + glam::vec2(t, t), [220, 30, 30]);
+            plot(narrowest.center + glam::vec2(t, -t), [220, 30, 30]);
+        }
+    }
+
+    overlay
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let args = Args::parse(std::env::args().skip(1))?;
+    let map = load_map(&args).with_context(|| format!("Loading {}", args.map.display()))?;
+    let size = map.size;
+
+    log::debug!("Analysing every {} m", args.resolution);
+    let analysis = MapAnalysis::new(&map, args.resolution);
+
+    println!("{}: {} x {} cells", args.map.display(), size.x, size.y);
+    println!("Free area: {:.0} m²", analysis.free_area);
+    println!("Occupied area: {:.0} m²", analysis.occupied_area);
+    println!("Objects: {}", analysis.object_count);
+    println!("Boundary length: {:.0} m", analysis.boundary_length);
+    match analysis.narrowest_passage() {
+        Some(p) => println!(
+            "Narrowest passage: {:.2} m at ({:.2}, {:.2})",
+            p.width, p.center.x, p.center.y
+        ),
+        None => println!("Narrowest passage: none"),
+    }
+
+    if let Some(path) = &args.overlay {
+        render_overlay(&map, &analysis)
+            .save(path)
+            .with_context(|| format!("Saving {}", path.display()))?;
+        println!("Overlay written to {}", path.display());
+    }
+
+    Ok(())
+}
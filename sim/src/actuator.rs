@@ -0,0 +1,112 @@
+//! Actuators that take time to deliver what they are commanded. Drive motors and steering racks on real robots lag
+//! and slew, and controllers tuned against instant actuation oscillate or overshoot once they meet them.
+
+use crate::config::{self, ConfigError, Validate};
+
+/// A first-order lag followed by a slew-rate limit, between what is commanded and what is delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Actuator {
+    /// Seconds for the output to close all but 1/e of the gap to the command. 0 for no lag.
+    pub time_constant: f32,
+    /// Fastest the output can change, per second. `None` for no limit.
+    pub max_rate: Option<f32>,
+}
+
+/// The drive and steering [Actuator]s of an agent.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Actuators {
+    /// In N·m.
+    pub torque: Actuator,
+    /// In radians.
+    pub beta: Actuator,
+}
+
+impl Validate for Actuator {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("time_constant", self.time_constant)?;
+        config::non_negative("time_constant", self.time_constant)?;
+
+        match self.max_rate {
+            Some(rate) => config::positive("max_rate", rate),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Validate for Actuators {
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.torque.validate().map_err(|e| e.in_field("torque"))?;
+        self.beta.validate().map_err(|e| e.in_field("beta"))
+    }
+}
+
+impl Actuator {
+    /// The output `dt` after it was `output`, chasing `command`.
+    pub fn respond(&self, output: f32, command: f32, dt: f32) -> f32 {
+        let target = if self.time_constant > 0. {
+            output + (command - output) * (1. - (-dt / self.time_constant).exp())
+        } else {
+            command
+        };
+
+        match self.max_rate {
+            Some(rate) => output + (target - output).clamp(-rate * dt, rate * dt),
+            None => target,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D,
+        actuator::{Actuator, Actuators},
+        controller::ControlInput,
+    };
+
+    #[test]
+    fn test_actuators() {
+        let lag = Actuator {
+            time_constant: 0.5,
+            max_rate: None,
+        };
+        assert!((lag.respond(0., 1., 0.5) - (1. - (-1f32).exp())).abs() < 1e-6);
+        assert_eq!(Actuator::default().respond(0., 1., 0.1), 1.);
+
+        let slew = Actuator {
+            time_constant: 0.,
+            max_rate: Some(2.),
+        };
+        assert_eq!(slew.respond(0., 1., 0.1), 0.2);
+        assert_eq!(slew.respond(0., -1., 0.1), -0.2);
+        assert_eq!(slew.respond(0.9, 1., 0.1), 1.);
+
+        // Without actuators the agent takes its command at once; with them it works up to it.
+        let mut instant = Agent2D::default();
+        instant.command(ControlInput {
+            torque: 50.,
+            beta: 0.5,
+        });
+        assert_eq!((instant.state.torque, instant.state.beta), (50., 0.5));
+
+        let mut agent = Agent2D::default();
+        agent.config.actuators = Some(Actuators {
+            torque: lag,
+            beta: slew,
+        });
+        agent.command(ControlInput {
+            torque: 50.,
+            beta: 0.5,
+        });
+        assert_eq!((agent.state.torque, agent.state.beta), (0., 0.));
+        agent.update(0.1, None);
+        assert!(agent.last_state.unwrap().torque > 0. && agent.last_state.unwrap().torque < 50.);
+        assert!((agent.last_state.unwrap().beta - 0.2).abs() < 1e-6);
+        for _ in 0..20 {
+            agent.update(0.1, None);
+        }
+        assert!(agent.last_state.unwrap().beta > 0.49);
+    }
+}
@@ -1,52 +1,234 @@
-use parking_lot::RwLock;
-use std::{f32::consts::PI, sync::Arc};
+use parking_lot::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+use std::{collections::BTreeMap, f32::consts::PI, sync::Arc};
 
-use crate::{Lidar2D, sensors::{Sensor2D, TimeStamped}};
+use crate::{
+    Lidar2D,
+    actuator::Actuators,
+    battery::Battery,
+    config::{self, ConfigError, Validate},
+    controller::{AgentController, ControlInput},
+    dynamics::{Dynamics2D, DynamicsModel, Integrator},
+    env::ObservationConfig,
+    localization::{Localizer, PoseEstimate, PoseSource},
+    math::{ConvexPolygon, OrientedBox2D, Pose2D},
+    replay::Trajectory,
+    safety::SafetySupervisor,
+    scene::{SceneTime, mission::Mission, occupancy_map::OccupancyMap, surface::SurfaceMap},
+    sensors::{DynSensor2D, SensingCost, Sensor2D, SensorClock},
+    trailer::{Trailer, TrailerConfig},
+};
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Agent2DConfig {
+    /// In kilograms.
     pub mass: f32,
+    /// Wheelbase in metres, also the length of the footprint.
     pub length: f32,
+    /// In metres.
     pub width: f32,
+    /// In metres.
     pub radius_tyre: f32,
+    /// Moment of inertia of each tyre in kg·m².
     pub inertia_tyre: f32,
+    /// Drive torque limits in N·m, as (min, max).
     pub torque_range: (f32, f32),
+    /// Steering angle limits in radians, as (min, max). Must stay within (-π/2, π/2).
     pub beta_range: (f32, f32),
+    /// How the agent moves in response to its inputs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dynamics: DynamicsModel,
+    /// How the dynamics are stepped.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub integrator: Integrator,
+    /// Lag and slew between the inputs commanded and those delivered. `None` delivers them at once.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub actuators: Option<Actuators>,
+    /// Outline used for collisions and for other agents' sensors, in metres in the agent's frame with x forward and
+    /// y to the left. `None` uses the `length` × `width` rectangle centred on the agent.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub shape: Option<ConvexPolygon>,
+    /// Drained by driving and idling, cutting the drive once empty. `None` runs forever.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub battery: Option<Battery>,
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Agent2DState {
+    /// Steering angle in radians.
     pub beta: f32,
+    /// Forward speed in m/s.
     pub velocity: f32,
+    /// Speed to the left in m/s, for agents that can move sideways.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lateral_velocity: f32,
+    /// In rad/s, tracked by models where it can differ from what the steering asks for.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub yaw_rate: f32,
+    /// In N·m.
     pub torque: f32,
+    /// In metres.
     pub position: glam::Vec2,
+    /// Unit vector the agent faces.
     pub heading: glam::Vec2,
+    /// Share of the [battery](Agent2DConfig::battery)'s charge left, in [0, 1]. Stays full without one.
+    #[cfg_attr(feature = "serde", serde(default = "full_charge"))]
+    pub state_of_charge: f32,
+}
+
+#[cfg(feature = "serde")]
+fn full_charge() -> f32 {
+    1.
+}
+
+/// What people call an agent and how it is drawn. The simulation itself never looks at it.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AgentLabel {
+    pub name: Option<String>,
+    /// As sRGB.
+    pub color: Option<[u8; 3]>,
+    /// Anything else worth attaching, like a team or a role.
+    pub metadata: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Agent2D {
     pub config: Agent2DConfig,
+    pub label: AgentLabel,
     pub state: Agent2DState,
     pub last_state: Option<Agent2DState>,
+    /// Towed in order, each hitched behind the one before and the first behind the agent.
+    pub trailers: Vec<Trailer>,
     pub sensors: Agent2DSensors,
+    pub controller: Option<Arc<Mutex<dyn AgentController>>>,
+    pub safety: Option<SafetySupervisor>,
+    /// Latest input commanded, which the [actuators](Agent2DConfig::actuators) are catching up to.
+    pub command: Option<ControlInput>,
+    pub localizer: Option<Arc<Mutex<dyn Localizer>>>,
+    /// Which pose the controller is given.
+    pub pose_source: PoseSource,
+    /// Latest output of `localizer`.
+    pub estimate: Option<PoseEstimate>,
+    /// Map given to `localizer` in place of the scene's, e.g. one made before the scene changed.
+    pub prior_map: Option<Arc<OccupancyMap>>,
+    /// Goals the scene checks the agent's progress towards, reporting it as
+    /// [MissionEvent](crate::scene::mission::MissionEvent)s.
+    pub mission: Option<Mission>,
+    /// What the agent's sensors have cost so far.
+    pub sensing_cost: SensingCost,
+    /// Observation space used when the agent is driven through an [Env2D](crate::env::Env2D).
+    pub observation: Option<ObservationConfig>,
+    /// Followed in place of the dynamics model, ignoring the controller and walls alike. Others still see and bump
+    /// into the agent.
+    pub replay: Option<Trajectory>,
 }
 
-#[derive(Debug)]
-pub struct Agent2DSensors {
-    pub lidar: Arc<RwLock<Lidar2D>>,
+#[derive(Debug, Clone)]
+pub struct SensorEntry {
+    /// Topic the sensor's measurements are published under.
+    pub name: String,
+    pub sensor: Arc<RwLock<dyn DynSensor2D>>,
+    /// Sensing rate in Hz. `None` senses on every scene update.
+    pub rate: Option<f32>,
+    /// Delay between sensing and delivery in seconds.
+    pub latency: f32,
+    /// Standard deviation of the error on measurement timestamps in seconds.
+    pub jitter: f32,
+    /// Clock the measurements are stamped with.
+    pub clock: SensorClock,
+    /// Charged per reading in place of the sensor's own [cost](crate::sensors::Sensor2D::cost).
+    pub cost: Option<f32>,
 }
 
-#[derive(Debug, Clone)]
-pub struct Agent2DMeasurements {
-    pub lidar: Option<TimeStamped<<Lidar2D as Sensor2D>::SensorType>>,
+/// The sensors mounted on an agent, keyed by name. Names are unique; inserting under an existing name replaces the
+/// sensor.
+#[derive(Debug, Clone, Default)]
+pub struct Agent2DSensors {
+    entries: Vec<SensorEntry>,
 }
 
-impl Clone for Agent2DSensors {
-    fn clone(&self) -> Self {
+impl Agent2DSensors {
+    pub fn insert<S: DynSensor2D + 'static>(&mut self, name: impl Into<String>, sensor: S) {
+        self.insert_dyn(name, Arc::new(RwLock::new(sensor)));
+    }
+
+    pub fn insert_dyn(&mut self, name: impl Into<String>, sensor: Arc<RwLock<dyn DynSensor2D>>) {
+        let name = name.into();
+
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(entry) => entry.sensor = sensor,
+            None => self.entries.push(SensorEntry {
+                name,
+                sensor,
+                rate: None,
+                latency: 0.,
+                jitter: 0.,
+                clock: SensorClock::default(),
+                cost: None,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<SensorEntry> {
+        let index = self.entries.iter().position(|e| e.name == name)?;
+
+        Some(self.entries.remove(index))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SensorEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// For changing how the sensor named `name` is scheduled, delayed, stamped or charged. Renaming it here can break
+    /// the uniqueness of names.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut SensorEntry> {
+        self.entries.iter_mut().find(|e| e.name == name)
+    }
+
+    /// Locks the sensor named `name` for reading if it is an `S`.
+    pub fn read_as<S: 'static>(&self, name: &str) -> Option<MappedRwLockReadGuard<'_, S>> {
+        let guard = self.get(name)?.sensor.read();
+
+        RwLockReadGuard::try_map(guard, |s| s.as_any().downcast_ref()).ok()
+    }
+
+    /// Locks the sensor named `name` for writing if it is an `S`.
+    pub fn write_as<S: 'static>(&self, name: &str) -> Option<MappedRwLockWriteGuard<'_, S>> {
+        let guard = self.get(name)?.sensor.write();
+
+        RwLockWriteGuard::try_map(guard, |s| s.as_any_mut().downcast_mut()).ok()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SensorEntry> {
+        self.entries.iter()
+    }
+
+    /// Copies the sensors themselves, where [Clone] shares them between the two sets.
+    pub fn detached(&self) -> Self {
         Self {
-            lidar: Arc::clone(&self.lidar),
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| SensorEntry {
+                    sensor: entry.sensor.read().clone_dyn(),
+                    ..entry.clone()
+                })
+                .collect(),
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 impl Default for Agent2DConfig {
@@ -59,11 +241,64 @@ impl Default for Agent2DConfig {
             inertia_tyre: 0.2,
             torque_range: (-100., 100.),
             beta_range: (-PI / 3., PI / 3.),
+            dynamics: DynamicsModel::default(),
+            integrator: Integrator::default(),
+            actuators: None,
+            shape: None,
+            battery: None,
         }
     }
 }
 
 impl Agent2DConfig {
+    /// Moment of inertia about the centre in kg·m², that of a uniform `length` by `width` box.
+    pub fn yaw_inertia(&self) -> f32 {
+        self.mass * (self.length * self.length + self.width * self.width) / 12.
+    }
+
+    /// The agent's footprint rectangle, or the box around its [shape](Self::shape) if it has one.
+    pub fn footprint(&self, state: &Agent2DState) -> OrientedBox2D {
+        match &self.shape {
+            Some(shape) => {
+                let bx = shape.get_box();
+                OrientedBox2D {
+                    center: state.pose().transform_point(bx.centroid()),
+                    half_extent: bx.size() / 2.,
+                    heading: state.heading,
+                }
+            }
+            None => OrientedBox2D {
+                center: state.position,
+                half_extent: glam::vec2(self.length, self.width) / 2.,
+                heading: state.heading,
+            },
+        }
+    }
+
+    /// The agent's [shape](Self::shape), or its footprint rectangle, in its own frame.
+    pub fn outline(&self) -> ConvexPolygon {
+        self.footprint_polygon(&Agent2DState {
+            position: glam::Vec2::ZERO,
+            heading: glam::Vec2::X,
+            ..Default::default()
+        })
+    }
+
+    /// The agent's [shape](Self::shape) at `state`, or its footprint rectangle.
+    pub fn footprint_polygon(&self, state: &Agent2DState) -> ConvexPolygon {
+        match &self.shape {
+            Some(shape) => shape.transformed(&state.pose()),
+            None => self.footprint(state).into(),
+        }
+    }
+
+    /// Curvature of the tightest turn the agent can make both ways, in 1/m.
+    pub fn max_curvature(&self) -> f32 {
+        let beta = self.beta_range.0.abs().min(self.beta_range.1.abs());
+
+        beta.tan() / self.length
+    }
+
     fn with_scale(scale: f32) -> Self {
         let Self {
             mass,
@@ -73,6 +308,11 @@ impl Agent2DConfig {
             inertia_tyre,
             torque_range,
             beta_range,
+            dynamics,
+            integrator,
+            actuators,
+            shape,
+            battery,
         } = Self::default();
 
         Self {
@@ -86,6 +326,66 @@ impl Agent2DConfig {
                 torque_range.1 * scale.powi(4),
             ),
             beta_range,
+            dynamics,
+            integrator,
+            actuators,
+            shape,
+            battery,
+        }
+    }
+}
+
+impl Validate for Agent2DConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("mass", self.mass)?;
+        config::positive("length", self.length)?;
+        config::positive("width", self.width)?;
+        config::positive("radius_tyre", self.radius_tyre)?;
+        config::non_negative("inertia_tyre", self.inertia_tyre)?;
+        config::finite("inertia_tyre", self.inertia_tyre)?;
+
+        config::finite("torque_range.min", self.torque_range.0)?;
+        config::finite("torque_range.max", self.torque_range.1)?;
+        config::ordered("torque_range", self.torque_range)?;
+
+        // The bicycle model divides by cos(beta).
+        for (field, beta) in [
+            ("beta_range.min", self.beta_range.0),
+            ("beta_range.max", self.beta_range.1),
+        ] {
+            config::within(field, beta, beta.abs() < PI / 2., "(-π/2, π/2)")?;
+        }
+        config::ordered("beta_range", self.beta_range)?;
+
+        self.dynamics
+            .validate()
+            .map_err(|e| e.in_field("dynamics"))?;
+
+        if let Some(actuators) = &self.actuators {
+            actuators.validate().map_err(|e| e.in_field("actuators"))?;
+        }
+
+        match &self.battery {
+            Some(battery) => battery.validate().map_err(|e| e.in_field("battery")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Agent2DState {
+    pub fn pose(&self) -> Pose2D {
+        Pose2D {
+            position: self.position,
+            heading: self.heading,
+        }
+    }
+
+    /// The same state at a different pose.
+    pub fn with_pose(&self, pose: Pose2D) -> Self {
+        Self {
+            position: pose.position,
+            heading: pose.heading,
+            ..*self
         }
     }
 }
@@ -95,9 +395,12 @@ impl Default for Agent2DState {
         Self {
             beta: 0.,
             velocity: 0.,
+            lateral_velocity: 0.,
+            yaw_rate: 0.,
             torque: 0.,
             position: glam::Vec2::ZERO,
             heading: glam::Vec2::Y,
+            state_of_charge: 1.,
         }
     }
 }
@@ -106,16 +409,122 @@ impl Default for Agent2D {
     fn default() -> Self {
         Agent2D {
             config: Default::default(),
+            label: AgentLabel::default(),
             state: Agent2DState::default(),
             last_state: None,
-            sensors: Agent2DSensors {
-                lidar: Arc::new(RwLock::new(Lidar2D::default())),
+            trailers: Vec::new(),
+            sensors: {
+                let mut sensors = Agent2DSensors::default();
+                sensors.insert(Lidar2D::TOPIC, Lidar2D::default());
+                sensors
             },
+            controller: None,
+            safety: None,
+            command: None,
+            localizer: None,
+            pose_source: PoseSource::default(),
+            estimate: None,
+            prior_map: None,
+            mission: None,
+            sensing_cost: SensingCost::default(),
+            observation: None,
+            replay: None,
+        }
+    }
+}
+
+impl Validate for Agent2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate().map_err(|e| e.in_field("config"))?;
+        self.sensors.validate().map_err(|e| e.in_field("sensors"))?;
+        self.trailers
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, trailer)| {
+                trailer
+                    .config
+                    .validate()
+                    .map_err(|e| e.in_field(&format!("trailers[{i}]")))
+            })?;
+
+        if let Some(safety) = &self.safety {
+            safety.validate().map_err(|e| e.in_field("safety"))?;
+        }
+        if let Some(mission) = &self.mission {
+            mission.validate().map_err(|e| e.in_field("mission"))?;
         }
+        if let Some(observation) = &self.observation {
+            observation
+                .validate()
+                .map_err(|e| e.in_field("observation"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Validate for Agent2DSensors {
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.entries.iter().try_for_each(|entry| {
+            entry
+                .sensor
+                .read()
+                .validate()
+                .map_err(|e| e.in_field(&entry.name))?;
+
+            config::finite(&format!("{}.latency", entry.name), entry.latency)?;
+            config::non_negative(&format!("{}.latency", entry.name), entry.latency)?;
+            config::finite(&format!("{}.jitter", entry.name), entry.jitter)?;
+            config::non_negative(&format!("{}.jitter", entry.name), entry.jitter)?;
+            config::finite(&format!("{}.clock.offset", entry.name), entry.clock.offset)?;
+            config::within(
+                &format!("{}.clock.drift", entry.name),
+                entry.clock.drift,
+                entry.clock.drift > -1. && entry.clock.drift.is_finite(),
+                "(-1, ∞)",
+            )?;
+
+            if let Some(cost) = entry.cost {
+                config::finite(&format!("{}.cost", entry.name), cost)?;
+                config::non_negative(&format!("{}.cost", entry.name), cost)?;
+            }
+
+            match entry.rate {
+                Some(rate) => config::positive(&format!("{}.rate", entry.name), rate),
+                None => Ok(()),
+            }
+        })
     }
 }
 
 impl Agent2D {
+    pub fn footprint(&self) -> OrientedBox2D {
+        self.config.footprint(&self.state)
+    }
+
+    pub fn footprint_polygon(&self) -> ConvexPolygon {
+        self.config.footprint_polygon(&self.state)
+    }
+
+    /// The agent's outline followed by its trailers' footprints.
+    pub fn footprints(&self) -> impl Iterator<Item = ConvexPolygon> + '_ {
+        let trailers = self
+            .trailers
+            .iter()
+            .map(|trailer| trailer.footprint().into());
+
+        std::iter::once(self.footprint_polygon()).chain(trailers)
+    }
+
+    /// Hitches a trailer in line behind the last body of the train.
+    pub fn hitch(&mut self, config: TrailerConfig) {
+        let tow = self
+            .trailers
+            .last()
+            .map_or(self.state.pose(), |trailer| trailer.pose);
+        self.trailers.push(Trailer::hitched_behind(&tow, config));
+    }
+
     pub fn with_scale(scale: f32) -> Self {
         Self {
             config: Agent2DConfig::with_scale(scale),
@@ -123,49 +532,119 @@ impl Agent2D {
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
+    /// An agent replaying `trajectory`.
+    pub fn replaying(trajectory: Trajectory) -> Self {
+        let mut agent = Self::default();
+        agent.start_replay(trajectory);
+
+        agent
+    }
+
+    /// Moves the agent to the start of `trajectory` and has it [replay](Self::replay) it from then on.
+    pub fn start_replay(&mut self, trajectory: Trajectory) {
+        self.state = trajectory.state_at(SceneTime(f32::NEG_INFINITY), &self.state);
+        self.last_state = None;
+        self.replay = Some(trajectory);
+    }
+
+    /// Commands `input`, clamped to the agent's limits. Without actuators it is delivered at once.
+    pub fn command(&mut self, input: ControlInput) {
         let Agent2DConfig {
-            mass,
-            length,
-            radius_tyre,
-            inertia_tyre,
+            torque_range,
+            beta_range,
             ..
         } = self.config;
-        let Agent2DState {
-            beta,
-            velocity,
-            torque,
-            heading,
-            ..
-        } = self.state;
-
-        let (dbetadt, dvdt) = if let Some(last) = self.last_state {
-            ((beta - last.beta) / dt, (velocity - last.velocity) / dt)
-        } else {
-            (0., 0.)
+        let input = ControlInput {
+            torque: input.torque.clamp(torque_range.0, torque_range.1),
+            beta: input.beta.clamp(beta_range.0, beta_range.1),
         };
 
-        let tan_beta = beta.tan();
-        let cos2_beta = 1. / (1. + tan_beta * tan_beta);
+        self.command = Some(input);
+        if self.config.actuators.is_none() {
+            self.state.torque = input.torque;
+            self.state.beta = input.beta;
+        }
+    }
+
+    /// Advances the agent by `dt`, held back by the surface it stands on when there is a `surfaces` layer.
+    pub fn update(&mut self, dt: f32, surfaces: Option<&SurfaceMap>) {
+        if let (Some(actuators), Some(command)) = (&self.config.actuators, self.command) {
+            self.state.torque = actuators
+                .torque
+                .respond(self.state.torque, command.torque, dt);
+            self.state.beta = actuators.beta.respond(self.state.beta, command.beta, dt);
+        }
+
+        let mut charge = self.state.state_of_charge;
+        if let Some(battery) = &self.config.battery {
+            if charge <= 0. {
+                self.state.torque = 0.;
+            }
+            charge = battery.drain(&self.config, &self.state, dt);
+        }
 
-        let angular_velocity = (velocity) * tan_beta / length;
-        let angular_acceleration =
-            tan_beta / (length) * dvdt + (velocity) / (length * cos2_beta) * dbetadt;
+        let mut next = integrate(&self.config, &self.state, self.last_state.as_ref(), dt);
+        next.state_of_charge = charge;
+        if let Some(surfaces) = surfaces {
+            surfaces
+                .at(self.state.position)
+                .constrain(&self.state, &mut next, dt);
+        }
 
-        let acc = (radius_tyre) * (torque) / (2. * inertia_tyre + mass * radius_tyre * radius_tyre);
+        self.last_state = Some(self.state);
+        self.state = next;
+        self.pull_trailers();
+    }
 
-        let forward = heading;
+    /// Moves the agent to where its [replay](Self::replay) is at `time`, if it has one.
+    pub fn replay_to(&mut self, time: SceneTime) {
+        let Some(replay) = &self.replay else {
+            return;
+        };
 
         self.last_state = Some(self.state);
+        self.state = replay.state_at(time, &self.state);
+        self.pull_trailers();
+    }
 
-        self.state.position += forward * velocity * dt;
-        self.state.velocity += acc * dt;
-        self.state.heading =
-            glam::Vec2::from_angle(angular_velocity * dt + angular_acceleration * dt * dt / 2.0)
-                .rotate(heading)
-                .normalize_or_zero();
+    fn pull_trailers(&mut self) {
+        let mut tow = self.state.pose();
+        for trailer in &mut self.trailers {
+            trailer.follow(&tow);
+            tow = trailer.pose;
+        }
+    }
+}
+
+/// Advances the agent's [dynamics model](Agent2DConfig::dynamics) by `dt`, without touching any agent.
+pub fn integrate(
+    config: &Agent2DConfig,
+    state: &Agent2DState,
+    last_state: Option<&Agent2DState>,
+    dt: f32,
+) -> Agent2DState {
+    config.dynamics.integrate(config, state, last_state, dt)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Agent2D, Scene2D};
+
+    #[test]
+    fn test_agent_labels() {
+        let mut scene = Scene2D::new([10, 10], &[255; 100], Default::default()).unwrap();
+        let mut ids = Vec::new();
+        for (name, team) in [("scout", "red"), ("hauler", "blue"), ("scout", "blue")] {
+            let mut agent = Agent2D::default();
+            agent.label.name = Some(name.into());
+            agent.label.metadata.insert("team".into(), team.into());
+            ids.push(scene.add_agent(agent));
+        }
 
-        self.state.torque *= (0.01f32).powf(dt);
-        self.state.beta *= (0.3f32).powf(dt);
+        assert_eq!(scene.agent_named("scout"), Some(ids[0]));
+        assert_eq!(scene.agent_named("hauler"), Some(ids[1]));
+        assert_eq!(scene.agent_named("charger"), None);
+        assert_eq!(scene.agents_tagged("team", "blue"), [ids[1], ids[2]]);
+        assert!(scene.agents_tagged("role", "blue").is_empty());
     }
 }
@@ -0,0 +1,140 @@
+//! Energy use of battery-powered agents, for task allocation and charging strategies that have to plan around it.
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+};
+
+/// A battery feeding the drive and everything else on the agent. Drive power is torque times wheel speed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Battery {
+    /// In joules.
+    pub capacity: f32,
+    /// Share of the power the drive draws that reaches the wheels, in (0, 1].
+    pub efficiency: f32,
+    /// Share of braking power returned to the battery, in [0, 1].
+    pub regeneration: f32,
+    /// Constant draw of everything besides the drive, in watts.
+    pub idle_power: f32,
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self {
+            capacity: 36_000.,
+            efficiency: 0.8,
+            regeneration: 0.,
+            idle_power: 2.,
+        }
+    }
+}
+
+impl Validate for Battery {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("capacity", self.capacity)?;
+        config::within(
+            "efficiency",
+            self.efficiency,
+            self.efficiency > 0. && self.efficiency <= 1.,
+            "(0, 1]",
+        )?;
+        config::within(
+            "regeneration",
+            self.regeneration,
+            (0. ..=1.).contains(&self.regeneration),
+            "[0, 1]",
+        )?;
+        config::finite("idle_power", self.idle_power)?;
+        config::non_negative("idle_power", self.idle_power)
+    }
+}
+
+impl Battery {
+    /// Power drawn at `state` in watts, negative while braking recovers more than the idle draw.
+    pub fn power(&self, config: &Agent2DConfig, state: &Agent2DState) -> f32 {
+        let wheels = state.torque * state.velocity / config.radius_tyre;
+        let drive = if wheels > 0. {
+            wheels / self.efficiency
+        } else {
+            wheels * self.regeneration
+        };
+
+        drive + self.idle_power
+    }
+
+    /// The state of charge after drawing at `state` for `dt`.
+    pub fn drain(&self, config: &Agent2DConfig, state: &Agent2DState, dt: f32) -> f32 {
+        let used = self.power(config, state) * dt / self.capacity;
+
+        (state.state_of_charge - used).clamp(0., 1.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D, battery::Battery, controller::ControlInput, scene::events::SceneEvent,
+    };
+
+    /// A 100 J battery that loses half of what passes through it, and idles at 1 W.
+    const BATTERY: Battery = Battery {
+        capacity: 100.,
+        efficiency: 0.5,
+        regeneration: 0.5,
+        idle_power: 1.,
+    };
+
+    #[test]
+    fn test_battery_power() {
+        let battery = BATTERY;
+        let mut agent = Agent2D::default();
+        agent.config.battery = Some(battery);
+
+        // 10 N·m at 2 m/s on 0.1 m wheels is 200 W at the wheels and 400 W from the battery.
+        agent.config.radius_tyre = 0.1;
+        agent.state.velocity = 2.;
+        agent.state.torque = 10.;
+        assert_eq!(battery.power(&agent.config, &agent.state), 401.);
+        agent.state.torque = -10.;
+        assert_eq!(battery.power(&agent.config, &agent.state), -99.);
+
+        // Braking puts charge back, but never past full.
+        assert_eq!(battery.drain(&agent.config, &agent.state, 0.1), 1.);
+        agent.state.state_of_charge = 0.5;
+        assert!((battery.drain(&agent.config, &agent.state, 0.1) - 0.599).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_battery_depletion() {
+        // Driving flat out empties it, after which the drive gives nothing.
+        let mut scene = Scene2D::new([10, 10], &[255; 100], Default::default()).unwrap();
+        let events = scene.subscribe_events();
+        let mut agent = Agent2D::default();
+        agent.config.battery = Some(Battery {
+            capacity: 50.,
+            ..BATTERY
+        });
+        let id = scene.add_agent(agent);
+        scene.agents.get_mut(&id).unwrap().command(ControlInput {
+            torque: 100.,
+            beta: 0.,
+        });
+
+        let depleted = (0..200).any(|_| {
+            scene.update(0.05);
+            events
+                .try_iter()
+                .any(|e| matches!(e, SceneEvent::BatteryDepleted { agent, .. } if agent == id))
+        });
+        assert!(depleted);
+
+        let agent = &scene.agents[&id];
+        assert_eq!(agent.state.state_of_charge, 0.);
+        let velocity = agent.state.velocity;
+        scene.update(0.05);
+        assert!(scene.agents[&id].state.velocity <= velocity);
+        assert!(events.try_iter().next().is_none());
+    }
+}
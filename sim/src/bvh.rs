@@ -1,9 +1,9 @@
-use crate::math::{Box2D, LineSegment};
+use crate::math::{Box2D, LineSegment, clip_line_segment_box, ray_box_entry};
 use dashmap::DashMap;
 use rayon::prelude::*;
 use rustc_hash::FxBuildHasher;
 use smallvec::{SmallVec, smallvec};
-use std::{hash::BuildHasher, sync::atomic::AtomicU64};
+use std::{collections::VecDeque, hash::BuildHasher, sync::atomic::AtomicU64};
 
 const MAX_PRIMS_IN_NODE: usize = 16;
 
@@ -14,6 +14,9 @@ pub struct BVHNodeId(u64);
 pub struct BVH {
     pub box_map: DashMap<BVHNodeId, BVHNode, FxBuildHasher>,
     pub root: BVHNodeId,
+    /// The same tree in one array, root first, so traversals index rather than hash their way down. Edits keep it and
+    /// `box_map` in step.
+    pub nodes: Vec<FlatBVHNode>,
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +26,15 @@ pub struct BVHNode {
     pub elements: Option<SmallVec<[usize; MAX_PRIMS_IN_NODE]>>,
 }
 
+#[derive(Debug, Clone)]
+pub struct FlatBVHNode {
+    pub id: BVHNodeId,
+    pub rect: Box2D,
+    /// Indices into [BVH::nodes]. Empty for leaves.
+    pub children: SmallVec<[u32; 2]>,
+    pub elements: SmallVec<[usize; MAX_PRIMS_IN_NODE]>,
+}
+
 fn embed_even_bits(x: u32) -> u64 {
     let mut x = x as u64;
     x = (x | (x << 16)) & 0x0000FFFF0000FFFF;
@@ -53,7 +65,7 @@ fn morton_encode(x: u32, y: u32) -> u64 {
 //     (extract_even_bits(n), extract_even_bits(n >> 1))
 // }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     North,
     East,
@@ -94,14 +106,18 @@ impl BVH {
             box_map.insert(node_id, node);
 
             return BVH {
+                nodes: Self::flatten(&box_map, node_id),
                 box_map,
                 root: node_id,
             };
         };
 
+        // Segments that all lie on one line have no extent across it, which would make the normalized boxes NaN.
+        let extent = (bounding.max - bounding.min).max(glam::Vec2::splat(f32::EPSILON));
+
         boxes.par_iter_mut().for_each(|(_, bx, morton)| {
-            bx.min = (bx.min - bounding.min) / (bounding.max - bounding.min);
-            bx.max = (bx.max - bounding.min) / (bounding.max - bounding.min);
+            bx.min = (bx.min - bounding.min) / extent;
+            bx.max = (bx.max - bounding.min) / extent;
 
             let centroid = bx.centroid() * (1 << 20) as f32;
             *morton = morton_encode(centroid.x as u32, centroid.y as u32);
@@ -311,10 +327,480 @@ impl BVH {
 
         box_map.iter_mut().for_each(|mut r| {
             let bx = &mut r.rect;
-            bx.min = bx.min * (bounding.max - bounding.min) + bounding.min;
-            bx.max = bx.max * (bounding.max - bounding.min) + bounding.min;
+            bx.min = bx.min * extent + bounding.min;
+            bx.max = bx.max * extent + bounding.min;
         });
 
-        Self { box_map, root: id }
+        Self {
+            nodes: Self::flatten(&box_map, id),
+            box_map,
+            root: id,
+        }
+    }
+
+    /// Lays the tree out in a flat array, parents before their children and each first child straight after its
+    /// parent.
+    fn flatten(
+        box_map: &DashMap<BVHNodeId, BVHNode, FxBuildHasher>,
+        root: BVHNodeId,
+    ) -> Vec<FlatBVHNode> {
+        let mut nodes = Vec::<FlatBVHNode>::with_capacity(box_map.len());
+        let mut stack = vec![(root, None)];
+        while let Some((id, parent)) = stack.pop() {
+            let Some(node) = box_map.get(&id) else {
+                continue;
+            };
+
+            let index = nodes.len() as u32;
+            if let Some(parent) = parent {
+                nodes[parent as usize].children.push(index);
+            }
+            nodes.push(FlatBVHNode {
+                id,
+                rect: node.rect,
+                children: SmallVec::new(),
+                elements: node.elements.clone().unwrap_or_default(),
+            });
+            if let Some(children) = &node.children {
+                stack.extend(children.iter().rev().map(|&child| (child, Some(index))));
+            }
+        }
+
+        nodes
+    }
+
+    /// Copies an edit to the flat node at `index` over to `box_map`.
+    fn sync(&self, index: usize) {
+        let flat = &self.nodes[index];
+        if let Some(mut node) = self.box_map.get_mut(&flat.id) {
+            node.rect = flat.rect;
+            if flat.children.is_empty() {
+                node.elements = Some(flat.elements.clone());
+            }
+        }
+    }
+
+    /// Adds `element`, whose box is `bx`, to the leaf whose box grows least to take it, growing the boxes on the way
+    /// down. Leaves may end up holding more than [MAX_PRIMS_IN_NODE] elements, so after large edits it pays to rebuild
+    /// with [BVH::new].
+    pub fn insert(&mut self, element: usize, bx: Box2D) {
+        let mut index = 0;
+        loop {
+            let node = &mut self.nodes[index];
+            let empty = node.children.is_empty() && node.elements.is_empty();
+            node.rect = if empty { bx } else { node.rect.encase(&bx) };
+            if node.children.is_empty() {
+                node.elements.push(element);
+                self.sync(index);
+                return;
+            }
+            self.sync(index);
+
+            let growth = |&child: &u32| {
+                let rect = self.nodes[child as usize].rect;
+                let area = |b: Box2D| b.size().x * b.size().y;
+                area(rect.encase(&bx)) - area(rect)
+            };
+            index = self.nodes[index]
+                .children
+                .iter()
+                .min_by(|a, b| growth(a).total_cmp(&growth(b)))
+                .map(|&child| child as usize)
+                .expect("Checked to be non-empty");
+        }
+    }
+
+    /// Takes `element`, whose box is `bx`, out of its leaf, returning whether it was found. Boxes are left as they are,
+    /// still covering everything below them.
+    pub fn remove(&mut self, element: usize, bx: Box2D) -> bool {
+        self.rename(element, None, bx)
+    }
+
+    /// Renumbers `from` to `to`, e.g. after the element list had one swapped into another's place.
+    pub fn relabel(&mut self, from: usize, to: usize, bx: Box2D) -> bool {
+        self.rename(from, Some(to), bx)
+    }
+
+    fn rename(&mut self, element: usize, to: Option<usize>, bx: Box2D) -> bool {
+        // Boxes were scaled in and out of the unit square on building, so may be off from the elements' by rounding.
+        let margin = bx.size().max_element().max(1.) * 1e-4;
+        let bx = Box2D {
+            min: bx.min - margin,
+            max: bx.max + margin,
+        };
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &mut self.nodes[index];
+            if !node.rect.intersects(&bx) {
+                continue;
+            }
+
+            if let Some(i) = node.elements.iter().position(|&e| e == element) {
+                match to {
+                    Some(to) => node.elements[i] = to,
+                    None => {
+                        node.elements.swap_remove(i);
+                    }
+                }
+                self.sync(index);
+                return true;
+            }
+            stack.extend(node.children.iter().map(|&child| child as usize));
+        }
+
+        false
+    }
+
+    /// Fits every node's box back around its elements, whose boxes are given by index in `boxes`, after they have
+    /// moved. The tree keeps its shape, so casts slow down as elements wander from where they were built, and a
+    /// rebuild with [BVH::new] is in order once they have gone far.
+    pub fn refit(&mut self, boxes: &[Box2D]) {
+        let mut fitted = vec![None::<Box2D>; self.nodes.len()];
+        // Children come after their parents, so going backwards fits each one before its parent needs it.
+        for index in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[index];
+            fitted[index] = node
+                .elements
+                .iter()
+                .map(|&i| boxes[i])
+                .chain(
+                    node.children
+                        .iter()
+                        .filter_map(|&child| fitted[child as usize]),
+                )
+                .reduce(|a, b| a.encase(&b));
+
+            // Empty nodes get the same empty box as on building.
+            self.nodes[index].rect = fitted[index].unwrap_or(Box2D {
+                min: glam::Vec2::ZERO,
+                max: glam::Vec2::ZERO,
+            });
+            self.sync(index);
+        }
+    }
+
+    /// Elements of the leaves whose boxes meet `bx`. Their own boxes may still miss it.
+    pub fn query(&self, bx: Box2D) -> Vec<usize> {
+        let mut queue = VecDeque::from([0]);
+        let mut found = Vec::new();
+        while let Some(index) = queue.pop_front() {
+            let node = &self.nodes[index as usize];
+            if !node.rect.intersects(&bx) {
+                continue;
+            }
+
+            queue.extend(node.children.iter().copied());
+            found.extend(node.elements.iter().copied());
+        }
+
+        found
+    }
+
+    /// Elements of the leaves whose boxes `segment` crosses. Their own boxes may still miss it.
+    pub fn query_segment(&self, segment: &LineSegment) -> Vec<usize> {
+        let mut stack = vec![0];
+        let mut found = Vec::new();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index as usize];
+            if clip_line_segment_box(segment, node.rect).is_none() {
+                continue;
+            }
+
+            stack.extend(node.children.iter().copied());
+            found.extend(node.elements.iter().copied());
+        }
+
+        found
+    }
+
+    /// The element nearest `point` by `distance`, which gives how far an element is or `None` to pass it over. Each
+    /// element must be at least as far as its box, so nodes further off than the nearest so far can be skipped. The
+    /// lowest-numbered element wins a tie.
+    pub fn nearest(
+        &self,
+        point: glam::Vec2,
+        mut distance: impl FnMut(usize) -> Option<f32>,
+    ) -> Option<(f32, usize)> {
+        let mut stack = vec![(self.nodes[0].rect.distance(point), 0)];
+        let mut nearest: Option<(f32, usize)> = None;
+        while let Some((bound, index)) = stack.pop() {
+            if nearest.is_some_and(|(min, _)| bound > min) {
+                continue;
+            }
+            let node = &self.nodes[index as usize];
+
+            // Farther child first onto the stack, so the nearer comes off first.
+            let mut children = node
+                .children
+                .iter()
+                .map(|&child| (self.nodes[child as usize].rect.distance(point), child))
+                .collect::<SmallVec<[_; 2]>>();
+            children.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            stack.extend(children);
+
+            for &i in &node.elements {
+                if let Some(d) = distance(i)
+                    && nearest.is_none_or(|(min, j)| d < min || (d == min && i < j))
+                {
+                    nearest = Some((d, i));
+                }
+            }
+        }
+
+        nearest
+    }
+
+    /// The element nearest along the ray by `hit`, which gives the distance to an element or `None` if the ray misses
+    /// it. The ray goes down the nearer child first and skips nodes it enters beyond the nearest hit so far, so only
+    /// elements around the first hit tend to be tried. The lowest-numbered element wins a tie.
+    pub fn cast_ray(
+        &self,
+        pos: glam::Vec2,
+        dir: glam::Vec2,
+        mut hit: impl FnMut(usize) -> Option<f32>,
+    ) -> Option<(f32, usize)> {
+        let mut stack = Vec::with_capacity(32);
+        stack.extend(ray_box_entry(pos, dir, self.nodes[0].rect).map(|entry| (entry, 0)));
+        let mut nearest: Option<(f32, usize)> = None;
+        while let Some((entry, index)) = stack.pop() {
+            if nearest.is_some_and(|(min, _)| entry > min) {
+                continue;
+            }
+            let node = &self.nodes[index as usize];
+
+            // Farther child first onto the stack, so the nearer comes off first.
+            let mut children = node
+                .children
+                .iter()
+                .filter_map(|&child| {
+                    Some((
+                        ray_box_entry(pos, dir, self.nodes[child as usize].rect)?,
+                        child,
+                    ))
+                })
+                .collect::<SmallVec<[_; 2]>>();
+            children.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            stack.extend(children);
+
+            for &i in &node.elements {
+                if let Some(t) = hit(i)
+                    && nearest.is_none_or(|(min, j)| t < min || (t == min && i < j))
+                {
+                    nearest = Some((t, i));
+                }
+            }
+        }
+
+        nearest
+    }
+
+    /// [cast_ray](Self::cast_ray) for every ray in `dirs` from the one origin, walking the tree once for them all.
+    /// Each node hands on only the rays that enter it before their nearest hit so far, nearer children first, so rays
+    /// share the work of reaching the nodes around them. `hit` is given the element and the index of the ray.
+    pub fn cast_ray_batch(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+        mut hit: impl FnMut(usize, usize) -> Option<f32>,
+    ) -> Vec<Option<(f32, usize)>> {
+        let mut nearest: Vec<Option<(f32, usize)>> = vec![None; dirs.len()];
+        let mut stack = vec![(0, (0..dirs.len()).collect::<Vec<_>>())];
+        while let Some((index, rays)) = stack.pop() {
+            let node = &self.nodes[index as usize];
+            let rays = rays
+                .into_iter()
+                .filter(|&ray| {
+                    ray_box_entry(pos, dirs[ray], node.rect)
+                        .is_some_and(|entry| nearest[ray].is_none_or(|(min, _)| entry <= min))
+                })
+                .collect::<Vec<_>>();
+            if rays.is_empty() {
+                continue;
+            }
+
+            // Farther child first onto the stack, so the nearer comes off first.
+            let mut children = node
+                .children
+                .iter()
+                .map(|&child| (self.nodes[child as usize].rect.distance(pos), child))
+                .collect::<SmallVec<[_; 2]>>();
+            children.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+            stack.extend(children.into_iter().map(|(_, child)| (child, rays.clone())));
+
+            for &i in &node.elements {
+                for &ray in &rays {
+                    if let Some(t) = hit(i, ray)
+                        && nearest[ray].is_none_or(|(min, j)| t < min || (t == min && i < j))
+                    {
+                        nearest[ray] = Some((t, i));
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        bvh::BVH,
+        math::{LineSegment, intersect_ray_line_segment},
+    };
+
+    fn random_segment(rng: &mut StdRng) -> LineSegment {
+        let a = glam::vec2(rng.random_range(-50. ..50.), rng.random_range(-50. ..50.));
+        let b = a + glam::vec2(rng.random_range(-2. ..2.), rng.random_range(-2. ..2.));
+        LineSegment(a, b)
+    }
+
+    /// 2000 short segments scattered over a 100 metre square, the rng that scattered them and a tree over them.
+    fn field() -> (StdRng, Vec<LineSegment>, BVH) {
+        let mut rng = StdRng::seed_from_u64(3);
+        let segments = (0..2000)
+            .map(|_| random_segment(&mut rng))
+            .collect::<Vec<_>>();
+        let bvh = BVH::new(segments.iter());
+        assert_eq!(bvh.nodes.len(), bvh.box_map.len());
+
+        (rng, segments, bvh)
+    }
+
+    /// Rays cast through the tree find the nearest hit a linear walk would.
+    fn check_rays(bvh: &BVH, segments: &[LineSegment]) {
+        for k in 0..200 {
+            let pos = glam::vec2(k as f32 * 0.5 - 50., 0.);
+            let dir = glam::Vec2::from_angle(k as f32);
+            let brute = segments
+                .iter()
+                .enumerate()
+                .filter_map(|(i, s)| Some((intersect_ray_line_segment(pos, dir, s)?, i)))
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            let found = bvh.cast_ray(pos, dir, |i| {
+                intersect_ray_line_segment(pos, dir, &segments[i])
+            });
+            assert_eq!(found, brute);
+        }
+    }
+
+    /// A whole fan of rays at once finds what they find one by one.
+    fn check_batch(bvh: &BVH, segments: &[LineSegment]) {
+        let pos = glam::vec2(3., -4.);
+        let dirs = (0..500)
+            .map(|k| glam::Vec2::from_angle(k as f32 * 0.0125))
+            .collect::<Vec<_>>();
+        let batch = bvh.cast_ray_batch(pos, &dirs, |i, ray| {
+            intersect_ray_line_segment(pos, dirs[ray], &segments[i])
+        });
+        for (&dir, found) in dirs.iter().zip(batch) {
+            let single = bvh.cast_ray(pos, dir, |i| {
+                intersect_ray_line_segment(pos, dir, &segments[i])
+            });
+            assert_eq!(found, single);
+        }
+    }
+
+    /// Nearest elements and those crossed by a segment, as a linear walk would find them.
+    fn check_queries(bvh: &BVH, segments: &[LineSegment]) {
+        let crosses = |a: &LineSegment, b: &LineSegment| {
+            intersect_ray_line_segment(a.0, a.1 - a.0, b).is_some_and(|t| t <= 1.)
+        };
+        for k in 0..50 {
+            let point = glam::vec2(k as f32 * 2. - 50., k as f32 - 25.);
+            let distance = |i: usize| segments[i].closest_point(point).distance(point);
+            let brute = (0..segments.len())
+                .map(|i| (distance(i), i))
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            assert_eq!(bvh.nearest(point, |i| Some(distance(i))), brute);
+
+            let across = LineSegment(point, -point.perp());
+            let mut crossed = bvh
+                .query_segment(&across)
+                .into_iter()
+                .filter(|&i| crosses(&across, &segments[i]))
+                .collect::<Vec<_>>();
+            crossed.sort();
+            let brute = (0..segments.len())
+                .filter(|&i| crosses(&across, &segments[i]))
+                .collect::<Vec<_>>();
+            assert_eq!(crossed, brute);
+        }
+    }
+
+    fn check(bvh: &BVH, segments: &[LineSegment]) {
+        check_rays(bvh, segments);
+        check_batch(bvh, segments);
+        check_queries(bvh, segments);
+    }
+
+    #[test]
+    fn test_bvh_rays() {
+        let (_, segments, bvh) = field();
+        check_rays(&bvh, &segments);
+    }
+
+    #[test]
+    fn test_bvh_ray_batch() {
+        let (_, segments, bvh) = field();
+        check_batch(&bvh, &segments);
+    }
+
+    #[test]
+    fn test_bvh_queries() {
+        let (_, segments, bvh) = field();
+        check_queries(&bvh, &segments);
+    }
+
+    #[test]
+    fn test_bvh_pruning() {
+        // Across the whole field, yet only segments near the first hit are tried.
+        let (_, segments, bvh) = field();
+        let mut tried = 0;
+        let pos = glam::vec2(-60., 0.5);
+        bvh.cast_ray(pos, glam::Vec2::X, |i| {
+            tried += 1;
+            intersect_ray_line_segment(pos, glam::Vec2::X, &segments[i])
+        })
+        .unwrap();
+        assert!(tried < 100, "{tried}");
+    }
+
+    #[test]
+    fn test_bvh_edits() {
+        // Edits reach the flat tree as well as the map it was built from.
+        let (mut rng, mut segments, mut bvh) = field();
+        for (i, moved) in segments.iter_mut().enumerate().take(100) {
+            assert!(bvh.remove(i, moved.get_box()));
+            *moved = random_segment(&mut rng);
+            bvh.insert(i, moved.get_box());
+        }
+        check(&bvh, &segments);
+        for flat in &bvh.nodes {
+            let node = &bvh.box_map.get(&flat.id).unwrap();
+            assert_eq!(node.rect, flat.rect);
+            assert_eq!(node.elements.clone().unwrap_or_default(), flat.elements);
+        }
+    }
+
+    #[test]
+    fn test_bvh_refit() {
+        // Everything drifts a little and the boxes follow it.
+        let (mut rng, mut segments, mut bvh) = field();
+        for moved in &mut segments {
+            let offset = glam::vec2(rng.random_range(-1. ..1.), rng.random_range(-1. ..1.));
+            *moved = LineSegment(moved.0 + offset, moved.1 + offset);
+        }
+        let boxes = segments
+            .iter()
+            .map(LineSegment::get_box)
+            .collect::<Vec<_>>();
+        bvh.refit(&boxes);
+        check(&bvh, &segments);
+        let all = boxes.iter().copied().reduce(|a, b| a.encase(&b)).unwrap();
+        assert_eq!(bvh.nodes[0].rect, all);
     }
 }
@@ -0,0 +1,362 @@
+//! Finding where a map has changed, for lifelong mapping: [OccupancyMap::diff] compares two maps cell by cell, and
+//! [ChangeDetector] compares a prior map against a [LogOddsGrid] being built live to flag objects that were moved.
+
+use std::collections::VecDeque;
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    mapping::{LogOddsGrid, probability},
+    math::Box2D,
+    scene::occupancy_map::OccupancyMap,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// Free before, occupied now.
+    Added,
+    /// Occupied before, free now.
+    Removed,
+}
+
+/// Connected cells that changed the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedRegion {
+    pub kind: ChangeKind,
+    /// As `(col, row)`, like the indices of [OccupancyMap::pixels].
+    pub cells: Vec<glam::USizeVec2>,
+    pub bounds: Box2D,
+    /// Mean of the cell centres.
+    pub center: glam::Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapDiff {
+    pub size: glam::USizeVec2,
+    /// Largest first.
+    pub regions: Vec<ChangedRegion>,
+}
+
+impl MapDiff {
+    pub fn added(&self) -> impl Iterator<Item = &ChangedRegion> {
+        self.regions.iter().filter(|r| r.kind == ChangeKind::Added)
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &ChangedRegion> {
+        self.regions
+            .iter()
+            .filter(|r| r.kind == ChangeKind::Removed)
+    }
+
+    pub fn changed_cells(&self) -> usize {
+        self.regions.iter().map(|r| r.cells.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Groups the changed cells, given one entry per cell in row-major order, into 8-connected regions.
+    fn from_changes(size: glam::USizeVec2, changes: &[Option<ChangeKind>]) -> Self {
+        let center = |cell: glam::USizeVec2| {
+            glam::vec2(
+                cell.x as f32 + 0.5 - size.x as f32 / 2.,
+                size.y as f32 / 2. - cell.y as f32 - 0.5,
+            )
+        };
+
+        let mut seen = vec![false; changes.len()];
+        let mut regions = Vec::new();
+        let mut queue = VecDeque::new();
+        for start in 0..changes.len() {
+            let Some(kind) = changes[start] else {
+                continue;
+            };
+            if seen[start] {
+                continue;
+            }
+
+            seen[start] = true;
+            queue.push_back(start);
+            let mut cells = Vec::new();
+            while let Some(i) = queue.pop_front() {
+                let cell = glam::usizevec2(i % size.x, i / size.x);
+                cells.push(cell);
+
+                let (x, y) = (cell.x as i64, cell.y as i64);
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if !(0..size.x as i64).contains(&nx) || !(0..size.y as i64).contains(&ny) {
+                        continue;
+                    }
+                    let n = nx as usize + ny as usize * size.x;
+                    if !seen[n] && changes[n] == Some(kind) {
+                        seen[n] = true;
+                        queue.push_back(n);
+                    }
+                }
+            }
+
+            let centers = cells.iter().map(|&c| center(c));
+            let half = glam::Vec2::splat(0.5);
+            let bounds = centers
+                .clone()
+                .map(|c| Box2D {
+                    min: c - half,
+                    max: c + half,
+                })
+                .reduce(|a, b| a.encase(&b))
+                .expect("Regions have at least one cell");
+            let center = centers.sum::<glam::Vec2>() / cells.len() as f32;
+
+            regions.push(ChangedRegion {
+                kind,
+                cells,
+                bounds,
+                center,
+            });
+        }
+        regions.sort_by_key(|r| std::cmp::Reverse(r.cells.len()));
+
+        Self { size, regions }
+    }
+}
+
+impl OccupancyMap {
+    /// The cells that are occupied in one map but not the other, as changes from `self` to `other`.
+    pub fn diff(&self, other: &OccupancyMap) -> Result<MapDiff, ChangeError> {
+        if self.size != other.size {
+            return Err(ChangeError::SizeMismatch(self.size, other.size));
+        }
+
+        let changes = self
+            .pixels
+            .iter()
+            .zip(&other.pixels)
+            .map(|(&before, &after)| match (before, after) {
+                (false, true) => Some(ChangeKind::Added),
+                (true, false) => Some(ChangeKind::Removed),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(MapDiff::from_changes(self.size, &changes))
+    }
+}
+
+/// An object that disappeared from one place and showed up nearby.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovedObject {
+    /// Index of the [ChangeKind::Removed] region in [ChangeReport::diff].
+    pub from: usize,
+    /// Index of the [ChangeKind::Added] region.
+    pub to: usize,
+    /// From the old centre to the new one, in metres. Only as good as the view of the object from where it was seen.
+    pub offset: glam::Vec2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeReport {
+    pub diff: MapDiff,
+    pub moved: Vec<MovedObject>,
+}
+
+/// Compares a prior map against one being built from live scans. Only cells the live map is sure of count, so the
+/// parts not yet seen again don't show up as changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeDetector {
+    /// Live cells at least this likely to be occupied are occupied.
+    pub occupied_threshold: f32,
+    /// Live cells at most this likely to be occupied are free.
+    pub free_threshold: f32,
+    /// Smaller regions are put down to noise and dropped.
+    pub min_cells: usize,
+    /// Removed and added regions whose centres are at most this many metres apart are taken to be one object that
+    /// moved.
+    pub max_move: f32,
+}
+
+impl Default for ChangeDetector {
+    fn default() -> Self {
+        Self {
+            occupied_threshold: 0.8,
+            free_threshold: 0.2,
+            min_cells: 2,
+            max_move: 3.,
+        }
+    }
+}
+
+impl Validate for ChangeDetector {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::within(
+            "free_threshold",
+            self.free_threshold,
+            (0. ..0.5).contains(&self.free_threshold),
+            "[0, 0.5)",
+        )?;
+        config::within(
+            "occupied_threshold",
+            self.occupied_threshold,
+            self.occupied_threshold > 0.5 && self.occupied_threshold <= 1.,
+            "(0.5, 1]",
+        )?;
+        config::at_least("min_cells", self.min_cells, 1)?;
+        config::finite("max_move", self.max_move)?;
+        config::non_negative("max_move", self.max_move)
+    }
+}
+
+impl ChangeDetector {
+    pub fn detect(
+        &self,
+        prior: &OccupancyMap,
+        live: &LogOddsGrid,
+    ) -> Result<ChangeReport, ChangeError> {
+        if prior.size != live.size {
+            return Err(ChangeError::SizeMismatch(prior.size, live.size));
+        }
+
+        let changes = prior
+            .pixels
+            .iter()
+            .zip(&live.cells)
+            .map(|(&before, &log_odds)| {
+                let p = probability(log_odds);
+                match before {
+                    false if p >= self.occupied_threshold => Some(ChangeKind::Added),
+                    true if p <= self.free_threshold => Some(ChangeKind::Removed),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut diff = MapDiff::from_changes(prior.size, &changes);
+        diff.regions.retain(|r| r.cells.len() >= self.min_cells);
+
+        // Largest removals pick first, each taking the nearest addition still free.
+        let mut taken = vec![false; diff.regions.len()];
+        let mut moved = Vec::new();
+        for (from, removed) in diff.regions.iter().enumerate() {
+            if removed.kind != ChangeKind::Removed {
+                continue;
+            }
+
+            let nearest = diff
+                .regions
+                .iter()
+                .enumerate()
+                .filter(|&(i, r)| r.kind == ChangeKind::Added && !taken[i])
+                .map(|(i, r)| (i, r.center.distance(removed.center)))
+                .filter(|&(_, d)| d <= self.max_move)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((to, _)) = nearest {
+                taken[to] = true;
+                moved.push(MovedObject {
+                    from,
+                    to,
+                    offset: diff.regions[to].center - removed.center,
+                });
+            }
+        }
+
+        Ok(ChangeReport { diff, moved })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChangeError {
+    #[error("Maps of different sizes can't be compared: {0} and {1}")]
+    SizeMismatch(glam::USizeVec2, glam::USizeVec2),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        change::{ChangeDetector, ChangeError, ChangeKind},
+        mapping::LogOddsGrid,
+        scene::occupancy_map::OccupancyMap,
+    };
+
+    /// A box on the left of the map.
+    fn prior() -> OccupancyMap {
+        OccupancyMap::from_ascii(&[
+            "............",
+            "............",
+            "...##.......",
+            "...##.......",
+            "............",
+            "............",
+        ])
+    }
+
+    /// The box moved three cells to the right.
+    fn now() -> OccupancyMap {
+        OccupancyMap::from_ascii(&[
+            "............",
+            "............",
+            "......##....",
+            "......##....",
+            "............",
+            "............",
+        ])
+    }
+
+    #[test]
+    fn test_map_diff() {
+        let diff = prior().diff(&now()).unwrap();
+        assert_eq!(diff.regions.len(), 2);
+        assert_eq!(diff.changed_cells(), 8);
+        let removed = diff.removed().next().unwrap();
+        assert_eq!(removed.center, glam::vec2(-2., 0.));
+        assert_eq!(removed.bounds.size(), glam::vec2(2., 2.));
+        assert_eq!(diff.added().next().unwrap().center, glam::vec2(1., 0.));
+    }
+
+    #[test]
+    fn test_map_diff_unchanged() {
+        let prior = prior();
+        assert!(prior.diff(&prior).unwrap().is_empty());
+        assert!(matches!(
+            prior.diff(&OccupancyMap::from_ascii(&["..."])),
+            Err(ChangeError::SizeMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn test_change_moved() {
+        // Live scans have seen the box's new place but only its front face, a stray cell of noise, and nothing of the
+        // left of the map since the box left.
+        let mut live = LogOddsGrid::from_occupancy_map(&now());
+        for row in 2..4 {
+            live.cells[row * 12 + 7] = 0.;
+        }
+        live.cells[5 * 12 + 11] = live.clamp;
+        for row in 0..6 {
+            live.cells[row * 12] = 0.;
+        }
+
+        let report = ChangeDetector::default().detect(&prior(), &live).unwrap();
+        assert_eq!(report.diff.regions.len(), 2, "{report:?}");
+        assert_eq!(report.moved.len(), 1);
+        let moved = report.moved[0];
+        assert_eq!(report.diff.regions[moved.from].kind, ChangeKind::Removed);
+        assert_eq!(report.diff.regions[moved.to].cells.len(), 2);
+        assert_eq!(moved.offset, glam::vec2(2.5, 0.));
+    }
+
+    #[test]
+    fn test_change_too_far() {
+        // Too far away to be the same box.
+        let live = LogOddsGrid::from_occupancy_map(&OccupancyMap::from_ascii(&[
+            "...........#",
+            "...........#",
+            "............",
+            "............",
+            "............",
+            "............",
+        ]));
+        let report = ChangeDetector::default().detect(&prior(), &live).unwrap();
+        assert_eq!(report.diff.regions.len(), 2);
+        assert!(report.moved.is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! Validation of user-facing configuration.
+//!
+//! Units throughout the simulator: lengths in metres, with one occupancy map cell being one metre unless the map sets its
+//! [resolution](crate::scene::OccupancyMap::resolution); angles in radians, counter-clockwise; time in seconds; mass in
+//! kilograms; torque in newton-metres; rates in hertz.
+
+/// A configuration value that would make the simulation misbehave, e.g. produce NaNs mid-run.
+///
+/// `field` is a path to the offending value, such as `sensors.lidar.directions`. Loaders prefix it with
+/// [ConfigError::in_field] so it points at the field in the scenario file.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    #[error("`{field}` must be a finite number, got {value}")]
+    NotFinite { field: String, value: f32 },
+
+    #[error("`{field}` must be greater than zero, got {value}")]
+    NotPositive { field: String, value: f32 },
+
+    #[error("`{field}` must not be negative, got {value}")]
+    Negative { field: String, value: f32 },
+
+    #[error("`{field}` must be ordered as (min, max), got ({min}, {max})")]
+    Unordered { field: String, min: f32, max: f32 },
+
+    #[error("`{field}` must be within {expected}, got {value}")]
+    OutOfRange {
+        field: String,
+        value: f32,
+        expected: &'static str,
+    },
+
+    #[error("`{field}` needs at least {min}, got {count}")]
+    TooFew {
+        field: String,
+        count: usize,
+        min: usize,
+    },
+}
+
+impl ConfigError {
+    pub fn field(&self) -> &str {
+        match self {
+            Self::NotFinite { field, .. }
+            | Self::NotPositive { field, .. }
+            | Self::Negative { field, .. }
+            | Self::Unordered { field, .. }
+            | Self::OutOfRange { field, .. }
+            | Self::TooFew { field, .. } => field,
+        }
+    }
+
+    /// Nests the error under `parent`, e.g. `mass` becomes `agents[0].mass`.
+    pub fn in_field(mut self, parent: &str) -> Self {
+        let (Self::NotFinite { field, .. }
+        | Self::NotPositive { field, .. }
+        | Self::Negative { field, .. }
+        | Self::Unordered { field, .. }
+        | Self::OutOfRange { field, .. }
+        | Self::TooFew { field, .. }) = &mut self;
+        *field = format!("{parent}.{field}");
+
+        self
+    }
+}
+
+pub trait Validate {
+    fn validate(&self) -> Result<(), ConfigError>;
+}
+
+pub fn finite(field: &str, value: f32) -> Result<(), ConfigError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(ConfigError::NotFinite {
+            field: field.to_string(),
+            value,
+        })
+    }
+}
+
+pub fn positive(field: &str, value: f32) -> Result<(), ConfigError> {
+    finite(field, value)?;
+
+    if value > 0. {
+        Ok(())
+    } else {
+        Err(ConfigError::NotPositive {
+            field: field.to_string(),
+            value,
+        })
+    }
+}
+
+/// Unlike [positive], allows infinity, e.g. for unlimited ranges.
+pub fn non_negative(field: &str, value: f32) -> Result<(), ConfigError> {
+    if value >= 0. {
+        Ok(())
+    } else if value.is_nan() {
+        Err(ConfigError::NotFinite {
+            field: field.to_string(),
+            value,
+        })
+    } else {
+        Err(ConfigError::Negative {
+            field: field.to_string(),
+            value,
+        })
+    }
+}
+
+pub fn ordered(field: &str, (min, max): (f32, f32)) -> Result<(), ConfigError> {
+    if min <= max {
+        Ok(())
+    } else {
+        Err(ConfigError::Unordered {
+            field: field.to_string(),
+            min,
+            max,
+        })
+    }
+}
+
+pub fn within(
+    field: &str,
+    value: f32,
+    valid: bool,
+    expected: &'static str,
+) -> Result<(), ConfigError> {
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange {
+            field: field.to_string(),
+            value,
+            expected,
+        })
+    }
+}
+
+pub fn at_least(field: &str, count: usize, min: usize) -> Result<(), ConfigError> {
+    if count >= min {
+        Ok(())
+    } else {
+        Err(ConfigError::TooFew {
+            field: field.to_string(),
+            count,
+            min,
+        })
+    }
+}
+
+pub fn direction(field: &str, value: glam::Vec2) -> Result<(), ConfigError> {
+    positive(field, value.length())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D,
+        config::{ConfigError, Validate},
+    };
+
+    #[test]
+    fn test_validate_agent() {
+        let mut agent = Agent2D::default();
+        assert_eq!(agent.validate(), Ok(()));
+
+        agent.config.torque_range = (10., -10.);
+        let err = agent.validate().unwrap_err().in_field("agents[3]");
+        assert_eq!(
+            err,
+            ConfigError::Unordered {
+                field: "agents[3].config.torque_range".to_string(),
+                min: 10.,
+                max: -10.,
+            }
+        );
+
+        let agent = Agent2D::with_scale(0.);
+        assert_eq!(agent.validate().unwrap_err().field(), "config.mass");
+    }
+}
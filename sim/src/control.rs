@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    controller::{AgentController, ControlContext, ControlInput},
+    planning::PlannedPath,
+};
+
+/// Path follower that steers towards the point `lookahead` metres further along the path than the agent, on the arc
+/// through it that the agent's heading is tangent to, while holding `speed` with a proportional throttle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurePursuit {
+    /// Waypoints to drive through, in order.
+    pub path: Vec<glam::Vec2>,
+    /// In metres. Longer cuts corners more but weaves less.
+    pub lookahead: f32,
+    /// Cruising speed in m/s. The agent slows down over the last few metres so it stops at the end.
+    pub speed: f32,
+    /// Torque in N·m per m/s of speed error.
+    pub speed_gain: f32,
+    /// The path is done once the agent is this close to its end, in metres.
+    pub goal_tolerance: f32,
+    /// Index of the segment the agent was last nearest to. Only moves forward, so paths crossing themselves are
+    /// followed in order.
+    segment: usize,
+    finished: bool,
+}
+
+impl Default for PurePursuit {
+    fn default() -> Self {
+        Self {
+            path: Vec::new(),
+            lookahead: 1.,
+            speed: 1.,
+            speed_gain: 50.,
+            goal_tolerance: 0.2,
+            segment: 0,
+            finished: false,
+        }
+    }
+}
+
+impl Validate for PurePursuit {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("lookahead", self.lookahead)?;
+        config::finite("speed", self.speed)?;
+        config::non_negative("speed", self.speed)?;
+        config::positive("speed_gain", self.speed_gain)?;
+        config::positive("goal_tolerance", self.goal_tolerance)
+    }
+}
+
+impl PurePursuit {
+    pub fn new(path: Vec<glam::Vec2>) -> Self {
+        Self {
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// Follows a path from [RrtStar](crate::planning::RrtStar).
+    pub fn follow(path: &PlannedPath) -> Self {
+        Self::new(path.poses.iter().map(|p| p.position).collect())
+    }
+
+    /// Starts over on a new path.
+    pub fn set_path(&mut self, path: Vec<glam::Vec2>) {
+        self.path = path;
+        self.segment = 0;
+        self.finished = false;
+    }
+
+    /// Whether the agent has reached the end of the path. It is then only braked.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The torque and steering angle that keep `state` on the path, within the limits in `config`.
+    pub fn command(&mut self, config: &Agent2DConfig, state: &Agent2DState) -> ControlInput {
+        let (target, remaining) = match self.path.as_slice() {
+            [] => (None, 0.),
+            &[end] => (Some(end), state.position.distance(end)),
+            _ => {
+                let (target, remaining) = self.track(state.position);
+                (Some(target), remaining)
+            }
+        };
+        if remaining <= self.goal_tolerance {
+            self.finished = true;
+        }
+
+        let (speed, beta) = match target {
+            Some(target) if !self.finished => {
+                // The arc from the agent through the target has curvature 2 sin(α) / d, and the bicycle model turns
+                // with curvature tan(β) / L.
+                let offset = target - state.position;
+                let distance = offset.length().max(f32::EPSILON);
+                let sin_alpha = state.heading.perp_dot(offset) / distance;
+                let curvature = 2. * sin_alpha / distance;
+
+                (
+                    self.speed.min(remaining),
+                    (curvature * config.length).atan(),
+                )
+            }
+            _ => (0., 0.),
+        };
+
+        let (torque_min, torque_max) = config.torque_range;
+        let (beta_min, beta_max) = config.beta_range;
+        ControlInput {
+            torque: (self.speed_gain * (speed - state.velocity)).clamp(torque_min, torque_max),
+            beta: beta.clamp(beta_min, beta_max),
+        }
+    }
+
+    /// Moves `segment` up to the one nearest `position` and returns the lookahead point and the distance left along
+    /// the path.
+    fn track(&mut self, position: glam::Vec2) -> (glam::Vec2, f32) {
+        let segments = self
+            .path
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .collect::<Vec<_>>();
+        let nearest = |(a, b): (glam::Vec2, glam::Vec2)| {
+            let along = b - a;
+            let t = ((position - a).dot(along) / along.length_squared().max(f32::EPSILON))
+                .clamp(0., 1.);
+            (t, position.distance(a + along * t))
+        };
+
+        // Only look a little way ahead, so a later stretch of path passing close by isn't skipped to.
+        let (mut best, mut best_distance) = (self.segment, nearest(segments[self.segment]).1);
+        let mut searched = 0.;
+        for (i, &segment) in segments.iter().enumerate().skip(self.segment + 1) {
+            if searched > best_distance + self.lookahead {
+                break;
+            }
+            let distance = nearest(segment).1;
+            if distance < best_distance {
+                (best, best_distance) = (i, distance);
+            }
+            searched += segment.0.distance(segment.1);
+        }
+        self.segment = best;
+
+        // Walk `lookahead` metres on from the nearest point.
+        let (a, b) = segments[best];
+        let t = nearest((a, b)).0;
+        let mut point = a.lerp(b, t);
+        let mut left = self.lookahead;
+        let mut target = None;
+        let mut remaining = 0.;
+        for &(_, end) in &segments[best..] {
+            let length = point.distance(end);
+            if target.is_none() {
+                if length >= left {
+                    target = Some(point + (end - point) / length * left);
+                } else {
+                    left -= length;
+                }
+            }
+            remaining += length;
+            point = end;
+        }
+
+        (target.unwrap_or(point), remaining)
+    }
+}
+
+impl AgentController for PurePursuit {
+    fn control(&mut self, ctx: &ControlContext) -> ControlInput {
+        let was_finished = self.finished;
+        let input = self.command(ctx.config, ctx.state);
+        if self.finished && !was_finished {
+            ctx.log.info("Reached the end of the path");
+        }
+
+        input
+    }
+
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn AgentController>>> {
+        Some(Arc::new(Mutex::new(self.clone())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{Agent2D, Scene2D, control::PurePursuit};
+
+    #[test]
+    fn test_pure_pursuit() {
+        let mut scene = Scene2D::from_pixels([20, 20], &[255; 400]).unwrap();
+        let path = vec![
+            glam::vec2(-6., -4.),
+            glam::vec2(2., -4.),
+            glam::vec2(2., 4.),
+            glam::vec2(-4., 4.),
+        ];
+        let controller = Arc::new(Mutex::new(PurePursuit::new(path.clone())));
+        let mut agent = Agent2D {
+            controller: Some(controller.clone()),
+            ..Default::default()
+        };
+        agent.state.position = glam::vec2(-6., -4.5);
+        let id = scene.add_agent(agent);
+
+        let distance_to_path = |p: glam::Vec2| {
+            path.windows(2)
+                .map(|w| {
+                    let along = w[1] - w[0];
+                    let t = ((p - w[0]).dot(along) / along.length_squared()).clamp(0., 1.);
+                    p.distance(w[0] + along * t)
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        let mut worst: f32 = 0.;
+        for _ in 0..600 {
+            scene.update(0.05);
+            let state = scene.agents[&id].state;
+            worst = worst.max(distance_to_path(state.position));
+            if controller.lock().is_finished() && state.velocity.abs() < 0.05 {
+                break;
+            }
+        }
+
+        let state = scene.agents[&id].state;
+        assert!(controller.lock().is_finished());
+        assert!(
+            state.position.distance(glam::vec2(-4., 4.)) < 0.5,
+            "{state:?}"
+        );
+        assert!(state.velocity.abs() < 0.05, "{state:?}");
+        // Corners are cut by less than the lookahead.
+        assert!(worst < 0.75, "{worst}");
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    localization::PoseEstimate,
+    logging::AgentLogger,
+    scene::{AgentId, SceneTime, scene_loop::Scene2DLoop},
+    sensors::TimeStamped,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlInput {
+    pub torque: f32,
+    pub beta: f32,
+}
+
+/// Everything a controller may look at when choosing its next [ControlInput].
+pub struct ControlContext<'a> {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    pub dt: f32,
+    pub config: &'a Agent2DConfig,
+    /// The agent's state, with the pose taken from `estimate` when the agent is set to [PoseSource::Estimated].
+    ///
+    /// [PoseSource::Estimated]: crate::localization::PoseSource::Estimated
+    pub state: &'a Agent2DState,
+    pub estimate: Option<&'a PoseEstimate>,
+    pub scene_loop: &'a Scene2DLoop,
+    /// Records what the controller was thinking, for replay alongside the scene.
+    pub log: &'a AgentLogger,
+}
+
+impl ControlContext<'_> {
+    pub fn measurement<T: Send + Sync + 'static>(
+        &self,
+        topic: &str,
+    ) -> Option<TimeStamped<Arc<T>>> {
+        self.scene_loop.query_topic_as(self.agent, topic)
+    }
+}
+
+/// Closed-loop driver of an [Agent2D](crate::Agent2D). [Scene2D::update](crate::Scene2D::update) asks it for inputs
+/// before integrating the agent's dynamics, then clamps them to the agent's limits.
+pub trait AgentController: std::fmt::Debug + Send + Sync {
+    fn control(&mut self, ctx: &ControlContext) -> ControlInput;
+
+    /// Reloads the controller implementation if its source changed, returning whether it did.
+    fn hot_reload(&mut self) -> bool {
+        false
+    }
+
+    /// A copy sharing no state with this controller, for [Scene2DSnapshot]s to restore. `None` if it can't be
+    /// copied, in which case snapshots share it with the scene.
+    ///
+    /// [Scene2DSnapshot]: crate::scene::Scene2DSnapshot
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn AgentController>>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{f32::consts::PI, sync::Arc};
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        controller::{AgentController, ControlContext, ControlInput},
+        scene::SceneTime,
+        sensors::BumperSensed,
+        testing,
+    };
+
+    /// Floors it while noting what it was shown.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        seen: Vec<(SceneTime, glam::Vec2, Option<SceneTime>)>,
+    }
+
+    impl AgentController for Recorder {
+        fn control(&mut self, ctx: &ControlContext) -> ControlInput {
+            let measured = ctx.measurement::<BumperSensed>("bumper").map(|m| m.time);
+            self.seen.push((ctx.time, ctx.state.position, measured));
+
+            ControlInput {
+                torque: 1e6,
+                beta: 2.,
+            }
+        }
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut scene = testing::open_room();
+        let recorder = Arc::new(Mutex::new(Recorder::default()));
+        let mut agent = testing::bumper_agent();
+        agent.controller = Some(recorder.clone());
+        let id = scene.add_agent(agent);
+
+        let start = scene.agents[&id].state.position;
+        scene.update(0.125);
+        // The dynamics act on the input in the update it was asked for.
+        assert!(scene.agents[&id].state.velocity > 0.);
+        for _ in 0..2 {
+            scene.update(0.125);
+        }
+
+        // Called once per update, before the dynamics, with the reading the previous update sensed after its own.
+        let seen = &recorder.lock().seen;
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[0], (SceneTime(0.125), start, None));
+        assert_ne!(seen[2].1, start);
+        assert_eq!(seen[1].2, Some(SceneTime(0.125)));
+
+        let agent = &scene.agents[&id];
+        assert_eq!(
+            agent.command,
+            Some(ControlInput {
+                torque: agent.config.torque_range.1,
+                beta: PI / 3.,
+            })
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Coverage path planning by boustrophedon decomposition: free space is split into cells that can each be covered by
+//! back-and-forth sweeps, and the sweeps are joined into one lawnmower path, as a cleaning or inspection robot drives.
+
+use std::{collections::VecDeque, ops::Range};
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::Box2D,
+    planning::PlanError,
+    scene::occupancy_map::OccupancyMap,
+};
+
+/// Free space a sweep line crosses as a single interval. Sweeps run down the map's columns, so cells begin and end
+/// where an obstacle splits or joins the free space of neighbouring columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageCell {
+    /// Map columns the cell spans.
+    pub columns: Range<usize>,
+    /// Free rows of each column, as inclusive `(top, bottom)` pairs.
+    pub rows: Vec<(usize, usize)>,
+    pub bounds: Box2D,
+}
+
+/// Lawnmower path through every cell the start can reach.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoveragePlan {
+    pub cells: Vec<CoverageCell>,
+    /// Indices into `cells`, in the order they are swept. Cells that can't be reached are left out.
+    pub order: Vec<usize>,
+    /// From the start, turning only where needed.
+    pub path: Vec<glam::Vec2>,
+    /// In metres.
+    pub length: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoveragePlanner {
+    /// Distance between neighbouring sweeps in metres, rounded to whole cells. About the width the robot covers.
+    pub spacing: f32,
+    /// Distance to keep from walls in metres. Free space closer than this is neither swept nor driven through.
+    pub clearance: f32,
+}
+
+impl Default for CoveragePlanner {
+    fn default() -> Self {
+        Self {
+            spacing: 1.,
+            clearance: 0.,
+        }
+    }
+}
+
+impl Validate for CoveragePlanner {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("spacing", self.spacing)?;
+        // Anything less rounds to no cells between sweeps.
+        config::within("spacing", self.spacing, self.spacing >= 0.5, "[0.5, ∞)")?;
+        config::finite("clearance", self.clearance)?;
+        config::non_negative("clearance", self.clearance)
+    }
+}
+
+impl CoveragePlanner {
+    /// Splits the free space of `map` into [CoverageCell]s, left to right.
+    pub fn decompose(&self, map: &OccupancyMap) -> Vec<CoverageCell> {
+        let free = self.free_cells(map);
+        let (w, h) = (map.size.x, map.size.y);
+
+        let mut cells: Vec<CoverageCell> = Vec::new();
+        // Intervals of the previous column, with the cell each belongs to.
+        let mut open: Vec<((usize, usize), usize)> = Vec::new();
+        for col in 0..w {
+            let mut intervals = Vec::new();
+            let mut row = 0;
+            while row < h {
+                if !free[col + row * w] {
+                    row += 1;
+                    continue;
+                }
+                let top = row;
+                while row < h && free[col + row * w] {
+                    row += 1;
+                }
+                intervals.push((top, row - 1));
+            }
+
+            let overlaps = |a: (usize, usize), b: (usize, usize)| a.0 <= b.1 && b.0 <= a.1;
+            let mut next = Vec::new();
+            for &interval in &intervals {
+                let touching = open
+                    .iter()
+                    .filter(|(prev, _)| overlaps(*prev, interval))
+                    .collect::<Vec<_>>();
+                // A cell carries on only while nothing splits from or joins it.
+                let cell = match touching.as_slice() {
+                    &[&(prev, cell)]
+                        if intervals.iter().filter(|&&i| overlaps(prev, i)).count() == 1 =>
+                    {
+                        cells[cell].rows.push(interval);
+                        cells[cell].columns.end = col + 1;
+                        cell
+                    }
+                    _ => {
+                        cells.push(CoverageCell {
+                            columns: col..col + 1,
+                            rows: vec![interval],
+                            bounds: Box2D {
+                                min: glam::Vec2::ZERO,
+                                max: glam::Vec2::ZERO,
+                            },
+                        });
+                        cells.len() - 1
+                    }
+                };
+                next.push((interval, cell));
+            }
+            open = next;
+        }
+
+        for cell in &mut cells {
+            cell.bounds = cell
+                .columns
+                .clone()
+                .zip(&cell.rows)
+                .flat_map(|(col, &(top, bottom))| {
+                    [
+                        map.get_box(glam::usizevec2(col, top)),
+                        map.get_box(glam::usizevec2(col, bottom)),
+                    ]
+                })
+                .reduce(|a, b| a.encase(&b))
+                .expect("Cells have at least one column");
+        }
+
+        cells
+    }
+
+    /// Sweeps every cell reachable from `start`, always driving to the nearest end of a cell not yet swept.
+    pub fn plan(&self, map: &OccupancyMap, start: glam::Vec2) -> Result<CoveragePlan, PlanError> {
+        self.validate()?;
+
+        let free = self.free_cells(map);
+        let w = map.size.x;
+        let start_cell = map.translate(start);
+        if !map.is_valid_vec2(start) || !free[start_cell.x as usize + start_cell.y as usize * w] {
+            return Err(PlanError::StartBlocked(start));
+        }
+
+        let cells = self.decompose(map);
+        let stride = (self.spacing.round() as usize).max(1);
+        let center = |i: usize| map.get_box(glam::usizevec2(i % w, i / w)).centroid();
+
+        let mut swept = vec![false; cells.len()];
+        let mut order = Vec::new();
+        let mut cells_path = vec![start_cell.x as usize + start_cell.y as usize * w];
+        loop {
+            let here = *cells_path.last().expect("The path has at least the start");
+            let (distances, parents) = self.flood(map, &free, here);
+
+            // Each cell can be entered at any of its four corners, sweeping away from it.
+            let Some((cell, from_left, from_top, _)) = (0..cells.len())
+                .filter(|&c| !swept[c])
+                .flat_map(|c| {
+                    let cell = &cells[c];
+                    let (first, last) = (cell.rows[0], cell.rows[cell.rows.len() - 1]);
+                    let (left, right) = (cell.columns.start, cell.columns.end - 1);
+                    [
+                        (true, true, left + first.0 * w),
+                        (true, false, left + first.1 * w),
+                        (false, true, right + last.0 * w),
+                        (false, false, right + last.1 * w),
+                    ]
+                    .map(|(from_left, from_top, i)| (c, from_left, from_top, distances[i]))
+                })
+                .filter(|e| e.3 != usize::MAX)
+                .min_by_key(|e| e.3)
+            else {
+                break;
+            };
+
+            swept[cell] = true;
+            order.push(cell);
+            let cell = &cells[cell];
+            let mut columns = cell.columns.clone().step_by(stride).collect::<Vec<_>>();
+            if columns.last() != Some(&(cell.columns.end - 1)) {
+                columns.push(cell.columns.end - 1);
+            }
+            if !from_left {
+                columns.reverse();
+            }
+
+            let mut top_first = from_top;
+            let mut parents = Some(parents);
+            for col in columns {
+                let (top, bottom) = cell.rows[col - cell.columns.start];
+                let (a, b) = if top_first {
+                    (col + top * w, col + bottom * w)
+                } else {
+                    (col + bottom * w, col + top * w)
+                };
+                // The flood from before the first sweep leads into the cell; later sweeps are reached from the last.
+                let parents = match parents.take() {
+                    Some(parents) => parents,
+                    None => self.flood(map, &free, *cells_path.last().unwrap()).1,
+                };
+                let here = *cells_path.last().unwrap();
+                let mut route = vec![a];
+                while let Some(&last) = route.last()
+                    && last != here
+                {
+                    route.push(parents[last]);
+                }
+                cells_path.extend(route.into_iter().rev().skip(1));
+
+                let step = if top_first { w as isize } else { -(w as isize) };
+                let mut i = a;
+                while i != b {
+                    i = (i as isize + step) as usize;
+                    cells_path.push(i);
+                }
+                top_first = !top_first;
+            }
+        }
+
+        let mut path = vec![start];
+        path.extend(cells_path.into_iter().skip(1).map(center));
+        // Drop the points along straight runs.
+        let mut i = 1;
+        while i + 1 < path.len() {
+            let (a, b, c) = (path[i - 1], path[i], path[i + 1]);
+            if (b - a).perp_dot(c - b).abs() < 1e-4 && (b - a).dot(c - b) > 0. {
+                path.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        let length = path.windows(2).map(|w| w[0].distance(w[1])).sum();
+
+        Ok(CoveragePlan {
+            cells,
+            order,
+            path,
+            length,
+        })
+    }
+
+    /// Whether each map cell can be swept, row-major like [OccupancyMap::pixels].
+    fn free_cells(&self, map: &OccupancyMap) -> Vec<bool> {
+        let esdf = (self.clearance > 0.).then(|| map.to_esdf(self.clearance + 1.));
+
+        (0..map.pixels.len())
+            .map(|i| {
+                let cell = glam::usizevec2(i % map.size.x, i / map.size.x);
+                !map.pixels[i]
+                    && esdf
+                        .as_ref()
+                        .is_none_or(|e| e.distance(map.get_box(cell).centroid()) >= self.clearance)
+            })
+            .collect()
+    }
+
+    /// Breadth-first search over free cells, moving between edge neighbours, giving the number of moves to each cell
+    /// from `from` and the cell each is reached from.
+    fn flood(&self, map: &OccupancyMap, free: &[bool], from: usize) -> (Vec<usize>, Vec<usize>) {
+        let (w, h) = (map.size.x, map.size.y);
+        let mut distances = vec![usize::MAX; free.len()];
+        let mut parents = vec![usize::MAX; free.len()];
+        let mut queue = VecDeque::from([from]);
+        distances[from] = 0;
+        while let Some(i) = queue.pop_front() {
+            let (col, row) = (i % w, i / w);
+            let neighbours = [
+                (col > 0).then(|| i - 1),
+                (col + 1 < w).then(|| i + 1),
+                (row > 0).then(|| i - w),
+                (row + 1 < h).then(|| i + w),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if free[n] && distances[n] == usize::MAX {
+                    distances[n] = distances[i] + 1;
+                    parents[n] = i;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        (distances, parents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        coverage::CoveragePlanner, planning::PlanError, scene::occupancy_map::OccupancyMap,
+    };
+
+    #[test]
+    fn test_coverage_planning() {
+        // A pillar splits the room into a cell on each side of it and one above and below it, with a closed-off
+        // cupboard in the corner.
+        let map = OccupancyMap::from_ascii(&[
+            "############",
+            "#.......#..#",
+            "#..##...####",
+            "#..##......#",
+            "#..........#",
+            "############",
+        ]);
+        let planner = CoveragePlanner::default();
+        let start = glam::vec2(-4.5, -1.5);
+        let plan = planner.plan(&map, start).unwrap();
+
+        assert_eq!(plan.cells.len(), 5, "{:?}", plan.cells);
+        assert_eq!(plan.order.len(), 4);
+        assert_eq!(plan.path[0], start);
+        assert!(plan.path.windows(2).all(|w| map.segment_clear(w[0], w[1])));
+
+        // Every free cell the start can reach is driven through.
+        let on_path = |p: glam::Vec2| {
+            plan.path.windows(2).any(|w| {
+                let along = w[1] - w[0];
+                let t = ((p - w[0]).dot(along) / along.length_squared()).clamp(0., 1.);
+                p.distance(w[0] + along * t) < 1e-3
+            })
+        };
+        for row in 1..5 {
+            for col in 1..11 {
+                let p = map.get_box(glam::usizevec2(col, row)).centroid();
+                let cupboard = row == 1 && col > 8;
+                if !map.is_occupied_vec2(p) {
+                    assert_eq!(on_path(p), !cupboard, "{p}");
+                }
+            }
+        }
+        let free = map.pixels.iter().filter(|&&p| !p).count() as f32;
+        assert!(plan.length < free * 1.5, "{}", plan.length);
+
+        // Wider sweeps cover the same space in fewer turns.
+        let wide = CoveragePlanner {
+            spacing: 2.,
+            ..Default::default()
+        }
+        .plan(&map, start)
+        .unwrap();
+        assert!(wide.length < plan.length);
+
+        assert!(matches!(
+            planner.plan(&map, glam::vec2(0., 2.5)),
+            Err(PlanError::StartBlocked(_))
+        ));
+    }
+}
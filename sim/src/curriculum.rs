@@ -0,0 +1,345 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::{
+    Scene2D,
+    controller::ControlInput,
+    env::{Env2D, EnvError, Step},
+    scene::AgentId,
+};
+
+pub type CustomCriterion = Arc<dyn Fn(&Scene2D, AgentId) -> Outcome + Send + Sync>;
+
+/// What an episode must achieve to count as a success. Colliding with the map always fails the episode.
+#[derive(Clone)]
+pub enum SuccessCriterion {
+    /// Keep going for `duration` seconds.
+    Survive {
+        duration: f32,
+    },
+    /// Pass within `radius` of each point in order. Repeating the first point at the end makes a lap.
+    Checkpoints {
+        points: Vec<glam::Vec2>,
+        radius: f32,
+    },
+    Custom(CustomCriterion),
+}
+
+impl std::fmt::Debug for SuccessCriterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Survive { duration } => f
+                .debug_struct("Survive")
+                .field("duration", duration)
+                .finish(),
+            Self::Checkpoints { points, radius } => f
+                .debug_struct("Checkpoints")
+                .field("points", points)
+                .field("radius", radius)
+                .finish(),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Running,
+    Success,
+    Failure,
+}
+
+pub type EnvFactory = Box<dyn Fn() -> Result<Env2D, EnvError> + Send + Sync>;
+
+pub struct Stage {
+    pub name: String,
+    pub env: EnvFactory,
+    pub criterion: SuccessCriterion,
+    /// Episodes still running after this many seconds fail.
+    pub time_limit: f32,
+}
+
+impl std::fmt::Debug for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stage")
+            .field("name", &self.name)
+            .field("criterion", &self.criterion)
+            .field("time_limit", &self.time_limit)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeReport {
+    pub stage: usize,
+    pub outcome: Outcome,
+    /// Scene time the episode lasted, in seconds.
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurriculumStep {
+    pub step: Step,
+    pub outcome: Outcome,
+}
+
+type EpisodeCallback = Box<dyn FnMut(&EpisodeReport) + Send>;
+type AdvanceCallback = Box<dyn FnMut(usize, &Stage) + Send>;
+
+/// Runs episodes through an ordered list of stages, moving on once the agent succeeds often enough.
+///
+/// Call [Curriculum::reset] to start each episode and [Curriculum::step] until the outcome is no longer
+/// [Outcome::Running].
+pub struct Curriculum {
+    stages: Vec<Stage>,
+    /// Number of most recent episodes considered for promotion.
+    pub window: usize,
+    /// Fraction of the last `window` episodes that must succeed to move to the next stage.
+    pub promote_rate: f32,
+    current: usize,
+    complete: bool,
+    recent: VecDeque<bool>,
+    env: Option<Env2D>,
+    episode_start: f32,
+    next_checkpoint: usize,
+    on_episode_end: Vec<EpisodeCallback>,
+    on_advance: Vec<AdvanceCallback>,
+}
+
+impl std::fmt::Debug for Curriculum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Curriculum")
+            .field("stages", &self.stages)
+            .field("window", &self.window)
+            .field("promote_rate", &self.promote_rate)
+            .field("current", &self.current)
+            .field("complete", &self.complete)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Curriculum {
+    pub fn new(stages: Vec<Stage>) -> Self {
+        Self {
+            stages,
+            window: 10,
+            promote_rate: 0.8,
+            current: 0,
+            complete: false,
+            recent: VecDeque::new(),
+            env: None,
+            episode_start: 0.,
+            next_checkpoint: 0,
+            on_episode_end: Vec::new(),
+            on_advance: Vec::new(),
+        }
+    }
+
+    pub fn stage_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn stage(&self) -> Option<&Stage> {
+        self.stages.get(self.current)
+    }
+
+    /// Whether the last stage has been passed. Episodes keep running on the last stage afterwards.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The environment of the current stage, once [Curriculum::reset] has been called.
+    pub fn env(&self) -> Option<&Env2D> {
+        self.env.as_ref()
+    }
+
+    pub fn on_episode_end(&mut self, callback: impl FnMut(&EpisodeReport) + Send + 'static) {
+        self.on_episode_end.push(Box::new(callback));
+    }
+
+    /// Called with the index and stage that was just entered.
+    pub fn on_advance(&mut self, callback: impl FnMut(usize, &Stage) + Send + 'static) {
+        self.on_advance.push(Box::new(callback));
+    }
+
+    /// Starts an episode on the current stage, building its environment first if needed.
+    pub fn reset(&mut self) -> Result<Vec<f32>, EnvError> {
+        let env = match &mut self.env {
+            Some(env) => env,
+            None => {
+                let stage = self.stages.get(self.current).ok_or(EnvError::NoStages)?;
+                self.env.insert((stage.env)()?)
+            }
+        };
+
+        let observation = env.reset();
+        self.episode_start = env.scene.time.0;
+        self.next_checkpoint = 0;
+
+        Ok(observation)
+    }
+
+    /// # Panics
+    /// If called before [Curriculum::reset].
+    pub fn step(&mut self, action: ControlInput) -> CurriculumStep {
+        let env = self
+            .env
+            .as_mut()
+            .expect("Curriculum::reset must be called before stepping");
+        let step = env.step(action);
+
+        let outcome = if step.done {
+            Outcome::Failure
+        } else {
+            self.evaluate()
+        };
+
+        if outcome != Outcome::Running {
+            self.finish_episode(outcome);
+        }
+
+        CurriculumStep { step, outcome }
+    }
+
+    fn evaluate(&mut self) -> Outcome {
+        let (Some(env), Some(stage)) = (&self.env, self.stages.get(self.current)) else {
+            return Outcome::Failure;
+        };
+        let Some(agent) = env.scene.agents.get(&env.agent) else {
+            return Outcome::Failure;
+        };
+        if env.scene.occupancy_map.overlaps(&agent.footprint()) {
+            return Outcome::Failure;
+        }
+
+        let elapsed = env.scene.time.0 - self.episode_start;
+        let outcome = match &stage.criterion {
+            SuccessCriterion::Survive { duration } if elapsed >= *duration => Outcome::Success,
+            SuccessCriterion::Survive { .. } => Outcome::Running,
+            SuccessCriterion::Checkpoints { points, radius } => {
+                if let Some(&point) = points.get(self.next_checkpoint)
+                    && agent.state.position.distance(point) <= *radius
+                {
+                    self.next_checkpoint += 1;
+                }
+
+                if self.next_checkpoint >= points.len() {
+                    Outcome::Success
+                } else {
+                    Outcome::Running
+                }
+            }
+            SuccessCriterion::Custom(criterion) => criterion(&env.scene, env.agent),
+        };
+
+        match outcome {
+            Outcome::Running if elapsed >= stage.time_limit => Outcome::Failure,
+            outcome => outcome,
+        }
+    }
+
+    fn finish_episode(&mut self, outcome: Outcome) {
+        let duration = self
+            .env
+            .as_ref()
+            .map_or(0., |env| env.scene.time.0 - self.episode_start);
+        let report = EpisodeReport {
+            stage: self.current,
+            outcome,
+            duration,
+        };
+        for callback in &mut self.on_episode_end {
+            callback(&report);
+        }
+
+        self.recent.push_back(outcome == Outcome::Success);
+        while self.recent.len() > self.window.max(1) {
+            self.recent.pop_front();
+        }
+
+        let successes = self.recent.iter().filter(|&&s| s).count();
+        let promoted = self.recent.len() >= self.window.max(1)
+            && successes as f32 >= self.promote_rate * self.recent.len() as f32;
+        if !promoted || self.complete {
+            return;
+        }
+
+        if self.current + 1 >= self.stages.len() {
+            self.complete = true;
+            return;
+        }
+
+        self.current += 1;
+        self.recent.clear();
+        self.env = None;
+        for callback in &mut self.on_advance {
+            callback(self.current, &self.stages[self.current]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        Agent2D, Scene2D,
+        controller::ControlInput,
+        curriculum::{Curriculum, Outcome, Stage, SuccessCriterion},
+        env::Env2D,
+    };
+
+    fn stage(criterion: SuccessCriterion) -> Stage {
+        Stage {
+            name: format!("{criterion:?}"),
+            env: Box::new(|| {
+                let mut scene = Scene2D::from_pixels([16, 16], &[255; 256]).unwrap();
+                let id = scene.add_agent(Agent2D::default());
+
+                Env2D::new(scene, id, 0.1)
+            }),
+            criterion,
+            time_limit: 2.,
+        }
+    }
+
+    #[test]
+    fn test_curriculum() {
+        let mut curriculum = Curriculum::new(vec![
+            stage(SuccessCriterion::Survive { duration: 0.5 }),
+            stage(SuccessCriterion::Checkpoints {
+                points: vec![glam::vec2(100., 0.)],
+                radius: 1.,
+            }),
+        ]);
+        curriculum.window = 2;
+
+        let advanced = Arc::new(AtomicUsize::new(0));
+        curriculum.on_advance({
+            let advanced = Arc::clone(&advanced);
+            move |i, _| advanced.store(i, Ordering::SeqCst)
+        });
+
+        let run_episode = |curriculum: &mut Curriculum| {
+            curriculum.reset().unwrap();
+            loop {
+                let outcome = curriculum.step(ControlInput::default()).outcome;
+                if outcome != Outcome::Running {
+                    return outcome;
+                }
+            }
+        };
+
+        assert_eq!(run_episode(&mut curriculum), Outcome::Success);
+        assert_eq!(curriculum.stage_index(), 0);
+        assert_eq!(run_episode(&mut curriculum), Outcome::Success);
+        assert_eq!(curriculum.stage_index(), 1);
+        assert_eq!(advanced.load(Ordering::SeqCst), 1);
+
+        // The checkpoint is off the map, so the episode times out.
+        assert_eq!(run_episode(&mut curriculum), Outcome::Failure);
+        assert!(!curriculum.is_complete());
+    }
+}
@@ -0,0 +1,651 @@
+//! How agents move in response to their inputs. Every model reads the same drive torque and steering angle, so
+//! controllers carry over between them, but each turns them into motion in its own way. The model is chosen per agent
+//! through [Agent2DConfig::dynamics].
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    scene::surface::GRAVITY,
+};
+
+/// Longest step the tyre model is integrated with, in seconds. Tyre forces settle far faster than a scene update.
+const TYRE_STEP: f32 = 1e-3;
+
+/// Advances an agent's state by `dt` on ideal ground.
+pub trait Dynamics2D {
+    fn integrate(
+        &self,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState;
+}
+
+/// How the kinematic models step their equations of motion. Inputs are held for the whole step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Integrator {
+    /// Explicit Euler. The [Bicycle] also turns with the change in steering and speed over the last step, estimated
+    /// from the state before it.
+    #[default]
+    Euler,
+    /// Updates the speeds first, then moves at the new speeds.
+    SemiImplicitEuler,
+    /// Fourth-order Runge-Kutta, accurate and stable at much larger steps.
+    Rk4,
+}
+
+/// Rates of change of an agent's state, with its inputs held.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StateDerivative {
+    /// Of the position, in m/s.
+    pub velocity: glam::Vec2,
+    /// Of the heading, in rad/s.
+    pub yaw_rate: f32,
+    /// Of the forward and lateral speeds, in m/s².
+    pub acceleration: glam::Vec2,
+}
+
+impl StateDerivative {
+    /// `state` after moving at these rates for `dt`.
+    fn advance(&self, state: &Agent2DState, dt: f32) -> Agent2DState {
+        let mut next = *state;
+        next.position += self.velocity * dt;
+        next.heading = glam::Vec2::from_angle(self.yaw_rate * dt)
+            .rotate(state.heading)
+            .normalize_or_zero();
+        next.velocity += self.acceleration.x * dt;
+        next.lateral_velocity += self.acceleration.y * dt;
+        next
+    }
+
+    fn weighted(k: [Self; 4], w: [f32; 4]) -> Self {
+        k.into_iter()
+            .zip(w)
+            .fold(Self::default(), |sum, (k, w)| Self {
+                velocity: sum.velocity + k.velocity * w,
+                yaw_rate: sum.yaw_rate + k.yaw_rate * w,
+                acceleration: sum.acceleration + k.acceleration * w,
+            })
+    }
+}
+
+impl Integrator {
+    /// Advances `state` by `dt` along `derivative`.
+    pub fn step(
+        &self,
+        state: &Agent2DState,
+        dt: f32,
+        derivative: impl Fn(&Agent2DState) -> StateDerivative,
+    ) -> Agent2DState {
+        match self {
+            Self::Euler => derivative(state).advance(state, dt),
+            Self::SemiImplicitEuler => {
+                let d = derivative(state);
+                let sped_up = StateDerivative {
+                    acceleration: d.acceleration,
+                    ..Default::default()
+                }
+                .advance(state, dt);
+                let moved = derivative(&sped_up);
+
+                StateDerivative {
+                    acceleration: glam::Vec2::ZERO,
+                    ..moved
+                }
+                .advance(&sped_up, dt)
+            }
+            Self::Rk4 => {
+                let k1 = derivative(state);
+                let k2 = derivative(&k1.advance(state, dt / 2.));
+                let k3 = derivative(&k2.advance(state, dt / 2.));
+                let k4 = derivative(&k3.advance(state, dt));
+
+                StateDerivative::weighted([k1, k2, k3, k4], [1. / 6., 1. / 3., 1. / 3., 1. / 6.])
+                    .advance(state, dt)
+            }
+        }
+    }
+}
+
+/// Car-like: the drive torque turns the rear wheel and the front wheel steers by `beta`. Without `tyres` the wheels
+/// never slip, turning the agent with a curvature of tan(beta) / length at any speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bicycle {
+    /// Lets the wheels slip sideways, so fast corners run wide and the agent can spin out. Tyre forces are stepped in
+    /// fine substeps of their own, whatever the [Integrator].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tyres: Option<Tyres>,
+}
+
+/// Simplified Pacejka tyres: an axle's sideways force is `friction * load * sin(shape * atan(stiffness * slip))` for
+/// a slip angle `slip` between where its wheel points and where it is going. Each axle carries half the agent's
+/// weight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tyres {
+    /// Pacejka B, per radian.
+    pub stiffness: f32,
+    /// Pacejka C.
+    pub shape: f32,
+    /// Peak coefficient of friction.
+    pub friction: f32,
+    /// Below this forward speed in m/s, where slip angles stop meaning much, the wheels track without slipping.
+    pub min_speed: f32,
+}
+
+/// Two wheels `width` apart, driven independently, like most indoor robots. `beta` asks for the same curvature as the
+/// [Bicycle], which the wheels follow by running at different speeds. Turns that would take a wheel past
+/// `max_wheel_speed` are taken slower.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DifferentialDrive {
+    /// In m/s.
+    pub max_wheel_speed: f32,
+}
+
+/// Omnidirectional or mecanum wheels, moving any way without turning. The drive torque pushes along the heading turned
+/// by `beta`, and the agent keeps facing the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Omni;
+
+/// One of the built-in [Dynamics2D] models.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DynamicsModel {
+    Bicycle(Bicycle),
+    DifferentialDrive(DifferentialDrive),
+    Omni(Omni),
+}
+
+impl Default for DifferentialDrive {
+    fn default() -> Self {
+        Self {
+            max_wheel_speed: 2.,
+        }
+    }
+}
+
+impl Default for DynamicsModel {
+    fn default() -> Self {
+        Self::Bicycle(Bicycle::default())
+    }
+}
+
+impl Default for Tyres {
+    fn default() -> Self {
+        Self {
+            stiffness: 10.,
+            shape: 1.3,
+            friction: 1.,
+            min_speed: 1.,
+        }
+    }
+}
+
+impl Validate for Tyres {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("stiffness", self.stiffness)?;
+        config::positive("shape", self.shape)?;
+        config::positive("friction", self.friction)?;
+        config::positive("min_speed", self.min_speed)
+    }
+}
+
+impl Tyres {
+    /// Sideways force in N of an axle carrying `load` N at `slip` radians of slip angle, pushing towards where the
+    /// wheel points.
+    pub fn lateral_force(&self, slip: f32, load: f32) -> f32 {
+        self.friction * load * (self.shape * (self.stiffness * slip).atan()).sin()
+    }
+}
+
+impl Validate for DynamicsModel {
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            Self::Bicycle(Bicycle { tyres: None }) | Self::Omni(_) => Ok(()),
+            Self::Bicycle(Bicycle { tyres: Some(tyres) }) => {
+                tyres.validate().map_err(|e| e.in_field("tyres"))
+            }
+            Self::DifferentialDrive(drive) => {
+                config::positive("max_wheel_speed", drive.max_wheel_speed)
+            }
+        }
+    }
+}
+
+impl DynamicsModel {
+    /// Whether the agent keeps a sideways velocity of its own, so it can be pushed sideways.
+    pub fn slides(&self) -> bool {
+        matches!(
+            self,
+            Self::Omni(_) | Self::Bicycle(Bicycle { tyres: Some(_) })
+        )
+    }
+
+    /// Whether the agent keeps a yaw rate of its own, rather than one set by its speed and steering, so it can be
+    /// spun.
+    pub fn spins(&self) -> bool {
+        matches!(self, Self::Bicycle(Bicycle { tyres: Some(_) }))
+    }
+}
+
+impl Dynamics2D for DynamicsModel {
+    fn integrate(
+        &self,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        match self {
+            Self::Bicycle(model) => model.integrate(config, state, last_state, dt),
+            Self::DifferentialDrive(model) => model.integrate(config, state, last_state, dt),
+            Self::Omni(model) => model.integrate(config, state, last_state, dt),
+        }
+    }
+}
+
+/// Acceleration the drive torque gives the agent, in m/s².
+fn acceleration(config: &Agent2DConfig, torque: f32) -> f32 {
+    let &Agent2DConfig {
+        mass,
+        radius_tyre,
+        inertia_tyre,
+        ..
+    } = config;
+
+    radius_tyre * torque / (2. * inertia_tyre + mass * radius_tyre * radius_tyre)
+}
+
+/// Inputs fade unless the controller keeps them up.
+fn relax_inputs(next: &mut Agent2DState, dt: f32) {
+    next.torque *= (0.01f32).powf(dt);
+    next.beta *= (0.3f32).powf(dt);
+}
+
+impl Dynamics2D for Bicycle {
+    fn integrate(
+        &self,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        match &self.tyres {
+            Some(tyres) if state.velocity.abs() >= tyres.min_speed => {
+                Self::slipping(tyres, config, state, dt)
+            }
+            Some(_) => Self::rolling(config, state, last_state, dt),
+            None => Self::kinematic(config, state, last_state, dt),
+        }
+    }
+}
+
+impl Bicycle {
+    /// Rates of change of the kinematic model, where the wheels never slip.
+    pub fn derivative(config: &Agent2DConfig, state: &Agent2DState) -> StateDerivative {
+        StateDerivative {
+            velocity: state.heading * state.velocity,
+            yaw_rate: state.velocity * state.beta.tan() / config.length,
+            acceleration: glam::vec2(acceleration(config, state.torque), 0.),
+        }
+    }
+
+    fn kinematic(
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        if config.integrator != Integrator::Euler {
+            let mut next = config
+                .integrator
+                .step(state, dt, |s| Self::derivative(config, s));
+            relax_inputs(&mut next, dt);
+            return next;
+        }
+
+        let length = config.length;
+        let &Agent2DState {
+            beta,
+            velocity,
+            torque,
+            heading,
+            ..
+        } = state;
+
+        let (dbetadt, dvdt) = if let Some(last) = last_state {
+            ((beta - last.beta) / dt, (velocity - last.velocity) / dt)
+        } else {
+            (0., 0.)
+        };
+
+        let tan_beta = beta.tan();
+        let cos2_beta = 1. / (1. + tan_beta * tan_beta);
+
+        let angular_velocity = (velocity) * tan_beta / length;
+        let angular_acceleration =
+            tan_beta / (length) * dvdt + (velocity) / (length * cos2_beta) * dbetadt;
+
+        let acc = acceleration(config, torque);
+
+        let forward = heading;
+
+        let mut next = *state;
+
+        next.position += forward * velocity * dt;
+        next.velocity += acc * dt;
+        next.heading =
+            glam::Vec2::from_angle(angular_velocity * dt + angular_acceleration * dt * dt / 2.0)
+                .rotate(heading)
+                .normalize_or_zero();
+
+        relax_inputs(&mut next, dt);
+
+        next
+    }
+
+    /// The kinematic model for tyres too slow to slip, keeping the lateral velocity and yaw rate up to date.
+    fn rolling(
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        let mut next = Self::kinematic(config, state, last_state, dt);
+        next.lateral_velocity = 0.;
+        next.yaw_rate = state.velocity * state.beta.tan() / config.length;
+        next
+    }
+
+    /// The dynamic bicycle model, with the centre of mass midway between the axles and the footprint's moment of
+    /// inertia.
+    fn slipping(
+        tyres: &Tyres,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        dt: f32,
+    ) -> Agent2DState {
+        let &Agent2DConfig { mass, length, .. } = config;
+        let half = length / 2.;
+        let inertia = config.yaw_inertia();
+        let load = mass * GRAVITY / 2.;
+        let drive = acceleration(config, state.torque);
+        let (sin_beta, cos_beta) = state.beta.sin_cos();
+
+        let steps = (dt / TYRE_STEP).ceil().max(1.);
+        let h = dt / steps;
+        let mut next = *state;
+        for i in 0..steps as usize {
+            // Braking can take the agent below `min_speed` partway through the step, where dividing by the forward
+            // speed would blow the slip angles up, so the rest of the step rolls.
+            if next.velocity.abs() < tyres.min_speed {
+                let mut rest = Self::rolling(config, &next, None, h * (steps - i as f32));
+                rest.torque = state.torque;
+                rest.beta = state.beta;
+                relax_inputs(&mut rest, dt);
+                return rest;
+            }
+
+            let Agent2DState {
+                velocity: vx,
+                lateral_velocity: vy,
+                yaw_rate: r,
+                ..
+            } = next;
+
+            // Forces oppose sideways sliding, whichever way the wheels roll.
+            let direction = vx.signum();
+            let front = tyres.lateral_force(state.beta - ((vy + half * r) / vx).atan(), load);
+            let rear = tyres.lateral_force(-((vy - half * r) / vx).atan(), load);
+            let (front, rear) = (front * direction, rear * direction);
+
+            next.velocity += (drive - front * sin_beta / mass + r * vy) * h;
+            next.lateral_velocity += ((front * cos_beta + rear) / mass - r * vx) * h;
+            next.yaw_rate += half * (front * cos_beta - rear) / inertia * h;
+
+            next.heading = glam::Vec2::from_angle(next.yaw_rate * h)
+                .rotate(next.heading)
+                .normalize_or_zero();
+            next.position +=
+                (next.heading * next.velocity + next.heading.perp() * next.lateral_velocity) * h;
+        }
+
+        relax_inputs(&mut next, dt);
+
+        next
+    }
+}
+
+impl DifferentialDrive {
+    /// Speeds of the (left, right) wheels in m/s when moving at `velocity` and turning at `yaw_rate` rad/s.
+    pub fn wheel_speeds(velocity: f32, yaw_rate: f32, track: f32) -> (f32, f32) {
+        let turn = yaw_rate * track / 2.;
+
+        (velocity - turn, velocity + turn)
+    }
+
+    /// Forward speed in m/s and yaw rate in rad/s of wheels `track` apart running at `left` and `right` m/s.
+    pub fn body_velocity(left: f32, right: f32, track: f32) -> (f32, f32) {
+        ((left + right) / 2., (right - left) / track)
+    }
+
+    /// Forward speed and yaw rate following the steering, slowed until neither wheel is past its top speed.
+    fn body_velocity_limited(&self, config: &Agent2DConfig, state: &Agent2DState) -> (f32, f32) {
+        let track = config.width;
+        let yaw_rate = state.velocity * state.beta.tan() / config.length;
+        let (left, right) = Self::wheel_speeds(state.velocity, yaw_rate, track);
+
+        let fastest = left.abs().max(right.abs());
+        let scale = if fastest > self.max_wheel_speed {
+            self.max_wheel_speed / fastest
+        } else {
+            1.
+        };
+
+        Self::body_velocity(left * scale, right * scale, track)
+    }
+}
+
+impl Dynamics2D for DifferentialDrive {
+    fn integrate(
+        &self,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        _last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        // The wheels can't be asked for more than they give, so the speed starts the step within their limits.
+        let (velocity, _) = self.body_velocity_limited(config, state);
+        let start = Agent2DState { velocity, ..*state };
+
+        let mut next = config.integrator.step(&start, dt, |s| {
+            let (velocity, yaw_rate) = self.body_velocity_limited(config, s);
+            StateDerivative {
+                velocity: s.heading * velocity,
+                yaw_rate,
+                acceleration: glam::vec2(acceleration(config, s.torque), 0.),
+            }
+        });
+        next.velocity = next
+            .velocity
+            .clamp(-self.max_wheel_speed, self.max_wheel_speed);
+
+        relax_inputs(&mut next, dt);
+
+        next
+    }
+}
+
+impl Dynamics2D for Omni {
+    fn integrate(
+        &self,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        _last_state: Option<&Agent2DState>,
+        dt: f32,
+    ) -> Agent2DState {
+        let mut next = config.integrator.step(state, dt, |s| StateDerivative {
+            velocity: s.heading * s.velocity + s.heading.perp() * s.lateral_velocity,
+            yaw_rate: 0.,
+            acceleration: glam::Vec2::from_angle(s.beta) * acceleration(config, s.torque),
+        });
+
+        relax_inputs(&mut next, dt);
+
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::{
+        Agent2D, Scene2D,
+        dynamics::{Bicycle, DifferentialDrive, DynamicsModel, Integrator, Omni, Tyres},
+        scene::OccupancyMap,
+    };
+
+    #[test]
+    fn test_differential_drive() {
+        // Slowly, a differential drive follows the same curvature as the bicycle.
+        let turn = |dynamics: DynamicsModel, velocity: f32| {
+            let mut agent = Agent2D::default();
+            agent.config.dynamics = dynamics;
+            agent.state.velocity = velocity;
+            agent.state.beta = 0.5;
+            agent.update(0.01, None);
+            glam::Vec2::Y.angle_to(agent.state.heading) / 0.01
+        };
+        let drive = DynamicsModel::DifferentialDrive(DifferentialDrive::default());
+        let curvature = 0.5f32.tan() / Agent2D::default().config.length;
+        assert!((turn(drive, 0.5) - 0.5 * curvature).abs() < 1e-3);
+        assert!((turn(DynamicsModel::default(), 0.5) - 0.5 * curvature).abs() < 1e-3);
+
+        // Fast, the outer wheel can't keep up, so the same curve is taken at its top speed.
+        let yaw_rate = turn(drive, 1.9);
+        let (left, right) = DifferentialDrive::wheel_speeds(yaw_rate / curvature, yaw_rate, 0.25);
+        assert!((right - 2.).abs() < 1e-3 && left < right, "{left} {right}");
+        assert!(turn(DynamicsModel::default(), 1.9) > turn(drive, 1.9));
+    }
+
+    #[test]
+    fn test_integrators() {
+        // Over one long step round a steady turn, Runge-Kutta stays on the circle where Euler flies off it.
+        let arc = |integrator: Integrator| {
+            let mut agent = Agent2D::default();
+            agent.config.integrator = integrator;
+            agent.state.velocity = 2.;
+            agent.state.beta = 0.5;
+            agent.update(0.5, None);
+            agent.state
+        };
+        let radius = 0.5 / 0.5f32.tan();
+        let angle = 2. * 0.5 / radius;
+        let exact = glam::vec2(-radius * (1. - angle.cos()), radius * angle.sin());
+        let rk4 = arc(Integrator::Rk4);
+        assert!(rk4.position.distance(exact) < 0.01, "{}", rk4.position);
+        assert!((glam::Vec2::Y.angle_to(rk4.heading) - angle).abs() < 1e-4);
+        let semi_implicit = arc(Integrator::SemiImplicitEuler).position.distance(exact);
+        assert!(
+            arc(Integrator::Euler).position.distance(exact) > 10. * rk4.position.distance(exact)
+        );
+        assert!(semi_implicit > rk4.position.distance(exact));
+    }
+
+    #[test]
+    fn test_omni() {
+        // Omni wheels push sideways without turning.
+        let mut scene = Scene2D::from_occupancy_map(
+            OccupancyMap::from_pixels(glam::usizevec2(20, 20), vec![false; 400]).unwrap(),
+        );
+        let mut agent = Agent2D::default();
+        agent.config.dynamics = DynamicsModel::Omni(Omni);
+        agent.state.torque = 50.;
+        agent.state.beta = FRAC_PI_2;
+        let id = scene.add_agent(agent);
+        for _ in 0..10 {
+            scene.update(0.1);
+        }
+        let state = scene.agents[&id].state;
+        assert!(state.position.x < -0.1, "{}", state.position);
+        assert!(state.lateral_velocity > 0.);
+        assert_eq!(state.heading, glam::Vec2::Y);
+    }
+
+    #[test]
+    fn test_tyres_grip() {
+        // With tyres, gentle turns still track the kinematic model, but a hard turn at speed can only pull as much
+        // sideways as the tyres grip, and the agent slides.
+        let corner = |velocity: f32, beta: f32| {
+            let mut agent = Agent2D::default();
+            agent.config.dynamics = DynamicsModel::Bicycle(Bicycle {
+                tyres: Some(Tyres::default()),
+            });
+            agent.state.velocity = velocity;
+            for _ in 0..20 {
+                agent.state.beta = beta;
+                agent.update(0.05, None);
+            }
+            agent.state
+        };
+        let gentle = corner(3., 0.05);
+        let kinematic = 3. * 0.05f32.tan() / 0.5;
+        assert!(
+            (gentle.yaw_rate / kinematic - 1.).abs() < 0.1,
+            "{}",
+            gentle.yaw_rate
+        );
+
+        let hard = corner(8., 0.5);
+        assert!(hard.yaw_rate.abs() * hard.velocity < 1.1 * 9.81, "{hard:?}");
+        assert!(hard.lateral_velocity.abs() > 0.1, "{hard:?}");
+
+        let slow = corner(0.5, 0.5);
+        assert_eq!(slow.lateral_velocity, 0.);
+        assert!((slow.yaw_rate - 0.5 * 0.5f32.tan() / 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tyres_braking() {
+        // Braking hard through `min_speed` and on to a stop, in steps long enough to cross it partway through.
+        let mut agent = Agent2D::default();
+        agent.config.dynamics = DynamicsModel::Bicycle(Bicycle {
+            tyres: Some(Tyres::default()),
+        });
+        agent.state.velocity = 4.;
+        let mut stopped = false;
+        let mut crossed = false;
+        for _ in 0..100 {
+            let slipping = agent.state.velocity >= Tyres::default().min_speed;
+            agent.state.torque = -20.;
+            agent.state.beta = 0.3;
+            agent.update(0.25, None);
+
+            let state = agent.state;
+            assert!(
+                state.position.is_finite()
+                    && state.heading.is_finite()
+                    && [state.velocity, state.lateral_velocity, state.yaw_rate]
+                        .iter()
+                        .all(|v| v.is_finite()),
+                "{state:?}"
+            );
+            // The step that slowed below it finished rolling, without sliding sideways.
+            if slipping && state.velocity < Tyres::default().min_speed {
+                crossed = true;
+                assert_eq!(state.lateral_velocity, 0.);
+            }
+            if state.velocity <= 0. {
+                stopped = true;
+                break;
+            }
+        }
+        assert!(crossed && stopped);
+    }
+}
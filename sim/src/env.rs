@@ -0,0 +1,393 @@
+use std::collections::VecDeque;
+
+use crate::{
+    Scene2D,
+    config::{self, ConfigError, Validate},
+    controller::ControlInput,
+    math::Pose2D,
+    plugin::{PluginError, PluginParams},
+    scene::{AgentId, history::Scene2DSnapshot},
+};
+
+/// What a learning agent observes at each step. Meant to be declared in the scenario, so the observation design is
+/// part of the experiment rather than of the training code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObservationConfig {
+    /// Include the true pose as `(x, y, cos, sin)`. Off by default, since a real robot doesn't have it.
+    pub ground_truth_pose: bool,
+    /// Include the localizer's pose estimate as `(x, y, cos, sin)`, zeros until it produces one.
+    pub estimated_pose: bool,
+    /// Include `(velocity, beta)`.
+    pub odometry: bool,
+    /// Topics whose [features](crate::sensors::Sensor2D::features) are included, in order.
+    pub sensors: Vec<String>,
+    /// Number of most recent frames concatenated into each observation, oldest first.
+    pub frame_stack: usize,
+    /// Standardize each feature by its running mean and variance.
+    pub normalize: bool,
+    /// Clamp each feature to `[-clip, clip]`, after normalization.
+    pub clip: Option<f32>,
+}
+
+impl Default for ObservationConfig {
+    fn default() -> Self {
+        Self {
+            ground_truth_pose: false,
+            estimated_pose: false,
+            odometry: true,
+            sensors: Vec::new(),
+            frame_stack: 1,
+            normalize: false,
+            clip: None,
+        }
+    }
+}
+
+impl Validate for ObservationConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("frame_stack", self.frame_stack, 1)?;
+
+        match self.clip {
+            Some(clip) => config::positive("clip", clip),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ObservationConfig {
+    pub fn from_params(params: &PluginParams) -> Result<Self, PluginError> {
+        let default = Self::default();
+        let config = Self {
+            ground_truth_pose: params.bool_or("ground_truth_pose", default.ground_truth_pose)?,
+            estimated_pose: params.bool_or("estimated_pose", default.estimated_pose)?,
+            odometry: params.bool_or("odometry", default.odometry)?,
+            sensors: params
+                .strs("sensors")?
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            frame_stack: params.usize_or("frame_stack", default.frame_stack)?,
+            normalize: params.bool_or("normalize", default.normalize)?,
+            clip: match params.get("clip") {
+                Some(_) => Some(params.f32_or("clip", 0.)?),
+                None => None,
+            },
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub observation: Vec<f32>,
+    /// The agent is no longer in the scene, e.g. it drove off an open map.
+    pub done: bool,
+    /// What the agent's sensors cost this step, see [Sensor2D::cost](crate::sensors::Sensor2D::cost).
+    pub sensing_cost: f32,
+    /// How far `sensing_cost` went over [Env2D::sensing_budget], zero without one. For use as a constraint or a
+    /// penalty in the reward.
+    pub over_budget: f32,
+}
+
+/// Welford's running mean and variance, per feature.
+#[derive(Debug, Clone, Default)]
+struct RunningStats {
+    count: f32,
+    mean: Vec<f32>,
+    m2: Vec<f32>,
+}
+
+impl RunningStats {
+    fn normalize(&mut self, frame: &mut [f32]) {
+        if self.mean.len() != frame.len() {
+            *self = Self {
+                count: 0.,
+                mean: vec![0.; frame.len()],
+                m2: vec![0.; frame.len()],
+            };
+        }
+
+        self.count += 1.;
+        for ((x, mean), m2) in frame.iter_mut().zip(&mut self.mean).zip(&mut self.m2) {
+            let delta = *x - *mean;
+            *mean += delta / self.count;
+            *m2 += delta * (*x - *mean);
+
+            let variance = *m2 / self.count;
+            *x = (*x - *mean) / (variance + 1e-8).sqrt();
+        }
+    }
+}
+
+/// Gym-style wrapper that steps a [Scene2D] with actions for one agent and returns its observations.
+///
+/// The agent's own controller is removed; [Env2D::step] sets its inputs directly.
+#[derive(Debug)]
+pub struct Env2D {
+    pub scene: Scene2D,
+    pub agent: AgentId,
+    pub dt: f32,
+    /// Sensing cost the agent may spend per step.
+    pub sensing_budget: Option<f32>,
+    observation: ObservationConfig,
+    initial: Scene2DSnapshot,
+    frames: VecDeque<Vec<f32>>,
+    stats: RunningStats,
+}
+
+impl Env2D {
+    /// Uses the agent's [observation](crate::Agent2D::observation) config, or the default if it has none.
+    pub fn new(mut scene: Scene2D, agent: AgentId, dt: f32) -> Result<Self, EnvError> {
+        let entry = scene
+            .agents
+            .get_mut(&agent)
+            .ok_or(EnvError::UnknownAgent(agent))?;
+        entry.controller = None;
+
+        let observation = entry.observation.clone().unwrap_or_default();
+        observation.validate()?;
+        for topic in &observation.sensors {
+            let sensor = entry
+                .sensors
+                .get(topic)
+                .ok_or_else(|| EnvError::UnknownSensor(topic.clone()))?;
+            if sensor.sensor.read().feature_len() == 0 {
+                return Err(EnvError::NoFeatures(topic.clone()));
+            }
+        }
+
+        let initial = scene.snapshot();
+
+        Ok(Self {
+            scene,
+            agent,
+            dt,
+            sensing_budget: None,
+            observation,
+            initial,
+            frames: VecDeque::new(),
+            stats: RunningStats::default(),
+        })
+    }
+
+    pub fn observation_config(&self) -> &ObservationConfig {
+        &self.observation
+    }
+
+    /// Length of the vectors returned by [Env2D::reset] and [Env2D::step].
+    pub fn observation_len(&self) -> usize {
+        self.frame_len() * self.observation.frame_stack
+    }
+
+    fn frame_len(&self) -> usize {
+        let ObservationConfig {
+            ground_truth_pose,
+            estimated_pose,
+            odometry,
+            ..
+        } = self.observation;
+        let sensors: usize = self
+            .scene
+            .agents
+            .get(&self.agent)
+            .map(|agent| {
+                self.observation
+                    .sensors
+                    .iter()
+                    .filter_map(|topic| agent.sensors.get(topic))
+                    .map(|entry| entry.sensor.read().feature_len())
+                    .sum()
+            })
+            .unwrap_or_default();
+
+        4 * ground_truth_pose as usize
+            + 4 * estimated_pose as usize
+            + 2 * odometry as usize
+            + sensors
+    }
+
+    /// Puts the scene back the way it was when the environment was created. Normalization statistics are kept.
+    pub fn reset(&mut self) -> Vec<f32> {
+        self.scene.restore(&self.initial);
+        if let Some(agent) = self.scene.agents.get(&self.agent)
+            && let Some(localizer) = &agent.localizer
+        {
+            localizer.lock().reset(agent.state.pose());
+        }
+        self.frames.clear();
+
+        self.observe()
+    }
+
+    /// Applies `action`, clamped to the agent's limits, for one `dt`.
+    pub fn step(&mut self, action: ControlInput) -> Step {
+        if let Some(agent) = self.scene.agents.get_mut(&self.agent) {
+            agent.command(action);
+        }
+
+        self.scene.update(self.dt);
+
+        let sensing_cost = self
+            .scene
+            .agents
+            .get(&self.agent)
+            .map_or(0., |agent| agent.sensing_cost.step);
+        let over_budget = self
+            .sensing_budget
+            .map_or(0., |budget| (sensing_cost - budget).max(0.));
+
+        Step {
+            observation: self.observe(),
+            done: !self.scene.agents.contains_key(&self.agent),
+            sensing_cost,
+            over_budget,
+        }
+    }
+
+    fn observe(&mut self) -> Vec<f32> {
+        let mut frame = self.frame();
+        if self.observation.normalize {
+            self.stats.normalize(&mut frame);
+        }
+        if let Some(clip) = self.observation.clip {
+            frame.iter_mut().for_each(|x| *x = x.clamp(-clip, clip));
+        }
+
+        if self.frames.is_empty() {
+            self.frames
+                .extend(std::iter::repeat_n(frame, self.observation.frame_stack));
+        } else {
+            self.frames.push_back(frame);
+            while self.frames.len() > self.observation.frame_stack {
+                self.frames.pop_front();
+            }
+        }
+
+        self.frames.iter().flatten().copied().collect()
+    }
+
+    fn frame(&self) -> Vec<f32> {
+        let len = self.frame_len();
+        let mut frame = Vec::with_capacity(len);
+        let Some(agent) = self.scene.agents.get(&self.agent) else {
+            frame.resize(len, 0.);
+            return frame;
+        };
+
+        let pose = |pose: Pose2D| {
+            [
+                pose.position.x,
+                pose.position.y,
+                pose.heading.x,
+                pose.heading.y,
+            ]
+        };
+        if self.observation.ground_truth_pose {
+            frame.extend(pose(agent.state.pose()));
+        }
+        if self.observation.estimated_pose {
+            frame.extend(agent.estimate.map_or([0.; 4], |e| pose(e.pose)));
+        }
+        if self.observation.odometry {
+            frame.extend([agent.state.velocity, agent.state.beta]);
+        }
+
+        for topic in &self.observation.sensors {
+            let Some(entry) = agent.sensors.get(topic) else {
+                continue;
+            };
+            let sensor = entry.sensor.read();
+            let start = frame.len();
+
+            // Sensors that haven't reported yet read as zeros.
+            if let Some(measurement) = self.scene.scene_loop.query_topic(self.agent, topic) {
+                sensor.features_any(&measurement.state, &mut frame);
+            }
+            frame.resize(start + sensor.feature_len(), 0.);
+        }
+
+        frame
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EnvError {
+    #[error("Unknown agent: {0:?}")]
+    UnknownAgent(AgentId),
+
+    #[error("Agent has no sensor named `{0}`")]
+    UnknownSensor(String),
+
+    #[error("Sensor `{0}` doesn't provide observation features")]
+    NoFeatures(String),
+
+    #[error("Invalid observation space: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("The curriculum has no stages")]
+    NoStages,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Lidar2D,
+        controller::ControlInput,
+        env::{Env2D, EnvError, ObservationConfig},
+        sensors::Sensor2D,
+        testing,
+    };
+
+    #[test]
+    fn test_observation_space() {
+        let mut scene = testing::open_room();
+        let mut agent = Agent2D::default();
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(4);
+        }
+        agent.observation = Some(ObservationConfig {
+            sensors: vec![Lidar2D::TOPIC.to_string()],
+            frame_stack: 3,
+            ..Default::default()
+        });
+        let id = scene.add_agent(agent);
+
+        let mut env = Env2D::new(scene.clone(), id, 0.1).unwrap();
+        env.sensing_budget = Some(3.);
+        assert_eq!(env.observation_len(), 3 * (2 + 4));
+        assert_eq!(env.reset(), vec![0.; 18]);
+
+        let mut observation = Vec::new();
+        let mut sensing_cost = 0.;
+        for _ in 0..5 {
+            let step = env.step(ControlInput {
+                torque: 10.,
+                beta: 0.,
+            });
+            observation = step.observation;
+            sensing_cost += step.sensing_cost;
+            assert_eq!(step.over_budget, (step.sensing_cost - 3.).max(0.));
+            // Sensors run on the thread pool and are only picked up on a later step.
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(observation.len(), 18);
+        assert!(observation[12] > 0.);
+        // Walls are less than 6 m away in every direction.
+        assert!(observation[14..].iter().all(|&r| r > 1. / 6.));
+        // Four rays per lidar scan plus one for each other sensor, whenever they ran.
+        let agent = &env.scene.agents[&id];
+        assert!(sensing_cost >= 4.);
+        assert_eq!(agent.sensing_cost.total, sensing_cost);
+
+        scene.agents.get_mut(&id).unwrap().observation = Some(ObservationConfig {
+            sensors: vec!["missing".to_string()],
+            ..Default::default()
+        });
+        assert!(matches!(
+            Env2D::new(scene, id, 0.1),
+            Err(EnvError::UnknownSensor(_))
+        ));
+    }
+}
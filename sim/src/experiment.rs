@@ -0,0 +1,591 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rayon::prelude::*;
+
+use std::sync::Arc;
+
+use crate::{
+    Agent2D, Lidar2D, Scene2D,
+    change::{ChangeDetector, ChangeError, MapDiff},
+    localization::{ParticleFilter, PoseSource},
+    mapping::LogOddsGrid,
+    math::Pose2D,
+    rng,
+    scene::{SceneTime, occupancy_map::OccupancyMap},
+    sensors::{Sensor2D, lidar::Lidar2DSensed},
+};
+
+/// Runs a localizer from many random starting points and measures how often, and how quickly, its estimate locks on
+/// to the true pose.
+///
+/// Each trial places the agent at a random free pose and seeds its localizer with an independent random guess, so
+/// this exercises global localization rather than tracking, unless the guess is kept to within
+/// [initial_error](Self::initial_error) of the start. A trial's scene is seeded too, so the same `seed` gives the same
+/// report.
+#[derive(Debug, Clone)]
+pub struct ConvergenceExperiment {
+    pub trials: usize,
+    /// Scene time each trial runs for, in seconds.
+    pub duration: f32,
+    pub dt: f32,
+    /// Position error in metres below which an estimate counts as converged.
+    pub position_tolerance: f32,
+    /// Heading error in radians below which an estimate counts as converged.
+    pub heading_tolerance: f32,
+    /// How long the estimate must stay within tolerance, in seconds.
+    pub settle_time: f32,
+    /// Distance in metres of the localizer's initial guess from the start, in a random direction and with the true
+    /// heading. `None` guesses anywhere on the map.
+    pub initial_error: Option<f32>,
+    pub seed: u64,
+}
+
+impl Default for ConvergenceExperiment {
+    fn default() -> Self {
+        Self {
+            trials: 100,
+            duration: 30.,
+            dt: 0.05,
+            position_tolerance: 0.5,
+            heading_tolerance: 0.2,
+            settle_time: 1.,
+            initial_error: None,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrialResult {
+    pub start: Pose2D,
+    pub initial_guess: Pose2D,
+    /// Scene time at which the estimate entered the window it then stayed in.
+    pub converged_at: Option<f32>,
+    pub final_position_error: f32,
+    pub final_heading_error: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConvergenceReport {
+    pub trials: Vec<TrialResult>,
+}
+
+impl ConvergenceReport {
+    /// Fraction of trials that converged.
+    pub fn convergence_rate(&self) -> f32 {
+        if self.trials.is_empty() {
+            return 0.;
+        }
+
+        self.converged_times().len() as f32 / self.trials.len() as f32
+    }
+
+    /// Convergence times of the trials that converged, sorted.
+    pub fn converged_times(&self) -> Vec<f32> {
+        let mut times = self
+            .trials
+            .iter()
+            .filter_map(|t| t.converged_at)
+            .collect::<Vec<_>>();
+        times.sort_by(f32::total_cmp);
+
+        times
+    }
+
+    pub fn mean_time(&self) -> Option<f32> {
+        let times = self.converged_times();
+
+        (!times.is_empty()).then(|| times.iter().sum::<f32>() / times.len() as f32)
+    }
+
+    pub fn median_time(&self) -> Option<f32> {
+        let times = self.converged_times();
+
+        (!times.is_empty()).then(|| times[times.len() / 2])
+    }
+}
+
+impl std::fmt::Display for ConvergenceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} trials, {:.1}% converged",
+            self.trials.len(),
+            self.convergence_rate() * 100.
+        )?;
+
+        if let (Some(mean), Some(median)) = (self.mean_time(), self.median_time()) {
+            write!(
+                f,
+                ", time to converge: mean {mean:.2} s, median {median:.2} s"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn heading_error(a: glam::Vec2, b: glam::Vec2) -> f32 {
+    a.angle_to(b).abs()
+}
+
+/// Samples poses uniformly over the map until the agent's footprint is clear.
+fn random_free_pose(map: &OccupancyMap, agent: &Agent2D, rng: &mut impl Rng) -> Option<Pose2D> {
+    let bounds = map.bounds();
+    if bounds.size().min_element() <= 0. {
+        return None;
+    }
+
+    (0..1000).find_map(|_| {
+        let pose = Pose2D::new(
+            glam::vec2(
+                rng.random_range(bounds.min.x..bounds.max.x),
+                rng.random_range(bounds.min.y..bounds.max.y),
+            ),
+            rng.random_range(-std::f32::consts::PI..std::f32::consts::PI),
+        );
+
+        let footprint = agent.config.footprint(&agent.state.with_pose(pose));
+        (!map.overlaps(&footprint)).then_some(pose)
+    })
+}
+
+impl ConvergenceExperiment {
+    /// Runs every trial in parallel on its own copy of `map`. `make_agent` must return an agent with a localizer;
+    /// agents without one never converge.
+    pub fn run(
+        &self,
+        map: &OccupancyMap,
+        make_agent: impl Fn() -> Agent2D + Send + Sync,
+    ) -> ConvergenceReport {
+        let trials = (0..self.trials)
+            .into_par_iter()
+            .filter_map(|trial| {
+                self.run_trial(map, make_agent(), self.seed.wrapping_add(trial as u64))
+            })
+            .collect();
+
+        ConvergenceReport { trials }
+    }
+
+    fn run_trial(&self, map: &OccupancyMap, mut agent: Agent2D, seed: u64) -> Option<TrialResult> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let start = random_free_pose(map, &agent, &mut rng)?;
+        let initial_guess = match self.initial_error {
+            Some(error) => {
+                let direction = rng.random_range(-std::f32::consts::PI..std::f32::consts::PI);
+                Pose2D {
+                    position: start.position + glam::Vec2::from_angle(direction) * error,
+                    ..start
+                }
+            }
+            None => random_free_pose(map, &agent, &mut rng)?,
+        };
+
+        agent.state = agent.state.with_pose(start);
+        agent.pose_source = PoseSource::Estimated;
+        let localizer = agent.localizer.clone();
+
+        let mut scene = Scene2D::from_occupancy_map(map.clone());
+        scene.set_deterministic(Some(seed));
+        let id = scene.add_agent(agent);
+        if let Some(localizer) = localizer {
+            rng::with_seed(Some(seed), || localizer.lock().reset(initial_guess));
+        }
+
+        let mut within_since = None;
+        let mut result = TrialResult {
+            start,
+            initial_guess,
+            converged_at: None,
+            final_position_error: f32::INFINITY,
+            final_heading_error: f32::INFINITY,
+        };
+
+        while scene.time.0 < self.duration {
+            scene.update(self.dt);
+
+            let Some(agent) = scene.agents.get(&id) else {
+                break;
+            };
+            let Some(estimate) = agent.estimate else {
+                continue;
+            };
+
+            result.final_position_error = agent.state.position.distance(estimate.pose.position);
+            result.final_heading_error = heading_error(agent.state.heading, estimate.pose.heading);
+
+            if result.final_position_error <= self.position_tolerance
+                && result.final_heading_error <= self.heading_tolerance
+            {
+                let since = *within_since.get_or_insert(scene.time.0);
+                if scene.time.0 - since >= self.settle_time {
+                    result.converged_at = Some(since);
+                    break;
+                }
+            } else {
+                within_since = None;
+            }
+        }
+
+        Some(result)
+    }
+}
+
+/// How many separate hypotheses a [ParticleFilter] holds over a run, to measure how long perceptual aliasing keeps its
+/// estimate ambiguous, e.g. in a [hallway](crate::scene::generate::hallway) of identical rooms.
+#[derive(Debug, Clone)]
+pub struct ModalityTrace {
+    /// Particles within this many metres of a cluster's leading particle count as the same hypothesis.
+    pub radius: f32,
+    /// Clusters with less of the total weight than this are ignored as stragglers.
+    pub min_weight: f32,
+    /// Scene time and number of hypotheses at each recorded step.
+    pub samples: Vec<(SceneTime, usize)>,
+}
+
+impl Default for ModalityTrace {
+    fn default() -> Self {
+        Self {
+            radius: 1.,
+            min_weight: 0.05,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl ModalityTrace {
+    /// Records the filter's current number of hypotheses and returns it.
+    pub fn record(&mut self, time: SceneTime, filter: &ParticleFilter) -> usize {
+        let modes = filter.modes(self.radius, self.min_weight);
+        self.samples.push((time, modes));
+
+        modes
+    }
+
+    pub fn max_modes(&self) -> usize {
+        self.samples.iter().map(|&(_, n)| n).max().unwrap_or(0)
+    }
+
+    /// Fraction of the samples with more than one hypothesis.
+    pub fn ambiguous_fraction(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.;
+        }
+
+        self.samples.iter().filter(|&&(_, n)| n > 1).count() as f32 / self.samples.len() as f32
+    }
+
+    /// Time from which the filter held a single hypothesis until the end of the trace, if it ended that way.
+    pub fn resolved_at(&self) -> Option<SceneTime> {
+        let ambiguous = self.samples.iter().rposition(|&(_, n)| n != 1);
+        let first = ambiguous.map_or(0, |i| i + 1);
+
+        self.samples.get(first).map(|&(time, _)| time)
+    }
+}
+
+/// Starts an agent with a prior map that no longer matches the scene, e.g. with furniture moved since it was made, and
+/// measures how quickly its localizer settles despite that and how quickly mapping its scans finds what changed.
+#[derive(Debug, Clone)]
+pub struct StalenessExperiment {
+    /// Scene time to run for at most, in seconds.
+    pub duration: f32,
+    pub dt: f32,
+    /// Position error in metres below which the estimate counts as settled.
+    pub position_tolerance: f32,
+    /// How long the estimate must stay within tolerance, in seconds.
+    pub settle_time: f32,
+    /// Share of the changed cells that must be found for the map to count as recovered.
+    pub recovered_fraction: f32,
+    /// Compares the prior map against the one mapped from the agent's scans.
+    pub detector: ChangeDetector,
+}
+
+impl Default for StalenessExperiment {
+    fn default() -> Self {
+        Self {
+            duration: 30.,
+            dt: 0.05,
+            position_tolerance: 0.5,
+            settle_time: 1.,
+            recovered_fraction: 0.8,
+            detector: ChangeDetector::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StalenessSample {
+    pub time: SceneTime,
+    /// `None` until the localizer produces an estimate.
+    pub position_error: Option<f32>,
+    /// Changed cells the detector has found.
+    pub detected: usize,
+    /// Cells the detector flags that didn't change.
+    pub spurious: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StalenessReport {
+    /// What really changed between the prior map and the scene.
+    pub changes: MapDiff,
+    pub samples: Vec<StalenessSample>,
+    /// Scene time at which the estimate entered the window it then stayed in.
+    pub localized_at: Option<f32>,
+    /// Scene time at which the detector had found [StalenessExperiment::recovered_fraction] of the changed cells.
+    pub mapped_at: Option<f32>,
+    /// Mapped from the agent's scans over the run.
+    pub grid: LogOddsGrid,
+}
+
+impl StalenessReport {
+    /// Share of the changed cells found by the end of the run, 1 if nothing changed.
+    pub fn detected_fraction(&self) -> f32 {
+        match (self.changes.changed_cells(), self.samples.last()) {
+            (0, _) => 1.,
+            (_, None) => 0.,
+            (changed, Some(last)) => last.detected as f32 / changed as f32,
+        }
+    }
+}
+
+impl StalenessExperiment {
+    /// Runs `agent` in a scene of `live` with `prior` as its [prior map](Agent2D::prior_map), until both localization
+    /// and mapping have recovered or time runs out. The agent has to drive itself past the changes, e.g. with a
+    /// [PurePursuit](crate::control::PurePursuit) controller. Its lidar scans are mapped from its estimated pose, or
+    /// its true one without a localizer.
+    pub fn run(
+        &self,
+        live: &OccupancyMap,
+        prior: &OccupancyMap,
+        mut agent: Agent2D,
+    ) -> Result<StalenessReport, ChangeError> {
+        let changes = prior.diff(live)?;
+        let mut truth = vec![None; prior.pixels.len()];
+        for region in &changes.regions {
+            for cell in &region.cells {
+                truth[cell.x + cell.y * prior.size.x] = Some(region.kind);
+            }
+        }
+        let changed = changes.changed_cells();
+
+        agent.prior_map = Some(Arc::new(prior.clone()));
+        let mut scene = Scene2D::from_occupancy_map(live.clone());
+        let id = scene.add_agent(agent);
+
+        let mut report = StalenessReport {
+            changes,
+            samples: Vec::new(),
+            localized_at: None,
+            mapped_at: None,
+            grid: LogOddsGrid::new(live.size),
+        };
+        let mut last_scan = None;
+        let mut within_since = None;
+        let (mut detected, mut spurious) = (0, 0);
+
+        while scene.time.0 < self.duration {
+            scene.update(self.dt);
+
+            let Some(agent) = scene.agents.get(&id) else {
+                break;
+            };
+            let pose = agent.estimate.map_or(agent.state.pose(), |e| e.pose);
+
+            let scan = scene
+                .scene_loop
+                .query_topic_as::<Lidar2DSensed>(id, Lidar2D::TOPIC);
+            let lidar = agent.sensors.read_as::<Lidar2D>(Lidar2D::TOPIC);
+            if let (Some(scan), Some(lidar)) = (scan, lidar)
+                && last_scan != Some(scan.time)
+            {
+                last_scan = Some(scan.time);
+                report.grid.update(&lidar, &scan.state, pose);
+
+                let found = self.detector.detect(prior, &report.grid)?.diff;
+                (detected, spurious) = (0, 0);
+                for region in &found.regions {
+                    for cell in &region.cells {
+                        match truth[cell.x + cell.y * prior.size.x] {
+                            Some(kind) if kind == region.kind => detected += 1,
+                            _ => spurious += 1,
+                        }
+                    }
+                }
+            }
+
+            let position_error = agent
+                .estimate
+                .map(|e| e.pose.position.distance(agent.state.position));
+            report.samples.push(StalenessSample {
+                time: scene.time,
+                position_error,
+                detected,
+                spurious,
+            });
+
+            if position_error.is_some_and(|e| e <= self.position_tolerance) {
+                let since = *within_since.get_or_insert(scene.time.0);
+                if report.localized_at.is_none() && scene.time.0 - since >= self.settle_time {
+                    report.localized_at = Some(since);
+                }
+            } else {
+                within_since = None;
+                report.localized_at = None;
+            }
+            if report.mapped_at.is_none()
+                && detected as f32 >= self.recovered_fraction * changed as f32
+            {
+                report.mapped_at = Some(scene.time.0);
+            }
+
+            if report.localized_at.is_some() && report.mapped_at.is_some() {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Lidar2D,
+        control::PurePursuit,
+        experiment::{ConvergenceExperiment, StalenessExperiment},
+        localization::{Localizer, ParticleFilter},
+        math::Pose2D,
+        scene::occupancy_map::OccupancyMap,
+        sensors::Sensor2D,
+    };
+
+    fn room() -> OccupancyMap {
+        OccupancyMap::from_ascii(&[
+            "############",
+            "#..........#",
+            "#..........#",
+            "#..##......#",
+            "#..##......#",
+            "#..........#",
+            "#.......####",
+            "############",
+        ])
+    }
+
+    fn localized_agent() -> Agent2D {
+        let mut agent = Agent2D::default();
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(90);
+        }
+        let mut filter = ParticleFilter::default();
+        filter.particle_count = 100;
+        agent.localizer = Some(Arc::new(Mutex::new(filter)) as Arc<Mutex<dyn Localizer>>);
+        agent
+    }
+
+    #[test]
+    fn test_convergence_from_true_pose() {
+        let experiment = ConvergenceExperiment {
+            trials: 4,
+            duration: 3.,
+            dt: 0.1,
+            initial_error: Some(0.),
+            ..Default::default()
+        };
+        let report = experiment.run(&room(), localized_agent);
+
+        assert_eq!(report.trials.len(), 4);
+        assert_eq!(report.convergence_rate(), 1., "{report}");
+        assert!(report.trials.iter().all(|t| t.initial_guess == t.start));
+    }
+
+    #[test]
+    fn test_convergence_seed() {
+        // Sensor noise and resampling are seeded along with the poses.
+        let experiment = ConvergenceExperiment {
+            trials: 2,
+            duration: 2.,
+            dt: 0.1,
+            seed: 7,
+            ..Default::default()
+        };
+        // Moving, so the filter's motion noise comes into play, with a jittery lidar.
+        let moving_agent = || {
+            let mut agent = localized_agent();
+            agent.state.velocity = 0.3;
+            if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+                lidar.ray_jitter = 0.01;
+            }
+            agent
+        };
+        let report = experiment.run(&room(), moving_agent);
+
+        assert_eq!(experiment.run(&room(), moving_agent).trials, report.trials);
+        let reseeded = ConvergenceExperiment {
+            seed: 8,
+            ..experiment
+        };
+        assert_ne!(reseeded.run(&room(), moving_agent).trials, report.trials);
+    }
+
+    #[test]
+    fn test_map_staleness() {
+        // A crate was pushed from the middle of the room up against the top wall.
+        let prior = OccupancyMap::from_ascii(&[
+            "################",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "#.....##.......#",
+            "#.....##.......#",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "################",
+        ]);
+        let live = OccupancyMap::from_ascii(&[
+            "################",
+            "#.........##...#",
+            "#.........##...#",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "#..............#",
+            "################",
+        ]);
+
+        let start = Pose2D::new(glam::vec2(-5.5, -2.5), 0.);
+        let mut agent = Agent2D::default();
+        agent.state = agent.state.with_pose(start);
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(90);
+        }
+        agent.controller = Some(Arc::new(Mutex::new(PurePursuit::new(vec![
+            start.position,
+            glam::vec2(5.5, -2.5),
+        ]))));
+        let mut filter = ParticleFilter::default();
+        filter.particle_count = 200;
+        agent.localizer = Some(Arc::new(Mutex::new(filter)) as Arc<Mutex<dyn Localizer>>);
+
+        let experiment = StalenessExperiment {
+            duration: 15.,
+            ..Default::default()
+        };
+        let report = experiment.run(&live, &prior, agent).unwrap();
+
+        assert_eq!(report.changes.changed_cells(), 8);
+        assert_eq!(report.changes.regions.len(), 2);
+        assert!(report.mapped_at.is_some(), "{:?}", report.samples.last());
+        assert!(report.localized_at.is_some(), "{:?}", report.samples.last());
+        assert!(report.detected_fraction() >= 0.8);
+        assert!(report.samples.iter().all(|s| s.detected <= 8));
+    }
+}
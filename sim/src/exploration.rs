@@ -0,0 +1,414 @@
+//! Frontier-based exploration over a [LogOddsGrid] being built from scans: [FrontierExplorer::frontiers] finds where
+//! mapped free space meets the unknown, and [FrontierExplorer::select] picks one to drive to next. Exploring is
+//! finished once there are no reachable frontiers left.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    mapping::{LogOddsGrid, probability},
+};
+
+/// Connected free cells bordering unknown ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frontier {
+    /// As `(col, row)`, like the indices of [LogOddsGrid::cells].
+    pub cells: Vec<glam::USizeVec2>,
+    /// Mean of the cell centres. Can lie in unknown space or a wall if the frontier is curved.
+    pub center: glam::Vec2,
+}
+
+/// How [FrontierExplorer::select] ranks the reachable frontiers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FrontierPolicy {
+    /// Shortest drive first, which explores like a flood fill.
+    #[default]
+    Nearest,
+    /// Most frontier cells first, wherever they are.
+    Largest,
+    /// Trades the drive against the size of the frontier: `gain` metres of driving are worth one frontier cell.
+    Utility { gain: f32 },
+}
+
+/// A frontier chosen to drive to, and the way there through known free space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontierTarget {
+    pub frontier: Frontier,
+    /// Centre of the reachable frontier cell nearest [Frontier::center].
+    pub goal: glam::Vec2,
+    /// Length of `path` in metres.
+    pub distance: f32,
+    /// Cell centres from the one the agent is in to `goal`, moving to any of the 8 neighbours without cutting the
+    /// corners of cells that aren't free.
+    pub path: Vec<glam::Vec2>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontierExplorer {
+    /// Cells at most this likely to be occupied are free.
+    pub free_threshold: f32,
+    /// Cells at least this likely to be occupied are walls. Those between the thresholds are unknown.
+    pub occupied_threshold: f32,
+    /// Smaller frontiers are put down to gaps between rays and ignored.
+    pub min_cells: usize,
+    pub policy: FrontierPolicy,
+}
+
+impl Default for FrontierExplorer {
+    fn default() -> Self {
+        Self {
+            free_threshold: 0.3,
+            occupied_threshold: 0.7,
+            min_cells: 2,
+            policy: FrontierPolicy::default(),
+        }
+    }
+}
+
+impl Validate for FrontierExplorer {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::within(
+            "free_threshold",
+            self.free_threshold,
+            (0. ..0.5).contains(&self.free_threshold),
+            "[0, 0.5)",
+        )?;
+        config::within(
+            "occupied_threshold",
+            self.occupied_threshold,
+            self.occupied_threshold > 0.5 && self.occupied_threshold <= 1.,
+            "(0.5, 1]",
+        )?;
+        config::at_least("min_cells", self.min_cells, 1)?;
+        if let FrontierPolicy::Utility { gain } = self.policy {
+            config::finite("policy.gain", gain)?;
+            config::non_negative("policy.gain", gain)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellState {
+    Free,
+    Unknown,
+    Occupied,
+}
+
+/// Entry of the Dijkstra queue, ordered so the nearest cell pops first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Visit {
+    distance: f32,
+    cell: usize,
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl FrontierExplorer {
+    fn states(&self, grid: &LogOddsGrid) -> Vec<CellState> {
+        grid.cells
+            .iter()
+            .map(|&l| match probability(l) {
+                p if p <= self.free_threshold => CellState::Free,
+                p if p >= self.occupied_threshold => CellState::Occupied,
+                _ => CellState::Unknown,
+            })
+            .collect()
+    }
+
+    /// Every frontier of at least [min_cells](Self::min_cells) cells, largest first. Cells on the edge of the grid
+    /// don't border the unknown beyond it, as nothing can be driven to there.
+    pub fn frontiers(&self, grid: &LogOddsGrid) -> Vec<Frontier> {
+        let states = self.states(grid);
+        let (w, h) = (grid.size.x as i64, grid.size.y as i64);
+        let neighbours = |i: usize, diagonal: bool| {
+            let (x, y) = ((i % grid.size.x) as i64, (i / grid.size.x) as i64);
+            (-1..=1)
+                .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                .filter(move |&(dx, dy)| (dx, dy) != (0, 0) && (diagonal || dx == 0 || dy == 0))
+                .map(move |(dx, dy)| (x + dx, y + dy))
+                .filter(move |&(x, y)| (0..w).contains(&x) && (0..h).contains(&y))
+                .map(|(x, y)| (x + y * w) as usize)
+        };
+
+        let on_frontier = (0..states.len())
+            .map(|i| {
+                states[i] == CellState::Free
+                    && neighbours(i, false).any(|n| states[n] == CellState::Unknown)
+            })
+            .collect::<Vec<_>>();
+
+        let mut seen = vec![false; states.len()];
+        let mut frontiers = Vec::new();
+        let mut queue = VecDeque::new();
+        for start in 0..states.len() {
+            if !on_frontier[start] || seen[start] {
+                continue;
+            }
+
+            seen[start] = true;
+            queue.push_back(start);
+            let mut cells = Vec::new();
+            while let Some(i) = queue.pop_front() {
+                cells.push(glam::usizevec2(i % grid.size.x, i / grid.size.x));
+                for n in neighbours(i, true) {
+                    if on_frontier[n] && !seen[n] {
+                        seen[n] = true;
+                        queue.push_back(n);
+                    }
+                }
+            }
+            if cells.len() < self.min_cells {
+                continue;
+            }
+
+            let center = cells
+                .iter()
+                .map(|&c| cell_center(grid, c))
+                .sum::<glam::Vec2>()
+                / cells.len() as f32;
+            frontiers.push(Frontier { cells, center });
+        }
+        frontiers.sort_by_key(|f| std::cmp::Reverse(f.cells.len()));
+
+        frontiers
+    }
+
+    /// The best frontier by [policy](Self::policy) that an agent at `position` can reach through free space, or
+    /// `None` once there are none left to explore.
+    pub fn select(&self, grid: &LogOddsGrid, position: glam::Vec2) -> Option<FrontierTarget> {
+        let start = grid.cell(position)?;
+        let start = start.x + start.y * grid.size.x;
+        let states = self.states(grid);
+        let (w, h) = (grid.size.x as i64, grid.size.y as i64);
+        let free = |x: i64, y: i64| {
+            (0..w).contains(&x)
+                && (0..h).contains(&y)
+                && states[(x + y * w) as usize] == CellState::Free
+        };
+
+        // Dijkstra out from the agent's cell, which counts as free whatever the grid thinks of it.
+        let mut distances = vec![f32::INFINITY; states.len()];
+        let mut parents = vec![usize::MAX; states.len()];
+        let mut queue = BinaryHeap::new();
+        distances[start] = 0.;
+        queue.push(Visit {
+            distance: 0.,
+            cell: start,
+        });
+        while let Some(Visit { distance, cell }) = queue.pop() {
+            if distance > distances[cell] {
+                continue;
+            }
+
+            let (x, y) = ((cell % grid.size.x) as i64, (cell / grid.size.x) as i64);
+            for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                let (nx, ny) = (x + dx, y + dy);
+                if (dx, dy) == (0, 0) || !free(nx, ny) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && !(free(x + dx, y) && free(x, y + dy)) {
+                    continue;
+                }
+
+                let n = (nx + ny * w) as usize;
+                let next = distance
+                    + if diagonal {
+                        std::f32::consts::SQRT_2
+                    } else {
+                        1.
+                    };
+                if next < distances[n] {
+                    distances[n] = next;
+                    parents[n] = cell;
+                    queue.push(Visit {
+                        distance: next,
+                        cell: n,
+                    });
+                }
+            }
+        }
+
+        let score = |frontier: &Frontier, distance: f32| match self.policy {
+            FrontierPolicy::Nearest => distance,
+            FrontierPolicy::Largest => -(frontier.cells.len() as f32),
+            FrontierPolicy::Utility { gain } => distance - gain * frontier.cells.len() as f32,
+        };
+        let (frontier, goal, _) = self
+            .frontiers(grid)
+            .into_iter()
+            .filter_map(|frontier| {
+                let goal = frontier
+                    .cells
+                    .iter()
+                    .map(|&c| c.x + c.y * grid.size.x)
+                    .filter(|&i| distances[i].is_finite())
+                    .min_by(|&a, &b| {
+                        let off = |i: usize| {
+                            cell_center(grid, glam::usizevec2(i % grid.size.x, i / grid.size.x))
+                                .distance_squared(frontier.center)
+                        };
+                        off(a).total_cmp(&off(b))
+                    })?;
+                let score = score(&frontier, distances[goal]);
+                Some((frontier, goal, score))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))?;
+
+        let mut path = vec![goal];
+        while let Some(&last) = path.last()
+            && last != start
+        {
+            path.push(parents[last]);
+        }
+        let path = path
+            .into_iter()
+            .rev()
+            .map(|i| cell_center(grid, glam::usizevec2(i % grid.size.x, i / grid.size.x)))
+            .collect::<Vec<_>>();
+
+        Some(FrontierTarget {
+            frontier,
+            goal: *path.last().expect("The path has at least the goal"),
+            distance: distances[goal],
+            path,
+        })
+    }
+}
+
+fn cell_center(grid: &LogOddsGrid, cell: glam::USizeVec2) -> glam::Vec2 {
+    glam::vec2(
+        cell.x as f32 + 0.5 - grid.size.x as f32 / 2.,
+        grid.size.y as f32 / 2. - cell.y as f32 - 0.5,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Lidar2D, Scene2D,
+        exploration::{FrontierExplorer, FrontierPolicy},
+        mapping::LogOddsGrid,
+        scene::OccupancyMap,
+        sensors::Sensor2D,
+    };
+
+    #[test]
+    fn test_frontiers_unknown_map() {
+        // Nothing is known until the first scan.
+        let grid = LogOddsGrid::new(glam::usizevec2(16, 8));
+        let explorer = FrontierExplorer::default();
+        assert!(explorer.frontiers(&grid).is_empty());
+        assert!(explorer.select(&grid, glam::Vec2::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_frontier_exploration() {
+        // Two rooms joined by a doorway.
+        let scene = Scene2D::from_occupancy_map(OccupancyMap::from_ascii(&[
+            "################",
+            "#......#.......#",
+            "#......#.......#",
+            "#..............#",
+            "#......#.......#",
+            "#......#.......#",
+            "#......#.......#",
+            "################",
+        ]));
+
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(-5.5, -1.5);
+        let mut lidar = Lidar2D::regular(180);
+        let mut grid = LogOddsGrid::new(glam::usizevec2(16, 8));
+        let explorer = FrontierExplorer::default();
+
+        let mut targets = 0;
+        loop {
+            for _ in 0..3 {
+                let scan = lidar
+                    .sense(agent.config, agent.state, scene.state())
+                    .unwrap();
+                grid.update(&lidar, &scan.state, agent.state.pose());
+            }
+            let Some(target) = explorer.select(&grid, agent.state.position) else {
+                break;
+            };
+            assert!(target.path.windows(2).all(|w| w[0].distance(w[1]) < 1.5));
+            assert!(
+                (target
+                    .path
+                    .windows(2)
+                    .map(|w| w[0].distance(w[1]))
+                    .sum::<f32>()
+                    - target.distance)
+                    .abs()
+                    < 1e-3
+            );
+            agent.state.position = target.goal;
+            targets += 1;
+            assert!(targets < 10, "{target:?}");
+        }
+
+        // Every free cell of both rooms has been seen.
+        let map = &scene.state().occupancy_map;
+        for i in 0..grid.cells.len() {
+            let (col, row) = (i % 16, i / 16);
+            if !map.pixels[i] {
+                assert!(grid.cells[i] < 0., "({col}, {row})");
+            }
+        }
+        assert!(targets >= 1);
+    }
+
+    #[test]
+    fn test_frontier_policies() {
+        // A short frontier up a dead end next to the agent, and a longer one at the end of a corridor.
+        let rows = [
+            "..??????????",
+            "..??????????",
+            "..??????????",
+            "..##########",
+            "............",
+            "######??????",
+        ];
+        let mut grid = LogOddsGrid::new(glam::usizevec2(12, 6));
+        for (i, c) in rows.iter().flat_map(|row| row.chars()).enumerate() {
+            grid.cells[i] = match c {
+                '.' => -grid.clamp,
+                '#' => grid.clamp,
+                _ => 0.,
+            };
+        }
+        let position = glam::vec2(-5.5, 1.5);
+        let explorer = FrontierExplorer::default();
+        assert_eq!(explorer.frontiers(&grid).len(), 2);
+        let nearest = explorer.select(&grid, position).unwrap();
+        assert_eq!(nearest.frontier.cells.len(), 3);
+        assert!(nearest.goal.x < -4.);
+        let largest = FrontierExplorer {
+            policy: FrontierPolicy::Largest,
+            ..Default::default()
+        }
+        .select(&grid, position)
+        .unwrap();
+        assert_eq!(largest.frontier.cells.len(), 6);
+        assert!(largest.distance > nearest.distance);
+    }
+}
@@ -0,0 +1,438 @@
+//! Golden traces for regression tests: record a short seeded run once, then check later runs still match it.
+
+use crate::{
+    Scene2D,
+    math::Pose2D,
+    scene::{AgentId, SceneTime},
+};
+
+/// Latest measurement of one topic, flattened into its [features](crate::sensors::Sensor2D::features).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicFrame {
+    pub topic: String,
+    pub time: SceneTime,
+    pub features: Vec<f32>,
+}
+
+/// What one agent did and sensed in a step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentFrame {
+    pub agent: AgentId,
+    pub pose: Pose2D,
+    pub velocity: f32,
+    pub torque: f32,
+    pub beta: f32,
+    pub estimate: Option<Pose2D>,
+    /// Topics with features and a measurement so far, in the order the agent's sensors were added.
+    pub measurements: Vec<TopicFrame>,
+}
+
+/// The scene after one step, with agents in order of their ids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub time: SceneTime,
+    pub agents: Vec<AgentFrame>,
+}
+
+/// A run of a scene recorded step by step, kept in memory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GoldenTrace {
+    pub frames: Vec<TraceFrame>,
+}
+
+/// Largest absolute differences [GoldenTrace::compare] lets through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceTolerance {
+    /// In metres, for both poses and estimates.
+    pub position: f32,
+    /// In radians, for both poses and estimates.
+    pub heading: f32,
+    /// For velocity, torque and steering angle.
+    pub control: f32,
+    pub features: f32,
+    /// In seconds, for frame and measurement times.
+    pub time: f32,
+}
+
+impl TraceTolerance {
+    /// Bit-for-bit, for comparing runs of the same build on the same machine.
+    pub const EXACT: Self = Self {
+        position: 0.,
+        heading: 0.,
+        control: 0.,
+        features: 0.,
+        time: 0.,
+    };
+}
+
+impl Default for TraceTolerance {
+    /// Loose enough for floating point differences between platforms and compilers.
+    fn default() -> Self {
+        Self {
+            position: 1e-3,
+            heading: 1e-3,
+            control: 1e-3,
+            features: 1e-3,
+            time: 1e-4,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum TraceMismatch {
+    #[error("Expected {expected} frames but got {actual}")]
+    FrameCount { expected: usize, actual: usize },
+
+    #[error("Frame {frame}: expected agents {expected:?} but got {actual:?}")]
+    Agents {
+        frame: usize,
+        expected: Vec<AgentId>,
+        actual: Vec<AgentId>,
+    },
+
+    #[error("Frame {frame}, {agent:?}: expected topics {expected:?} but got {actual:?}")]
+    Topics {
+        frame: usize,
+        agent: AgentId,
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+
+    #[error(
+        "Frame {frame}, {agent:?}: {field} is {actual} but expected {expected}, more than {tolerance} off"
+    )]
+    Value {
+        frame: usize,
+        agent: Option<AgentId>,
+        field: String,
+        expected: f32,
+        actual: f32,
+        tolerance: f32,
+    },
+}
+
+impl GoldenTrace {
+    /// Steps `scene` by `dt` for `steps` steps, recording every agent after each one. The scene is
+    /// [deterministic](Scene2D::set_deterministic) with `seed` for the run, so a scene built the same way gives the same
+    /// trace as long as its controllers and localizers draw any randomness from [crate::rng::rng].
+    pub fn capture(scene: &mut Scene2D, seed: u64, steps: usize, dt: f32) -> Self {
+        let previous = (scene.ordered, scene.scene_loop.seed());
+        scene.set_deterministic(Some(seed));
+
+        let mut trace = Self::default();
+        for _ in 0..steps {
+            scene.update(dt);
+            trace.record(scene);
+        }
+
+        scene.ordered = previous.0;
+        scene.scene_loop.set_seed(previous.1);
+        trace
+    }
+
+    /// Captures a run of a scene from `build` in a thread pool of each size in `threads`, checking every trace is
+    /// bit-identical to the first. Returns that trace, or the first difference found.
+    pub fn capture_across_threads(
+        build: impl Fn() -> Scene2D + Sync,
+        seed: u64,
+        steps: usize,
+        dt: f32,
+        threads: &[usize],
+    ) -> Result<Self, TraceMismatch> {
+        let capture = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("A thread pool of any size can be built")
+                .install(|| Self::capture(&mut build(), seed, steps, dt))
+        };
+
+        let Some((&first, rest)) = threads.split_first() else {
+            return Ok(Self::default());
+        };
+        let golden = capture(first);
+        for &threads in rest {
+            golden.compare(&capture(threads), &TraceTolerance::EXACT)?;
+        }
+
+        Ok(golden)
+    }
+
+    /// Appends the current state of `scene`, for runs stepped by hand.
+    pub fn record(&mut self, scene: &Scene2D) {
+        let mut agents = scene
+            .agents
+            .iter()
+            .map(|(&id, agent)| {
+                let measurements = agent
+                    .sensors
+                    .iter()
+                    .filter_map(|entry| {
+                        let sensor = entry.sensor.read();
+                        if sensor.feature_len() == 0 {
+                            return None;
+                        }
+
+                        let measurement = scene.scene_loop.query_topic(id, &entry.name)?;
+                        let mut features = Vec::with_capacity(sensor.feature_len());
+                        sensor.features_any(&measurement.state, &mut features);
+
+                        Some(TopicFrame {
+                            topic: entry.name.clone(),
+                            time: measurement.time,
+                            features,
+                        })
+                    })
+                    .collect();
+
+                AgentFrame {
+                    agent: id,
+                    pose: agent.state.pose(),
+                    velocity: agent.state.velocity,
+                    torque: agent.state.torque,
+                    beta: agent.state.beta,
+                    estimate: agent.estimate.map(|e| e.pose),
+                    measurements,
+                }
+            })
+            .collect::<Vec<_>>();
+        agents.sort_by_key(|a| a.agent);
+
+        self.frames.push(TraceFrame {
+            time: scene.time,
+            agents,
+        });
+    }
+
+    /// Checks `actual` against this trace, returning the first difference larger than `tolerance`.
+    pub fn compare(&self, actual: &Self, tolerance: &TraceTolerance) -> Result<(), TraceMismatch> {
+        if self.frames.len() != actual.frames.len() {
+            return Err(TraceMismatch::FrameCount {
+                expected: self.frames.len(),
+                actual: actual.frames.len(),
+            });
+        }
+
+        for (frame, (expected, actual)) in self.frames.iter().zip(&actual.frames).enumerate() {
+            let check = |agent: Option<AgentId>,
+                         field: &str,
+                         expected: f32,
+                         actual: f32,
+                         tolerance: f32| {
+                // Equal infinities, e.g. lidar misses, match without a tolerance.
+                if expected == actual || (expected - actual).abs() <= tolerance {
+                    Ok(())
+                } else {
+                    Err(TraceMismatch::Value {
+                        frame,
+                        agent,
+                        field: field.to_string(),
+                        expected,
+                        actual,
+                        tolerance,
+                    })
+                }
+            };
+
+            check(None, "time", expected.time.0, actual.time.0, tolerance.time)?;
+
+            let ids = |frame: &TraceFrame| frame.agents.iter().map(|a| a.agent).collect::<Vec<_>>();
+            if ids(expected) != ids(actual) {
+                return Err(TraceMismatch::Agents {
+                    frame,
+                    expected: ids(expected),
+                    actual: ids(actual),
+                });
+            }
+
+            for (expected, actual) in expected.agents.iter().zip(&actual.agents) {
+                let agent = Some(expected.agent);
+                let check_pose = |name: &str, expected: Pose2D, actual: Pose2D| {
+                    check(
+                        agent,
+                        &format!("{name} x"),
+                        expected.position.x,
+                        actual.position.x,
+                        tolerance.position,
+                    )?;
+                    check(
+                        agent,
+                        &format!("{name} y"),
+                        expected.position.y,
+                        actual.position.y,
+                        tolerance.position,
+                    )?;
+                    // Unwrapped around the expected angle, so headings either side of ±π compare as close.
+                    check(
+                        agent,
+                        &format!("{name} angle"),
+                        expected.angle(),
+                        expected.angle() + expected.heading.angle_to(actual.heading),
+                        tolerance.heading,
+                    )
+                };
+
+                check_pose("pose", expected.pose, actual.pose)?;
+                match (expected.estimate, actual.estimate) {
+                    (Some(e), Some(a)) => check_pose("estimate", e, a)?,
+                    (None, None) => {}
+                    (e, a) => check(
+                        agent,
+                        "estimate presence",
+                        e.is_some() as u8 as f32,
+                        a.is_some() as u8 as f32,
+                        0.,
+                    )?,
+                }
+                check(
+                    agent,
+                    "velocity",
+                    expected.velocity,
+                    actual.velocity,
+                    tolerance.control,
+                )?;
+                check(
+                    agent,
+                    "torque",
+                    expected.torque,
+                    actual.torque,
+                    tolerance.control,
+                )?;
+                check(agent, "beta", expected.beta, actual.beta, tolerance.control)?;
+
+                let topics = |frame: &AgentFrame| {
+                    frame
+                        .measurements
+                        .iter()
+                        .map(|m| m.topic.clone())
+                        .collect::<Vec<_>>()
+                };
+                let lengths = |frame: &AgentFrame| {
+                    frame
+                        .measurements
+                        .iter()
+                        .map(|m| m.features.len())
+                        .collect::<Vec<_>>()
+                };
+                if topics(expected) != topics(actual) || lengths(expected) != lengths(actual) {
+                    return Err(TraceMismatch::Topics {
+                        frame,
+                        agent: expected.agent,
+                        expected: topics(expected),
+                        actual: topics(actual),
+                    });
+                }
+
+                for (expected, actual) in expected.measurements.iter().zip(&actual.measurements) {
+                    check(
+                        agent,
+                        &format!("{} time", expected.topic),
+                        expected.time.0,
+                        actual.time.0,
+                        tolerance.time,
+                    )?;
+                    for (i, (&e, &a)) in expected.features.iter().zip(&actual.features).enumerate()
+                    {
+                        check(
+                            agent,
+                            &format!("{} feature {i}", expected.topic),
+                            e,
+                            a,
+                            tolerance.features,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Lidar2D, Scene2D,
+        controller::{AgentController, ControlContext, ControlInput},
+        golden::{GoldenTrace, TraceMismatch, TraceTolerance},
+        sensors::lidar::Lidar2DSensed,
+    };
+
+    /// Steers away from whichever side its lidar sees closer walls on.
+    #[derive(Debug)]
+    struct Wanderer;
+
+    impl AgentController for Wanderer {
+        fn control(&mut self, ctx: &ControlContext) -> ControlInput {
+            let Some(scan) = ctx.measurement::<Lidar2DSensed>("lidar") else {
+                return ControlInput::default();
+            };
+            let half = scan.state.ranges.len() / 2;
+            let side = |r: &[f32]| r.iter().map(|r| r.min(10.)).sum::<f32>();
+
+            ControlInput {
+                torque: 1.,
+                beta: 0.1
+                    * (side(&scan.state.ranges[..half]) - side(&scan.state.ranges[half..]))
+                        .signum(),
+            }
+        }
+    }
+
+    fn scene() -> Scene2D {
+        let mut scene = Scene2D::from_pixels([16, 16], &[255; 256]).unwrap();
+        for x in [-3., 3.] {
+            let mut agent = Agent2D::default();
+            agent.state.position = glam::vec2(x, 0.);
+            agent.sensors.insert("lidar", Lidar2D::regular(16));
+            agent.sensors.get_mut("lidar").unwrap().jitter = 0.01;
+            agent.controller = Some(Arc::new(Mutex::new(Wanderer)));
+            scene.add_agent(agent);
+        }
+
+        scene
+    }
+
+    fn run(seed: u64) -> GoldenTrace {
+        GoldenTrace::capture(&mut scene(), seed, 20, 0.05)
+    }
+
+    #[test]
+    fn test_golden_trace() {
+        let golden = run(7);
+        assert_eq!(golden.frames.len(), 20);
+        assert!(!golden.frames[0].agents[0].measurements.is_empty());
+
+        assert_eq!(golden.compare(&run(7), &TraceTolerance::EXACT), Ok(()));
+        assert!(matches!(
+            golden.compare(&run(8), &TraceTolerance::EXACT),
+            Err(TraceMismatch::Value { .. })
+        ));
+
+        let mut moved = golden.clone();
+        moved.frames[5].agents[0].pose.position.x += 1e-4;
+        assert!(golden.compare(&moved, &TraceTolerance::default()).is_ok());
+        moved.frames[5].agents[0].pose.position.x += 1.;
+        assert!(matches!(
+            golden.compare(&moved, &TraceTolerance::default()),
+            Err(TraceMismatch::Value { frame: 5, .. })
+        ));
+
+        let mut short = golden.clone();
+        short.frames.pop();
+        assert_eq!(
+            golden.compare(&short, &TraceTolerance::default()),
+            Err(TraceMismatch::FrameCount {
+                expected: 20,
+                actual: 19
+            })
+        );
+
+        let across = GoldenTrace::capture_across_threads(scene, 7, 20, 0.05, &[1, 2, 8]).unwrap();
+        assert_eq!(across, golden);
+    }
+}
@@ -1,9 +1,38 @@
 pub mod scene;
 pub mod sensors;
 pub mod agent;
+pub mod actuator;
+pub mod battery;
 pub mod math;
 pub mod bvh;
+pub mod change;
+pub mod controller;
+pub mod dynamics;
+pub mod coverage;
+pub mod exploration;
+pub mod control;
+pub mod plugin;
+pub mod safety;
+pub mod config;
+pub mod localization;
+pub mod logging;
+pub mod mapping;
+pub mod planning;
+pub mod raster;
+pub mod rng;
+pub mod sim_config;
+pub mod slam;
+pub mod experiment;
+pub mod golden;
+pub mod prelude;
+pub mod env;
+pub mod curriculum;
+pub mod replay;
+pub mod trailer;
+#[cfg(test)]
+mod testing;
 
 pub use scene::Scene2D;
 pub use agent::Agent2D;
 pub use sensors::lidar::Lidar2D;
+pub use sim_config::SimConfig;
@@ -0,0 +1,748 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::{
+    Lidar2D,
+    agent::Agent2DConfig,
+    config::{self, ConfigError, Validate},
+    logging::AgentLogger,
+    mapping::LikelihoodField,
+    math::Pose2D,
+    rng,
+    scene::{AgentId, SceneTime, occupancy_map::OccupancyMap, scene_loop::Scene2DLoop},
+    sensors::{Sensor2D, TimeStamped, lidar::Lidar2DSensed},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseEstimate {
+    pub time: SceneTime,
+    pub pose: Pose2D,
+    /// Covariance of `(x, y, angle)`.
+    pub covariance: glam::Mat3,
+}
+
+/// Which pose an agent's controller sees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PoseSource {
+    #[default]
+    GroundTruth,
+    /// The agent's localizer output. Falls back to ground truth until the localizer produces its first estimate.
+    Estimated,
+}
+
+/// What the wheel and steering encoders report.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Odometry {
+    pub velocity: f32,
+    pub beta: f32,
+}
+
+/// Everything a localizer may look at. Deliberately excludes the true pose.
+pub struct LocalizerContext<'a> {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    pub dt: f32,
+    pub config: &'a Agent2DConfig,
+    pub odometry: Odometry,
+    pub scene_loop: &'a Scene2DLoop,
+    /// The static map, which localizers are allowed to know in advance.
+    pub map: &'a Arc<OccupancyMap>,
+    /// Records what the localizer was thinking, for replay alongside the scene.
+    pub log: &'a AgentLogger,
+}
+
+impl LocalizerContext<'_> {
+    pub fn measurement<T: Send + Sync + 'static>(
+        &self,
+        topic: &str,
+    ) -> Option<TimeStamped<Arc<T>>> {
+        self.scene_loop.query_topic_as(self.agent, topic)
+    }
+}
+
+pub trait Localizer: std::fmt::Debug + Send + Sync {
+    /// Seeds the estimate, e.g. with the pose the agent was spawned at.
+    fn reset(&mut self, pose: Pose2D);
+
+    /// Runs once per scene update, before the controller.
+    fn update(&mut self, ctx: &LocalizerContext) -> Option<PoseEstimate>;
+
+    /// A copy sharing no state with this localizer, for [Scene2DSnapshot]s to restore. `None` if it can't be copied,
+    /// in which case snapshots share it with the scene.
+    ///
+    /// [Scene2DSnapshot]: crate::scene::Scene2DSnapshot
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn Localizer>>> {
+        None
+    }
+}
+
+/// Accumulates wheel odometry or inertial readings into a pose, with a covariance that grows as the readings' errors
+/// add up. Feed it readings taken alongside a run to see how far odometry alone drifts from the ground truth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OdometryIntegrator {
+    /// Standard deviation of velocity readings, relative to the velocity.
+    pub velocity_noise: f32,
+    /// Standard deviation of steering readings in radians.
+    pub beta_noise: f32,
+    /// Standard deviation of yaw rate readings in radians per second.
+    pub yaw_rate_noise: f32,
+    pose: Pose2D,
+    covariance: glam::Mat3,
+}
+
+impl Default for OdometryIntegrator {
+    fn default() -> Self {
+        Self::new(Pose2D::default())
+    }
+}
+
+impl Validate for OdometryIntegrator {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (field, value) in [
+            ("velocity_noise", self.velocity_noise),
+            ("beta_noise", self.beta_noise),
+            ("yaw_rate_noise", self.yaw_rate_noise),
+        ] {
+            config::finite(field, value)?;
+            config::non_negative(field, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OdometryIntegrator {
+    /// Starts at `pose` with no uncertainty and noiseless readings.
+    pub fn new(pose: Pose2D) -> Self {
+        Self {
+            velocity_noise: 0.,
+            beta_noise: 0.,
+            yaw_rate_noise: 0.,
+            pose,
+            covariance: glam::Mat3::ZERO,
+        }
+    }
+
+    pub fn reset(&mut self, pose: Pose2D) {
+        self.pose = pose;
+        self.covariance = glam::Mat3::ZERO;
+    }
+
+    pub fn pose(&self) -> Pose2D {
+        self.pose
+    }
+
+    /// Covariance of `(x, y, angle)`.
+    pub fn covariance(&self) -> glam::Mat3 {
+        self.covariance
+    }
+
+    pub fn estimate(&self, time: SceneTime) -> PoseEstimate {
+        PoseEstimate {
+            time,
+            pose: self.pose,
+            covariance: self.covariance,
+        }
+    }
+
+    /// Advances through the bicycle model of a vehicle `length` long.
+    pub fn integrate_wheel(&mut self, odometry: Odometry, length: f32, dt: f32) {
+        let Odometry { velocity, beta } = odometry;
+        let d_beta = glam::vec3(0., 0., velocity / (length * beta.cos().powi(2)) * dt);
+
+        self.propagate(
+            velocity,
+            velocity * beta.tan() / length,
+            dt,
+            beta.tan() / length * dt,
+            (d_beta, self.beta_noise),
+        );
+    }
+
+    /// Advances by a forward velocity and yaw rate, e.g. from an IMU.
+    pub fn integrate_inertial(&mut self, velocity: f32, yaw_rate: f32, dt: f32) {
+        self.propagate(
+            velocity,
+            yaw_rate,
+            dt,
+            0.,
+            (glam::vec3(0., 0., dt), self.yaw_rate_noise),
+        );
+    }
+
+    /// `d_turn` is how much the turn depends on the velocity reading, and `turn_noise` the derivative of the step with
+    /// respect to the other reading along with that reading's standard deviation.
+    fn propagate(
+        &mut self,
+        velocity: f32,
+        yaw_rate: f32,
+        dt: f32,
+        d_turn: f32,
+        turn_noise: (glam::Vec3, f32),
+    ) {
+        let velocity_std = self.velocity_noise * velocity.abs();
+        let (sin, cos) = (self.pose.heading.y, self.pose.heading.x);
+
+        // Linearized propagation of the covariance through the motion model.
+        let jacobian = glam::Mat3::from_cols(
+            glam::Vec3::X,
+            glam::Vec3::Y,
+            glam::vec3(-velocity * sin * dt, velocity * cos * dt, 1.),
+        );
+        let d_velocity = glam::vec3(cos * dt, sin * dt, d_turn);
+        self.covariance = jacobian * self.covariance * jacobian.transpose()
+            + outer(d_velocity) * velocity_std.powi(2)
+            + outer(turn_noise.0) * turn_noise.1.powi(2);
+
+        self.pose.position += self.pose.heading * velocity * dt;
+        self.pose.heading = glam::Vec2::from_angle(yaw_rate * dt)
+            .rotate(self.pose.heading)
+            .normalize_or(glam::Vec2::X);
+    }
+}
+
+/// Integrates noisy odometry through the bicycle model, so the estimate drifts away from the true pose.
+#[derive(Debug, Clone, Default)]
+pub struct DeadReckoning {
+    /// Standard deviation of the velocity reading, relative to the velocity.
+    pub velocity_noise: f32,
+    /// Standard deviation of the steering reading in radians.
+    pub beta_noise: f32,
+    integrator: Option<OdometryIntegrator>,
+}
+
+impl DeadReckoning {
+    pub fn new(velocity_noise: f32, beta_noise: f32) -> Self {
+        Self {
+            velocity_noise,
+            beta_noise,
+            integrator: None,
+        }
+    }
+}
+
+impl Validate for DeadReckoning {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("velocity_noise", self.velocity_noise)?;
+        config::non_negative("velocity_noise", self.velocity_noise)?;
+        config::finite("beta_noise", self.beta_noise)?;
+        config::non_negative("beta_noise", self.beta_noise)
+    }
+}
+
+pub(crate) fn outer(v: glam::Vec3) -> glam::Mat3 {
+    glam::Mat3::from_cols(v * v.x, v * v.y, v * v.z)
+}
+
+impl Localizer for DeadReckoning {
+    fn reset(&mut self, pose: Pose2D) {
+        self.integrator = Some(OdometryIntegrator::new(pose));
+    }
+
+    fn update(&mut self, ctx: &LocalizerContext) -> Option<PoseEstimate> {
+        let integrator = self.integrator.as_mut()?;
+        integrator.velocity_noise = self.velocity_noise;
+        integrator.beta_noise = self.beta_noise;
+
+        let sample = |std_dev: f32| {
+            Normal::new(0., std_dev)
+                .map(|n| n.sample(&mut rng::rng()))
+                .unwrap_or(0.)
+        };
+        let odometry = Odometry {
+            velocity: ctx.odometry.velocity
+                + sample(self.velocity_noise * ctx.odometry.velocity.abs()),
+            beta: ctx.odometry.beta + sample(self.beta_noise),
+        };
+        integrator.integrate_wheel(odometry, ctx.config.length, ctx.dt);
+
+        Some(integrator.estimate(ctx.time))
+    }
+
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn Localizer>>> {
+        Some(Arc::new(Mutex::new(self.clone())))
+    }
+}
+
+/// A group of nearby particles: one of the hypotheses a [ParticleFilter] holds when it can't tell places apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleCluster {
+    /// Weighted mean position of the particles.
+    pub position: glam::Vec2,
+    /// Total weight of the particles, out of one.
+    pub weight: f32,
+    pub count: usize,
+}
+
+/// Monte Carlo localization: a cloud of pose hypotheses moved by noisy odometry and weighted by how well lidar scans
+/// fit the [map](LocalizerContext::map) under a likelihood-field model.
+#[derive(Debug, Clone)]
+pub struct ParticleFilter {
+    pub particle_count: usize,
+    /// Standard deviation of the velocity reading, relative to the velocity.
+    pub velocity_noise: f32,
+    /// Standard deviation of the steering reading in radians.
+    pub beta_noise: f32,
+    /// Standard deviation of the distance from a range reading's end point to the nearest wall, in metres.
+    pub hit_sigma: f32,
+    /// Share of range readings explained as random clutter rather than walls, so outliers don't wipe out particles.
+    pub random_weight: f32,
+    /// At most this many rays, evenly spread through the scan, are used per update.
+    pub max_beams: usize,
+    /// Standard deviations of the position in metres and heading in radians particles are spread by on reset.
+    pub initial_spread: (f32, f32),
+    pub lidar_topic: String,
+    /// The lidar's ray directions relative to the heading, `None` to assume [Lidar2D::regular] with as many rays as
+    /// each scan has.
+    pub directions: Option<Vec<glam::Vec2>>,
+    particles: Vec<Pose2D>,
+    weights: Vec<f32>,
+    field: Option<(Arc<OccupancyMap>, Arc<LikelihoodField>)>,
+    last_scan: Option<SceneTime>,
+}
+
+impl Default for ParticleFilter {
+    fn default() -> Self {
+        Self {
+            particle_count: 500,
+            velocity_noise: 0.1,
+            beta_noise: 0.05,
+            hit_sigma: 0.2,
+            random_weight: 0.1,
+            max_beams: 30,
+            initial_spread: (0.2, 0.05),
+            lidar_topic: Lidar2D::TOPIC.to_string(),
+            directions: None,
+            particles: Vec::new(),
+            weights: Vec::new(),
+            field: None,
+            last_scan: None,
+        }
+    }
+}
+
+impl Validate for ParticleFilter {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("particle_count", self.particle_count, 1)?;
+        config::at_least("max_beams", self.max_beams, 1)?;
+        for (field, value) in [
+            ("velocity_noise", self.velocity_noise),
+            ("beta_noise", self.beta_noise),
+            ("initial_spread.0", self.initial_spread.0),
+            ("initial_spread.1", self.initial_spread.1),
+        ] {
+            config::finite(field, value)?;
+            config::non_negative(field, value)?;
+        }
+        config::positive("hit_sigma", self.hit_sigma)?;
+        config::within(
+            "random_weight",
+            self.random_weight,
+            self.random_weight > 0. && self.random_weight <= 1.,
+            "(0, 1]",
+        )?;
+
+        match &self.directions {
+            Some(directions) => directions
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, &d)| config::direction(&format!("directions[{i}]"), d)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ParticleFilter {
+    pub fn particles(&self) -> &[Pose2D] {
+        &self.particles
+    }
+
+    /// One per particle, summing to one.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Groups the particles around the heaviest ones, each joining the first group whose leading particle is within
+    /// `radius` metres. Heaviest group first.
+    pub fn clusters(&self, radius: f32) -> Vec<ParticleCluster> {
+        let mut order = (0..self.particles.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| self.weights[b].total_cmp(&self.weights[a]));
+
+        // Leading position, weighted position sum and the cluster so far.
+        let mut clusters: Vec<(glam::Vec2, glam::Vec2, ParticleCluster)> = Vec::new();
+        for i in order {
+            let (position, weight) = (self.particles[i].position, self.weights[i]);
+            match clusters
+                .iter_mut()
+                .find(|(leader, ..)| leader.distance(position) <= radius)
+            {
+                Some((_, sum, cluster)) => {
+                    *sum += position * weight;
+                    cluster.weight += weight;
+                    cluster.count += 1;
+                }
+                None => clusters.push((
+                    position,
+                    position * weight,
+                    ParticleCluster {
+                        position,
+                        weight,
+                        count: 1,
+                    },
+                )),
+            }
+        }
+
+        // The leaders were heaviest first, but the totals needn't be.
+        let mut clusters = clusters
+            .into_iter()
+            .map(|(leader, sum, cluster)| ParticleCluster {
+                position: if cluster.weight > 0. {
+                    sum / cluster.weight
+                } else {
+                    leader
+                },
+                ..cluster
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+        clusters
+    }
+
+    /// Number of [clusters](Self::clusters) holding at least `min_weight` of the total weight, 1 once the filter has
+    /// settled on a single pose.
+    pub fn modes(&self, radius: f32, min_weight: f32) -> usize {
+        self.clusters(radius)
+            .iter()
+            .filter(|c| c.weight >= min_weight)
+            .count()
+    }
+
+    fn field(&mut self, map: &Arc<OccupancyMap>) -> Arc<LikelihoodField> {
+        match &self.field {
+            Some((cached, field))
+                if Arc::ptr_eq(cached, map) && field.sigma() == self.hit_sigma =>
+            {
+                Arc::clone(field)
+            }
+            _ => {
+                // Fine enough that the sampling error is small next to the sensor model.
+                let resolution = (self.hit_sigma / 2.).min(0.25);
+                let field = Arc::new(map.likelihood_field(resolution, self.hit_sigma));
+                self.field = Some((Arc::clone(map), Arc::clone(&field)));
+                field
+            }
+        }
+    }
+
+    fn weigh(&mut self, scan: &Lidar2DSensed, field: &LikelihoodField) {
+        let regular;
+        let directions = match &self.directions {
+            Some(directions) => directions,
+            None => {
+                regular = Lidar2D::regular(scan.ranges.len()).directions;
+                &regular
+            }
+        };
+        let stride = scan.ranges.len().div_ceil(self.max_beams).max(1);
+        let beams = directions
+            .iter()
+            .zip(&scan.ranges)
+            .step_by(stride)
+            .filter(|(_, range)| range.is_finite())
+            .map(|(&dir, &range)| dir * range)
+            .collect::<Vec<_>>();
+        if beams.is_empty() {
+            return;
+        }
+
+        // Log-likelihoods, shifted by the largest before exponentiating so the weights don't underflow.
+        let log_likelihoods = self
+            .particles
+            .iter()
+            .map(|pose| {
+                beams
+                    .iter()
+                    .map(|&beam| {
+                        let hit = field.lookup(pose.transform_point(beam));
+                        ((1. - self.random_weight) * hit + self.random_weight).ln()
+                    })
+                    .sum::<f32>()
+            })
+            .collect::<Vec<_>>();
+        let max = log_likelihoods
+            .iter()
+            .copied()
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        for (weight, log_likelihood) in self.weights.iter_mut().zip(log_likelihoods) {
+            *weight *= (log_likelihood - max).exp();
+        }
+        let total: f32 = self.weights.iter().sum();
+        let n = self.weights.len() as f32;
+        self.weights
+            .iter_mut()
+            .for_each(|w| *w = if total > 0. { *w / total } else { 1. / n });
+
+        let effective = self.weights.iter().map(|w| w * w).sum::<f32>().recip();
+        if effective < n / 2. {
+            self.resample();
+        }
+    }
+
+    /// Low-variance resampling, which keeps particles in proportion to their weights with little added noise.
+    fn resample(&mut self) {
+        let n = self.particles.len();
+        let step = 1. / n as f32;
+        let mut target = rng::rng().random_range(0. ..step);
+        let mut cumulative = 0.;
+
+        let mut resampled = Vec::with_capacity(n);
+        for (particle, &weight) in self.particles.iter().zip(&self.weights) {
+            cumulative += weight;
+            while target < cumulative && resampled.len() < n {
+                resampled.push(*particle);
+                target += step;
+            }
+        }
+        // Rounding can leave the cumulative weight just short of one.
+        while resampled.len() < n {
+            resampled.push(self.particles[n - 1]);
+        }
+
+        self.particles = resampled;
+        self.weights = vec![step; n];
+    }
+
+    fn estimate(&self, time: SceneTime) -> PoseEstimate {
+        let weighted = || self.particles.iter().zip(self.weights.iter().copied());
+        let position = weighted().map(|(p, w)| p.position * w).sum::<glam::Vec2>();
+        let heading = weighted()
+            .map(|(p, w)| p.heading * w)
+            .sum::<glam::Vec2>()
+            .normalize_or(glam::Vec2::X);
+
+        let covariance = weighted()
+            .map(|(p, w)| {
+                let d = p.position - position;
+                outer(glam::vec3(d.x, d.y, heading.angle_to(p.heading))) * w
+            })
+            .fold(glam::Mat3::ZERO, |a, b| a + b);
+
+        PoseEstimate {
+            time,
+            pose: Pose2D { position, heading },
+            covariance,
+        }
+    }
+}
+
+impl Localizer for ParticleFilter {
+    fn reset(&mut self, pose: Pose2D) {
+        let (position_std, heading_std) = self.initial_spread;
+        let position = Normal::new(0., position_std).ok();
+        let heading = Normal::new(0., heading_std).ok();
+        let mut rng = rng::rng();
+        let mut sample = |n: Option<Normal<f32>>| n.map_or(0., |n| n.sample(&mut rng));
+
+        self.particles = (0..self.particle_count.max(1))
+            .map(|_| {
+                Pose2D::new(
+                    pose.position + glam::vec2(sample(position), sample(position)),
+                    pose.angle() + sample(heading),
+                )
+            })
+            .collect();
+        self.weights = vec![1. / self.particles.len() as f32; self.particles.len()];
+        self.last_scan = None;
+    }
+
+    fn update(&mut self, ctx: &LocalizerContext) -> Option<PoseEstimate> {
+        if self.particles.is_empty() {
+            return None;
+        }
+
+        let velocity_std = self.velocity_noise * ctx.odometry.velocity.abs();
+        let velocity = Normal::new(ctx.odometry.velocity, velocity_std).ok();
+        let beta = Normal::new(ctx.odometry.beta, self.beta_noise).ok();
+        let mut rng = rng::rng();
+        let length = ctx.config.length;
+        for particle in &mut self.particles {
+            let v = velocity.map_or(ctx.odometry.velocity, |n| n.sample(&mut rng));
+            let b = beta.map_or(ctx.odometry.beta, |n| n.sample(&mut rng));
+
+            particle.position += particle.heading * v * ctx.dt;
+            particle.heading = glam::Vec2::from_angle(v * b.tan() / length * ctx.dt)
+                .rotate(particle.heading)
+                .normalize_or(glam::Vec2::X);
+        }
+
+        if let Some(scan) = ctx.measurement::<Lidar2DSensed>(&self.lidar_topic)
+            && self.last_scan.is_none_or(|last| last.0 < scan.time.0)
+        {
+            self.last_scan = Some(scan.time);
+            let field = self.field(ctx.map);
+            self.weigh(&scan.state, &field);
+        }
+
+        Some(self.estimate(ctx.time))
+    }
+
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn Localizer>>> {
+        Some(Arc::new(Mutex::new(self.clone())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Lidar2D, Scene2D,
+        experiment::ModalityTrace,
+        localization::{Localizer, Odometry, OdometryIntegrator, ParticleFilter},
+        math::Pose2D,
+        scene::generate,
+        sensors::Sensor2D,
+    };
+
+    #[test]
+    fn test_particle_filter() {
+        let size = [24, 24];
+        let mut scene = Scene2D::from_pixels(size, &generate::rooms(size, [2, 2], 1, 4)).unwrap();
+
+        let mut agent = Agent2D::default();
+        agent.state = agent
+            .state
+            .with_pose(Pose2D::new(glam::vec2(-6., -6.), 0.3));
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(60);
+        }
+        let filter = ParticleFilter {
+            particle_count: 200,
+            initial_spread: (0.5, 0.1),
+            ..Default::default()
+        };
+        agent.localizer = Some(Arc::new(Mutex::new(filter)) as Arc<Mutex<dyn Localizer>>);
+        let id = scene.add_agent(agent);
+
+        for _ in 0..20 {
+            scene.agents.get_mut(&id).unwrap().state.torque = 1.;
+            scene.update(0.05);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let agent = &scene.agents[&id];
+        let estimate = agent.estimate.unwrap();
+        assert!(
+            estimate.pose.position.distance(agent.state.position) < 0.3,
+            "{estimate:?} vs {:?}",
+            agent.state.pose()
+        );
+        assert!(estimate.pose.heading.angle_to(agent.state.heading).abs() < 0.1);
+        assert!(estimate.covariance.x_axis.x < 0.25);
+    }
+
+    #[test]
+    fn test_perceptual_aliasing() {
+        let size = [40, 24];
+        let mut scene = Scene2D::from_pixels(size, &generate::hallway(size, 4, 4, 1, 2)).unwrap();
+
+        // In the second of four identical rooms along the top of the hallway.
+        let truth = Pose2D::new(glam::vec2(-4.5, 6.), 0.);
+        let mut agent = Agent2D::default();
+        agent.state = agent.state.with_pose(truth);
+        if let Some(mut lidar) = agent.sensors.write_as::<Lidar2D>(Lidar2D::TOPIC) {
+            lidar.set_regular(60);
+        }
+        let filter = Arc::new(Mutex::new(ParticleFilter {
+            particle_count: 200,
+            ..Default::default()
+        }));
+        agent.localizer = Some(Arc::clone(&filter) as Arc<Mutex<dyn Localizer>>);
+        scene.add_agent(agent);
+
+        // Half the particles in the right room and a copy of them in the one next door.
+        let mut guard = filter.lock();
+        let half = guard.particles.len() / 2;
+        for i in 0..half {
+            guard.particles[half + i] = guard.particles[i];
+            guard.particles[half + i].position.x += 10.;
+        }
+        drop(guard);
+
+        let mut trace = ModalityTrace::default();
+        for _ in 0..10 {
+            scene.update(0.05);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            trace.record(scene.time, &filter.lock());
+        }
+
+        // The scans can't tell the rooms apart, so neither hypothesis wins.
+        let clusters = filter.lock().clusters(1.);
+        assert_eq!(clusters.len(), 2, "{clusters:?}");
+        assert!(clusters.iter().all(|c| c.weight > 0.2), "{clusters:?}");
+        assert!(
+            clusters[0].position.distance(truth.position) < 0.5
+                || clusters[1].position.distance(truth.position) < 0.5
+        );
+        assert_eq!(trace.max_modes(), 2);
+        assert_eq!(trace.ambiguous_fraction(), 1.);
+        assert_eq!(trace.resolved_at(), None);
+
+        filter.lock().reset(truth);
+        trace.record(scene.time, &filter.lock());
+        assert_eq!(trace.resolved_at(), Some(scene.time));
+    }
+
+    #[test]
+    fn test_odometry_integrator() {
+        let mut imu = OdometryIntegrator {
+            velocity_noise: 0.05,
+            yaw_rate_noise: 0.01,
+            ..Default::default()
+        };
+        // A full circle of radius 2 brings the pose back to the start, more uncertain the further it went.
+        let steps = 1000;
+        let dt = std::f32::consts::TAU / steps as f32;
+        let mut trace = Vec::new();
+        for _ in 0..steps {
+            imu.integrate_inertial(2., 1., dt);
+            trace.push(imu.covariance().x_axis.x + imu.covariance().y_axis.y);
+        }
+        assert!(imu.pose().position.length() < 0.02, "{:?}", imu.pose());
+        assert!(imu.pose().heading.angle_to(glam::Vec2::X).abs() < 1e-3);
+        assert!(trace.windows(2).all(|w| w[1] >= w[0]));
+        assert!(
+            (imu.covariance().z_axis.z - 0.01f32.powi(2) * std::f32::consts::TAU * dt).abs() < 1e-6
+        );
+
+        // Steering for the same yaw rate traces the same circle.
+        let length = 2.;
+        let mut wheel = OdometryIntegrator::default();
+        for _ in 0..steps / 4 {
+            wheel.integrate_wheel(
+                Odometry {
+                    velocity: 2.,
+                    beta: (length / 2f32).atan(),
+                },
+                length,
+                dt,
+            );
+        }
+        assert!(
+            wheel.pose().position.distance(glam::vec2(2., 2.)) < 0.02,
+            "{:?}",
+            wheel.pose()
+        );
+        assert_eq!(wheel.covariance(), glam::Mat3::ZERO);
+    }
+}
@@ -0,0 +1,147 @@
+//! Per-agent logging. Controllers and localizers log through the [AgentLogger] in their context, which stamps each
+//! record with the agent and scene time, forwards it to the [log] crate under a target naming both, and keeps it so
+//! [SceneHistory](crate::scene::SceneHistory) can replay it alongside the scene.
+
+use parking_lot::Mutex;
+
+use crate::scene::{AgentId, SceneTime};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    pub level: log::Level,
+    /// Which part of the agent logged it, e.g. `controller` or `localizer`.
+    pub source: &'static str,
+    pub message: String,
+}
+
+/// Logger for one part of one agent during one scene update.
+#[derive(Debug)]
+pub struct AgentLogger {
+    agent: AgentId,
+    time: SceneTime,
+    source: &'static str,
+    target: String,
+    records: Mutex<Vec<LogRecord>>,
+}
+
+impl AgentLogger {
+    pub fn new(agent: AgentId, time: SceneTime, source: &'static str) -> Self {
+        Self {
+            agent,
+            time,
+            source,
+            target: format!("{agent}::{source}"),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn log(&self, level: log::Level, message: impl std::fmt::Display) {
+        let message = message.to_string();
+        log::log!(target: &self.target, level, "[{:.3}s] {message}", self.time.0);
+
+        self.records.lock().push(LogRecord {
+            agent: self.agent,
+            time: self.time,
+            level,
+            source: self.source,
+            message,
+        });
+    }
+
+    pub fn error(&self, message: impl std::fmt::Display) {
+        self.log(log::Level::Error, message);
+    }
+
+    pub fn warn(&self, message: impl std::fmt::Display) {
+        self.log(log::Level::Warn, message);
+    }
+
+    pub fn info(&self, message: impl std::fmt::Display) {
+        self.log(log::Level::Info, message);
+    }
+
+    pub fn debug(&self, message: impl std::fmt::Display) {
+        self.log(log::Level::Debug, message);
+    }
+
+    pub fn trace(&self, message: impl std::fmt::Display) {
+        self.log(log::Level::Trace, message);
+    }
+
+    /// The records logged so far, oldest first.
+    pub fn into_records(self) -> Vec<LogRecord> {
+        self.records.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Scene2D,
+        controller::{AgentController, ControlContext, ControlInput},
+        scene::SceneHistory,
+    };
+
+    #[derive(Debug)]
+    struct Chatty;
+
+    impl AgentController for Chatty {
+        fn control(&mut self, ctx: &ControlContext) -> ControlInput {
+            let speed = ctx.state.velocity;
+            ctx.log.info(format_args!("speed {speed:.1}"));
+            if ctx.time.0 > 0.35 {
+                ctx.log.warn("running late");
+            }
+
+            ControlInput {
+                torque: 1.,
+                beta: 0.,
+            }
+        }
+    }
+
+    #[test]
+    fn test_agent_logging() {
+        let mut scene = Scene2D::from_pixels([16, 16], &[255; 256]).unwrap();
+        let mut history = SceneHistory::default();
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            ids.push(scene.add_agent(Agent2D {
+                controller: Some(Arc::new(Mutex::new(Chatty))),
+                ..Default::default()
+            }));
+        }
+        // Logs nothing, so it doesn't show up.
+        scene.add_agent(Agent2D::default());
+
+        for _ in 0..10 {
+            scene.update(0.1);
+            history.record(&scene);
+        }
+
+        let latest = scene.logs();
+        assert_eq!(
+            latest.iter().map(|r| r.agent).collect::<Vec<_>>(),
+            [ids[0], ids[0], ids[1], ids[1]]
+        );
+        assert!(
+            latest
+                .iter()
+                .all(|r| r.time == scene.time && r.source == "controller")
+        );
+
+        let first = history.iter().next().unwrap();
+        assert_eq!(first.logs().len(), 2);
+        assert_eq!(first.logs()[0].message, "speed 0.0");
+        assert_eq!(history.logs().count(), 2 * 10 + 2 * 7);
+
+        history.rewind(&mut scene, 0.85);
+        assert!(scene.logs().iter().all(|r| r.time.0 < 0.3));
+    }
+}
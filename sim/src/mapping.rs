@@ -0,0 +1,716 @@
+use crate::{
+    Lidar2D,
+    config::{self, ConfigError, Validate},
+    math::Pose2D,
+    scene::{Scene2DError, occupancy_map::OccupancyMap},
+    sensors::lidar::Lidar2DSensed,
+};
+
+/// Occupancy grid built up from lidar scans, holding the log-odds that each cell is occupied. Cells line up with those
+/// of an [OccupancyMap] of the same size: one unit square each, with the map centred on the origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogOddsGrid {
+    pub size: glam::USizeVec2,
+    /// Row-major with the first row at the top, like [OccupancyMap::pixels]. Zero means unknown.
+    pub cells: Vec<f32>,
+    /// Added to the cell a ray ends in.
+    pub occupied_update: f32,
+    /// Added to every cell a ray passes through, so negative.
+    pub free_update: f32,
+    /// Log-odds are kept within `[-clamp, clamp]`, so the map can still change its mind.
+    pub clamp: f32,
+    /// Rays that hit nothing clear the cells up to this range.
+    pub max_range: f32,
+}
+
+impl Validate for LogOddsGrid {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("occupied_update", self.occupied_update)?;
+        config::finite("free_update", self.free_update)?;
+        config::within(
+            "free_update",
+            self.free_update,
+            self.free_update < 0.,
+            "< 0",
+        )?;
+        config::positive("clamp", self.clamp)?;
+        config::positive("max_range", self.max_range)
+    }
+}
+
+impl LogOddsGrid {
+    /// A grid of `size` cells, all unknown.
+    pub fn new(size: glam::USizeVec2) -> Self {
+        Self {
+            size,
+            cells: vec![0.; size.x * size.y],
+            occupied_update: 0.85,
+            free_update: -0.4,
+            clamp: 5.,
+            max_range: 10.,
+        }
+    }
+
+    /// A grid that is certain of every cell of `map`.
+    pub fn from_occupancy_map(map: &OccupancyMap) -> Self {
+        let mut grid = Self::new(map.size);
+        grid.cells = map
+            .pixels
+            .iter()
+            .map(|&occupied| if occupied { grid.clamp } else { -grid.clamp })
+            .collect();
+
+        grid
+    }
+
+    /// Cells more likely than `threshold` to be occupied become walls. Unknown cells are free for any threshold of at
+    /// least one half.
+    pub fn to_occupancy_map(&self, threshold: f32) -> Result<OccupancyMap, Scene2DError> {
+        let pixels = self
+            .cells
+            .iter()
+            .map(|&l| probability(l) > threshold)
+            .collect();
+
+        OccupancyMap::from_pixels(self.size, pixels)
+    }
+
+    /// Black for occupied, white for free and grey for unknown, so the image can be loaded back as a track.
+    pub fn to_image(&self) -> image::GrayImage {
+        let pixels = self
+            .cells
+            .iter()
+            .map(|&l| ((1. - probability(l)) * 255.).round() as u8)
+            .collect();
+
+        image::GrayImage::from_raw(self.size.x as u32, self.size.y as u32, pixels)
+            .expect("The grid has one cell per pixel")
+    }
+
+    /// Cell containing `loc`, if it lies on the grid.
+    pub fn cell(&self, loc: glam::Vec2) -> Option<glam::USizeVec2> {
+        let cell = self.grid_coords(loc).floor().as_i64vec2();
+        self.index(cell).map(|_| cell.as_usizevec2())
+    }
+
+    /// Probability that the cell containing `loc` is occupied, a half off the grid.
+    pub fn probability(&self, loc: glam::Vec2) -> f32 {
+        let cell = self.grid_coords(loc).floor().as_i64vec2();
+        self.index(cell).map_or(0.5, |i| probability(self.cells[i]))
+    }
+
+    /// Integrates a scan taken by `lidar` from `pose`. The pose needn't be the true one, which is what makes the grid
+    /// useful for evaluating localizers. Every ray is treated as fired from `pose`, ignoring any
+    /// [time offsets](Lidar2DSensed::time_offsets).
+    pub fn update(&mut self, lidar: &Lidar2D, scan: &Lidar2DSensed, pose: Pose2D) {
+        for (&dir, &range) in lidar.directions.iter().zip(&scan.ranges) {
+            let dir = pose.heading.rotate(dir);
+            let hit = range <= self.max_range;
+            let range = range.min(self.max_range);
+
+            // Nudge the end point off the boundary it lies on, into the cell that was hit.
+            self.trace(pose.position, pose.position + dir * (range + 1e-3), hit);
+        }
+    }
+
+    /// Continuous coordinates in which cell `(col, row)` covers `[col, col + 1) x [row, row + 1)`.
+    fn grid_coords(&self, loc: glam::Vec2) -> glam::Vec2 {
+        let half = self.size.as_vec2() / 2.;
+        glam::vec2(loc.x + half.x, half.y - loc.y)
+    }
+
+    fn index(&self, cell: glam::I64Vec2) -> Option<usize> {
+        let size = self.size.as_i64vec2();
+        (cell.cmpge(glam::I64Vec2::ZERO).all() && cell.cmplt(size).all())
+            .then(|| (cell.x + cell.y * size.x) as usize)
+    }
+
+    fn add(&mut self, cell: glam::I64Vec2, update: f32) {
+        if let Some(i) = self.index(cell) {
+            self.cells[i] = (self.cells[i] + update).clamp(-self.clamp, self.clamp);
+        }
+    }
+
+    /// Walks the cells between `from` and `to`, clearing all but the last, which is marked occupied if `hit`.
+    fn trace(&mut self, from: glam::Vec2, to: glam::Vec2, hit: bool) {
+        let start = self.grid_coords(from);
+        let end = self.grid_coords(to);
+        let delta = end - start;
+
+        let mut cell = start.floor().as_i64vec2();
+        let last = end.floor().as_i64vec2();
+        let step = glam::i64vec2(
+            if delta.x < 0. { -1 } else { 1 },
+            if delta.y < 0. { -1 } else { 1 },
+        );
+
+        // Distance along the ray, as a fraction of `delta`, to the next cell boundary on each axis.
+        let boundary = |start: f32, cell: i64, delta: f32| match delta {
+            0. => f32::INFINITY,
+            d if d > 0. => (cell as f32 + 1. - start) / d,
+            d => (start - cell as f32) / -d,
+        };
+        let mut t_max = glam::vec2(
+            boundary(start.x, cell.x, delta.x),
+            boundary(start.y, cell.y, delta.y),
+        );
+        let t_delta = delta.recip().abs();
+
+        for _ in 0..(last - cell).abs().element_sum() {
+            self.add(cell, self.free_update);
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+        }
+
+        let update = if hit {
+            self.occupied_update
+        } else {
+            self.free_update
+        };
+        self.add(last, update);
+    }
+}
+
+/// Distance from every point of a map to the nearest occupied cell, sampled on a grid finer than the map. The basis of
+/// likelihood-field sensor models, which score a range reading by how close its end point lands to a wall.
+#[derive(Debug, Clone)]
+pub struct LikelihoodField {
+    resolution: f32,
+    /// World position of the lower corner of cell `(0, 0)`.
+    origin: glam::Vec2,
+    size: glam::IVec2,
+    /// In metres, row-major from the bottom row up.
+    distances: Vec<f32>,
+    sigma: f32,
+    /// Same layout as `distances`.
+    likelihoods: Vec<f32>,
+}
+
+impl OccupancyMap {
+    /// Precomputes a [LikelihoodField] sampled every `resolution` metres, for measurement models that score many rays
+    /// per particle and can't afford a ray cast for each.
+    pub fn likelihood_field(&self, resolution: f32, sigma: f32) -> LikelihoodField {
+        LikelihoodField::new(self, resolution).with_sigma(sigma)
+    }
+
+    /// Signed distances to the nearest obstacle surface, clamped to `±truncation` metres.
+    pub fn to_esdf(&self, truncation: f32) -> Esdf {
+        Esdf::new(self, truncation)
+    }
+}
+
+impl LikelihoodField {
+    /// Samples the map every `resolution` metres, over the map and a cell beyond it, where the boundary walls are.
+    /// Likelihoods fall off over one sample until set [with_sigma](Self::with_sigma).
+    pub fn new(map: &OccupancyMap, resolution: f32) -> Self {
+        let extent = map.extent() + 2. * map.resolution;
+        let origin = map.origin - extent / 2.;
+        let size = (extent / resolution)
+            .ceil()
+            .as_ivec2()
+            .max(glam::IVec2::ONE);
+        let cell_center =
+            |i: i32, j: i32| origin + (glam::vec2(i as f32, j as f32) + 0.5) * resolution;
+
+        // Chamfer distance transform to the nearest occupied cell, in cells.
+        let mut distances = (0..size.y)
+            .flat_map(|j| (0..size.x).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                if map.is_occupied_vec2(cell_center(i, j)) {
+                    0.
+                } else {
+                    f32::INFINITY
+                }
+            })
+            .collect::<Vec<f32>>();
+        let index = |i: i32, j: i32| (i + j * size.x) as usize;
+        let forward = [
+            (-1, 0, 1.),
+            (-1, -1, 2f32.sqrt()),
+            (0, -1, 1.),
+            (1, -1, 2f32.sqrt()),
+        ];
+        let mut relax = |i: i32, j: i32, ni: i32, nj: i32, cost: f32| {
+            if (0..size.x).contains(&ni) && (0..size.y).contains(&nj) {
+                distances[index(i, j)] =
+                    distances[index(i, j)].min(distances[index(ni, nj)] + cost);
+            }
+        };
+        for j in 0..size.y {
+            for i in 0..size.x {
+                for (di, dj, cost) in forward {
+                    relax(i, j, i + di, j + dj, cost);
+                }
+            }
+        }
+        for j in (0..size.y).rev() {
+            for i in (0..size.x).rev() {
+                for (di, dj, cost) in forward {
+                    relax(i, j, i - di, j - dj, cost);
+                }
+            }
+        }
+        distances.iter_mut().for_each(|d| *d *= resolution);
+
+        Self {
+            resolution,
+            origin,
+            size,
+            distances,
+            sigma: 0.,
+            likelihoods: Vec::new(),
+        }
+        .with_sigma(resolution)
+    }
+
+    /// Recomputes the likelihoods as a Gaussian of standard deviation `sigma` metres on the distance to the nearest
+    /// wall, peaking at one on the walls themselves.
+    pub fn with_sigma(mut self, sigma: f32) -> Self {
+        self.sigma = sigma;
+        self.likelihoods = self
+            .distances
+            .iter()
+            .map(|d| (-0.5 * (d / sigma).powi(2)).exp())
+            .collect();
+
+        self
+    }
+
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// World position of the lower corner of cell `(0, 0)`.
+    pub fn origin(&self) -> glam::Vec2 {
+        self.origin
+    }
+
+    pub fn size(&self) -> glam::IVec2 {
+        self.size
+    }
+
+    /// One per cell in metres, row-major from the bottom row up. Infinite when the map has no occupied cells.
+    pub fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+
+    /// Same layout as [distances](Self::distances).
+    pub fn likelihoods(&self) -> &[f32] {
+        &self.likelihoods
+    }
+
+    fn index(&self, loc: glam::Vec2) -> Option<usize> {
+        let cell = ((loc - self.origin) / self.resolution).floor().as_ivec2();
+        (cell.cmpge(glam::IVec2::ZERO).all() && cell.cmplt(self.size).all())
+            .then(|| (cell.x + cell.y * self.size.x) as usize)
+    }
+
+    /// Distance from `loc` to the nearest wall, infinite off the sampled area.
+    pub fn distance(&self, loc: glam::Vec2) -> f32 {
+        self.index(loc).map_or(f32::INFINITY, |i| self.distances[i])
+    }
+
+    /// Unnormalized likelihood of a range reading ending at `loc`, zero off the sampled area.
+    pub fn lookup(&self, loc: glam::Vec2) -> f32 {
+        self.index(loc).map_or(0., |i| self.likelihoods[i])
+    }
+
+    /// One pixel per sample with the top row first, white on the walls and fading to black away from them.
+    pub fn to_image(&self) -> image::GrayImage {
+        layer_image(self.size, &self.likelihoods, |l| {
+            image::Luma([(l * 255.).round() as u8])
+        })
+    }
+}
+
+/// An image of a layer stored row-major from the bottom row up, flipped so its top row comes first.
+fn layer_image<P: image::Pixel>(
+    size: glam::IVec2,
+    values: &[f32],
+    pixel: impl Fn(f32) -> P,
+) -> image::ImageBuffer<P, Vec<P::Subpixel>> {
+    let (width, height) = (size.x as u32, size.y as u32);
+    image::ImageBuffer::from_fn(width, height, |x, y| {
+        pixel(values[(x + (height - 1 - y) * width) as usize])
+    })
+}
+
+/// Euclidean signed distance field: distance to the nearest obstacle surface in free space, negative inside obstacles.
+/// Sampled at the centre of every map cell and a ring of cells beyond the map, and interpolated between them.
+#[derive(Debug, Clone)]
+pub struct Esdf {
+    truncation: f32,
+    /// Metres per cell, as for the map.
+    resolution: f32,
+    /// World position of the centre of cell `(0, 0)`.
+    origin: glam::Vec2,
+    size: glam::IVec2,
+    /// In metres, row-major from the bottom row up.
+    distances: Vec<f32>,
+}
+
+impl Esdf {
+    pub fn new(map: &OccupancyMap, truncation: f32) -> Self {
+        let resolution = map.resolution;
+        let size = map.size.as_ivec2() + 2;
+        let origin = map.origin + (0.5 - size.as_vec2() / 2.) * resolution;
+        let occupied = (0..size.y)
+            .flat_map(|j| (0..size.x).map(move |i| glam::ivec2(i, j)))
+            .map(|cell| map.is_occupied_vec2(origin + cell.as_vec2() * resolution))
+            .collect::<Vec<_>>();
+
+        let outside = squared_distance_transform(size, |i| occupied[i]);
+        let inside = squared_distance_transform(size, |i| !occupied[i]);
+        // Cell centres either side of a surface are a cell apart, with the surface halfway between.
+        let distances = occupied
+            .iter()
+            .zip(outside.iter().zip(&inside))
+            .map(|(&occupied, (outside, inside))| {
+                let cells = if occupied {
+                    0.5 - inside.sqrt()
+                } else {
+                    outside.sqrt() - 0.5
+                };
+                let signed = cells * resolution;
+                signed.max(-truncation).min(truncation)
+            })
+            .collect();
+
+        Self {
+            truncation,
+            resolution,
+            origin,
+            size,
+            distances,
+        }
+    }
+
+    pub fn truncation(&self) -> f32 {
+        self.truncation
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// World position of the centre of cell `(0, 0)`.
+    pub fn origin(&self) -> glam::Vec2 {
+        self.origin
+    }
+
+    pub fn size(&self) -> glam::IVec2 {
+        self.size
+    }
+
+    /// One per cell in metres, row-major from the bottom row up.
+    pub fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+
+    /// The four cells around `loc` and how far `loc` is between them, clamped to the sampled area.
+    fn corners(&self, loc: glam::Vec2) -> ([f32; 4], glam::Vec2) {
+        let max = (self.size - 1).as_vec2();
+        let cell = ((loc - self.origin) / self.resolution).clamp(glam::Vec2::ZERO, max);
+        let low = cell.floor().min(max - 1.).max(glam::Vec2::ZERO).as_ivec2();
+        let high = (low + 1).min(self.size - 1);
+        let at = |i: i32, j: i32| self.distances[(i + j * self.size.x) as usize];
+
+        (
+            [
+                at(low.x, low.y),
+                at(high.x, low.y),
+                at(low.x, high.y),
+                at(high.x, high.y),
+            ],
+            cell - low.as_vec2(),
+        )
+    }
+
+    /// Signed distance at `loc`, interpolated bilinearly between cell centres.
+    pub fn distance(&self, loc: glam::Vec2) -> f32 {
+        let ([d00, d10, d01, d11], t) = self.corners(loc);
+        let bottom = d00 + (d10 - d00) * t.x;
+        let top = d01 + (d11 - d01) * t.x;
+
+        bottom + (top - bottom) * t.y
+    }
+
+    /// Gradient of [distance](Self::distance) at `loc`, pointing away from the nearest obstacle. Zero where the
+    /// distance is truncated.
+    pub fn gradient(&self, loc: glam::Vec2) -> glam::Vec2 {
+        let ([d00, d10, d01, d11], t) = self.corners(loc);
+
+        glam::vec2(
+            (d10 - d00) * (1. - t.y) + (d11 - d01) * t.y,
+            (d01 - d00) * (1. - t.x) + (d11 - d10) * t.x,
+        ) / self.resolution
+    }
+
+    /// One pixel per cell with the top row first. Free space brightens from black at the surface to white at the
+    /// truncation distance, and obstacles redden towards their middles. Without truncation, the furthest distance
+    /// sets the scale.
+    pub fn to_image(&self) -> image::RgbImage {
+        let scale = if self.truncation.is_finite() {
+            self.truncation
+        } else {
+            self.distances.iter().fold(0f32, |max, d| max.max(d.abs()))
+        }
+        .max(f32::EPSILON);
+
+        layer_image(self.size, &self.distances, |d| {
+            let shade = ((d.abs() / scale).min(1.) * 255.).round() as u8;
+            if d >= 0. {
+                image::Rgb([shade; 3])
+            } else {
+                image::Rgb([shade, 0, 0])
+            }
+        })
+    }
+}
+
+/// Squared distance in cells from every cell to the nearest one where `site` holds, infinite without any. Exact and
+/// linear in the number of cells (Felzenszwalb and Huttenlocher), as a 1D transform down each column then along each
+/// row.
+fn squared_distance_transform(size: glam::IVec2, site: impl Fn(usize) -> bool) -> Vec<f32> {
+    let (width, height) = (size.x as usize, size.y as usize);
+    let mut grid = (0..width * height)
+        .map(|i| if site(i) { 0. } else { f64::INFINITY })
+        .collect::<Vec<f64>>();
+
+    let mut line = Vec::new();
+    let mut envelope = Envelope::default();
+    for i in 0..width {
+        line.clear();
+        line.extend((0..height).map(|j| grid[i + j * width]));
+        envelope.transform(&mut line);
+        for (j, &d) in line.iter().enumerate() {
+            grid[i + j * width] = d;
+        }
+    }
+    for row in grid.chunks_exact_mut(width) {
+        envelope.transform(row);
+    }
+
+    grid.into_iter().map(|d| d as f32).collect()
+}
+
+/// Lower envelope of the parabolas `f[q] + (x - q)²`, reused between lines to save allocations.
+#[derive(Default)]
+struct Envelope {
+    /// Where each parabola sits.
+    vertices: Vec<usize>,
+    /// Where each parabola starts being the lowest.
+    starts: Vec<f64>,
+    values: Vec<f64>,
+}
+
+impl Envelope {
+    /// Replaces each `f[x]` with `min_q f[q] + (x - q)²`. Infinite entries never take part.
+    fn transform(&mut self, f: &mut [f64]) {
+        self.vertices.clear();
+        self.starts.clear();
+        for (q, &fq) in f.iter().enumerate() {
+            if !fq.is_finite() {
+                continue;
+            }
+            let height = fq + (q * q) as f64;
+
+            while let Some(&p) = self.vertices.last() {
+                let s = (height - (f[p] + (p * p) as f64)) / (2. * (q - p) as f64);
+                if self.starts.last().is_some_and(|&start| s <= start) {
+                    self.vertices.pop();
+                    self.starts.pop();
+                } else {
+                    self.vertices.push(q);
+                    self.starts.push(s);
+                    break;
+                }
+            }
+            if self.vertices.is_empty() {
+                self.vertices.push(q);
+                self.starts.push(f64::NEG_INFINITY);
+            }
+        }
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        self.values.clear();
+        let mut k = 0;
+        for x in 0..f.len() {
+            while k + 1 < self.vertices.len() && self.starts[k + 1] < x as f64 {
+                k += 1;
+            }
+            let q = self.vertices[k];
+            self.values.push(f[q] + (x as f64 - q as f64).powi(2));
+        }
+        f.copy_from_slice(&self.values);
+    }
+}
+
+pub(crate) fn probability(log_odds: f32) -> f32 {
+    1. / (1. + (-log_odds).exp())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Lidar2D, mapping::LogOddsGrid, math::Pose2D, scene::occupancy_map::OccupancyMap,
+        sensors::lidar::Lidar2DSensed,
+    };
+
+    #[test]
+    fn test_log_odds_grid() {
+        let mut grid = LogOddsGrid::new(glam::usizevec2(8, 8));
+        let mut lidar = Lidar2D::default();
+        lidar.update_directions(vec![glam::Vec2::X, glam::Vec2::Y]);
+        let scan = Lidar2DSensed {
+            ranges: vec![3., f32::INFINITY],
+            points: vec![glam::vec2(3.5, 0.5)],
+            tags: None,
+            time_offsets: None,
+        };
+
+        for _ in 0..3 {
+            grid.update(&lidar, &scan, Pose2D::new(glam::vec2(0.5, 0.5), 0.));
+        }
+        assert!(grid.probability(glam::vec2(3.5, 0.5)) > 0.9);
+        assert!(grid.probability(glam::vec2(1.5, 0.5)) < 0.3);
+        // The ray that missed clears up to the edge of the grid.
+        assert!(grid.probability(glam::vec2(0.5, 3.5)) < 0.3);
+        assert_eq!(grid.probability(glam::vec2(-2.5, -2.5)), 0.5);
+
+        let map = grid.to_occupancy_map(0.5).unwrap();
+        assert!(map.is_occupied_vec2(glam::vec2(3.5, 0.5)));
+        assert!(!map.is_occupied_vec2(glam::vec2(-2.5, -2.5)));
+
+        let image = grid.to_image();
+        assert_eq!(image.dimensions(), (8, 8));
+        let cell = grid.cell(glam::vec2(3.5, 0.5)).unwrap();
+        assert!(image.get_pixel(cell.x as u32, cell.y as u32).0[0] < 127);
+
+        let map = OccupancyMap::from_pixels(
+            glam::usizevec2(4, 2),
+            vec![true, false, false, true, false, false, true, false],
+        )
+        .unwrap();
+        let round_trip = LogOddsGrid::from_occupancy_map(&map)
+            .to_occupancy_map(0.5)
+            .unwrap();
+        assert_eq!(round_trip.pixels, map.pixels);
+    }
+
+    #[test]
+    fn test_likelihood_field() {
+        // A single wall cell in the middle of a free 8x8 map.
+        let mut pixels = vec![false; 64];
+        pixels[4 + 4 * 8] = true;
+        let map = OccupancyMap::from_pixels(glam::usizevec2(8, 8), pixels).unwrap();
+        let wall = (0..64)
+            .map(|i| glam::vec2((i % 8) as f32 - 3.5, (i / 8) as f32 - 3.5))
+            .find(|&p| map.is_occupied_vec2(p))
+            .unwrap();
+
+        let field = map.likelihood_field(0.25, 0.5);
+        assert_eq!(field.sigma(), 0.5);
+        assert_eq!(field.likelihoods().len(), field.distances().len());
+        assert_eq!(field.lookup(wall), 1.);
+        let near = field.lookup(wall + glam::vec2(1., 0.));
+        let far = field.lookup(wall + glam::vec2(2., 0.));
+        assert!(near < 1. && far < near, "{near} {far}");
+        let d = field.distance(wall + glam::vec2(1., 0.));
+        assert!((near - (-0.5 * (d / 0.5f32).powi(2)).exp()).abs() < 1e-6);
+        assert_eq!(field.lookup(glam::vec2(100., 0.)), 0.);
+
+        let wider = field.with_sigma(2.);
+        assert!(wider.lookup(wall + glam::vec2(2., 0.)) > far);
+
+        // The wall lies below the middle of the map, so in the lower half of the image.
+        let image = wider.to_image();
+        assert_eq!(image.dimensions(), (40, 40));
+        let cell = ((wall - wider.origin()) / 0.25).as_uvec2();
+        assert_eq!(image.get_pixel(cell.x, 39 - cell.y).0[0], 255);
+        assert!(image.get_pixel(cell.x, cell.y).0[0] < 255);
+    }
+
+    /// Scattered occupied cells on a 10x10 map, inside its solid border.
+    fn scattered() -> OccupancyMap {
+        let pixels = (0..100)
+            .map(|i: usize| (i * 7919).is_multiple_of(13))
+            .collect::<Vec<_>>();
+        OccupancyMap::from_pixels(glam::usizevec2(10, 10), pixels).unwrap()
+    }
+
+    #[test]
+    fn test_esdf() {
+        // A 2x2 block in the middle of a free 10x10 map, at least 4 cells from the solid border.
+        let pixels = (0..100)
+            .map(|i: usize| (4..6).contains(&(i % 10)) && (4..6).contains(&(i / 10)))
+            .collect();
+        let map = OccupancyMap::from_pixels(glam::usizevec2(10, 10), pixels).unwrap();
+
+        let esdf = map.to_esdf(f32::INFINITY);
+        assert_eq!(esdf.size(), glam::ivec2(12, 12));
+        assert!(esdf.distance(glam::vec2(1., 0.)).abs() < 1e-5);
+        assert!((esdf.distance(glam::vec2(0.5, 0.5)) + 0.5).abs() < 1e-5);
+        assert!((esdf.distance(glam::vec2(2.5, 0.5)) - 1.5).abs() < 1e-5);
+        assert!((esdf.distance(glam::vec2(2.5, 2.5)) - (2. * 2f32.sqrt() - 0.5)).abs() < 1e-5);
+        let gradient = esdf.gradient(glam::vec2(1.7, 0.2));
+        assert!(gradient.x > 0.9 && gradient.y.abs() < 0.1, "{gradient}");
+    }
+
+    #[test]
+    fn test_esdf_brute_force() {
+        // Against brute force, on a map with the solid border.
+        let map = scattered();
+        let esdf = map.to_esdf(f32::INFINITY);
+        let origin = esdf.origin();
+        let centers = (0..12)
+            .flat_map(|j| (0..12).map(move |i| origin + glam::vec2(i as f32, j as f32)))
+            .collect::<Vec<_>>();
+        for (&center, &distance) in centers.iter().zip(esdf.distances()) {
+            let occupied = map.is_occupied_vec2(center);
+            let nearest = centers
+                .iter()
+                .filter(|&&c| map.is_occupied_vec2(c) != occupied)
+                .map(|c| c.distance(center))
+                .fold(f32::INFINITY, f32::min);
+            let expected = if occupied {
+                0.5 - nearest
+            } else {
+                nearest - 0.5
+            };
+            assert!(
+                (distance - expected).abs() < 1e-4,
+                "{center}: {distance} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_esdf_truncated() {
+        let map = scattered();
+        let truncated = map.to_esdf(1.);
+        assert!(truncated.distances().iter().all(|d| d.abs() <= 1.));
+        assert_eq!(truncated.truncation(), 1.);
+
+        // Obstacles, the solid border among them, are red and free space grey.
+        let image = truncated.to_image();
+        assert_eq!(image.dimensions(), (12, 12));
+        let origin = truncated.origin();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, _] = pixel.0;
+            let center = origin + glam::vec2(x as f32, 11. - y as f32);
+            assert_eq!(map.is_occupied_vec2(center), r > 0 && g == 0);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Box2D {
     pub min: glam::Vec2,
     pub max: glam::Vec2,
@@ -30,7 +31,16 @@ impl Box2D {
         let min = self.min.max(query.min);
         let max = self.max.min(query.max);
 
-        min.cmpge(max).any()
+        min.cmple(max).all()
+    }
+
+    /// How far `point` is from the box, zero inside it.
+    #[inline]
+    pub fn distance(&self, point: glam::Vec2) -> f32 {
+        (self.min - point)
+            .max(point - self.max)
+            .max(glam::Vec2::ZERO)
+            .length()
     }
 
     #[inline]
@@ -95,6 +105,387 @@ impl Box2D {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrientedBox2D {
+    pub center: glam::Vec2,
+    pub half_extent: glam::Vec2,
+    /// Unit vector along the box's local x-axis.
+    pub heading: glam::Vec2,
+}
+
+impl OrientedBox2D {
+    /// Maps a world-frame vector into the box frame.
+    #[inline]
+    pub fn to_local(&self, v: glam::Vec2) -> glam::Vec2 {
+        glam::vec2(self.heading.x, -self.heading.y).rotate(v)
+    }
+
+    #[inline]
+    pub fn local_box(&self) -> Box2D {
+        Box2D {
+            min: -self.half_extent,
+            max: self.half_extent,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        self.local_box().contains(self.to_local(point - self.center))
+    }
+
+    pub fn corners(&self) -> [glam::Vec2; 4] {
+        let front = self.heading * self.half_extent.x;
+        let left = self.heading.perp() * self.half_extent.y;
+
+        [
+            self.center + front + left,
+            self.center - front + left,
+            self.center - front - left,
+            self.center + front - left,
+        ]
+    }
+
+    /// Separating-axis test against another oriented box.
+    pub fn intersects(&self, other: &OrientedBox2D) -> bool {
+        self.penetration(other).is_some()
+    }
+
+    /// The shortest way to push `other` out of this box, as a unit direction away from this box and a distance, or
+    /// `None` if they don't meet.
+    pub fn penetration(&self, other: &OrientedBox2D) -> Option<(glam::Vec2, f32)> {
+        let project = |corners: &[glam::Vec2; 4], axis: glam::Vec2| {
+            corners
+                .iter()
+                .map(|c| c.dot(axis))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                    (lo.min(p), hi.max(p))
+                })
+        };
+
+        let (a, b) = (self.corners(), other.corners());
+        let between = other.center - self.center;
+
+        let mut best: Option<(glam::Vec2, f32)> = None;
+        for axis in [
+            self.heading,
+            self.heading.perp(),
+            other.heading,
+            other.heading.perp(),
+        ] {
+            let (a_lo, a_hi) = project(&a, axis);
+            let (b_lo, b_hi) = project(&b, axis);
+            if a_lo > b_hi || b_lo > a_hi {
+                return None;
+            }
+
+            let depth = (a_hi - b_lo).min(b_hi - a_lo);
+            if best.is_none_or(|(_, d)| depth < d) {
+                let axis = if between.dot(axis) < 0. { -axis } else { axis };
+                best = Some((axis, depth));
+            }
+        }
+
+        best
+    }
+
+    pub fn get_box(&self) -> Box2D {
+        let corners = self.corners();
+        let mut bx = Box2D {
+            min: corners[0],
+            max: corners[0],
+        };
+        for corner in &corners[1..] {
+            bx.min = bx.min.min(*corner);
+            bx.max = bx.max.max(*corner);
+        }
+
+        bx
+    }
+}
+
+impl From<Box2D> for OrientedBox2D {
+    fn from(bx: Box2D) -> Self {
+        Self {
+            center: bx.centroid(),
+            half_extent: bx.size() / 2.,
+            heading: glam::Vec2::X,
+        }
+    }
+}
+
+/// Most corners a [ConvexPolygon] can have.
+pub const MAX_POLYGON_CORNERS: usize = 8;
+
+/// A convex polygon of up to [MAX_POLYGON_CORNERS] corners, wound counter-clockwise. The corners are stored inline so
+/// it stays `Copy`, like the boxes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "Vec<glam::Vec2>", into = "Vec<glam::Vec2>")
+)]
+pub struct ConvexPolygon {
+    corners: [glam::Vec2; MAX_POLYGON_CORNERS],
+    len: usize,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum PolygonError {
+    #[error("Polygon has {0} corners, but needs between 3 and {MAX_POLYGON_CORNERS}")]
+    CornerCount(usize),
+
+    #[error("Polygon is not convex, or has no area")]
+    NotConvex,
+}
+
+impl ConvexPolygon {
+    /// The polygon with `corners` in order, either way round.
+    pub fn new(corners: &[glam::Vec2]) -> Result<Self, PolygonError> {
+        let len = corners.len();
+        if !(3..=MAX_POLYGON_CORNERS).contains(&len) {
+            return Err(PolygonError::CornerCount(len));
+        }
+        if !corners.iter().all(|c| c.is_finite()) {
+            return Err(PolygonError::NotConvex);
+        }
+
+        let turns = (0..len).map(|i| {
+            let (a, b, c) = (corners[i], corners[(i + 1) % len], corners[(i + 2) % len]);
+            (b - a).perp_dot(c - b)
+        });
+        let (left, right) = turns.fold((true, true), |(l, r), t| (l && t > 0., r && t < 0.));
+        if !left && !right {
+            return Err(PolygonError::NotConvex);
+        }
+
+        let mut polygon = Self {
+            corners: [glam::Vec2::ZERO; MAX_POLYGON_CORNERS],
+            len,
+        };
+        polygon.corners[..len].copy_from_slice(corners);
+        if right {
+            polygon.corners[..len].reverse();
+        }
+
+        Ok(polygon)
+    }
+
+    #[inline]
+    pub fn corners(&self) -> &[glam::Vec2] {
+        &self.corners[..self.len]
+    }
+
+    /// Sides paired with their outward unit normals.
+    pub fn edges(&self) -> impl Iterator<Item = (LineSegment, glam::Vec2)> + '_ {
+        let corners = self.corners();
+        (0..corners.len()).map(move |i| {
+            let side = LineSegment(corners[i], corners[(i + 1) % corners.len()]);
+            (side, -(side.1 - side.0).perp().normalize())
+        })
+    }
+
+    /// The polygon moved from `pose`'s frame into the parent frame.
+    pub fn transformed(&self, pose: &Pose2D) -> Self {
+        let mut moved = *self;
+        for corner in &mut moved.corners[..self.len] {
+            *corner = pose.transform_point(*corner);
+        }
+
+        moved
+    }
+
+    /// Average of the corners.
+    pub fn centroid(&self) -> glam::Vec2 {
+        self.corners().iter().sum::<glam::Vec2>() / self.len as f32
+    }
+
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        self.edges()
+            .all(|(side, normal)| (point - side.0).dot(normal) <= 0.)
+    }
+
+    pub fn get_box(&self) -> Box2D {
+        let corners = self.corners();
+        corners[1..].iter().fold(
+            Box2D {
+                min: corners[0],
+                max: corners[0],
+            },
+            |bx, &c| Box2D {
+                min: bx.min.min(c),
+                max: bx.max.max(c),
+            },
+        )
+    }
+
+    /// The part of the line through `segment` inside the polygon, as a range of parameters along it, limited to
+    /// `range`.
+    fn clip(&self, segment: &LineSegment, (mut t0, mut t1): (f32, f32)) -> Option<(f32, f32)> {
+        let dir = segment.1 - segment.0;
+        for (side, normal) in self.edges() {
+            let num = (side.0 - segment.0).dot(normal);
+            let den = dir.dot(normal);
+            if den == 0. {
+                if num < 0. {
+                    return None;
+                }
+            } else if den < 0. {
+                t0 = t0.max(num / den);
+            } else {
+                t1 = t1.min(num / den);
+            }
+        }
+
+        (t0 <= t1).then_some((t0, t1))
+    }
+
+    /// The part of `segment` inside the polygon, as a range of parameters along it from `.0` to `.1`.
+    pub fn clip_segment(&self, segment: &LineSegment) -> Option<(f32, f32)> {
+        self.clip(segment, (0., 1.))
+    }
+
+    /// Distance along `dir` to where the ray enters the polygon, or leaves it when it starts inside, like
+    /// [intersect_ray_box].
+    pub fn cast_ray(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
+        let (t_n, t_f) = self.clip(
+            &LineSegment(pos, pos + dir),
+            (f32::NEG_INFINITY, f32::INFINITY),
+        )?;
+
+        if t_f < f32::EPSILON {
+            None
+        } else if t_n < f32::EPSILON {
+            Some(t_f)
+        } else {
+            Some(t_n)
+        }
+    }
+
+    /// Separating-axis test against another convex polygon.
+    pub fn intersects(&self, other: &ConvexPolygon) -> bool {
+        self.penetration(other).is_some()
+    }
+
+    /// The shortest way to push `other` out of this polygon, as a unit direction away from this polygon and a
+    /// distance, or `None` if they don't meet.
+    pub fn penetration(&self, other: &ConvexPolygon) -> Option<(glam::Vec2, f32)> {
+        let project = |corners: &[glam::Vec2], axis: glam::Vec2| {
+            corners
+                .iter()
+                .map(|c| c.dot(axis))
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                    (lo.min(p), hi.max(p))
+                })
+        };
+
+        let between = other.centroid() - self.centroid();
+        let mut best: Option<(glam::Vec2, f32)> = None;
+        for (_, axis) in self.edges().chain(other.edges()) {
+            let (a_lo, a_hi) = project(self.corners(), axis);
+            let (b_lo, b_hi) = project(other.corners(), axis);
+            if a_lo > b_hi || b_lo > a_hi {
+                return None;
+            }
+
+            let depth = (a_hi - b_lo).min(b_hi - a_lo);
+            if best.is_none_or(|(_, d)| depth < d) {
+                let axis = if between.dot(axis) < 0. { -axis } else { axis };
+                best = Some((axis, depth));
+            }
+        }
+
+        best
+    }
+}
+
+impl From<OrientedBox2D> for ConvexPolygon {
+    fn from(obb: OrientedBox2D) -> Self {
+        let mut corners = [glam::Vec2::ZERO; MAX_POLYGON_CORNERS];
+        corners[..4].copy_from_slice(&obb.corners());
+
+        Self { corners, len: 4 }
+    }
+}
+
+impl TryFrom<Vec<glam::Vec2>> for ConvexPolygon {
+    type Error = PolygonError;
+
+    fn try_from(corners: Vec<glam::Vec2>) -> Result<Self, Self::Error> {
+        Self::new(&corners)
+    }
+}
+
+impl From<ConvexPolygon> for Vec<glam::Vec2> {
+    fn from(polygon: ConvexPolygon) -> Self {
+        polygon.corners().to_vec()
+    }
+}
+
+/// A rigid transform in the plane: a position and the unit vector the body faces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pose2D {
+    pub position: glam::Vec2,
+    pub heading: glam::Vec2,
+}
+
+impl Default for Pose2D {
+    fn default() -> Self {
+        Self {
+            position: glam::Vec2::ZERO,
+            heading: glam::Vec2::X,
+        }
+    }
+}
+
+impl Pose2D {
+    pub fn new(position: glam::Vec2, angle: f32) -> Self {
+        Self {
+            position,
+            heading: glam::Vec2::from_angle(angle),
+        }
+    }
+
+    /// Heading in radians, counter-clockwise from the x-axis.
+    #[inline]
+    pub fn angle(&self) -> f32 {
+        self.heading.to_angle()
+    }
+
+    /// Maps a point from this pose's frame into the parent frame.
+    #[inline]
+    pub fn transform_point(&self, point: glam::Vec2) -> glam::Vec2 {
+        self.position + self.heading.rotate(point)
+    }
+
+    /// `self * other`, i.e. `other` expressed in this pose's frame mapped into the parent frame.
+    pub fn compose(&self, other: &Self) -> Self {
+        Self {
+            position: self.transform_point(other.position),
+            heading: self.heading.rotate(other.heading).normalize_or(glam::Vec2::X),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        let heading = glam::vec2(self.heading.x, -self.heading.y);
+
+        Self {
+            position: -heading.rotate(self.position),
+            heading,
+        }
+    }
+
+    /// Moves `t` of the way from `self` to `other`, turning through the smaller angle.
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, t),
+            heading: glam::Vec2::from_angle(self.heading.angle_to(other.heading) * t)
+                .rotate(self.heading)
+                .normalize_or(glam::Vec2::X),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct LineSegment(pub glam::Vec2, pub glam::Vec2);
 
@@ -116,6 +507,25 @@ impl LineSegment {
             max: self.0.max(self.1),
         }
     }
+
+    /// Unit normal on the left of the segment, looking from `.0` to `.1`. Zero for degenerate segments.
+    #[inline]
+    pub fn normal(&self) -> glam::Vec2 {
+        (self.1 - self.0).perp().normalize_or_zero()
+    }
+
+    /// The point of the segment nearest `point`.
+    #[inline]
+    pub fn closest_point(&self, point: glam::Vec2) -> glam::Vec2 {
+        let along = self.1 - self.0;
+        let t = if along == glam::Vec2::ZERO {
+            0.
+        } else {
+            ((point - self.0).dot(along) / along.length_squared()).clamp(0., 1.)
+        };
+
+        self.0 + along * t
+    }
 }
 
 #[inline]
@@ -145,6 +555,65 @@ pub fn intersect_ray_box(
     }
 }
 
+/// How far along the ray it enters `bx`, zero if it starts inside. Unlike [intersect_ray_box], never the exit, and
+/// rays along an axis miss boxes they pass beside.
+#[inline]
+pub fn ray_box_entry(pos: glam::Vec2, dir: glam::Vec2, Box2D { min, max }: Box2D) -> Option<f32> {
+    let (mut near, mut far) = (0f32, f32::INFINITY);
+    for axis in 0..2 {
+        if dir[axis] == 0. {
+            if pos[axis] < min[axis] || pos[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let a = (min[axis] - pos[axis]) / dir[axis];
+        let b = (max[axis] - pos[axis]) / dir[axis];
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+
+    (near <= far && far >= f32::EPSILON).then_some(near)
+}
+
+/// The part of `segment` inside `bx`, as a range of parameters along it from `.0` to `.1`.
+pub fn clip_line_segment_box(
+    segment: &LineSegment,
+    Box2D { min, max }: Box2D,
+) -> Option<(f32, f32)> {
+    let dir = segment.1 - segment.0;
+    let (mut t0, mut t1) = (0f32, 1f32);
+
+    for (p, q) in [
+        (-dir.x, segment.0.x - min.x),
+        (dir.x, max.x - segment.0.x),
+        (-dir.y, segment.0.y - min.y),
+        (dir.y, max.y - segment.0.y),
+    ] {
+        if p == 0. {
+            if q < 0. {
+                return None;
+            }
+        } else if p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+
+    (t0 <= t1).then_some((t0, t1))
+}
+
+#[inline]
+pub fn intersect_ray_oriented_box(
+    pos: glam::Vec2,
+    dir: glam::Vec2,
+    obb: &OrientedBox2D,
+) -> Option<f32> {
+    intersect_ray_box(obb.to_local(pos - obb.center), obb.to_local(dir), obb.local_box())
+}
+
 #[inline]
 pub fn intersect_ray_line_segment(
     pos: glam::Vec2,
@@ -173,7 +642,10 @@ pub fn intersect_ray_line_segment(
 
 #[cfg(test)]
 mod test {
-    use crate::math::{Box2D, intersect_ray_box};
+    use crate::math::{
+        Box2D, ConvexPolygon, LineSegment, OrientedBox2D, PolygonError, Pose2D, intersect_ray_box,
+        intersect_ray_oriented_box,
+    };
 
     #[test]
     fn test_collisions() {
@@ -249,4 +721,120 @@ mod test {
             None
         );
     }
+
+    #[test]
+    fn test_oriented_box_collisions() {
+        let obb = OrientedBox2D {
+            center: glam::vec2(2., 0.),
+            half_extent: glam::vec2(0.5, 0.25),
+            heading: glam::Vec2::from_angle(std::f32::consts::FRAC_PI_2),
+        };
+
+        let t = intersect_ray_oriented_box(glam::vec2(0., 0.), glam::vec2(1., 0.), &obb).unwrap();
+        assert!((t - 1.75).abs() < 1e-5);
+
+        assert!(obb.contains(glam::vec2(2., 0.4)));
+        assert!(!obb.contains(glam::vec2(2.4, 0.)));
+
+        assert_eq!(
+            intersect_ray_oriented_box(glam::vec2(0., 1.), glam::vec2(1., 0.), &obb),
+            None
+        );
+
+        let diamond = OrientedBox2D {
+            center: glam::vec2(2.6, 0.6),
+            half_extent: glam::vec2(0.25, 0.25),
+            heading: glam::Vec2::from_angle(std::f32::consts::FRAC_PI_4),
+        };
+        assert!(!obb.intersects(&diamond));
+        assert!(obb.intersects(&OrientedBox2D { center: glam::vec2(2.4, 0.4), ..diamond }));
+
+        let (normal, depth) = obb
+            .penetration(&OrientedBox2D {
+                center: glam::vec2(2.4, 0.),
+                ..obb
+            })
+            .unwrap();
+        assert!(normal.abs_diff_eq(glam::Vec2::X, 1e-6), "{normal}");
+        assert!((depth - 0.1).abs() < 1e-5, "{depth}");
+        assert_eq!(obb.penetration(&diamond), None);
+    }
+
+    #[test]
+    fn test_convex_polygon() {
+        // A wedge pointing along +x, given clockwise.
+        let wedge = ConvexPolygon::new(&[
+            glam::vec2(1., 0.),
+            glam::vec2(-1., -0.5),
+            glam::vec2(-1., 0.5),
+        ])
+        .unwrap();
+        assert_eq!(wedge.corners()[0], glam::vec2(-1., 0.5));
+        assert!(wedge.contains(glam::vec2(0.5, 0.)));
+        assert!(!wedge.contains(glam::vec2(0.5, 0.4)));
+
+        let t = wedge
+            .cast_ray(glam::vec2(0., 2.), glam::vec2(0., -1.))
+            .unwrap();
+        assert!((t - 1.75).abs() < 1e-5, "{t}");
+        let t = wedge
+            .cast_ray(glam::Vec2::ZERO, glam::vec2(1., 0.))
+            .unwrap();
+        assert!((t - 1.).abs() < 1e-5, "{t}");
+        assert_eq!(wedge.cast_ray(glam::vec2(0., 2.), glam::vec2(0., 1.)), None);
+
+        let (t0, t1) = wedge
+            .clip_segment(&LineSegment(glam::vec2(-2., 0.), glam::vec2(2., 0.)))
+            .unwrap();
+        assert!((t0 - 0.25).abs() < 1e-5, "{t0}");
+        assert!((t1 - 0.75).abs() < 1e-5, "{t1}");
+
+        let moved = wedge.transformed(&Pose2D::new(glam::vec2(3., 0.), std::f32::consts::PI));
+        assert!(moved.contains(glam::vec2(2.5, 0.)));
+        assert!(!moved.intersects(&wedge));
+
+        let obb = ConvexPolygon::from(OrientedBox2D::from(Box2D {
+            min: glam::vec2(0.8, -0.5),
+            max: glam::vec2(1.8, 0.5),
+        }));
+        let (normal, depth) = wedge.penetration(&obb).unwrap();
+        assert!(normal.abs_diff_eq(glam::Vec2::X, 1e-6), "{normal}");
+        assert!((depth - 0.2).abs() < 1e-5, "{depth}");
+
+        assert_eq!(
+            ConvexPolygon::new(&[glam::Vec2::ZERO, glam::Vec2::X]),
+            Err(PolygonError::CornerCount(2))
+        );
+        assert_eq!(
+            ConvexPolygon::new(&[
+                glam::vec2(0., 0.),
+                glam::vec2(2., 0.),
+                glam::vec2(1., 0.5),
+                glam::vec2(2., 2.),
+                glam::vec2(0., 2.),
+            ]),
+            Err(PolygonError::NotConvex)
+        );
+    }
+
+    #[test]
+    fn test_pose_compose() {
+        let a = Pose2D::new(glam::vec2(1., 2.), std::f32::consts::FRAC_PI_2);
+        let b = Pose2D::new(glam::vec2(1., 0.), 0.3);
+
+        let ab = a.compose(&b);
+        assert!(ab.position.abs_diff_eq(glam::vec2(1., 3.), 1e-5));
+        assert!((ab.angle() - (std::f32::consts::FRAC_PI_2 + 0.3)).abs() < 1e-5);
+
+        let identity = ab.compose(&ab.inverse());
+        assert!(identity.position.abs_diff_eq(glam::Vec2::ZERO, 1e-5));
+        assert!(identity.heading.abs_diff_eq(glam::Vec2::X, 1e-5));
+
+        // Turning from 170° to -170° goes through 180°, not through 0°.
+        let from = Pose2D::new(glam::Vec2::ZERO, 170f32.to_radians());
+        let to = Pose2D::new(glam::vec2(2., 0.), -170f32.to_radians());
+        let halfway = from.interpolate(&to, 0.5);
+        assert!(halfway.position.abs_diff_eq(glam::vec2(1., 0.), 1e-5));
+        assert!(halfway.heading.abs_diff_eq(glam::Vec2::NEG_X, 1e-5));
+    }
 }
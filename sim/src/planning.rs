@@ -0,0 +1,436 @@
+use rand::Rng;
+
+use crate::{
+    agent::Agent2DConfig,
+    config::{self, ConfigError, Validate},
+    mapping::Esdf,
+    math::Pose2D,
+    rng,
+    scene::occupancy_map::OccupancyMap,
+};
+
+/// Sampling-based planner that grows a tree of collision-free edges from the start, rewiring it as it goes so the
+/// paths through it keep getting shorter.
+///
+/// With [RrtStar::max_curvature] set the edges are circular arcs leaving each node along its heading, so a car-like
+/// agent can drive the path. Nodes are then only rewired while they have no children, since a new parent changes the
+/// heading they are reached with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RrtStar {
+    pub max_iterations: usize,
+    /// Longest edge grown towards each sample, in metres.
+    pub step: f32,
+    /// Nodes within this many metres of a new node are tried as its parent and rewired through it.
+    pub rewire_radius: f32,
+    /// Share of samples drawn at the goal rather than anywhere on the map.
+    pub goal_bias: f32,
+    /// Nodes this close to the goal, in metres, reach it.
+    pub goal_tolerance: f32,
+    /// Distance to keep from walls in metres, e.g. half the agent's width. 0 only keeps the path itself out of them.
+    pub clearance: f32,
+    /// Largest curvature of the path in 1/m, or `None` for straight edges the agent has to turn on the spot between.
+    pub max_curvature: Option<f32>,
+    /// Spacing of the poses in the planned path and of the collision checks along each edge, in metres.
+    pub resolution: f32,
+}
+
+impl Default for RrtStar {
+    fn default() -> Self {
+        Self {
+            max_iterations: 3000,
+            step: 1.,
+            rewire_radius: 3.,
+            goal_bias: 0.05,
+            goal_tolerance: 0.5,
+            clearance: 0.,
+            max_curvature: None,
+            resolution: 0.1,
+        }
+    }
+}
+
+impl Validate for RrtStar {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("max_iterations", self.max_iterations, 1)?;
+        config::positive("step", self.step)?;
+        config::positive("rewire_radius", self.rewire_radius)?;
+        config::within(
+            "goal_bias",
+            self.goal_bias,
+            (0. ..=1.).contains(&self.goal_bias),
+            "[0, 1]",
+        )?;
+        config::positive("goal_tolerance", self.goal_tolerance)?;
+        config::finite("clearance", self.clearance)?;
+        config::non_negative("clearance", self.clearance)?;
+        if let Some(max_curvature) = self.max_curvature {
+            config::positive("max_curvature", max_curvature)?;
+        }
+        config::positive("resolution", self.resolution)
+    }
+}
+
+/// Poses to drive through, from the start to within [RrtStar::goal_tolerance] of the goal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedPath {
+    pub poses: Vec<Pose2D>,
+    /// In metres.
+    pub length: f32,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlanError {
+    #[error("The start {0} is too close to a wall")]
+    StartBlocked(glam::Vec2),
+
+    #[error("The goal {0} is too close to a wall")]
+    GoalBlocked(glam::Vec2),
+
+    #[error("No path to the goal found in {0} iterations")]
+    NotFound(usize),
+
+    #[error("Invalid planner config: {0}")]
+    Config(#[from] ConfigError),
+}
+
+/// A circular arc leaving `start` along its heading, straight when `curvature` is zero.
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    start: Pose2D,
+    curvature: f32,
+    length: f32,
+}
+
+impl Edge {
+    fn at(&self, distance: f32) -> Pose2D {
+        let turn = self.curvature * distance;
+        let local = if self.curvature.abs() < 1e-6 {
+            glam::vec2(distance, 0.)
+        } else {
+            glam::vec2(turn.sin(), 1. - turn.cos()) / self.curvature
+        };
+
+        Pose2D::new(self.start.transform_point(local), self.start.angle() + turn)
+    }
+
+    fn end(&self) -> Pose2D {
+        self.at(self.length)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    pose: Pose2D,
+    /// Length of the path from the start.
+    cost: f32,
+    parent: Option<(usize, Edge)>,
+    children: Vec<usize>,
+}
+
+impl RrtStar {
+    /// Keeps the agent's footprint clear of walls and its turns within its steering limits.
+    pub fn for_agent(config: &Agent2DConfig) -> Self {
+        Self {
+            // The footprint's corners are this far from its centre.
+            clearance: glam::vec2(config.length, config.width).length() / 2.,
+            max_curvature: Some(config.max_curvature()),
+            ..Default::default()
+        }
+    }
+
+    /// Plans from `start` to any heading at `goal`, using every iteration to shorten the path once one is found.
+    pub fn plan(
+        &self,
+        map: &OccupancyMap,
+        start: Pose2D,
+        goal: glam::Vec2,
+    ) -> Result<PlannedPath, PlanError> {
+        self.validate()?;
+
+        let esdf = (self.clearance > 0.).then(|| map.to_esdf(self.clearance + 1.));
+        let esdf = esdf.as_ref();
+        let free = |loc: glam::Vec2| {
+            !map.is_occupied_vec2(loc) && esdf.is_none_or(|e| e.distance(loc) >= self.clearance)
+        };
+        if !free(start.position) {
+            return Err(PlanError::StartBlocked(start.position));
+        }
+        if !free(goal) {
+            return Err(PlanError::GoalBlocked(goal));
+        }
+
+        let mut nodes = vec![Node {
+            pose: start,
+            cost: 0.,
+            parent: None,
+            children: Vec::new(),
+        }];
+        let mut rng = rng::rng();
+        let bounds = map.bounds();
+        for _ in 0..self.max_iterations {
+            let sample = if rng.random::<f32>() < self.goal_bias {
+                goal
+            } else {
+                glam::vec2(
+                    rng.random_range(bounds.min.x..bounds.max.x),
+                    rng.random_range(bounds.min.y..bounds.max.y),
+                )
+            };
+
+            let nearest = (0..nodes.len())
+                .min_by(|&a, &b| {
+                    let distance = |i: usize| nodes[i].pose.position.distance_squared(sample);
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap_or(0);
+            let Some(edge) = self.steer(nodes[nearest].pose, sample) else {
+                continue;
+            };
+            if edge.length < 1e-3 || !self.edge_clear(&edge, map, esdf) {
+                continue;
+            }
+            let position = edge.end().position;
+
+            let near = (0..nodes.len())
+                .filter(|&i| nodes[i].pose.position.distance(position) <= self.rewire_radius)
+                .collect::<Vec<_>>();
+
+            // The cheapest neighbour to reach the new node from, starting with the one that found it.
+            let mut parent = (nearest, edge, nodes[nearest].cost + edge.length);
+            for &i in &near {
+                let Some(edge) = self.connect(nodes[i].pose, position) else {
+                    continue;
+                };
+                let cost = nodes[i].cost + edge.length;
+                if cost < parent.2 && self.edge_clear(&edge, map, esdf) {
+                    parent = (i, edge, cost);
+                }
+            }
+
+            let id = nodes.len();
+            let (parent, edge, cost) = parent;
+            nodes.push(Node {
+                pose: edge.end(),
+                cost,
+                parent: Some((parent, edge)),
+                children: Vec::new(),
+            });
+            nodes[parent].children.push(id);
+
+            for i in near {
+                if self.max_curvature.is_some() && !nodes[i].children.is_empty() {
+                    continue;
+                }
+                let Some(edge) = self.connect(nodes[id].pose, nodes[i].pose.position) else {
+                    continue;
+                };
+                let cost = nodes[id].cost + edge.length;
+                // Ancestors of the new node are all cheaper than it, so this can't make a cycle.
+                if cost >= nodes[i].cost || !self.edge_clear(&edge, map, esdf) {
+                    continue;
+                }
+                let Some((old, _)) = nodes[i].parent else {
+                    continue;
+                };
+
+                nodes[old].children.retain(|&c| c != i);
+                nodes[id].children.push(i);
+                let saving = nodes[i].cost - cost;
+                nodes[i].pose = edge.end();
+                nodes[i].cost = cost;
+                nodes[i].parent = Some((id, edge));
+
+                let mut descendants = nodes[i].children.clone();
+                while let Some(d) = descendants.pop() {
+                    nodes[d].cost -= saving;
+                    descendants.extend_from_slice(&nodes[d].children);
+                }
+            }
+        }
+
+        let best = (0..nodes.len())
+            .filter(|&i| nodes[i].pose.position.distance(goal) <= self.goal_tolerance)
+            .min_by(|&a, &b| nodes[a].cost.total_cmp(&nodes[b].cost))
+            .ok_or(PlanError::NotFound(self.max_iterations))?;
+
+        let mut edges = Vec::new();
+        let mut node = best;
+        while let Some((parent, edge)) = nodes[node].parent {
+            edges.push(edge);
+            node = parent;
+        }
+
+        let mut poses = vec![start];
+        for edge in edges.iter().rev() {
+            let steps = self.steps(edge);
+            poses.extend((1..=steps).map(|i| edge.at(edge.length * i as f32 / steps as f32)));
+        }
+
+        Ok(PlannedPath {
+            poses,
+            length: nodes[best].cost,
+        })
+    }
+
+    /// The edge from `from` that ends exactly at `to`, if one is within the curvature limit.
+    fn connect(&self, from: Pose2D, to: glam::Vec2) -> Option<Edge> {
+        let offset = to - from.position;
+        let distance = offset.length();
+        let Some(max_curvature) = self.max_curvature else {
+            return Some(Edge {
+                start: Pose2D {
+                    position: from.position,
+                    heading: offset.normalize_or(from.heading),
+                },
+                curvature: 0.,
+                length: distance,
+            });
+        };
+
+        let local = from.inverse().transform_point(to);
+        if local.x <= 0. {
+            return None;
+        }
+        // The arc through `to` tangent to the heading turns through twice the angle to `to`.
+        let curvature = 2. * local.y / distance.powi(2);
+        if curvature.abs() > max_curvature {
+            return None;
+        }
+        let length = if curvature.abs() < 1e-6 {
+            distance
+        } else {
+            2. * local.y.atan2(local.x) / curvature
+        };
+
+        Some(Edge {
+            start: from,
+            curvature,
+            length,
+        })
+    }
+
+    /// At most a step from `from` towards `to`, turning as hard as allowed if it can't be reached directly.
+    fn steer(&self, from: Pose2D, to: glam::Vec2) -> Option<Edge> {
+        let edge = match self.connect(from, to) {
+            Some(edge) => edge,
+            None => {
+                let side = from.inverse().transform_point(to).y;
+                Edge {
+                    start: from,
+                    curvature: self.max_curvature?.copysign(side),
+                    length: self.step,
+                }
+            }
+        };
+
+        Some(Edge {
+            length: edge.length.min(self.step),
+            ..edge
+        })
+    }
+
+    fn steps(&self, edge: &Edge) -> usize {
+        (edge.length / self.resolution).ceil().max(1.) as usize
+    }
+
+    fn edge_clear(&self, edge: &Edge, map: &OccupancyMap, esdf: Option<&Esdf>) -> bool {
+        let steps = self.steps(edge);
+        let points = (0..=steps)
+            .map(|i| edge.at(edge.length * i as f32 / steps as f32).position)
+            .collect::<Vec<_>>();
+
+        // Arcs are checked a chord at a time.
+        let clear = if edge.curvature == 0. {
+            map.segment_clear(points[0], points[steps])
+        } else {
+            points.windows(2).all(|w| map.segment_clear(w[0], w[1]))
+        };
+
+        clear && esdf.is_none_or(|e| points.iter().all(|&p| e.distance(p) >= self.clearance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        agent::Agent2DConfig,
+        math::Pose2D,
+        planning::{PlanError, PlannedPath, RrtStar},
+        rng,
+        scene::{generate, occupancy_map::OccupancyMap},
+    };
+
+    /// Two rooms joined by a door 2 cells wide at x = 0, -1 < y < 1.
+    fn two_rooms() -> OccupancyMap {
+        let size = glam::usizevec2(24, 12);
+        let pixels = generate::rooms(size.to_array(), [2, 1], 1, 2)
+            .into_iter()
+            .map(|p| p <= 127)
+            .collect();
+        OccupancyMap::from_pixels(size, pixels).unwrap()
+    }
+
+    /// Plans from the left room to the right one, checking the path goes clear through the door.
+    fn check(planner: &RrtStar) -> PlannedPath {
+        let map = two_rooms();
+        let start = Pose2D::new(glam::vec2(-6., 3.), 0.);
+        let goal = glam::vec2(6., -3.);
+        let path = rng::with_seed(Some(3), || planner.plan(&map, start, goal)).unwrap();
+        assert_eq!(path.poses[0], start);
+        assert!(path.poses.last().unwrap().position.distance(goal) <= planner.goal_tolerance);
+        assert!(
+            path.poses
+                .windows(2)
+                .all(|w| map.segment_clear(w[0].position, w[1].position))
+        );
+        assert!(
+            path.poses
+                .iter()
+                .any(|p| p.position.x.abs() < 0.5 && p.position.y.abs() < 1.)
+        );
+        // Through the door, at most a little longer than the two straight lines via its middle.
+        let shortest = start.position.length() + goal.length();
+        assert!(
+            path.length >= shortest - 1. && path.length < shortest * 1.25,
+            "{} vs {shortest}",
+            path.length
+        );
+
+        path
+    }
+
+    #[test]
+    fn test_rrt_star() {
+        check(&RrtStar {
+            max_iterations: 1500,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn test_rrt_star_kinematic() {
+        let agent = Agent2DConfig::default();
+        let path = check(&RrtStar {
+            max_iterations: 1500,
+            ..RrtStar::for_agent(&agent)
+        });
+        // Smooth enough to drive: the heading turns no faster than the steering allows.
+        for w in path.poses.windows(2).skip(1) {
+            let turn = w[0].heading.angle_to(w[1].heading).abs();
+            let distance = w[0].position.distance(w[1].position);
+            assert!(turn <= agent.max_curvature() * distance * 1.01 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_rrt_star_blocked() {
+        assert!(matches!(
+            RrtStar::default().plan(
+                &two_rooms(),
+                Pose2D::new(glam::vec2(0., 4.), 0.),
+                glam::vec2(6., -3.)
+            ),
+            Err(PlanError::StartBlocked(_))
+        ));
+    }
+}
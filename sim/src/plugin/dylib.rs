@@ -0,0 +1,251 @@
+use std::{
+    ffi::c_void,
+    mem::ManuallyDrop,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    Lidar2D,
+    controller::{AgentController, ControlContext, ControlInput},
+    plugin::PluginError,
+    sensors::{Sensor2D, lidar::Lidar2DSensed},
+};
+
+/// Bumped whenever the layout of [ControllerInputFfi] or the exported symbols change.
+pub const CONTROLLER_ABI_VERSION: u32 = 1;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"slam_stage_controller_abi_version";
+pub const CREATE_SYMBOL: &[u8] = b"slam_stage_controller_create";
+pub const CONTROL_SYMBOL: &[u8] = b"slam_stage_controller_control";
+pub const DESTROY_SYMBOL: &[u8] = b"slam_stage_controller_destroy";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ControllerInputFfi {
+    pub time: f32,
+    pub dt: f32,
+    pub position: [f32; 2],
+    pub heading: [f32; 2],
+    pub velocity: f32,
+    pub torque: f32,
+    pub beta: f32,
+    /// World-frame lidar hits, valid only for the duration of the call.
+    pub lidar_points: *const [f32; 2],
+    pub lidar_len: usize,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ControlOutputFfi {
+    pub torque: f32,
+    pub beta: f32,
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn() -> *mut c_void;
+type ControlFn = unsafe extern "C" fn(*mut c_void, *const ControllerInputFfi) -> ControlOutputFfi;
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+struct LoadedController {
+    handle: *mut c_void,
+    control: ControlFn,
+    destroy: DestroyFn,
+    // Dropped by hand so the library outlives the handle and function pointers above, and is closed before its shadow
+    // copy is removed.
+    library: ManuallyDrop<libloading::Library>,
+    shadow: PathBuf,
+}
+
+impl Drop for LoadedController {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.handle);
+            ManuallyDrop::drop(&mut self.library);
+        }
+        remove_shadow(&self.shadow);
+    }
+}
+
+/// A controller implemented in a dynamic library exporting the versioned C ABI above.
+///
+/// The library is copied to a temporary location before loading so the original can be rebuilt in place and picked up
+/// by [AgentController::hot_reload]. Each copy is removed once the library loaded from it is closed.
+pub struct DylibController {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    generation: u32,
+    loaded: LoadedController,
+}
+
+// SAFETY: The ABI contract requires controller handles to be movable across threads. Access is serialized by the
+// `Mutex` controllers are stored behind.
+unsafe impl Send for DylibController {}
+unsafe impl Sync for DylibController {}
+
+impl std::fmt::Debug for DylibController {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DylibController")
+            .field("path", &self.path)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn remove_shadow(shadow: &Path) {
+    if let Err(e) = std::fs::remove_file(shadow) {
+        log::warn!("Failed to remove {}: {e}", shadow.display());
+    }
+}
+
+fn shadow_path(path: &Path, generation: u32) -> Result<PathBuf, PluginError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| PluginError::Other(format!("Not a library path: {}", path.display())))?;
+
+    Ok(std::env::temp_dir().join(format!(
+        "{}-{}-{generation}",
+        std::process::id(),
+        file_name.to_string_lossy()
+    )))
+}
+
+fn load(path: &Path, generation: u32) -> Result<LoadedController, PluginError> {
+    let shadow = shadow_path(path, generation)?;
+    std::fs::copy(path, &shadow)?;
+
+    // On failure the library, if it was opened at all, is closed by the time this returns.
+    open(path, shadow.clone()).inspect_err(|_| remove_shadow(&shadow))
+}
+
+fn open(path: &Path, shadow: PathBuf) -> Result<LoadedController, PluginError> {
+    unsafe {
+        let library = libloading::Library::new(&shadow)?;
+
+        let version = library.get::<AbiVersionFn>(ABI_VERSION_SYMBOL)?();
+        if version != CONTROLLER_ABI_VERSION {
+            return Err(PluginError::AbiMismatch {
+                expected: CONTROLLER_ABI_VERSION,
+                found: version,
+            });
+        }
+
+        let create = *library.get::<CreateFn>(CREATE_SYMBOL)?;
+        let control = *library.get::<ControlFn>(CONTROL_SYMBOL)?;
+        let destroy = *library.get::<DestroyFn>(DESTROY_SYMBOL)?;
+
+        let handle = create();
+        if handle.is_null() {
+            return Err(PluginError::Other(format!(
+                "{} returned a null controller",
+                path.display()
+            )));
+        }
+
+        Ok(LoadedController {
+            handle,
+            control,
+            destroy,
+            library: ManuallyDrop::new(library),
+            shadow,
+        })
+    }
+}
+
+impl DylibController {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, PluginError> {
+        let path = path.into();
+
+        Ok(Self {
+            modified: modified(&path),
+            generation: 0,
+            loaded: load(&path, 0)?,
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AgentController for DylibController {
+    fn control(&mut self, ctx: &ControlContext) -> ControlInput {
+        let lidar = ctx.measurement::<Lidar2DSensed>(Lidar2D::TOPIC);
+        let points = lidar
+            .as_ref()
+            .map(|l| {
+                l.state
+                    .points
+                    .iter()
+                    .map(|p| p.to_array())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let input = ControllerInputFfi {
+            time: ctx.time.0,
+            dt: ctx.dt,
+            position: ctx.state.position.to_array(),
+            heading: ctx.state.heading.to_array(),
+            velocity: ctx.state.velocity,
+            torque: ctx.state.torque,
+            beta: ctx.state.beta,
+            lidar_points: points.as_ptr(),
+            lidar_len: points.len(),
+        };
+
+        let ControlOutputFfi { torque, beta } =
+            unsafe { (self.loaded.control)(self.loaded.handle, &input) };
+
+        ControlInput { torque, beta }
+    }
+
+    fn hot_reload(&mut self) -> bool {
+        let current = modified(&self.path);
+        if current.is_none() || current == self.modified {
+            return false;
+        }
+
+        match load(&self.path, self.generation + 1) {
+            Ok(loaded) => {
+                // Closes the previous library and removes its shadow copy.
+                self.loaded = loaded;
+                self.generation += 1;
+                self.modified = current;
+                log::info!("Reloaded controller {}", self.path.display());
+
+                true
+            }
+            Err(e) => {
+                // The library may still be mid-write; try again on the next poll.
+                log::warn!("Failed to reload {}: {e}", self.path.display());
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::plugin::{
+        PluginError,
+        dylib::{DylibController, shadow_path},
+    };
+
+    #[test]
+    fn test_failed_load_removes_shadow() {
+        let path = std::env::temp_dir().join(format!("{}-not-a-library.so", std::process::id()));
+        std::fs::write(&path, b"not a library").unwrap();
+
+        let result = DylibController::load(&path);
+        assert!(matches!(result, Err(PluginError::Library(_))), "{result:?}");
+        assert!(!shadow_path(&path, 0).unwrap().exists());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
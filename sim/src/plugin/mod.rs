@@ -0,0 +1,437 @@
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Lidar2D,
+    config::{ConfigError, Validate},
+    controller::AgentController,
+    localization::{DeadReckoning, Localizer, ParticleFilter},
+    math::Box2D,
+    plugin::dylib::DylibController,
+    sensors::{
+        DynSensor2D, Sensor2D,
+        beacon::BeaconRanger2D,
+        bumper::Bumper2D,
+        compass::{Compass2D, MagneticDisturbance},
+        landmark::LandmarkSensor2D,
+        radar::Radar2D,
+        sonar::Sonar2D,
+    },
+    slam::ekf::EkfSlam2D,
+};
+
+pub mod dylib;
+
+lazy_static::lazy_static! {
+    /// Registry consulted by scenario loaders. Downstream crates register their factories here before loading.
+    pub static ref PLUGINS: RwLock<PluginRegistry> = RwLock::new(PluginRegistry::with_builtins());
+}
+
+/// Format-agnostic parameter tree handed to plugin factories.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<ParamValue>),
+    Map(PluginParams),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PluginParams(pub FxHashMap<String, ParamValue>);
+
+impl PluginParams {
+    pub fn get(&self, key: &str) -> Option<&ParamValue> {
+        self.0.get(key)
+    }
+
+    pub fn f32_or(&self, key: &str, default: f32) -> Result<f32, PluginError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(ParamValue::Number(n)) => Ok(*n as f32),
+            Some(_) => Err(PluginError::InvalidParam(key.to_string(), "number")),
+        }
+    }
+
+    pub fn usize_or(&self, key: &str, default: usize) -> Result<usize, PluginError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(ParamValue::Number(n)) if *n >= 0. && n.fract() == 0. => Ok(*n as usize),
+            Some(_) => Err(PluginError::InvalidParam(
+                key.to_string(),
+                "non-negative integer",
+            )),
+        }
+    }
+
+    pub fn bool_or(&self, key: &str, default: bool) -> Result<bool, PluginError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(ParamValue::Bool(b)) => Ok(*b),
+            Some(_) => Err(PluginError::InvalidParam(key.to_string(), "boolean")),
+        }
+    }
+
+    pub fn vec2_or(&self, key: &str, default: glam::Vec2) -> Result<glam::Vec2, PluginError> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(ParamValue::List(list)) => match list.as_slice() {
+                [ParamValue::Number(x), ParamValue::Number(y)] => {
+                    Ok(glam::vec2(*x as f32, *y as f32))
+                }
+                _ => Err(PluginError::InvalidParam(key.to_string(), "[x, y]")),
+            },
+            Some(_) => Err(PluginError::InvalidParam(key.to_string(), "[x, y]")),
+        }
+    }
+
+    /// The entries of a list of maps, e.g. a list of regions.
+    pub fn maps(&self, key: &str) -> Result<Vec<&PluginParams>, PluginError> {
+        match self.get(key) {
+            None => Ok(Vec::new()),
+            Some(ParamValue::List(list)) => list
+                .iter()
+                .map(|v| match v {
+                    ParamValue::Map(m) => Ok(m),
+                    _ => Err(PluginError::InvalidParam(key.to_string(), "list of maps")),
+                })
+                .collect(),
+            Some(_) => Err(PluginError::InvalidParam(key.to_string(), "list of maps")),
+        }
+    }
+
+    pub fn strs(&self, key: &str) -> Result<Vec<&str>, PluginError> {
+        match self.get(key) {
+            None => Ok(Vec::new()),
+            Some(ParamValue::List(list)) => list
+                .iter()
+                .map(|v| match v {
+                    ParamValue::String(s) => Ok(s.as_str()),
+                    _ => Err(PluginError::InvalidParam(
+                        key.to_string(),
+                        "list of strings",
+                    )),
+                })
+                .collect(),
+            Some(_) => Err(PluginError::InvalidParam(
+                key.to_string(),
+                "list of strings",
+            )),
+        }
+    }
+
+    pub fn str(&self, key: &str) -> Result<&str, PluginError> {
+        match self.get(key) {
+            None => Err(PluginError::MissingParam(key.to_string())),
+            Some(ParamValue::String(s)) => Ok(s),
+            Some(_) => Err(PluginError::InvalidParam(key.to_string(), "string")),
+        }
+    }
+}
+
+pub type SensorFactory =
+    Box<dyn Fn(&PluginParams) -> Result<Arc<RwLock<dyn DynSensor2D>>, PluginError> + Send + Sync>;
+pub type ControllerFactory = Box<
+    dyn Fn(&PluginParams) -> Result<Arc<Mutex<dyn AgentController>>, PluginError> + Send + Sync,
+>;
+pub type LocalizerFactory =
+    Box<dyn Fn(&PluginParams) -> Result<Arc<Mutex<dyn Localizer>>, PluginError> + Send + Sync>;
+
+#[derive(Default)]
+pub struct PluginRegistry {
+    sensors: FxHashMap<String, SensorFactory>,
+    controllers: FxHashMap<String, ControllerFactory>,
+    localizers: FxHashMap<String, LocalizerFactory>,
+}
+
+impl std::fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginRegistry")
+            .field("sensors", &self.sensors.keys().collect::<Vec<_>>())
+            .field("controllers", &self.controllers.keys().collect::<Vec<_>>())
+            .field("localizers", &self.localizers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl PluginRegistry {
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+
+        registry.register_sensor(Lidar2D::TOPIC, |params| {
+            let mut lidar = Lidar2D::regular(params.usize_or("count", 60)?);
+            lidar.semantic = params.bool_or("semantic", false)?;
+            lidar.scan_duration = params.f32_or("scan_duration", 0.)?;
+            lidar.ray_jitter = params.f32_or("ray_jitter", 0.)?;
+
+            Ok(validated(lidar)?)
+        });
+
+        registry.register_sensor(LandmarkSensor2D::TOPIC, |params| {
+            let default = LandmarkSensor2D::default();
+
+            Ok(validated(LandmarkSensor2D {
+                fov: params.f32_or("fov", default.fov)?,
+                max_range: params.f32_or("max_range", default.max_range)?,
+            })?)
+        });
+
+        registry.register_sensor(BeaconRanger2D::TOPIC, |params| {
+            let default = BeaconRanger2D::default();
+            let nlos_bias = match params.bool_or("drop_occluded", false)? {
+                true => None,
+                false => Some(params.f32_or("nlos_bias", default.nlos_bias.unwrap_or(0.5))?),
+            };
+
+            Ok(validated(BeaconRanger2D {
+                max_range: params.f32_or("max_range", default.max_range)?,
+                noise: params.f32_or("noise", default.noise)?,
+                nlos_bias,
+            })?)
+        });
+
+        registry.register_sensor(Sonar2D::TOPIC, |params| {
+            let default = Sonar2D::default();
+            let angle = params.f32_or("angle", 0.)?;
+
+            Ok(validated(Sonar2D {
+                direction: glam::Vec2::from_angle(angle),
+                cone: params.f32_or("cone", default.cone)?,
+                min_range: params.f32_or("min_range", default.min_range)?,
+                max_range: params.f32_or("max_range", default.max_range)?,
+                rays: params.usize_or("rays", default.rays)?,
+            })?)
+        });
+
+        registry.register_sensor(Radar2D::TOPIC, |params| {
+            let default = Radar2D::default();
+
+            Ok(validated(Radar2D {
+                fov: params.f32_or("fov", default.fov)?,
+                max_range: params.f32_or("max_range", default.max_range)?,
+                range_noise: params.f32_or("range_noise", default.range_noise)?,
+                bearing_noise: params.f32_or("bearing_noise", default.bearing_noise)?,
+                velocity_noise: params.f32_or("velocity_noise", default.velocity_noise)?,
+                false_alarm_rate: params.f32_or("false_alarm_rate", default.false_alarm_rate)?,
+            })?)
+        });
+
+        registry.register_sensor(Bumper2D::TOPIC, |_| Ok(Arc::new(RwLock::new(Bumper2D))));
+
+        registry.register_sensor(Compass2D::TOPIC, |params| {
+            let mut compass = Compass2D::default();
+            compass.declination = params.f32_or("declination", 0.)?;
+            compass.bias = params.f32_or("bias", 0.)?;
+            compass.noise = params.f32_or("noise", 0.)?;
+            compass.bias_walk = params.f32_or("bias_walk", 0.)?;
+            compass.disturbances = params
+                .maps("disturbances")?
+                .into_iter()
+                .map(|d| {
+                    Ok(MagneticDisturbance {
+                        region: Box2D {
+                            min: d.vec2_or("min", glam::Vec2::ZERO)?,
+                            max: d.vec2_or("max", glam::Vec2::ZERO)?,
+                        },
+                        amplitude: d.f32_or("amplitude", 0.)?,
+                        period: d.f32_or("period", 0.)?,
+                    })
+                })
+                .collect::<Result<_, PluginError>>()?;
+
+            Ok(validated(compass)?)
+        });
+
+        registry.register_controller("dylib", |params| {
+            Ok(Arc::new(Mutex::new(DylibController::load(
+                params.str("path")?,
+            )?)))
+        });
+
+        registry.register_localizer("dead_reckoning", |params| {
+            let localizer = DeadReckoning::new(
+                params.f32_or("velocity_noise", 0.)?,
+                params.f32_or("beta_noise", 0.)?,
+            );
+            localizer.validate()?;
+
+            Ok(Arc::new(Mutex::new(localizer)))
+        });
+
+        registry.register_localizer("particle_filter", |params| {
+            let mut localizer = ParticleFilter::default();
+            localizer.particle_count =
+                params.usize_or("particle_count", localizer.particle_count)?;
+            localizer.velocity_noise = params.f32_or("velocity_noise", localizer.velocity_noise)?;
+            localizer.beta_noise = params.f32_or("beta_noise", localizer.beta_noise)?;
+            localizer.hit_sigma = params.f32_or("hit_sigma", localizer.hit_sigma)?;
+            localizer.random_weight = params.f32_or("random_weight", localizer.random_weight)?;
+            localizer.max_beams = params.usize_or("max_beams", localizer.max_beams)?;
+            localizer.initial_spread = (
+                params.f32_or("position_spread", localizer.initial_spread.0)?,
+                params.f32_or("heading_spread", localizer.initial_spread.1)?,
+            );
+            if params.get("lidar").is_some() {
+                localizer.lidar_topic = params.str("lidar")?.to_string();
+            }
+            localizer.validate()?;
+
+            Ok(Arc::new(Mutex::new(localizer)))
+        });
+
+        registry.register_localizer("ekf_slam", |params| {
+            let mut localizer = EkfSlam2D::default();
+            localizer.velocity_noise = params.f32_or("velocity_noise", localizer.velocity_noise)?;
+            localizer.beta_noise = params.f32_or("beta_noise", localizer.beta_noise)?;
+            localizer.range_noise = params.f32_or("range_noise", localizer.range_noise)?;
+            localizer.bearing_noise = params.f32_or("bearing_noise", localizer.bearing_noise)?;
+            localizer.association_gate =
+                params.f32_or("association_gate", localizer.association_gate)?;
+            localizer.new_landmark_gate =
+                params.f32_or("new_landmark_gate", localizer.new_landmark_gate)?;
+            if params.get("landmarks").is_some() {
+                localizer.landmark_topic = params.str("landmarks")?.to_string();
+            }
+            localizer.validate()?;
+
+            Ok(Arc::new(Mutex::new(localizer)))
+        });
+
+        registry
+    }
+
+    pub fn register_sensor<F>(&mut self, kind: impl Into<String>, factory: F)
+    where
+        F: Fn(&PluginParams) -> Result<Arc<RwLock<dyn DynSensor2D>>, PluginError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.sensors.insert(kind.into(), Box::new(factory));
+    }
+
+    pub fn register_controller<F>(&mut self, kind: impl Into<String>, factory: F)
+    where
+        F: Fn(&PluginParams) -> Result<Arc<Mutex<dyn AgentController>>, PluginError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.controllers.insert(kind.into(), Box::new(factory));
+    }
+
+    pub fn register_localizer<F>(&mut self, kind: impl Into<String>, factory: F)
+    where
+        F: Fn(&PluginParams) -> Result<Arc<Mutex<dyn Localizer>>, PluginError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.localizers.insert(kind.into(), Box::new(factory));
+    }
+
+    pub fn sensor_kinds(&self) -> impl Iterator<Item = &str> {
+        self.sensors.keys().map(String::as_str)
+    }
+
+    pub fn controller_kinds(&self) -> impl Iterator<Item = &str> {
+        self.controllers.keys().map(String::as_str)
+    }
+
+    pub fn localizer_kinds(&self) -> impl Iterator<Item = &str> {
+        self.localizers.keys().map(String::as_str)
+    }
+
+    pub fn create_sensor(
+        &self,
+        kind: &str,
+        params: &PluginParams,
+    ) -> Result<Arc<RwLock<dyn DynSensor2D>>, PluginError> {
+        let factory = self
+            .sensors
+            .get(kind)
+            .ok_or_else(|| PluginError::UnknownSensor(kind.to_string()))?;
+
+        factory(params)
+    }
+
+    pub fn create_controller(
+        &self,
+        kind: &str,
+        params: &PluginParams,
+    ) -> Result<Arc<Mutex<dyn AgentController>>, PluginError> {
+        let factory = self
+            .controllers
+            .get(kind)
+            .ok_or_else(|| PluginError::UnknownController(kind.to_string()))?;
+
+        factory(params)
+    }
+
+    pub fn create_localizer(
+        &self,
+        kind: &str,
+        params: &PluginParams,
+    ) -> Result<Arc<Mutex<dyn Localizer>>, PluginError> {
+        let factory = self
+            .localizers
+            .get(kind)
+            .ok_or_else(|| PluginError::UnknownLocalizer(kind.to_string()))?;
+
+        factory(params)
+    }
+}
+
+fn validated<S>(sensor: S) -> Result<Arc<RwLock<S>>, ConfigError>
+where
+    S: Validate,
+{
+    sensor.validate()?;
+
+    Ok(Arc::new(RwLock::new(sensor)))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PluginError {
+    #[error("Unknown sensor kind: {0}")]
+    UnknownSensor(String),
+
+    #[error("Unknown controller kind: {0}")]
+    UnknownController(String),
+
+    #[error("Unknown localizer kind: {0}")]
+    UnknownLocalizer(String),
+
+    #[error("Missing parameter: {0}")]
+    MissingParam(String),
+
+    #[error("Invalid parameter {0}: expected {1}")]
+    InvalidParam(String, &'static str),
+
+    #[error("Invalid configuration: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Controller ABI mismatch: expected version {expected}, library provides {found}")]
+    AbiMismatch { expected: u32, found: u32 },
+
+    #[error("IOError: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("LibraryError: {0}")]
+    Library(#[from] libloading::Error),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl PluginError {
+    /// Nests configuration errors under `parent`, see [ConfigError::in_field].
+    pub fn in_field(self, parent: &str) -> Self {
+        match self {
+            Self::Config(e) => Self::Config(e.in_field(parent)),
+            e => e,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! The types most users of the simulator need, to be glob imported with `use sim::prelude::*`.
+
+pub use crate::{
+    Agent2D, Scene2D,
+    agent::{Agent2DConfig, Agent2DState},
+    config::Validate,
+    control::PurePursuit,
+    controller::{AgentController, ControlContext, ControlInput},
+    dynamics::{Bicycle, DifferentialDrive, Dynamics2D, DynamicsModel, Integrator, Omni, Tyres},
+    env::{Env2D, EnvError, ObservationConfig, Step},
+    localization::{
+        DeadReckoning, Localizer, LocalizerContext, Odometry, OdometryIntegrator, ParticleFilter,
+        PoseEstimate, PoseSource,
+    },
+    math::Pose2D,
+    planning::{PlanError, PlannedPath, RrtStar},
+    plugin::PluginRegistry,
+    scene::{
+        AgentCollisionEvent, AgentId, BeaconId, BoundaryPolicy, CollisionEvent, HitTag, LandmarkId,
+        OccupancyMap, OutOfBoundsAction, OutOfBoundsEvent, Scene2DError, Scene2DLoop, SceneHistory,
+        SceneTime,
+        events::SceneEvent,
+        obstacles::{DynamicObstacle, ObstacleId, ObstaclePath, ObstacleShape},
+        ros_map::RosMap,
+        source::ObstacleSource,
+        surface::{Surface, SurfaceMap},
+        vector_map::VectorMap,
+    },
+    sensors::{
+        BeaconRanger2D, BeaconSensed, Bumper2D, BumperSensed, Compass2D, CompassSensed,
+        LandmarkSensed, LandmarkSensor2D, Lidar2D, Lidar2DSensed, Radar2D, RadarSensed,
+        SensingCost, Sensor2D, Sonar2D, SonarSensed, TimeStamped,
+    },
+    sim_config::{AgentCollisionMode, CollisionMode, SimConfig},
+};
@@ -0,0 +1,197 @@
+//! Egocentric top-down images of scans and grids, for learning pipelines that feed them to CNNs. Images are square,
+//! row-major with the first row at the top, and centred on the agent facing up. Pixels hold the probability of being
+//! occupied: 1 for a hit, 0 for free space a ray passed through, and one half for unknown.
+//!
+//! The workspace has no Python bindings yet, so this is Rust only. The flat `f32` buffers are laid out to be handed
+//! to NumPy as they are once it does.
+
+use rayon::prelude::*;
+
+use crate::{Lidar2D, mapping::LogOddsGrid, math::Pose2D, sensors::lidar::Lidar2DSensed};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanRasterizer {
+    /// Width and height of the image in pixels.
+    pub size: usize,
+    /// Metres per pixel.
+    pub resolution: f32,
+}
+
+impl Default for ScanRasterizer {
+    fn default() -> Self {
+        Self {
+            size: 64,
+            resolution: 0.1,
+        }
+    }
+}
+
+impl ScanRasterizer {
+    /// Number of values in each image.
+    pub fn len(&self) -> usize {
+        self.size * self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Pixel containing `point`, given in the agent's frame with x forward and y to the left.
+    fn pixel(&self, point: glam::Vec2) -> Option<usize> {
+        let half = self.size as f32 / 2.;
+        let col = (half - point.y / self.resolution).floor();
+        let row = (half - point.x / self.resolution).floor();
+        let range = 0. ..self.size as f32;
+
+        (range.contains(&col) && range.contains(&row))
+            .then(|| row as usize * self.size + col as usize)
+    }
+
+    /// Centre of pixel `i` in the agent's frame.
+    fn center(&self, i: usize) -> glam::Vec2 {
+        let half = self.size as f32 / 2.;
+        let (col, row) = ((i % self.size) as f32, (i / self.size) as f32);
+
+        glam::vec2(half - row - 0.5, half - col - 0.5) * self.resolution
+    }
+
+    /// Traces each ray of `scan`, taken by `lidar`, out from the centre of the image.
+    pub fn scan(&self, lidar: &Lidar2D, scan: &Lidar2DSensed) -> Vec<f32> {
+        let mut image = vec![0.5; self.len()];
+        // Far enough to leave the image in any direction.
+        let reach = self.size as f32 * self.resolution;
+        let step = self.resolution / 2.;
+
+        for (&dir, &range) in lidar.directions.iter().zip(&scan.ranges) {
+            let free = range.min(reach);
+            for i in 0..(free / step) as usize {
+                if let Some(p) = self.pixel(dir * i as f32 * step)
+                    && image[p] != 1.
+                {
+                    image[p] = 0.;
+                }
+            }
+            if range <= reach
+                && let Some(p) = self.pixel(dir * range)
+            {
+                image[p] = 1.;
+            }
+        }
+
+        image
+    }
+
+    /// Scans of one lidar concatenated into a tensor of shape `(scans.len(), size, size)`, rendered in parallel.
+    pub fn scans(&self, lidar: &Lidar2D, scans: &[Lidar2DSensed]) -> Vec<f32> {
+        let mut images = vec![0.; scans.len() * self.len()];
+        if self.is_empty() {
+            return images;
+        }
+
+        images
+            .par_chunks_mut(self.len())
+            .zip(scans)
+            .for_each(|(image, scan)| image.copy_from_slice(&self.scan(lidar, scan)));
+
+        images
+    }
+
+    /// Samples `grid` around an agent at `pose`, as the agent would see it.
+    pub fn grid(&self, grid: &LogOddsGrid, pose: Pose2D) -> Vec<f32> {
+        (0..self.len())
+            .map(|i| {
+                let local = self.center(i);
+                let world = pose.position + pose.heading * local.x + pose.heading.perp() * local.y;
+                grid.probability(world)
+            })
+            .collect()
+    }
+
+    /// Black for occupied, white for free and grey for unknown, like [LogOddsGrid::to_image].
+    pub fn to_image(&self, image: &[f32]) -> image::GrayImage {
+        let pixels = image
+            .iter()
+            .map(|&p| ((1. - p.clamp(0., 1.)) * 255.).round() as u8)
+            .collect();
+
+        image::GrayImage::from_raw(self.size as u32, self.size as u32, pixels)
+            .expect("The image has one value per pixel")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Lidar2D, mapping::LogOddsGrid, math::Pose2D, raster::ScanRasterizer,
+        scene::occupancy_map::OccupancyMap, sensors::lidar::Lidar2DSensed,
+    };
+
+    #[test]
+    fn test_scan_raster() {
+        let raster = ScanRasterizer {
+            size: 40,
+            resolution: 0.1,
+        };
+        let mut lidar = Lidar2D::default();
+        lidar.update_directions(vec![
+            glam::Vec2::X,
+            glam::Vec2::Y,
+            glam::Vec2::NEG_X,
+            glam::Vec2::NEG_Y,
+        ]);
+        let scan = Lidar2DSensed {
+            ranges: vec![1.05, f32::INFINITY, 0.55, 1.55],
+            points: Vec::new(),
+            tags: None,
+            time_offsets: None,
+        };
+
+        let image = raster.scan(&lidar, &scan);
+        let at = |forward: f32, left: f32| image[raster.pixel(glam::vec2(forward, left)).unwrap()];
+        assert_eq!(image.len(), 1600);
+        assert_eq!(at(1.05, 0.), 1.);
+        assert_eq!(at(0.5, 0.), 0.);
+        assert_eq!(at(1.5, 0.), 0.5);
+        // The ray to the left hit nothing, so is free up to the edge of the image.
+        assert_eq!(at(0., 1.95), 0.);
+        assert_eq!(at(-0.55, 0.), 1.);
+        assert_eq!(at(0., -1.55), 1.);
+        assert_eq!(at(1., 1.), 0.5);
+
+        let batch = raster.scans(&lidar, &[scan.clone(), scan]);
+        assert_eq!(batch.len(), 2 * 1600);
+        assert_eq!(&batch[1600..], image.as_slice());
+    }
+
+    #[test]
+    fn test_grid_raster() {
+        let raster = ScanRasterizer {
+            size: 40,
+            resolution: 0.1,
+        };
+
+        // A wall across the top of an 8 x 8 map, seen by an agent facing it and one facing away.
+        let mut pixels = vec![false; 64];
+        pixels[..8].fill(true);
+        let grid = LogOddsGrid::from_occupancy_map(
+            &OccupancyMap::from_pixels(glam::usizevec2(8, 8), pixels).unwrap(),
+        );
+        let facing = raster.grid(
+            &grid,
+            Pose2D::new(glam::vec2(0.5, 1.5), std::f32::consts::FRAC_PI_2),
+        );
+        let at =
+            |image: &[f32], forward: f32| image[raster.pixel(glam::vec2(forward, 0.)).unwrap()];
+        assert!(at(&facing, 1.5) < 0.01);
+        assert!(at(&facing, 1.95) > 0.99);
+        let away = raster.grid(
+            &grid,
+            Pose2D::new(glam::vec2(0.5, 1.5), -std::f32::consts::FRAC_PI_2),
+        );
+        assert!(at(&away, -1.95) > 0.99);
+
+        let picture = raster.to_image(&facing);
+        assert_eq!(picture.dimensions(), (40, 40));
+        assert!(picture.get_pixel(20, 0).0[0] < 5);
+    }
+}
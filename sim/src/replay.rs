@@ -0,0 +1,203 @@
+//! Agents that replay a timestamped trajectory instead of running their dynamics, so other traffic plays out the same
+//! way every run while the agent under test drives itself.
+
+use crate::{
+    agent::Agent2DState,
+    math::Pose2D,
+    scene::{AgentId, SceneTime, history::SceneHistory},
+};
+
+/// Where the agent is at `time`, in seconds of scene time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrajectoryPoint {
+    pub time: f32,
+    pub pose: Pose2D,
+}
+
+/// Poses at increasing times, interpolated between. The agent holds the first pose before it and the last after it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "Vec<TrajectoryPoint>", into = "Vec<TrajectoryPoint>")
+)]
+pub struct Trajectory {
+    points: Vec<TrajectoryPoint>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq)]
+pub enum TrajectoryError {
+    #[error("Trajectory has no points")]
+    Empty,
+
+    #[error("Point {0} has a time or pose that isn't finite")]
+    NotFinite(usize),
+
+    #[error("Point {0} comes no later than the one before it")]
+    Unordered(usize),
+}
+
+impl Trajectory {
+    pub fn new(points: Vec<TrajectoryPoint>) -> Result<Self, TrajectoryError> {
+        if points.is_empty() {
+            return Err(TrajectoryError::Empty);
+        }
+
+        for (i, point) in points.iter().enumerate() {
+            let Pose2D { position, heading } = point.pose;
+            if !point.time.is_finite() || !position.is_finite() || !heading.is_finite() {
+                return Err(TrajectoryError::NotFinite(i));
+            }
+            if i > 0 && point.time <= points[i - 1].time {
+                return Err(TrajectoryError::Unordered(i));
+            }
+        }
+
+        Ok(Self { points })
+    }
+
+    /// What `agent` did over `history`, or `None` if it wasn't there.
+    pub fn from_history(history: &SceneHistory, agent: AgentId) -> Option<Self> {
+        let points = history
+            .iter()
+            .filter_map(|snapshot| {
+                Some(TrajectoryPoint {
+                    time: snapshot.time.0,
+                    pose: snapshot.agent(agent)?.state.pose(),
+                })
+            })
+            .collect();
+
+        Self::new(points).ok()
+    }
+
+    #[inline]
+    pub fn points(&self) -> &[TrajectoryPoint] {
+        &self.points
+    }
+
+    /// The points either side of `time` and how far it is between them.
+    fn segment(&self, time: f32) -> (TrajectoryPoint, TrajectoryPoint, f32) {
+        let next = self.points.partition_point(|p| p.time <= time);
+        match next {
+            0 => (self.points[0], self.points[0], 0.),
+            n if n == self.points.len() => (self.points[n - 1], self.points[n - 1], 0.),
+            n => {
+                let (a, b) = (self.points[n - 1], self.points[n]);
+                (a, b, (time - a.time) / (b.time - a.time))
+            }
+        }
+    }
+
+    pub fn pose_at(&self, time: SceneTime) -> Pose2D {
+        let (a, b, t) = self.segment(time.0);
+
+        a.pose.interpolate(&b.pose, t)
+    }
+
+    /// `state` moved to where the trajectory is at `time`, moving as it does there. Steering and torque are left
+    /// alone, as nothing drives a replayed agent.
+    pub fn state_at(&self, time: SceneTime, state: &Agent2DState) -> Agent2DState {
+        let (a, b, _) = self.segment(time.0);
+        let pose = self.pose_at(time);
+
+        let span = b.time - a.time;
+        let (velocity, yaw_rate) = if span > 0. {
+            (
+                (b.pose.position - a.pose.position) / span,
+                a.pose.heading.angle_to(b.pose.heading) / span,
+            )
+        } else {
+            (glam::Vec2::ZERO, 0.)
+        };
+
+        Agent2DState {
+            velocity: velocity.dot(pose.heading),
+            lateral_velocity: velocity.dot(pose.heading.perp()),
+            yaw_rate,
+            ..state.with_pose(pose)
+        }
+    }
+}
+
+impl TryFrom<Vec<TrajectoryPoint>> for Trajectory {
+    type Error = TrajectoryError;
+
+    fn try_from(points: Vec<TrajectoryPoint>) -> Result<Self, Self::Error> {
+        Self::new(points)
+    }
+}
+
+impl From<Trajectory> for Vec<TrajectoryPoint> {
+    fn from(trajectory: Trajectory) -> Self {
+        trajectory.points
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        controller::ControlInput,
+        math::Pose2D,
+        replay::{Trajectory, TrajectoryError, TrajectoryPoint},
+        scene::{SceneTime, history::SceneHistory},
+    };
+
+    #[test]
+    fn test_replay() {
+        let point = |time, x, angle| TrajectoryPoint {
+            time,
+            pose: Pose2D::new(glam::vec2(x, 0.), angle),
+        };
+        assert_eq!(Trajectory::new(vec![]), Err(TrajectoryError::Empty));
+        assert_eq!(
+            Trajectory::new(vec![point(1., 0., 0.), point(1., 1., 0.)]),
+            Err(TrajectoryError::Unordered(1))
+        );
+
+        let trajectory = Trajectory::new(vec![point(1., 0., 0.), point(3., 4., 0.)]).unwrap();
+        assert_eq!(trajectory.pose_at(SceneTime(0.)).position, glam::Vec2::ZERO);
+        assert_eq!(
+            trajectory.pose_at(SceneTime(2.)).position,
+            glam::vec2(2., 0.)
+        );
+        assert_eq!(
+            trajectory.pose_at(SceneTime(9.)).position,
+            glam::vec2(4., 0.)
+        );
+        let state = trajectory.state_at(SceneTime(2.), &Default::default());
+        assert_eq!((state.velocity, state.lateral_velocity), (2., 0.));
+
+        // Record a driven agent, then replay it alongside one that is commanded to do something else.
+        let pixels = [255; 400];
+        let mut scene = Scene2D::from_pixels([20, 20], &pixels).unwrap();
+        let mut history = SceneHistory::new(10.);
+        let mut agent = Agent2D::default();
+        agent.command(ControlInput {
+            torque: 20.,
+            beta: 0.2,
+        });
+        let driven = scene.add_agent(agent);
+        for _ in 0..20 {
+            scene.update(0.05);
+            history.record(&scene);
+        }
+        let recorded = Trajectory::from_history(&history, driven).unwrap();
+
+        let mut scene = Scene2D::from_pixels([20, 20], &pixels).unwrap();
+        let mut agent = Agent2D::replaying(recorded.clone());
+        agent.command(ControlInput {
+            torque: -20.,
+            beta: -0.2,
+        });
+        let replayed = scene.add_agent(agent);
+        for point in recorded.points() {
+            scene.update(0.05);
+            let state = scene.agents[&replayed].state;
+            assert!(state.position.abs_diff_eq(point.pose.position, 1e-4));
+            assert!(state.heading.abs_diff_eq(point.pose.heading, 1e-4));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Randomness for the simulator. Draws come from the thread RNG unless the calling thread is inside
+//! [with_seed], which is how seeded scenes repeat exactly.
+
+use std::{
+    cell::RefCell,
+    hash::{Hash, Hasher},
+};
+
+use rand::{
+    RngCore, SeedableRng,
+    rngs::{StdRng, ThreadRng},
+};
+
+thread_local! {
+    static SEEDED: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Source of randomness used in place of [rand::rng] by sensors, localizers and anything else that should repeat in
+/// a seeded run.
+#[derive(Debug, Clone)]
+pub struct SimRng(ThreadRng);
+
+pub fn rng() -> SimRng {
+    SimRng(rand::rng())
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        SEEDED.with_borrow_mut(|seeded| match seeded {
+            Some(seeded) => seeded.next_u32(),
+            None => self.0.next_u32(),
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        SEEDED.with_borrow_mut(|seeded| match seeded {
+            Some(seeded) => seeded.next_u64(),
+            None => self.0.next_u64(),
+        })
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        SEEDED.with_borrow_mut(|seeded| match seeded {
+            Some(seeded) => seeded.fill_bytes(dst),
+            None => self.0.fill_bytes(dst),
+        })
+    }
+}
+
+/// Runs `f` with [rng] drawing from a generator seeded with `seed` on this thread, or from the thread RNG if `None`.
+pub fn with_seed<R>(seed: Option<u64>, f: impl FnOnce() -> R) -> R {
+    let Some(seed) = seed else {
+        return f();
+    };
+
+    let previous = SEEDED.replace(Some(StdRng::seed_from_u64(seed)));
+    let result = f();
+    SEEDED.set(previous);
+
+    result
+}
+
+/// Mixes `parts` into `seed`, giving each job of a seeded run its own stream that doesn't depend on which thread or
+/// in what order the jobs run.
+pub fn derive_seed(seed: u64, parts: impl Hash) -> u64 {
+    let mut hasher = rustc_hash::FxHasher::default();
+    (seed, parts).hash(&mut hasher);
+
+    hasher.finish()
+}
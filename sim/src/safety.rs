@@ -0,0 +1,254 @@
+use crate::{
+    agent::{Agent2DConfig, Agent2DState, integrate},
+    config::{self, ConfigError, Validate},
+    controller::ControlInput,
+    math::{ConvexPolygon, OrientedBox2D},
+    scene::{AgentId, Scene2DState, SceneTime},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SafetyIntervention {
+    pub time: SceneTime,
+    pub proposed: ControlInput,
+    pub applied: ControlInput,
+}
+
+/// Overrides controller outputs whose predicted swept footprint collides with the map or another agent within
+/// `horizon` seconds.
+#[derive(Debug, Clone)]
+pub struct SafetySupervisor {
+    /// Prediction horizon in seconds.
+    pub horizon: f32,
+    pub steps: usize,
+    /// Extra clearance added to each side of the footprint while predicting.
+    pub margin: f32,
+    pub interventions: Vec<SafetyIntervention>,
+}
+
+impl Default for SafetySupervisor {
+    fn default() -> Self {
+        Self {
+            horizon: 0.5,
+            steps: 10,
+            margin: 0.,
+            interventions: Vec::new(),
+        }
+    }
+}
+
+impl Validate for SafetySupervisor {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("horizon", self.horizon)?;
+        config::at_least("steps", self.steps, 1)?;
+        config::finite("margin", self.margin)?;
+        config::non_negative("margin", self.margin)
+    }
+}
+
+fn footprint(config: &Agent2DConfig, state: &Agent2DState, margin: f32) -> OrientedBox2D {
+    let mut footprint = config.footprint(state);
+    footprint.half_extent += margin;
+
+    footprint
+}
+
+impl SafetySupervisor {
+    /// Whether holding `input` keeps the agent clear of the map and other agents for the whole horizon.
+    pub fn is_safe(
+        &self,
+        agent: AgentId,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        input: ControlInput,
+        scene: &Scene2DState,
+    ) -> bool {
+        let dt = self.horizon / self.steps.max(1) as f32;
+
+        let mut last = *state;
+        let mut current = Agent2DState {
+            torque: input.torque,
+            beta: input.beta,
+            ..*state
+        };
+
+        for _ in 0..self.steps.max(1) {
+            let next = integrate(config, &current, Some(&last), dt);
+            (last, current) = (current, next);
+
+            let swept = footprint(config, &current, self.margin);
+            let outline = ConvexPolygon::from(swept);
+            if scene.obstacle_source().overlaps(&swept)
+                || scene
+                    .agents
+                    .iter()
+                    .any(|(id, other)| *id != agent && outline.intersects(other))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns `proposed` if it is safe, otherwise the first safe fallback (braking, then braking with the wheels
+    /// straightened), recording the intervention.
+    pub fn filter(
+        &mut self,
+        agent: AgentId,
+        config: &Agent2DConfig,
+        state: &Agent2DState,
+        proposed: ControlInput,
+        scene: &Scene2DState,
+    ) -> ControlInput {
+        if self.is_safe(agent, config, state, proposed, scene) {
+            return proposed;
+        }
+
+        let brake = if state.velocity > 0. {
+            config.torque_range.0
+        } else {
+            config.torque_range.1
+        };
+        let candidates = [
+            ControlInput {
+                torque: brake,
+                beta: proposed.beta,
+            },
+            ControlInput {
+                torque: brake,
+                beta: 0.,
+            },
+        ];
+
+        let applied = candidates
+            .into_iter()
+            .find(|&c| self.is_safe(agent, config, state, c, scene))
+            .unwrap_or(candidates[1]);
+
+        log::warn!("Safety supervisor overrode {agent:?}: {proposed:?} -> {applied:?}");
+        self.interventions.push(SafetyIntervention {
+            time: scene.time,
+            proposed,
+            applied,
+        });
+
+        applied
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Scene2D,
+        controller::{AgentController, ControlContext, ControlInput},
+        safety::SafetySupervisor,
+        scene::AgentId,
+        testing,
+    };
+
+    const FLOOR_IT: ControlInput = ControlInput {
+        torque: 100.,
+        beta: 0.,
+    };
+
+    /// Full throttle straight ahead, whatever is in the way.
+    #[derive(Debug)]
+    struct Floor;
+
+    impl AgentController for Floor {
+        fn control(&mut self, _: &ControlContext) -> ControlInput {
+            FLOOR_IT
+        }
+    }
+
+    /// An agent at `x` on the middle row of the open room, driving up it at `velocity`.
+    fn driving(x: f32, velocity: f32) -> (Scene2D, AgentId) {
+        let mut scene = testing::open_room();
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(x, 0.);
+        agent.state.velocity = velocity;
+        let id = scene.add_agent(agent);
+
+        (scene, id)
+    }
+
+    /// What the supervisor makes of `proposed` for the agent in `scene`, and the interventions it recorded.
+    fn filter(scene: &Scene2D, id: AgentId, proposed: ControlInput) -> (ControlInput, usize) {
+        let agent = &scene.agents[&id];
+        let mut safety = SafetySupervisor::default();
+        let applied = safety.filter(id, &agent.config, &agent.state, proposed, &scene.state());
+
+        (applied, safety.interventions.len())
+    }
+
+    #[test]
+    fn test_safety_stops_at_wall() {
+        let mut scene = testing::open_room();
+        let agent = Agent2D {
+            controller: Some(Arc::new(Mutex::new(Floor))),
+            safety: Some(SafetySupervisor::default()),
+            ..Default::default()
+        };
+        let id = scene.add_agent(agent);
+
+        // The room's top wall is at y = 4 and the agent reaches half its length ahead of its centre.
+        for _ in 0..100 {
+            scene.update(0.05);
+            assert!(scene.agents[&id].state.position.y + 0.25 < 4.);
+        }
+        assert!(scene.agents[&id].state.position.y > 2.);
+
+        let interventions = &scene.agents[&id].safety.as_ref().unwrap().interventions;
+        assert!(!interventions.is_empty());
+        assert_eq!(interventions[0].proposed, FLOOR_IT);
+        assert_eq!(
+            interventions[0].applied,
+            ControlInput {
+                torque: -100.,
+                beta: 0.,
+            }
+        );
+        assert!(interventions.windows(2).all(|w| w[0].time.0 < w[1].time.0));
+    }
+
+    #[test]
+    fn test_safety_passes_safe_input() {
+        let (scene, id) = driving(0., 1.);
+        let proposed = ControlInput {
+            torque: 50.,
+            beta: 0.5,
+        };
+
+        assert_eq!(filter(&scene, id, proposed), (proposed, 0));
+    }
+
+    #[test]
+    fn test_safety_fallback_order() {
+        // Near the right-hand wall, steering into it. Braking alone stops the agent before it gets there.
+        let (scene, id) = driving(3.6, 2.);
+        let proposed = ControlInput {
+            torque: 100.,
+            beta: -1.,
+        };
+        let brake = ControlInput {
+            torque: -100.,
+            beta: -1.,
+        };
+        assert_eq!(filter(&scene, id, proposed), (brake, 1));
+
+        // Closer to it, only straightening the wheels as well keeps the agent off it.
+        let (scene, id) = driving(3.75, 2.);
+        let agent = &scene.agents[&id];
+        let safety = SafetySupervisor::default();
+        assert!(!safety.is_safe(id, &agent.config, &agent.state, brake, &scene.state()));
+        let straight = ControlInput {
+            torque: -100.,
+            beta: 0.,
+        };
+        assert_eq!(filter(&scene, id, proposed), (straight, 1));
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::VecDeque;
+
+use crate::{
+    math::Box2D,
+    scene::{
+        AgentId, Scene2D,
+        occupancy_map::{ObjectTag, OccupancyMap},
+    },
+};
+
+/// A point on the centre line of a passage through free space: between two walls facing each other, or through a gap
+/// between the ends of two walls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Passage {
+    pub center: glam::Vec2,
+    /// Distance across between the walls, in metres.
+    pub width: f32,
+}
+
+/// Summary statistics of a map, for checking it before running long simulations on it.
+#[derive(Debug, Clone)]
+pub struct MapAnalysis {
+    /// In square metres.
+    pub free_area: f32,
+    /// In square metres.
+    pub occupied_area: f32,
+    /// Number of connected groups of occupied cells.
+    pub object_count: usize,
+    /// Length of the edges between free and occupied cells, not counting the edge of the map.
+    pub boundary_length: f32,
+    pub passages: Vec<Passage>,
+    resolution: f32,
+    origin: glam::Vec2,
+    size: glam::IVec2,
+    clearance: Vec<f32>,
+}
+
+impl MapAnalysis {
+    /// Samples the map every `resolution` metres to find passages. Finer samples find their widths more exactly, to
+    /// within about one sample.
+    pub fn new(map: &OccupancyMap, resolution: f32) -> Self {
+        let free_cells = map.pixels.iter().filter(|&&p| !p).count();
+        let object_count = map
+            .objects
+            .iter()
+            .flatten()
+            .map(|&ObjectTag(tag)| tag + 1)
+            .max()
+            .unwrap_or(0) as usize;
+        let boundary_length = map
+            .boundaries
+            .iter()
+            .zip(&map.boundary_tags)
+            .filter(|&(_, &tag)| tag != ObjectTag::MAP_EDGE)
+            .map(|(segment, _)| segment.0.distance(segment.1))
+            .sum();
+
+        // Over the map and a cell beyond it, where the boundary walls are.
+        let extent = map.extent() + 2. * map.resolution;
+        let origin = map.origin - extent / 2.;
+        let size = (extent / resolution)
+            .ceil()
+            .as_ivec2()
+            .max(glam::IVec2::ONE);
+        let center = |i: i32, j: i32| origin + (glam::vec2(i as f32, j as f32) + 0.5) * resolution;
+        let index = |i: i32, j: i32| (i + j * size.x) as usize;
+        let samples = || (0..size.y).flat_map(|j| (0..size.x).map(move |i| (i, j)));
+
+        // Nearest occupied sample to every sample, passed between neighbours in a sweep each way.
+        let mut nearest = samples()
+            .map(|(i, j)| map.is_occupied_vec2(center(i, j)).then(|| center(i, j)))
+            .collect::<Vec<_>>();
+        let forward = [(-1, 0), (-1, -1), (0, -1), (1, -1)];
+        let mut relax = |i: i32, j: i32, ni: i32, nj: i32| {
+            if !(0..size.x).contains(&ni) || !(0..size.y).contains(&nj) {
+                return;
+            }
+            let Some(site) = nearest[index(ni, nj)] else {
+                return;
+            };
+            let here = center(i, j);
+            if nearest[index(i, j)].is_none_or(|s| site.distance(here) < s.distance(here)) {
+                nearest[index(i, j)] = Some(site);
+            }
+        };
+        for j in 0..size.y {
+            for i in 0..size.x {
+                for (di, dj) in forward {
+                    relax(i, j, i + di, j + dj);
+                }
+            }
+        }
+        for j in (0..size.y).rev() {
+            for i in (0..size.x).rev() {
+                for (di, dj) in forward {
+                    relax(i, j, i - di, j - dj);
+                }
+            }
+        }
+
+        // Neighbouring free samples whose nearest walls lie in roughly opposite directions straddle a centre line.
+        let free = |i: i32, j: i32| {
+            let here = center(i, j);
+            let site = nearest[index(i, j)]?;
+            (map.is_valid_vec2(here) && site != here).then_some((here, site))
+        };
+        let mut passages = Vec::new();
+        for (i, j) in samples() {
+            let Some((here, site)) = free(i, j) else {
+                continue;
+            };
+            for (di, dj) in forward {
+                let (ni, nj) = (i + di, j + dj);
+                if !(0..size.x).contains(&ni) || !(0..size.y).contains(&nj) {
+                    continue;
+                }
+                let Some((there, other)) = free(ni, nj) else {
+                    continue;
+                };
+
+                if (site - here).normalize().dot((other - there).normalize()) < -0.7 {
+                    passages.push(Passage {
+                        center: (here + there) / 2.,
+                        // Sites are sample centres, about half a sample inside the walls.
+                        width: (site.distance(other) - resolution).max(0.),
+                    });
+                }
+            }
+        }
+
+        let clearance = samples()
+            .zip(&nearest)
+            .map(|((i, j), site)| site.map_or(f32::INFINITY, |s| s.distance(center(i, j))))
+            .collect();
+
+        Self {
+            free_area: free_cells as f32,
+            occupied_area: (map.pixels.len() - free_cells) as f32,
+            object_count,
+            boundary_length,
+            passages,
+            resolution,
+            origin,
+            size,
+            clearance,
+        }
+    }
+
+    pub fn narrowest_passage(&self) -> Option<Passage> {
+        self.passages
+            .iter()
+            .copied()
+            .min_by(|a, b| a.width.total_cmp(&b.width))
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.resolution
+    }
+
+    /// Distance from `loc` to the nearest occupied sample, infinite off the sampled area or in a map without walls.
+    pub fn clearance(&self, loc: glam::Vec2) -> f32 {
+        let cell = ((loc - self.origin) / self.resolution).floor().as_ivec2();
+        if cell.cmpge(glam::IVec2::ZERO).all() && cell.cmplt(self.size).all() {
+            self.clearance[(cell.x + cell.y * self.size.x) as usize]
+        } else {
+            f32::INFINITY
+        }
+    }
+}
+
+/// Where an agent of a given width can drive: the cells whose centres are at least half the width from every wall,
+/// split into regions it can't drive between. Free space outside them is too tight for the agent, so a planner failing
+/// to reach it is the map's fault.
+#[derive(Debug, Clone)]
+pub struct Traversability {
+    pub width: f32,
+    pub region_count: usize,
+    /// Passages narrower than the agent, which it can't get through.
+    pub narrow_passages: Vec<Passage>,
+    size: glam::USizeVec2,
+    bounds: Box2D,
+    regions: Vec<Option<u32>>,
+}
+
+impl Traversability {
+    pub fn new(map: &OccupancyMap, width: f32) -> Self {
+        let esdf = map.to_esdf(width);
+        let size = map.size;
+        let center = |i: usize| {
+            map.get_box(glam::usizevec2(i % size.x, i / size.x))
+                .centroid()
+        };
+        let fits = (0..map.pixels.len())
+            .map(|i| !map.pixels[i] && esdf.distance(center(i)) >= width / 2.)
+            .collect::<Vec<_>>();
+
+        let mut regions = vec![None; fits.len()];
+        let mut region_count = 0;
+        let mut queue = VecDeque::new();
+        for start in 0..fits.len() {
+            if !fits[start] || regions[start].is_some() {
+                continue;
+            }
+
+            regions[start] = Some(region_count);
+            queue.push_back(start);
+            while let Some(i) = queue.pop_front() {
+                let (x, y) = (i % size.x, i / size.x);
+                let neighbours = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < size.x).then(|| i + 1),
+                    (y > 0).then(|| i - size.x),
+                    (y + 1 < size.y).then(|| i + size.x),
+                ];
+                for n in neighbours.into_iter().flatten() {
+                    if fits[n] && regions[n].is_none() {
+                        regions[n] = Some(region_count);
+                        queue.push_back(n);
+                    }
+                }
+            }
+            region_count += 1;
+        }
+
+        let narrow_passages = MapAnalysis::new(map, 0.5)
+            .passages
+            .into_iter()
+            .filter(|p| p.width < width)
+            .collect();
+
+        Self {
+            width,
+            region_count: region_count as usize,
+            narrow_passages,
+            size,
+            bounds: map.bounds(),
+            regions,
+        }
+    }
+
+    /// One per map cell, in the same order as [OccupancyMap::pixels]: the region the agent's centre is in there, or
+    /// `None` where it doesn't fit.
+    pub fn regions(&self) -> &[Option<u32>] {
+        &self.regions
+    }
+
+    pub fn region(&self, loc: glam::Vec2) -> Option<usize> {
+        let Box2D { min, max } = self.bounds;
+        if !(loc.cmpgt(min).all() && loc.cmplt(max).all()) {
+            return None;
+        }
+        let cell = (glam::vec2(loc.x - min.x, max.y - loc.y) / (max - min) * self.size.as_vec2())
+            .as_usizevec2()
+            .min(self.size - 1);
+
+        self.regions[cell.x + cell.y * self.size.x].map(|r| r as usize)
+    }
+
+    /// Whether the agent can drive from `from` to `to` without squeezing between walls closer than its width.
+    pub fn reachable(&self, from: glam::Vec2, to: glam::Vec2) -> bool {
+        self.region(from)
+            .is_some_and(|r| self.region(to) == Some(r))
+    }
+}
+
+impl Scene2D {
+    /// Where `agent` can drive in the static map, judging by its width.
+    pub fn traversability(&self, agent: AgentId) -> Option<Traversability> {
+        let agent = self.agents.get(&agent)?;
+
+        Some(Traversability::new(&self.occupancy_map, agent.config.width))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        scene::{analysis::MapAnalysis, occupancy_map::OccupancyMap},
+    };
+
+    #[test]
+    fn test_map_analysis() {
+        // Two rooms either side of a wall with a 2 cell doorway, and a pillar 3 cells from everything in the left one.
+        let rows = [
+            ".......#......",
+            ".......#......",
+            ".......#......",
+            "...#..........",
+            "...#..........",
+            ".......#......",
+            ".......#......",
+            ".......#......",
+        ];
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect();
+        let map = OccupancyMap::from_pixels(glam::usizevec2(14, 8), pixels).unwrap();
+
+        let analysis = MapAnalysis::new(&map, 0.25);
+        assert_eq!(analysis.free_area, 104.);
+        assert_eq!(analysis.occupied_area, 8.);
+        assert_eq!(analysis.object_count, 3);
+        assert_eq!(analysis.boundary_length, 7. + 7. + 6.);
+
+        let narrowest = analysis.narrowest_passage().unwrap();
+        assert!((narrowest.width - 2.).abs() <= 0.25, "{narrowest:?}");
+        // In or just either side of the doorway through the wall at x = 0..1.
+        assert!(narrowest.center.y.abs() < 0.25, "{narrowest:?}");
+        assert!((-1. ..=2.).contains(&narrowest.center.x), "{narrowest:?}");
+
+        assert!(analysis.clearance(narrowest.center) >= 0.75);
+        assert_eq!(analysis.clearance(glam::vec2(-3.5, 0.)), 0.);
+    }
+
+    #[test]
+    fn test_traversability() {
+        // Two rooms joined by a 1 cell gap, with a 3 cell gap further down.
+        let rows = [
+            "......#......",
+            "......#......",
+            ".............",
+            "......#......",
+            "......#......",
+            "......#......",
+            ".............",
+            ".............",
+            ".............",
+            "......#......",
+        ];
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect::<Vec<_>>();
+        let mut scene = Scene2D::from_occupancy_map(
+            OccupancyMap::from_pixels(glam::usizevec2(13, 10), pixels.clone()).unwrap(),
+        );
+        let mut agent = Agent2D::default();
+        agent.config.width = 2.;
+        let id = scene.add_agent(agent);
+
+        let traversability = scene.traversability(id).unwrap();
+        let (left, right) = (glam::vec2(-4., 0.5), glam::vec2(4., 0.5));
+        assert_eq!(traversability.region_count, 1);
+        assert!(traversability.reachable(left, right));
+        // Both sides of the wall, but too close to it.
+        assert_eq!(traversability.region(glam::vec2(-1., 0.5)), None);
+        // The 1 cell gap is flagged, the 3 cell one is not.
+        assert!(!traversability.narrow_passages.is_empty());
+        assert!(
+            traversability
+                .narrow_passages
+                .iter()
+                .all(|p| p.center.y > 1.)
+        );
+
+        // Too wide for either gap.
+        scene.agents.get_mut(&id).unwrap().config.width = 3.5;
+        let traversability = scene.traversability(id).unwrap();
+        assert_eq!(traversability.region_count, 2);
+        assert!(!traversability.reachable(left, right));
+        assert!(traversability.region(left).is_some());
+        assert_eq!(traversability.regions().len(), pixels.len(),);
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use crate::{
+    Agent2D,
+    config::Validate,
+    math::LineSegment,
+    scene::{
+        Scene2D, Scene2DError, events::SceneEvent, obstacles::DynamicObstacle,
+        occupancy_map::OccupancyMap, surface::SurfaceMap, tiles::TiledWorld, vector_map::VectorMap,
+    },
+    sim_config::SimConfig,
+};
+
+/// Where a [Scene2DBuilder] gets its walls from.
+#[derive(Debug)]
+pub enum OccupancySource {
+    /// Row-major with the first row at the top, 127 and below for walls, as for [Scene2D::new] and the
+    /// [generators](crate::scene::generate).
+    Pixels {
+        size: [usize; 2],
+        pixels: Vec<u8>,
+    },
+    /// Pixels no brighter than `threshold` are walls.
+    Image {
+        image: image::GrayImage,
+        threshold: u8,
+    },
+    /// Walls drawn as line segments in metres over a map `size` cells across. Every cell a wall passes through is
+    /// occupied.
+    Vector {
+        size: [usize; 2],
+        walls: Vec<LineSegment>,
+    },
+    /// Walls kept as segments, without a grid.
+    VectorMap(VectorMap),
+    /// Keeps its own boundary policy.
+    Map(OccupancyMap),
+    Tiles(TiledWorld),
+}
+
+impl OccupancySource {
+    fn into_scene(self, config: SimConfig) -> Result<Scene2D, Scene2DError> {
+        let boundary = config.boundary;
+
+        match self {
+            Self::Pixels { size, pixels } => Scene2D::new(size, &pixels, config),
+            Self::Image { image, threshold } => {
+                let size = [image.width() as usize, image.height() as usize];
+                let pixels = image
+                    .pixels()
+                    .map(|p| p.0[0] <= threshold)
+                    .collect::<Vec<_>>();
+                let map = OccupancyMap::from_pixels_with_boundary(
+                    glam::USizeVec2::from(size),
+                    pixels,
+                    boundary,
+                )?;
+                Scene2D::with_config(map, config)
+            }
+            Self::Vector { size, walls } => {
+                Scene2D::with_config(VectorMap::new(walls, 0.).rasterize(size, boundary)?, config)
+            }
+            Self::VectorMap(map) => Scene2D::from_vector_map(map, config),
+            Self::Map(map) => Scene2D::with_config(map, config),
+            Self::Tiles(tiles) => Ok(Scene2D {
+                tiles: Some(Arc::new(tiles)),
+                ..Scene2D::with_config(OccupancyMap::empty(), config)?
+            }),
+        }
+    }
+}
+
+/// Puts a [Scene2D] together in one go: its map, config, event subscribers, agents, landmarks, beacons and obstacles.
+/// Subscribers are attached before any agent is added, so they hear every [SceneEvent::AgentSpawned].
+#[derive(Debug, Default)]
+pub struct Scene2DBuilder {
+    source: Option<OccupancySource>,
+    config: SimConfig,
+    subscribers: Vec<flume::Sender<SceneEvent>>,
+    agents: Vec<Agent2D>,
+    landmarks: Vec<glam::Vec2>,
+    beacons: Vec<glam::Vec2>,
+    obstacles: Vec<DynamicObstacle>,
+    surfaces: Option<SurfaceMap>,
+}
+
+impl Scene2D {
+    pub fn builder() -> Scene2DBuilder {
+        Scene2DBuilder::default()
+    }
+}
+
+impl Scene2DBuilder {
+    /// Replaces any source given before.
+    pub fn occupancy(mut self, source: OccupancySource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn pixels(self, size: [usize; 2], pixels: Vec<u8>) -> Self {
+        self.occupancy(OccupancySource::Pixels { size, pixels })
+    }
+
+    /// Pixels from a generator such as [generate::rooms](crate::scene::generate::rooms), given the map's size.
+    pub fn generated(self, size: [usize; 2], generate: impl FnOnce([usize; 2]) -> Vec<u8>) -> Self {
+        self.pixels(size, generate(size))
+    }
+
+    pub fn image(self, image: &image::DynamicImage, threshold: u8) -> Self {
+        self.occupancy(OccupancySource::Image {
+            image: image.to_luma8(),
+            threshold,
+        })
+    }
+
+    pub fn walls(self, size: [usize; 2], walls: impl IntoIterator<Item = LineSegment>) -> Self {
+        self.occupancy(OccupancySource::Vector {
+            size,
+            walls: walls.into_iter().collect(),
+        })
+    }
+
+    pub fn vector_map(self, map: VectorMap) -> Self {
+        self.occupancy(OccupancySource::VectorMap(map))
+    }
+
+    pub fn map(self, map: OccupancyMap) -> Self {
+        self.occupancy(OccupancySource::Map(map))
+    }
+
+    pub fn tiles(self, tiles: TiledWorld) -> Self {
+        self.occupancy(OccupancySource::Tiles(tiles))
+    }
+
+    pub fn config(mut self, config: SimConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sends every [SceneEvent] to `subscriber`, as [Scene2D::subscribe_events] does.
+    pub fn subscriber(mut self, subscriber: flume::Sender<SceneEvent>) -> Self {
+        self.subscribers.push(subscriber);
+        self
+    }
+
+    /// With its sensors, controller and localizer already set. Agents get ids in the order they're added.
+    pub fn agent(mut self, agent: Agent2D) -> Self {
+        self.agents.push(agent);
+        self
+    }
+
+    pub fn agents(mut self, agents: impl IntoIterator<Item = Agent2D>) -> Self {
+        self.agents.extend(agents);
+        self
+    }
+
+    pub fn landmark(mut self, position: glam::Vec2) -> Self {
+        self.landmarks.push(position);
+        self
+    }
+
+    pub fn beacon(mut self, position: glam::Vec2) -> Self {
+        self.beacons.push(position);
+        self
+    }
+
+    pub fn obstacle(mut self, obstacle: DynamicObstacle) -> Self {
+        self.obstacles.push(obstacle);
+        self
+    }
+
+    pub fn surfaces(mut self, surfaces: SurfaceMap) -> Self {
+        self.surfaces = Some(surfaces);
+        self
+    }
+
+    pub fn build(self) -> Result<Scene2D, Scene2DError> {
+        let source = self.source.ok_or(Scene2DError::NoOccupancy)?;
+        let mut scene = source.into_scene(self.config)?;
+        for subscriber in self.subscribers {
+            scene.events.subscribe(subscriber);
+        }
+        scene.landmarks = Arc::new(self.landmarks);
+        scene.beacons = Arc::new(self.beacons);
+        scene.obstacles = Arc::new(self.obstacles);
+        if let Some(surfaces) = self.surfaces {
+            surfaces.validate()?;
+            scene.surfaces = Some(Arc::new(surfaces));
+        }
+        for agent in self.agents {
+            scene.add_agent(agent);
+        }
+
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        math::LineSegment,
+        scene::{Scene2DError, events::SceneEvent, generate},
+        sim_config::SimConfig,
+    };
+
+    #[test]
+    fn test_scene_builder() {
+        // A wall drawn across the top of the map gives the same scene as painting it in.
+        let (snd, rcv) = flume::unbounded();
+        let config = SimConfig {
+            seed: Some(3),
+            ..Default::default()
+        };
+        let scene = Scene2D::builder()
+            .walls(
+                [10, 10],
+                [LineSegment(glam::vec2(-5., 3.5), glam::vec2(5., 3.5))],
+            )
+            .config(config.clone())
+            .subscriber(snd)
+            .agents([Agent2D::default(), Agent2D::default()])
+            .landmark(glam::vec2(1., 1.))
+            .beacon(glam::vec2(-2., 0.))
+            .build()
+            .unwrap();
+        let mut pixels = [255; 100];
+        pixels[10..20].fill(0);
+        let painted = Scene2D::new([10, 10], &pixels, config).unwrap();
+
+        assert_eq!(scene.occupancy_map.pixels, painted.occupancy_map.pixels);
+        assert_eq!(scene.config().seed, Some(3));
+        assert!(scene.ordered);
+        assert_eq!(scene.agents.len(), 2);
+        assert_eq!(*scene.landmarks, [glam::vec2(1., 1.)]);
+        assert_eq!(*scene.beacons, [glam::vec2(-2., 0.)]);
+        let spawned = rcv
+            .try_iter()
+            .filter(|e| matches!(e, SceneEvent::AgentSpawned { .. }))
+            .count();
+        assert_eq!(spawned, 2);
+
+        let generated = Scene2D::builder()
+            .generated([12, 12], |size| generate::rooms(size, [2, 2], 1, 2))
+            .build()
+            .unwrap();
+        let rooms =
+            Scene2D::from_pixels([12, 12], &generate::rooms([12, 12], [2, 2], 1, 2)).unwrap();
+        assert_eq!(generated.occupancy_map.pixels, rooms.occupancy_map.pixels);
+
+        assert!(matches!(
+            Scene2D::builder().build(),
+            Err(Scene2DError::NoOccupancy)
+        ));
+    }
+}
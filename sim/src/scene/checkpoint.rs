@@ -0,0 +1,503 @@
+//! Saving a whole simulation with serde and picking it up again later, e.g. to checkpoint a long experiment.
+//!
+//! Only data is saved. Controllers, localizers, safety supervisors, observation configs and prior maps are code or
+//! derived state, and have to be attached to the agents again after loading. Sensors are saved as their configs,
+//! which covers the built-in ones; others are left out with a warning. Measurements in flight are dropped, so
+//! sensing starts afresh in a resumed scene.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
+
+use crate::{
+    Agent2D, Lidar2D,
+    agent::{Agent2DConfig, Agent2DSensors, Agent2DState, AgentLabel},
+    controller::ControlInput,
+    localization::{PoseEstimate, PoseSource},
+    math::LineSegment,
+    replay::Trajectory,
+    scene::{
+        AgentId, Scene2D, SceneTime,
+        crowd::Crowd,
+        mission::Mission,
+        occupancy_map::{BoundaryPolicy, ObjectTag, OccupancyMap},
+        surface::SurfaceMap,
+        vector_map::VectorMap,
+    },
+    sensors::{
+        DynSensor2D, SensingCost, SensorClock, beacon::BeaconRanger2D, bumper::Bumper2D,
+        compass::Compass2D, landmark::LandmarkSensor2D, radar::Radar2D, sonar::Sonar2D,
+    },
+    sim_config::SimConfig,
+    trailer::Trailer,
+};
+
+/// One of the built-in sensors, by value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SensorConfig {
+    Lidar(Lidar2D),
+    Landmark(LandmarkSensor2D),
+    Beacon(BeaconRanger2D),
+    Sonar(Sonar2D),
+    Bumper(Bumper2D),
+    Compass(Compass2D),
+    Radar(Radar2D),
+}
+
+impl SensorConfig {
+    /// A copy of `sensor`, or `None` if it isn't a built-in sensor.
+    pub fn from_dyn(sensor: &dyn DynSensor2D) -> Option<Self> {
+        let any = sensor.as_any();
+
+        any.downcast_ref()
+            .cloned()
+            .map(Self::Lidar)
+            .or_else(|| any.downcast_ref().copied().map(Self::Landmark))
+            .or_else(|| any.downcast_ref().copied().map(Self::Beacon))
+            .or_else(|| any.downcast_ref().copied().map(Self::Sonar))
+            .or_else(|| any.downcast_ref().copied().map(Self::Bumper))
+            .or_else(|| any.downcast_ref().cloned().map(Self::Compass))
+            .or_else(|| any.downcast_ref().copied().map(Self::Radar))
+    }
+
+    /// Mounts the sensor on `sensors` as `name`.
+    pub fn insert_into(self, sensors: &mut Agent2DSensors, name: String) {
+        match self {
+            Self::Lidar(s) => sensors.insert(name, s),
+            Self::Landmark(s) => sensors.insert(name, s),
+            Self::Beacon(s) => sensors.insert(name, s),
+            Self::Sonar(s) => sensors.insert(name, s),
+            Self::Bumper(s) => sensors.insert(name, s),
+            Self::Compass(s) => sensors.insert(name, s),
+            Self::Radar(s) => sensors.insert(name, s),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SensorEntryData {
+    name: String,
+    sensor: SensorConfig,
+    rate: Option<f32>,
+    latency: f32,
+    jitter: f32,
+    clock: SensorClock,
+    cost: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AgentData {
+    config: Agent2DConfig,
+    #[serde(default)]
+    label: AgentLabel,
+    state: Agent2DState,
+    last_state: Option<Agent2DState>,
+    #[serde(default)]
+    command: Option<ControlInput>,
+    #[serde(default)]
+    trailers: Vec<Trailer>,
+    #[serde(default)]
+    replay: Option<Trajectory>,
+    sensors: Vec<SensorEntryData>,
+    pose_source: PoseSource,
+    estimate: Option<PoseEstimate>,
+    mission: Option<Mission>,
+    sensing_cost: SensingCost,
+}
+
+impl Serialize for Agent2D {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sensors = self
+            .sensors
+            .iter()
+            .filter_map(|entry| {
+                let Some(sensor) = SensorConfig::from_dyn(&*entry.sensor.read()) else {
+                    log::warn!("Not saving sensor {:?}, which isn't built in", entry.name);
+                    return None;
+                };
+
+                Some(SensorEntryData {
+                    name: entry.name.clone(),
+                    sensor,
+                    rate: entry.rate,
+                    latency: entry.latency,
+                    jitter: entry.jitter,
+                    clock: entry.clock,
+                    cost: entry.cost,
+                })
+            })
+            .collect();
+
+        AgentData {
+            config: self.config,
+            label: self.label.clone(),
+            state: self.state,
+            last_state: self.last_state,
+            command: self.command,
+            trailers: self.trailers.clone(),
+            replay: self.replay.clone(),
+            sensors,
+            pose_source: self.pose_source,
+            estimate: self.estimate,
+            mission: self.mission.clone(),
+            sensing_cost: self.sensing_cost,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Agent2D {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = AgentData::deserialize(deserializer)?;
+
+        let mut sensors = Agent2DSensors::default();
+        for entry in data.sensors {
+            entry.sensor.insert_into(&mut sensors, entry.name.clone());
+            if let Some(mounted) = sensors.get_mut(&entry.name) {
+                mounted.rate = entry.rate;
+                mounted.latency = entry.latency;
+                mounted.jitter = entry.jitter;
+                mounted.clock = entry.clock;
+                mounted.cost = entry.cost;
+            }
+        }
+
+        Ok(Agent2D {
+            config: data.config,
+            label: data.label,
+            state: data.state,
+            last_state: data.last_state,
+            command: data.command,
+            trailers: data.trailers,
+            replay: data.replay,
+            sensors,
+            pose_source: data.pose_source,
+            estimate: data.estimate,
+            mission: data.mission,
+            sensing_cost: data.sensing_cost,
+            controller: None,
+            safety: None,
+            localizer: None,
+            prior_map: None,
+            observation: None,
+        })
+    }
+}
+
+/// Cells as rows of `#` for occupied and `.` for free, top row first.
+#[derive(Serialize, Deserialize)]
+struct OccupancyMapData {
+    rows: Vec<String>,
+    boundary: BoundaryPolicy,
+    #[serde(default = "unit_resolution")]
+    resolution: f32,
+    #[serde(default)]
+    origin: glam::Vec2,
+}
+
+fn unit_resolution() -> f32 {
+    1.
+}
+
+impl Serialize for OccupancyMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows = self
+            .pixels
+            .chunks(self.size.x.max(1))
+            .map(|row| row.iter().map(|&p| if p { '#' } else { '.' }).collect())
+            .collect();
+
+        OccupancyMapData {
+            rows,
+            boundary: self.boundary,
+            resolution: self.resolution,
+            origin: self.origin,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for OccupancyMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = OccupancyMapData::deserialize(deserializer)?;
+        let width = data.rows.first().map_or(0, |row| row.chars().count());
+        let size = glam::usizevec2(width, data.rows.len());
+        let pixels = data
+            .rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                c => Err(de::Error::invalid_value(
+                    de::Unexpected::Char(c),
+                    &"'#' or '.'",
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+
+        OccupancyMap::from_pixels_with_boundary(size, pixels, data.boundary)
+            .and_then(|map| map.with_resolution(data.resolution, data.origin))
+            .map_err(de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VectorMapData {
+    walls: Vec<[glam::Vec2; 2]>,
+    tags: Vec<u64>,
+    thickness: f32,
+}
+
+impl Serialize for VectorMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        VectorMapData {
+            walls: self.walls.iter().map(|w| [w.0, w.1]).collect(),
+            tags: self.wall_tags.iter().map(|t| t.0).collect(),
+            thickness: self.thickness,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for VectorMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = VectorMapData::deserialize(deserializer)?;
+        if data.tags.len() != data.walls.len() {
+            return Err(de::Error::invalid_length(
+                data.tags.len(),
+                &"a tag for every wall",
+            ));
+        }
+
+        Ok(VectorMap::with_tags(
+            data.walls
+                .into_iter()
+                .zip(data.tags)
+                .map(|([a, b], tag)| (LineSegment(a, b), ObjectTag(tag))),
+            data.thickness,
+        ))
+    }
+}
+
+#[derive(Serialize)]
+struct SceneRef<'a> {
+    config: &'a SimConfig,
+    time: SceneTime,
+    /// `None` when the walls are a vector map.
+    occupancy_map: Option<&'a OccupancyMap>,
+    vector_map: Option<&'a VectorMap>,
+    surfaces: Option<&'a SurfaceMap>,
+    landmarks: &'a [glam::Vec2],
+    beacons: &'a [glam::Vec2],
+    crowd: &'a Crowd,
+    /// In id order.
+    agents: Vec<(AgentId, &'a Agent2D)>,
+    next_agent_id: u64,
+    out_of_bounds: Vec<AgentId>,
+    banked: f32,
+}
+
+#[derive(Deserialize)]
+struct SceneData {
+    config: SimConfig,
+    time: SceneTime,
+    occupancy_map: Option<OccupancyMap>,
+    #[serde(default)]
+    vector_map: Option<VectorMap>,
+    #[serde(default)]
+    surfaces: Option<SurfaceMap>,
+    landmarks: Vec<glam::Vec2>,
+    beacons: Vec<glam::Vec2>,
+    #[serde(default)]
+    crowd: Crowd,
+    agents: Vec<(AgentId, Agent2D)>,
+    next_agent_id: u64,
+    out_of_bounds: Vec<AgentId>,
+    banked: f32,
+}
+
+/// Scenes in a [tiled world](Scene2D::tiled) can't be saved, as their tiles are loaded from elsewhere. Obstacles are
+/// left out, as their paths may be scripted in code.
+impl Serialize for Scene2D {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.tiles.is_some() {
+            return Err(ser::Error::custom("Scenes in a tiled world can't be saved"));
+        }
+        if !self.obstacles.is_empty() {
+            log::warn!(
+                "Leaving {} obstacles out of the checkpoint",
+                self.obstacles.len()
+            );
+        }
+
+        let mut agents = self
+            .agents
+            .iter()
+            .map(|(&id, a)| (id, a))
+            .collect::<Vec<_>>();
+        agents.sort_by_key(|(id, _)| *id);
+        let mut out_of_bounds = self.out_of_bounds.iter().copied().collect::<Vec<_>>();
+        out_of_bounds.sort();
+
+        SceneRef {
+            config: &self.config,
+            time: self.time,
+            occupancy_map: self.vector_map.is_none().then_some(&*self.occupancy_map),
+            vector_map: self.vector_map.as_deref(),
+            surfaces: self.surfaces.as_deref(),
+            landmarks: &self.landmarks,
+            beacons: &self.beacons,
+            crowd: &self.crowd,
+            agents,
+            next_agent_id: self.next_agent_id,
+            out_of_bounds,
+            banked: self.banked,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scene2D {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = SceneData::deserialize(deserializer)?;
+
+        let mut scene = match (data.occupancy_map, data.vector_map) {
+            (_, Some(vector_map)) => Scene2D::from_vector_map(vector_map, data.config),
+            (Some(occupancy_map), None) => Scene2D::with_config(occupancy_map, data.config),
+            (None, None) => return Err(de::Error::missing_field("occupancy_map")),
+        }
+        .map_err(de::Error::custom)?;
+        scene.time = data.time;
+        scene.surfaces = data.surfaces.map(Arc::new);
+        scene.landmarks = Arc::new(data.landmarks);
+        scene.beacons = Arc::new(data.beacons);
+        scene.crowd = data.crowd;
+        for (id, agent) in data.agents {
+            scene.scene_loop.insert_agent(id, &agent);
+            scene.agents.insert(id, agent);
+        }
+        scene.next_agent_id = data.next_agent_id;
+        scene.out_of_bounds = data.out_of_bounds.into_iter().collect();
+        scene.banked = data.banked;
+
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Scene2D,
+        control::PurePursuit,
+        math::LineSegment,
+        scene::{mission::Mission, vector_map::VectorMap},
+        sensors::compass::Compass2D,
+        sim_config::{CollisionMode, SimConfig},
+        testing,
+    };
+
+    /// A scene ten steps in with a landmark, a wall, and two agents, one on a mission with a rate limited bumper and
+    /// a compass.
+    fn running_scene() -> Scene2D {
+        let mut pixels = [255; 100];
+        pixels[..20].fill(0);
+        let config = SimConfig {
+            seed: Some(11),
+            collision: CollisionMode::Stop,
+            ..Default::default()
+        };
+        let mut scene = Scene2D::new([10, 10], &pixels, config).unwrap();
+        scene.add_landmark(glam::vec2(1., 2.));
+        let mut agent = testing::bumper_agent();
+        agent.mission = Some(Mission::waypoints([glam::vec2(0., 2.)], 0.5));
+        agent.sensors.get_mut("bumper").unwrap().rate = Some(5.);
+        agent.sensors.insert("compass", Compass2D::default());
+        agent.state.velocity = 1.;
+        scene.add_agent(agent);
+        scene.add_agent(Agent2D::default());
+        for _ in 0..10 {
+            scene.step();
+        }
+
+        scene
+    }
+
+    fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+        serde_norway::from_str(&serde_norway::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_checkpoint_contents() {
+        let scene = running_scene();
+        let resumed = round_trip(&scene);
+        assert_eq!(resumed.time, scene.time);
+        assert_eq!(resumed.config(), scene.config());
+        assert_eq!(resumed.occupancy_map.pixels, scene.occupancy_map.pixels);
+        assert_eq!(
+            resumed.occupancy_map.boundaries.len(),
+            scene.occupancy_map.boundaries.len()
+        );
+        assert_eq!(resumed.landmarks, scene.landmarks);
+        assert_eq!(resumed.scene_loop.seed(), Some(11));
+        for (id, agent) in &scene.agents {
+            let copy = &resumed.agents[id];
+            assert_eq!(copy.state.position, agent.state.position);
+            assert_eq!(copy.mission, agent.mission);
+            let names = |a: &Agent2D| a.sensors.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+            assert_eq!(names(copy), names(agent));
+        }
+        let id = *scene.agents.keys().max().unwrap();
+        assert_eq!(
+            resumed.agents[&id].sensors.get("bumper").map(|e| e.rate),
+            scene.agents[&id].sensors.get("bumper").map(|e| e.rate)
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let mut scene = running_scene();
+        let mut resumed = round_trip(&scene);
+
+        // Both carry on the same way, and new agents don't reuse ids.
+        for _ in 0..10 {
+            scene.step();
+            resumed.step();
+        }
+        for (id, agent) in &scene.agents {
+            assert_eq!(resumed.agents[id].state.position, agent.state.position);
+        }
+        assert_eq!(
+            resumed.add_agent(Agent2D::default()),
+            scene.add_agent(Agent2D::default())
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_controllers() {
+        // Controllers aren't saved.
+        let agent = Agent2D {
+            controller: Some(Arc::new(Mutex::new(PurePursuit::new(vec![
+                glam::Vec2::ONE,
+            ])))),
+            ..Default::default()
+        };
+        let copy = round_trip(&agent);
+        assert!(copy.controller.is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_vector_map() {
+        // Vector maps are saved as their walls.
+        let walls = VectorMap::new([LineSegment(glam::vec2(-1., 1.), glam::vec2(1., 1.5))], 0.1);
+        let scene = Scene2D::from_vector_map(walls, SimConfig::default()).unwrap();
+        let resumed = round_trip(&scene);
+        let copy = resumed.vector_map.as_ref().unwrap();
+        assert_eq!(copy.walls[0].1, glam::vec2(1., 1.5));
+        assert_eq!(copy.thickness, 0.1);
+    }
+}
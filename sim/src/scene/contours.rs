@@ -0,0 +1,287 @@
+//! Obstacle outlines traced from an occupancy map by marching squares, then simplified with Douglas-Peucker. A few
+//! polygons are far cheaper to export, draw or plan around than the four boundary segments each cell can have.
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    math::LineSegment,
+    scene::occupancy_map::{ObjectTag, OccupancyMap},
+};
+
+/// A closed polygon around an obstacle, or around a hole inside one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline {
+    /// Corners in order, without repeating the first. Like the map's boundaries, they are wound so the obstacle is on
+    /// the right: clockwise around obstacles and counter-clockwise around holes.
+    pub points: Vec<glam::Vec2>,
+    /// The obstacle the outline belongs to.
+    pub tag: ObjectTag,
+}
+
+impl Outline {
+    /// Positive for counter-clockwise winding, in square metres.
+    pub fn signed_area(&self) -> f32 {
+        let Some(&last) = self.points.last() else {
+            return 0.;
+        };
+
+        let mut prev = last;
+        let mut area = 0.;
+        for &p in &self.points {
+            area += prev.perp_dot(p);
+            prev = p;
+        }
+
+        area / 2.
+    }
+
+    /// Whether this outlines free space enclosed by its obstacle.
+    pub fn is_hole(&self) -> bool {
+        self.signed_area() > 0.
+    }
+
+    /// The polygon's sides, back round to the first corner.
+    pub fn edges(&self) -> impl Iterator<Item = LineSegment> + '_ {
+        let n = self.points.len();
+        (0..n).map(move |i| LineSegment(self.points[i], self.points[(i + 1) % n]))
+    }
+}
+
+/// Douglas-Peucker: drops points of the open polyline `points` while it stays within `tolerance` of the original.
+/// The ends are always kept, and with a tolerance of 0 only points lying exactly in line with their neighbours go.
+pub fn simplify_polyline(points: &[glam::Vec2], tolerance: f32) -> Vec<glam::Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let chord = LineSegment(points[start], points[end]);
+        let farthest = (start + 1..end)
+            .map(|i| (i, points[i].distance(chord.closest_point(points[i]))))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((i, d)) = farthest
+            && d > tolerance
+        {
+            keep[i] = true;
+            stack.push((start, i));
+            stack.push((i, end));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(&p, keep)| keep.then_some(p))
+        .collect()
+}
+
+/// [simplify_polyline] for a closed polygon, which is split at its first point and the point farthest from it.
+pub fn simplify_polygon(points: &[glam::Vec2], tolerance: f32) -> Vec<glam::Vec2> {
+    if points.len() < 4 {
+        return points.to_vec();
+    }
+
+    let far = (1..points.len())
+        .max_by(|&i, &j| {
+            points[0]
+                .distance_squared(points[i])
+                .total_cmp(&points[0].distance_squared(points[j]))
+        })
+        .expect("Checked to have more than one point");
+
+    let mut simplified = simplify_polyline(&points[..=far], tolerance);
+    let back = points[far..]
+        .iter()
+        .chain([&points[0]])
+        .copied()
+        .collect::<Vec<_>>();
+    let back = simplify_polyline(&back, tolerance);
+    simplified.extend_from_slice(&back[1..back.len() - 1]);
+
+    simplified
+}
+
+impl OccupancyMap {
+    /// Outlines of every obstacle and every hole in one, simplified to within `tolerance` metres. Before simplifying,
+    /// outlines run through the middles of the cell sides between occupied and free cells, so cut each corner
+    /// diagonally. Cells touching only at a corner are outlined apart, as they are separate objects. Whatever lies
+    /// beyond the map, obstacles at its edge are closed off along it.
+    pub fn outlines(&self, tolerance: f32) -> Vec<Outline> {
+        let size = self.size.as_i64vec2();
+        let occupied = |cell: glam::I64Vec2| {
+            cell.cmpge(glam::I64Vec2::ZERO).all()
+                && cell.cmplt(size).all()
+                && self.pixels[(cell.y * size.x + cell.x) as usize]
+        };
+        // Crossings are keyed by the sum of the two cells either side, which is unique to that pair of neighbours.
+        let position = |key: glam::I64Vec2| {
+            let cell = key.as_vec2() / 2. + 0.5;
+            glam::vec2(
+                cell.x - self.size.x as f32 / 2.,
+                self.size.y as f32 / 2. - cell.y,
+            ) * self.resolution
+                + self.origin
+        };
+
+        // Each crossing leads to exactly one other, following the outline with the obstacle on the right.
+        let mut next = FxHashMap::default();
+        let mut starts = Vec::new();
+        for y in -1..size.y {
+            for x in -1..size.x {
+                // The square's corners are the centres of these cells, going round clockwise on screen.
+                let corners =
+                    [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(dx, dy)| glam::i64vec2(x + dx, y + dy));
+                let solid = corners.map(occupied);
+                let crossings = (0..4)
+                    .filter(|&i| solid[i] != solid[(i + 1) % 4])
+                    .collect::<Vec<_>>();
+
+                // Pairs of crossings along with an occupied corner on the obstacle's side of them. Where only diagonal
+                // corners are occupied, each is cut off on its own.
+                let pairs = match crossings[..] {
+                    [a, b] => vec![(a, b, if solid[b] { b } else { (b + 1) % 4 })],
+                    [_, _, _, _] => {
+                        let k = if solid[0] { 0 } else { 1 };
+                        vec![((k + 3) % 4, k, k), (k + 1, (k + 2) % 4, (k + 2) % 4)]
+                    }
+                    _ => continue,
+                };
+
+                for (a, b, solid_corner) in pairs {
+                    let key = |i: usize| corners[i] + corners[(i + 1) % 4];
+                    let (mut from, mut to) = (key(a), key(b));
+                    // Rows count down the screen, which mirrors which side is which.
+                    let along = (to - from).as_vec2();
+                    let side = (corners[solid_corner] * 2 - from).as_vec2();
+                    if along.perp_dot(side) < 0. {
+                        std::mem::swap(&mut from, &mut to);
+                    }
+
+                    let corner = corners[solid_corner];
+                    let tag = self.objects[(corner.y * size.x + corner.x) as usize]
+                        .unwrap_or(ObjectTag(0));
+                    next.insert(from, (to, tag));
+                    starts.push(from);
+                }
+            }
+        }
+
+        let mut outlines = Vec::new();
+        for start in starts {
+            let Some(&(_, tag)) = next.get(&start) else {
+                continue;
+            };
+
+            let mut points = Vec::new();
+            let mut key = start;
+            while let Some((to, _)) = next.remove(&key) {
+                points.push(position(key));
+                key = to;
+            }
+
+            outlines.push(Outline {
+                points: simplify_polygon(&points, tolerance),
+                tag,
+            });
+        }
+
+        outlines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        contours::{simplify_polygon, simplify_polyline},
+        occupancy_map::OccupancyMap,
+    };
+
+    /// A 3 by 3 ring, a lone cell beside it, and a wall along the top edge, on a map of 0.5 m cells.
+    fn shapes() -> OccupancyMap {
+        OccupancyMap::from_ascii(&[
+            "#######.", //
+            "........", //
+            ".###....", //
+            ".#.#.#..", //
+            ".###....", //
+            "........", //
+        ])
+        .with_resolution(0.5, glam::vec2(10., 0.))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_outline_count() {
+        let outlines = shapes().outlines(0.);
+        assert_eq!(outlines.len(), 4);
+        assert_eq!(outlines.iter().filter(|o| o.is_hole()).count(), 1);
+    }
+
+    #[test]
+    fn test_outline_hole() {
+        // The hole is a diamond through the middles of the sides of the ring's centre cell.
+        let map = shapes();
+        let outlines = map.outlines(0.);
+        let hole = outlines.iter().find(|o| o.is_hole()).unwrap();
+        let centre = map.get_box(glam::USizeVec2::new(2, 3)).centroid();
+        assert_eq!(hole.points.len(), 4);
+        for p in &hole.points {
+            assert!((p.distance(centre) - 0.25).abs() < 1e-5);
+        }
+        assert!((hole.signed_area() - 0.125).abs() < 1e-5);
+        assert_eq!(hole.tag, map.objects[3 * 8 + 1].unwrap());
+    }
+
+    #[test]
+    fn test_outline_corners() {
+        // Straight runs collapse, leaving a square with its corners cut.
+        let map = shapes();
+        let ring = map.objects[3 * 8 + 1].unwrap();
+        let outlines = map.outlines(0.);
+        let outer = outlines
+            .iter()
+            .find(|o| o.tag == ring && !o.is_hole())
+            .unwrap();
+        assert_eq!(outer.points.len(), 8);
+        assert!((outer.signed_area() + 2.125).abs() < 1e-5);
+
+        let lone = map.objects[3 * 8 + 5].unwrap();
+        let cell = outlines.iter().find(|o| o.tag == lone).unwrap();
+        assert_eq!(cell.points.len(), 4);
+        assert!(cell.signed_area() < 0.);
+    }
+
+    #[test]
+    fn test_outline_map_edge() {
+        // The wall along the top is closed off at the map's edge.
+        let map = shapes();
+        let outlines = map.outlines(0.);
+        let wall = outlines
+            .iter()
+            .find(|o| o.tag == map.objects[0].unwrap())
+            .unwrap();
+        let top = map.bounds().max.y;
+        assert!(wall.points.iter().any(|p| (p.y - top).abs() < 1e-5));
+        assert!(wall.points.iter().all(|p| p.y <= top + 1e-5));
+    }
+
+    #[test]
+    fn test_simplify() {
+        // Simplifying keeps the ends and drops wiggles within the tolerance.
+        let line = (0..=10)
+            .map(|i| glam::vec2(i as f32, if i % 2 == 0 { 0. } else { 0.05 }))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            simplify_polyline(&line, 0.1),
+            vec![glam::vec2(0., 0.), glam::vec2(10., 0.)]
+        );
+        assert_eq!(simplify_polyline(&line, 0.01).len(), 11);
+        let square =
+            [(0., 0.), (1., 0.), (2., 0.), (2., 2.), (0., 2.)].map(|(x, y)| glam::vec2(x, y));
+        assert_eq!(simplify_polygon(&square, 0.).len(), 4);
+    }
+}
@@ -0,0 +1,310 @@
+//! Pedestrians walking between goals under the social force model of Helbing and Molnár, for SLAM among crowds.
+//! They steer around walls, obstacles, agents and each other, and to agents they are moving disc obstacles.
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::ConvexPolygon,
+    scene::{AgentId, obstacles::ObstacleBody, source::ObstacleSource},
+};
+
+/// Directions pedestrians look in for walls and obstacles.
+const SIGHT_RAYS: usize = 16;
+
+/// How strongly pedestrians are drawn to their goals and pushed away from everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SocialForce {
+    /// Seconds a pedestrian takes to get back to walking at its preferred velocity.
+    pub relaxation_time: f32,
+    /// Push between pedestrians at touching distance, in m/s².
+    pub strength: f32,
+    /// Distance over which the push between pedestrians falls off by 1/e, in metres.
+    pub range: f32,
+    /// Push from walls, obstacles and agents at touching distance, in m/s².
+    pub wall_strength: f32,
+    /// Distance over which the push from walls, obstacles and agents falls off by 1/e, in metres.
+    pub wall_range: f32,
+    /// Furthest a pedestrian looks for walls and obstacles, in metres.
+    pub sight: f32,
+}
+
+impl Default for SocialForce {
+    fn default() -> Self {
+        Self {
+            relaxation_time: 0.5,
+            strength: 10.,
+            range: 0.3,
+            wall_strength: 10.,
+            wall_range: 0.2,
+            sight: 2.,
+        }
+    }
+}
+
+impl Validate for SocialForce {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("relaxation_time", self.relaxation_time)?;
+        config::finite("strength", self.strength)?;
+        config::non_negative("strength", self.strength)?;
+        config::positive("range", self.range)?;
+        config::finite("wall_strength", self.wall_strength)?;
+        config::non_negative("wall_strength", self.wall_strength)?;
+        config::positive("wall_range", self.wall_range)?;
+        config::positive("sight", self.sight)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pedestrian {
+    pub position: glam::Vec2,
+    /// In m/s.
+    pub velocity: glam::Vec2,
+    /// In metres.
+    pub radius: f32,
+    /// Preferred walking speed in m/s.
+    pub speed: f32,
+    /// Walked to in order. The pedestrian stands still once it has reached the last.
+    pub goals: Vec<glam::Vec2>,
+    /// Whether to start again from the first goal after reaching the last.
+    pub looped: bool,
+    /// Index into `goals` of the one being walked to.
+    pub next_goal: usize,
+}
+
+impl Pedestrian {
+    /// A pedestrian of typical size and pace standing at `position`.
+    pub fn new(position: glam::Vec2, goals: Vec<glam::Vec2>) -> Self {
+        Self {
+            position,
+            velocity: glam::Vec2::ZERO,
+            radius: 0.25,
+            speed: 1.3,
+            goals,
+            looped: false,
+            next_goal: 0,
+        }
+    }
+
+    pub fn goal(&self) -> Option<glam::Vec2> {
+        self.goals.get(self.next_goal).copied()
+    }
+
+    pub fn body(&self) -> ObstacleBody {
+        ObstacleBody::Disc {
+            center: self.position,
+            radius: self.radius,
+        }
+    }
+}
+
+impl Validate for Pedestrian {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("position.x", self.position.x)?;
+        config::finite("position.y", self.position.y)?;
+        config::positive("radius", self.radius)?;
+        config::finite("speed", self.speed)?;
+        config::non_negative("speed", self.speed)
+    }
+}
+
+/// The pedestrians in a scene and the model moving them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Crowd {
+    pub model: SocialForce,
+    pub pedestrians: Vec<Pedestrian>,
+}
+
+impl Validate for Crowd {
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.model.validate().map_err(|e| e.in_field("model"))?;
+        self.pedestrians.iter().enumerate().try_for_each(|(i, p)| {
+            p.validate()
+                .map_err(|e| e.in_field(&format!("pedestrians[{i}]")))
+        })
+    }
+}
+
+impl Crowd {
+    pub fn is_empty(&self) -> bool {
+        self.pedestrians.is_empty()
+    }
+
+    /// Each pedestrian as it stands.
+    pub fn bodies(&self) -> impl Iterator<Item = ObstacleBody> + '_ {
+        self.pedestrians.iter().map(Pedestrian::body)
+    }
+
+    /// Walks every pedestrian on by `dt`, pushed away from `walls`, `obstacles`, `agents` and each other as they all
+    /// stand now.
+    pub fn step(
+        &mut self,
+        dt: f32,
+        walls: &dyn ObstacleSource,
+        obstacles: &[ObstacleBody],
+        agents: &[(AgentId, ConvexPolygon)],
+    ) {
+        let forces = (0..self.pedestrians.len())
+            .map(|i| self.force(i, walls, obstacles, agents))
+            .collect::<Vec<_>>();
+
+        for (pedestrian, force) in self.pedestrians.iter_mut().zip(forces) {
+            // Allowed to hurry a little to get out of the way.
+            pedestrian.velocity =
+                (pedestrian.velocity + force * dt).clamp_length_max(1.3 * pedestrian.speed);
+            pedestrian.position += pedestrian.velocity * dt;
+
+            if let Some(goal) = pedestrian.goal()
+                && pedestrian.position.distance(goal) < pedestrian.radius + 0.2
+            {
+                pedestrian.next_goal += 1;
+                if pedestrian.looped && pedestrian.next_goal == pedestrian.goals.len() {
+                    pedestrian.next_goal = 0;
+                }
+            }
+        }
+    }
+
+    /// Acceleration of pedestrian `i`, in m/s².
+    fn force(
+        &self,
+        i: usize,
+        walls: &dyn ObstacleSource,
+        obstacles: &[ObstacleBody],
+        agents: &[(AgentId, ConvexPolygon)],
+    ) -> glam::Vec2 {
+        let model = &self.model;
+        let me = &self.pedestrians[i];
+
+        let desired = me.goal().map_or(glam::Vec2::ZERO, |goal| {
+            (goal - me.position).normalize_or_zero() * me.speed
+        });
+        let mut force = (desired - me.velocity) / model.relaxation_time;
+
+        for (j, other) in self.pedestrians.iter().enumerate() {
+            let between = me.position - other.position;
+            let distance = between.length();
+            if j == i || distance > model.sight {
+                continue;
+            }
+
+            let away = between.try_normalize().unwrap_or(glam::Vec2::X);
+            let gap = distance - me.radius - other.radius;
+            force += away * model.strength * (-gap / model.range).exp();
+        }
+
+        let push =
+            |distance: f32| model.wall_strength * ((me.radius - distance) / model.wall_range).exp();
+
+        // Only the nearest wall or obstacle, so a long wall seen by several rays pushes once.
+        let nearest = (0..SIGHT_RAYS)
+            .map(|k| glam::Vec2::from_angle(k as f32 * std::f32::consts::TAU / SIGHT_RAYS as f32))
+            .filter_map(|dir| {
+                let wall = walls.cast_rays_tagged(me.position, dir).map(|(t, _)| t);
+                let obstacle = obstacles
+                    .iter()
+                    .filter_map(|o| o.cast_ray(me.position, dir))
+                    .min_by(f32::total_cmp);
+                let t = wall.into_iter().chain(obstacle).min_by(f32::total_cmp)?;
+                (t < model.sight).then_some((t, dir))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((distance, dir)) = nearest {
+            force -= dir * push(distance);
+        }
+
+        for (_, footprint) in agents {
+            let (point, distance) = footprint
+                .edges()
+                .map(|(side, _)| {
+                    let point = side.closest_point(me.position);
+                    (point, point.distance(me.position))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or_default();
+            if distance > model.sight {
+                continue;
+            }
+
+            // From inside, back out the way the footprint's centre isn't.
+            let away = if footprint.contains(me.position) {
+                (me.position - footprint.centroid()).normalize_or(glam::Vec2::X)
+            } else {
+                (me.position - point).normalize_or_zero()
+            };
+            force += away * push(distance);
+        }
+
+        force
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        math::Pose2D,
+        scene::{
+            HitTag,
+            crowd::Pedestrian,
+            generate,
+            obstacles::{DynamicObstacle, ObstacleId, ObstaclePath, ObstacleShape},
+        },
+    };
+
+    #[test]
+    fn test_crowd() {
+        // Two pedestrians walking at each other down a corridor get past without touching, and without walking
+        // through the walls either side.
+        let size = [40, 24];
+        let pixels = generate::hallway(size, 4, 4, 1, 2);
+        let mut scene = Scene2D::from_pixels(size, &pixels).unwrap();
+        let (west, east) = (glam::vec2(-15., 0.), glam::vec2(15., 0.));
+        scene.crowd.pedestrians = vec![
+            Pedestrian::new(west, vec![east]),
+            Pedestrian::new(east + glam::vec2(0., 0.1), vec![west]),
+        ];
+
+        let mut closest = f32::INFINITY;
+        for _ in 0..600 {
+            scene.update(0.05);
+            let [a, b] = [0, 1].map(|i| scene.crowd.pedestrians[i].position);
+            closest = closest.min(a.distance(b));
+            assert!(!scene.is_occupied_vec2(a) && !scene.is_occupied_vec2(b));
+        }
+        assert!(closest > 0.5, "{closest}");
+        assert!(scene.crowd.pedestrians[0].position.distance(east) < 0.5);
+        assert!(scene.crowd.pedestrians[1].position.distance(west) < 0.5);
+
+        // Agents see pedestrians numbered after the obstacles, and pedestrians brushing past an agent keep off it.
+        let mut scene = Scene2D::from_pixels([20, 20], &[255; 400]).unwrap();
+        scene.obstacles = vec![DynamicObstacle {
+            shape: ObstacleShape::Disc { radius: 0.5 },
+            path: ObstaclePath::Fixed(Pose2D::new(glam::vec2(0., -5.), 0.)),
+        }]
+        .into();
+        scene.crowd.pedestrians = vec![Pedestrian::new(
+            glam::vec2(-5., 0.),
+            vec![glam::vec2(0., 1.), glam::vec2(5., 0.)],
+        )];
+        scene.add_agent(Agent2D::default());
+        let hit = scene
+            .state()
+            .cast_rays(glam::vec2(-5., 2.), glam::Vec2::NEG_Y);
+        assert_eq!(
+            hit.map(|(_, tag)| tag),
+            Some(HitTag::Obstacle(ObstacleId(1)))
+        );
+
+        for _ in 0..300 {
+            scene.update(0.05);
+            let pedestrian = &scene.crowd.pedestrians[0];
+            let footprint = scene.agents.values().next().unwrap().footprint_polygon();
+            assert!(!footprint.contains(pedestrian.position));
+        }
+        assert!(scene.crowd.pedestrians[0].position.x > 4.);
+    }
+}
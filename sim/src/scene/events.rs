@@ -0,0 +1,129 @@
+use crate::scene::{
+    AgentCollisionEvent, AgentId, CollisionEvent, OutOfBoundsEvent, Scene2D, SceneTime,
+    mission::MissionEvent,
+};
+
+/// Anything a [Scene2D] reports, as sent to [subscribers](Scene2D::subscribe_events).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneEvent {
+    AgentSpawned {
+        agent: AgentId,
+        time: SceneTime,
+    },
+    AgentRemoved {
+        agent: AgentId,
+        time: SceneTime,
+    },
+    Collision(CollisionEvent),
+    AgentCollision(AgentCollisionEvent),
+    OutOfBounds(OutOfBoundsEvent),
+    /// The agent's [battery](crate::agent::Agent2DConfig::battery) ran out during the update ending at `time`.
+    BatteryDepleted {
+        agent: AgentId,
+        time: SceneTime,
+    },
+    /// Goals reached, missions completed and timed out, and agents straying off route.
+    Mission(MissionEvent),
+    /// A measurement on `topic` became readable through the [Scene2DLoop](crate::scene::Scene2DLoop) during the
+    /// update ending at `time`.
+    MeasurementReady {
+        agent: AgentId,
+        topic: String,
+        time: SceneTime,
+        /// The measurement's own timestamp, on the sensor's clock.
+        stamp: SceneTime,
+    },
+}
+
+/// Senders of every live subscription to a scene's events.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventBus {
+    subscribers: Vec<flume::Sender<SceneEvent>>,
+}
+
+impl EventBus {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub(crate) fn subscribe(&mut self, subscriber: flume::Sender<SceneEvent>) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Sends `event` to every subscriber, forgetting those whose receivers are gone.
+    pub(crate) fn emit(&mut self, event: SceneEvent) {
+        self.subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
+
+impl Scene2D {
+    /// A channel receiving every [SceneEvent] from now on, in the order they happen. Drain it with
+    /// [try_iter](flume::Receiver::try_iter) after each step, or block on it from another thread. Dropping the
+    /// receiver ends the subscription.
+    pub fn subscribe_events(&mut self) -> flume::Receiver<SceneEvent> {
+        let (snd, rcv) = flume::unbounded();
+        self.events.subscribe(snd);
+
+        rcv
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        scene::{events::SceneEvent, mission::Mission},
+        sim_config::{CollisionMode, SimConfig},
+        testing,
+    };
+
+    #[test]
+    fn test_scene_events() {
+        // A wall across the top of the map, with a goal just short of it.
+        let mut pixels = [255; 100];
+        pixels[..20].fill(0);
+        let config = SimConfig {
+            seed: Some(1),
+            collision: CollisionMode::Stop,
+            ..Default::default()
+        };
+        let mut scene = Scene2D::new([10, 10], &pixels, config).unwrap();
+        let events = scene.subscribe_events();
+
+        let mut agent = testing::bumper_agent();
+        agent.mission = Some(Mission::waypoints([glam::vec2(0., 1.)], 0.5));
+        agent.state.velocity = 2.;
+        let id = scene.add_agent(agent);
+        for _ in 0..120 {
+            scene.step();
+        }
+        scene.remove_agent(id);
+
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert!(matches!(
+            received.first(),
+            Some(SceneEvent::AgentSpawned { agent, .. }) if *agent == id
+        ));
+        assert!(matches!(
+            received.last(),
+            Some(SceneEvent::AgentRemoved { agent, .. }) if *agent == id
+        ));
+        let position = |f: fn(&SceneEvent) -> bool| received.iter().position(f);
+        let reached = position(|e| matches!(e, SceneEvent::Mission(_))).unwrap();
+        let collided = position(|e| matches!(e, SceneEvent::Collision(_))).unwrap();
+        assert!(reached < collided);
+        let bumps = received
+            .iter()
+            .filter(
+                |e| matches!(e, SceneEvent::MeasurementReady { topic, .. } if topic == "bumper"),
+            )
+            .count();
+        assert_eq!(bumps, 120);
+
+        // Subscriptions end with their receivers.
+        drop(events);
+        drop(scene.subscribe_events());
+        scene.add_agent(Agent2D::default());
+        assert!(scene.events.is_empty());
+    }
+}
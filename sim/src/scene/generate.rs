@@ -0,0 +1,155 @@
+//! Procedural maps, as pixels for [Scene2D::from_pixels](crate::Scene2D::from_pixels): row-major with the first row
+//! at the top, 255 for free space and 0 for walls.
+
+/// World position of the centre of pixel `(col, row)`, matching [OccupancyMap::translate](super::occupancy_map::OccupancyMap::translate).
+fn pixel_center([width, height]: [usize; 2], col: usize, row: usize) -> glam::Vec2 {
+    glam::vec2(
+        col as f32 + 0.5 - width as f32 / 2.,
+        height as f32 / 2. - row as f32 - 0.5,
+    )
+}
+
+fn from_fn(size: [usize; 2], free: impl Fn(glam::Vec2) -> bool) -> Vec<u8> {
+    (0..size[0] * size[1])
+        .map(|i| {
+            let p = pixel_center(size, i % size[0], i / size[0]);
+            if free(p) { 255 } else { 0 }
+        })
+        .collect()
+}
+
+/// A closed loop shaped like a running track: two straights joined by semicircles, `lane_width` wide and centred in
+/// the map. Returns the pixels and the radius of the bends' centre line, so an agent can be placed at `(0, -radius)`
+/// facing along x.
+pub fn stadium_track(size: [usize; 2], lane_width: f32) -> (Vec<u8>, f32) {
+    let half = glam::vec2(size[0] as f32, size[1] as f32) / 2.;
+    // Leave a wall at least a cell thick around the outside.
+    let radius = half.y - lane_width / 2. - 1.;
+    let straight = (half.x - half.y).max(0.);
+
+    let pixels = from_fn(size, |p| {
+        let nearest = glam::vec2(p.x.clamp(-straight, straight), 0.);
+        (p.distance(nearest) - radius).abs() <= lane_width / 2.
+    });
+
+    (pixels, radius)
+}
+
+/// Whether pixel `i` along an axis split into `count` rooms `room` pixels long is in a wall between two of them.
+fn on_wall(i: usize, room: usize, count: usize, wall: usize) -> bool {
+    (1..count).any(|k| {
+        let start = (k * room).saturating_sub(wall / 2);
+        (start..start + wall).contains(&i)
+    })
+}
+
+/// Whether pixel `i` along an axis is in line with a door `door` wide in the middle of each room `room` pixels long.
+fn in_door(i: usize, room: usize, door: usize) -> bool {
+    let start = (room / 2).saturating_sub(door / 2);
+    (start..start + door).contains(&(i % room))
+}
+
+fn on_border([width, height]: [usize; 2], col: usize, row: usize, wall: usize) -> bool {
+    col < wall || row < wall || width - col <= wall || height - row <= wall
+}
+
+/// A `rooms[0]` by `rooms[1]` grid of rooms separated by walls `wall` thick, with a door `door` wide in the middle of
+/// every inner wall.
+pub fn rooms(size: [usize; 2], rooms: [usize; 2], wall: usize, door: usize) -> Vec<u8> {
+    let rooms = rooms.map(|n| n.max(1));
+    let room = [(size[0] / rooms[0]).max(1), (size[1] / rooms[1]).max(1)];
+
+    (0..size[0] * size[1])
+        .map(|p| {
+            let (col, row) = (p % size[0], p / size[0]);
+            let wall_x = on_wall(col, room[0], rooms[0], wall);
+            let wall_y = on_wall(row, room[1], rooms[1], wall);
+
+            let occupied = on_border(size, col, row, wall)
+                || (wall_x && (wall_y || !in_door(row, room[1], door)))
+                || (wall_y && !in_door(col, room[0], door));
+            if occupied { 0 } else { 255 }
+        })
+        .collect()
+}
+
+/// A corridor `corridor` wide along the middle of the map with a row of `rooms` identical rooms on each side, each
+/// with a door `door` wide onto the corridor. The two sides mirror each other and every room looks the same from
+/// inside, for testing how localizers cope with places they can't tell apart. The rooms are exactly alike when
+/// `rooms` divides the map's width.
+pub fn hallway(
+    size: [usize; 2],
+    rooms: usize,
+    corridor: usize,
+    wall: usize,
+    door: usize,
+) -> Vec<u8> {
+    let rooms = rooms.max(1);
+    let room = (size[0] / rooms).max(1);
+    // First row of the corridor and first row past it.
+    let top = size[1].saturating_sub(corridor) / 2;
+    let bottom = top + corridor;
+
+    (0..size[0] * size[1])
+        .map(|p| {
+            let (col, row) = (p % size[0], p / size[0]);
+            let in_corridor = (top..bottom).contains(&row);
+            let wall_x = !in_corridor && on_wall(col, room, rooms, wall);
+            let wall_y = (top.saturating_sub(wall)..top).contains(&row)
+                || (bottom..bottom + wall).contains(&row);
+
+            let occupied =
+                on_border(size, col, row, wall) || wall_x || (wall_y && !in_door(col, room, door));
+            if occupied { 0 } else { 255 }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Agent2D, Scene2D, math::Pose2D, scene::generate};
+
+    #[test]
+    fn test_generated_maps() {
+        let size = [48, 32];
+        let (pixels, radius) = generate::stadium_track(size, 4.);
+        let scene = Scene2D::from_pixels(size, &pixels).unwrap();
+
+        let agent = Agent2D::with_scale(2.);
+        let state = agent
+            .state
+            .with_pose(Pose2D::new(glam::vec2(0., -radius), 0.));
+        assert!(
+            !scene
+                .occupancy_map
+                .overlaps(&agent.config.footprint(&state))
+        );
+        assert!(scene.is_occupied_vec2(glam::Vec2::ZERO));
+        assert!(!scene.is_occupied_vec2(glam::vec2(20., 0.)));
+
+        let size = [48, 48];
+        let scene = Scene2D::from_pixels(size, &generate::rooms(size, [3, 3], 1, 4)).unwrap();
+        assert!(!scene.is_occupied_vec2(glam::Vec2::ZERO));
+        // Walls between the rooms at x = ±8, with doors in the middle of each room's side.
+        assert!(scene.is_occupied_vec2(glam::vec2(8.5, 4.5)));
+        assert!(!scene.is_occupied_vec2(glam::vec2(8.5, 0.5)));
+
+        let size = [40, 24];
+        let pixels = generate::hallway(size, 4, 4, 1, 2);
+        let scene = Scene2D::from_pixels(size, &pixels).unwrap();
+        // Rows 10 to 13 are the corridor, with walls on rows 9 and 14 and doors at columns 4, 5, 14, 15 and so on.
+        assert!(!scene.is_occupied_vec2(glam::vec2(-19.5 + 10., 0.5)));
+        assert!(scene.is_occupied_vec2(glam::vec2(-19.5 + 3., 2.5)));
+        assert!(!scene.is_occupied_vec2(glam::vec2(-19.5 + 4., 2.5)));
+        assert!(!scene.is_occupied_vec2(glam::vec2(-19.5 + 15., -2.5)));
+        // Walls between the rooms, but not across the corridor.
+        assert!(scene.is_occupied_vec2(glam::vec2(-19.5 + 10., 5.5)));
+        // The two sides mirror each other.
+        let mirrored = |col: usize, row: usize| pixels[(size[1] - 1 - row) * size[0] + col];
+        assert!(
+            (0..size[1]).all(
+                |row| (0..size[0]).all(|col| pixels[row * size[0] + col] == mirrored(col, row))
+            )
+        );
+    }
+}
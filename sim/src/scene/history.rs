@@ -0,0 +1,345 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    Agent2D,
+    logging::LogRecord,
+    scene::{
+        AgentId, OccupancyMap, Scene2D, SceneTime, crowd::Crowd, obstacles::DynamicObstacle,
+        scene_loop::MeasurementSnapshot,
+    },
+};
+
+/// The dynamic part of a [Scene2D] at one instant, including the map, the agents' sensors, controllers and localizers,
+/// and measurements sensed but not yet delivered, so a run restored from it plays out as it did. Controllers and
+/// localizers that can't be copied are [shared](Scene2DSnapshot::shared) instead.
+#[derive(Debug, Clone)]
+pub struct Scene2DSnapshot {
+    pub time: SceneTime,
+    agents: FxHashMap<AgentId, Agent2D>,
+    shared: Vec<(AgentId, SharedState)>,
+    occupancy_map: Arc<OccupancyMap>,
+    landmarks: Arc<Vec<glam::Vec2>>,
+    beacons: Arc<Vec<glam::Vec2>>,
+    obstacles: Arc<Vec<DynamicObstacle>>,
+    crowd: Crowd,
+    out_of_bounds: FxHashSet<AgentId>,
+    measurements: MeasurementSnapshot,
+    banked: f32,
+    logs: Vec<LogRecord>,
+}
+
+/// Part of an agent a [Scene2DSnapshot] couldn't copy, so a scene restored from it carries on with that part as it is
+/// now rather than as it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SharedState {
+    /// The [controller](crate::controller::AgentController::try_clone) can't be copied.
+    Controller,
+    /// The [localizer](crate::localization::Localizer::try_clone) can't be copied.
+    Localizer,
+}
+
+impl Scene2DSnapshot {
+    pub fn agent(&self, id: AgentId) -> Option<&Agent2D> {
+        self.agents.get(&id)
+    }
+
+    /// What the snapshot shares with the scene it was taken from, by agent in order of id. Empty if restoring it
+    /// repeats the run exactly.
+    pub fn shared(&self) -> &[(AgentId, SharedState)] {
+        &self.shared
+    }
+
+    /// Whether restoring the snapshot repeats the run exactly, i.e. nothing is [shared](Self::shared).
+    pub fn is_reproducible(&self) -> bool {
+        self.shared.is_empty()
+    }
+
+    /// What was logged during the update that led to this snapshot.
+    pub fn logs(&self) -> &[LogRecord] {
+        &self.logs
+    }
+}
+
+/// Copies `agent` down to its sensors, controller and localizer, noting in `shared` whichever of those it can't.
+fn detach(id: AgentId, agent: &Agent2D, shared: &mut Vec<(AgentId, SharedState)>) -> Agent2D {
+    let mut copy = agent.clone();
+    copy.sensors = agent.sensors.detached();
+
+    if let Some(controller) = &agent.controller {
+        match controller.lock().try_clone() {
+            Some(controller) => copy.controller = Some(controller),
+            None => shared.push((id, SharedState::Controller)),
+        }
+    }
+    if let Some(localizer) = &agent.localizer {
+        match localizer.lock().try_clone() {
+            Some(localizer) => copy.localizer = Some(localizer),
+            None => shared.push((id, SharedState::Localizer)),
+        }
+    }
+
+    copy
+}
+
+impl Scene2D {
+    pub fn snapshot(&self) -> Scene2DSnapshot {
+        let mut shared = Vec::new();
+        let agents = self
+            .agents
+            .iter()
+            .map(|(&id, agent)| (id, detach(id, agent, &mut shared)))
+            .collect();
+        shared.sort_by_key(|(id, _)| *id);
+
+        Scene2DSnapshot {
+            time: self.time,
+            agents,
+            shared,
+            occupancy_map: Arc::clone(&self.occupancy_map),
+            landmarks: Arc::clone(&self.landmarks),
+            beacons: Arc::clone(&self.beacons),
+            obstacles: Arc::clone(&self.obstacles),
+            crowd: self.crowd.clone(),
+            out_of_bounds: self.out_of_bounds.clone(),
+            measurements: self.scene_loop.snapshot_measurements(),
+            banked: self.banked,
+            logs: self.logs.clone(),
+        }
+    }
+
+    /// Rolls the scene back (or forward) to `snapshot`. Agents removed since are re-added under their old ids and
+    /// agents added since are removed. The snapshot keeps its own copies, so it can be restored again and again.
+    pub fn restore(&mut self, snapshot: &Scene2DSnapshot) {
+        let added = self
+            .agents
+            .keys()
+            .filter(|id| !snapshot.agents.contains_key(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in added {
+            self.remove_agent(id);
+        }
+
+        // What can't be copied was noted when the snapshot was taken.
+        let mut shared = Vec::new();
+        let agents = snapshot
+            .agents
+            .iter()
+            .map(|(&id, agent)| (id, detach(id, agent, &mut shared)))
+            .collect::<FxHashMap<_, _>>();
+        for (&id, agent) in &agents {
+            self.scene_loop.insert_agent(id, agent);
+            self.scene_loop.set_sensors(id, agent);
+        }
+
+        self.time = snapshot.time;
+        self.agents = agents;
+        self.occupancy_map = Arc::clone(&snapshot.occupancy_map);
+        self.landmarks = Arc::clone(&snapshot.landmarks);
+        self.beacons = Arc::clone(&snapshot.beacons);
+        self.obstacles = Arc::clone(&snapshot.obstacles);
+        self.crowd = snapshot.crowd.clone();
+        self.out_of_bounds = snapshot.out_of_bounds.clone();
+        self.banked = snapshot.banked;
+        self.logs = snapshot.logs.clone();
+        self.scene_loop.restore_measurements(&snapshot.measurements);
+    }
+}
+
+/// A rolling window of snapshots used to rewind the scene or play it back in reverse.
+#[derive(Debug, Clone)]
+pub struct SceneHistory {
+    /// Seconds of scene time kept.
+    pub duration: f32,
+    snapshots: VecDeque<Scene2DSnapshot>,
+}
+
+impl Default for SceneHistory {
+    fn default() -> Self {
+        Self::new(crate::SimConfig::default().history)
+    }
+}
+
+impl SceneHistory {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    /// Time span covered by the recorded snapshots.
+    pub fn span(&self) -> f32 {
+        match (self.snapshots.front(), self.snapshots.back()) {
+            (Some(first), Some(last)) => last.time.0 - first.time.0,
+            _ => 0.,
+        }
+    }
+
+    /// Oldest to newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Scene2DSnapshot> {
+        self.snapshots.iter()
+    }
+
+    /// Every log record in the history, oldest first.
+    pub fn logs(&self) -> impl Iterator<Item = &LogRecord> {
+        self.snapshots.iter().flat_map(|s| &s.logs)
+    }
+
+    pub fn record(&mut self, scene: &Scene2D) {
+        // Anything after the current time belongs to a timeline that was rewound away.
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|s| s.time.0 >= scene.time.0)
+        {
+            self.snapshots.pop_back();
+        }
+
+        self.snapshots.push_back(scene.snapshot());
+
+        while self
+            .snapshots
+            .front()
+            .is_some_and(|s| scene.time.0 - s.time.0 > self.duration)
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Restores the latest snapshot at least `seconds` before the scene's current time, or the oldest one if the
+    /// history doesn't reach that far. Returns `false` if nothing was recorded.
+    pub fn rewind(&mut self, scene: &mut Scene2D, seconds: f32) -> bool {
+        let target = scene.time.0 - seconds;
+
+        while self.snapshots.len() > 1 && self.snapshots.back().is_some_and(|s| s.time.0 > target) {
+            self.snapshots.pop_back();
+        }
+
+        match self.snapshots.back() {
+            Some(snapshot) => {
+                scene.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Scene2D,
+        localization::DeadReckoning,
+        scene::history::SceneHistory,
+        sensors::{Compass2D, CompassSensed},
+        testing,
+    };
+
+    #[test]
+    fn test_rewind() {
+        let mut scene = testing::open_room();
+        let mut agent = Agent2D::default();
+        agent.state.velocity = 1.;
+        let id = scene.add_agent(agent);
+
+        let mut history = SceneHistory::default();
+        let mut positions = Vec::new();
+        for _ in 0..10 {
+            history.record(&scene);
+            positions.push(scene.agents[&id].state.position);
+            scene.update(0.1);
+        }
+
+        assert!(history.rewind(&mut scene, 0.55));
+        assert!((scene.time.0 - 0.4).abs() < 1e-4);
+        assert_eq!(scene.agents[&id].state.position, positions[4]);
+
+        scene.remove_agent(id);
+        assert!(history.rewind(&mut scene, 10.));
+        assert_eq!(scene.time.0, 0.);
+        assert_eq!(scene.agents[&id].state.position, positions[0]);
+        assert!(scene.scene_loop.contains_agent(id));
+    }
+
+    #[test]
+    fn test_snapshot_branching() {
+        let mut scene = testing::open_room();
+        scene.set_deterministic(Some(3));
+        let mut agent = testing::bumper_agent();
+        agent.state.velocity = 1.;
+        agent.sensors.get_mut("bumper").unwrap().latency = 0.25;
+        // A drifting sensor and a localizer, both carrying state from one update to the next.
+        let mut compass = Compass2D::default();
+        compass.bias_walk = 0.1;
+        agent.sensors.insert("compass", compass);
+        agent.localizer = Some(Arc::new(Mutex::new(DeadReckoning::new(0.1, 0.05))));
+        let id = scene.add_agent(agent);
+        for _ in 0..3 {
+            scene.update(0.1);
+        }
+
+        // Readings taken before the snapshot are still on their way when it's restored.
+        let snapshot = scene.snapshot();
+        assert!(snapshot.is_reproducible());
+        let run = |scene: &mut Scene2D| {
+            (0..4)
+                .map(|_| {
+                    scene.update(0.1);
+                    let m = scene.scene_loop.query_topic(id, "bumper");
+                    let heading = scene
+                        .scene_loop
+                        .query_topic_as::<CompassSensed>(id, "compass")
+                        .map(|m| m.state.heading);
+                    let estimate = scene.agents[&id].estimate.map(|e| e.pose);
+                    (
+                        scene.agents[&id].state.position,
+                        m.map(|m| m.time),
+                        heading,
+                        estimate,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = run(&mut scene);
+        scene.restore(&snapshot);
+        assert_eq!(scene.time, snapshot.time);
+        let second = run(&mut scene);
+        assert_eq!(first, second);
+        assert!(first[0].1.is_some());
+        // Restoring twice still starts from the snapshot, not from where the last restore got to.
+        scene.restore(&snapshot);
+        assert_eq!(run(&mut scene), first);
+    }
+
+    #[test]
+    fn test_restore_map() {
+        let mut scene = testing::open_room();
+        let cell = glam::usizevec2(3, 3);
+        let snapshot = scene.snapshot();
+
+        scene.set_cells(&[(cell, true)]).unwrap();
+        assert!(scene.is_occupied(cell));
+
+        scene.restore(&snapshot);
+        assert!(!scene.is_occupied(cell));
+    }
+}
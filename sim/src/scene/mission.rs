@@ -0,0 +1,328 @@
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::Box2D,
+    scene::{AgentId, SceneTime},
+};
+
+/// Somewhere an agent has to get to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Goal {
+    /// Within `tolerance` metres of `position`.
+    Waypoint {
+        position: glam::Vec2,
+        tolerance: f32,
+    },
+    Region(Box2D),
+}
+
+impl Goal {
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        match self {
+            Self::Waypoint {
+                position,
+                tolerance,
+            } => point.distance(*position) <= *tolerance,
+            Self::Region(region) => region.contains(point),
+        }
+    }
+
+    pub fn center(&self) -> glam::Vec2 {
+        match self {
+            Self::Waypoint { position, .. } => *position,
+            Self::Region(region) => region.centroid(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MissionStatus {
+    #[default]
+    Active,
+    Completed,
+    /// A goal took longer than the [timeout](Mission::timeout). The rest are abandoned.
+    TimedOut,
+}
+
+/// Goals for one agent to reach in order. The scene checks the agent against the current goal after every update and
+/// reports progress as [MissionEvent]s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mission {
+    pub goals: Vec<Goal>,
+    /// Seconds allowed for each goal, counted from when it becomes current. `None` for no limit.
+    pub timeout: Option<f32>,
+    /// Furthest in metres the agent may stray from the straight line between where it set off for the current goal
+    /// and that goal's centre. `None` to not check.
+    pub max_deviation: Option<f32>,
+    current: usize,
+    status: MissionStatus,
+    /// Where and when the agent set off for the current goal, once the scene has seen it.
+    leg_start: Option<(glam::Vec2, SceneTime)>,
+    off_route: bool,
+}
+
+impl Validate for Mission {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (i, goal) in self.goals.iter().enumerate() {
+            match *goal {
+                Goal::Waypoint {
+                    position,
+                    tolerance,
+                } => {
+                    config::finite(&format!("goals[{i}].position.x"), position.x)?;
+                    config::finite(&format!("goals[{i}].position.y"), position.y)?;
+                    config::positive(&format!("goals[{i}].tolerance"), tolerance)?;
+                }
+                Goal::Region(region) => {
+                    config::ordered(
+                        &format!("goals[{i}].region.x"),
+                        (region.min.x, region.max.x),
+                    )?;
+                    config::ordered(
+                        &format!("goals[{i}].region.y"),
+                        (region.min.y, region.max.y),
+                    )?;
+                }
+            }
+        }
+        if let Some(timeout) = self.timeout {
+            config::positive("timeout", timeout)?;
+        }
+        if let Some(deviation) = self.max_deviation {
+            config::positive("max_deviation", deviation)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissionEventKind {
+    /// Reached goal `goal`. Followed by [MissionEventKind::Completed] after the last one.
+    Reached {
+        goal: usize,
+    },
+    Completed,
+    TimedOut {
+        goal: usize,
+    },
+    /// Strayed `distance` metres from the route to goal `goal`. Reported again only after getting back on it.
+    OffRoute {
+        goal: usize,
+        distance: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MissionEvent {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    pub kind: MissionEventKind,
+}
+
+impl Mission {
+    pub fn new(goals: Vec<Goal>) -> Self {
+        Self {
+            goals,
+            timeout: None,
+            max_deviation: None,
+            current: 0,
+            status: MissionStatus::default(),
+            leg_start: None,
+            off_route: false,
+        }
+    }
+
+    /// Waypoints of `tolerance` metres at each of `positions`.
+    pub fn waypoints(positions: impl IntoIterator<Item = glam::Vec2>, tolerance: f32) -> Self {
+        Self::new(
+            positions
+                .into_iter()
+                .map(|position| Goal::Waypoint {
+                    position,
+                    tolerance,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn status(&self) -> MissionStatus {
+        self.status
+    }
+
+    /// Index of the goal being driven to, or the number of goals once they are all reached.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn current_goal(&self) -> Option<&Goal> {
+        match self.status {
+            MissionStatus::Active => self.goals.get(self.current),
+            _ => None,
+        }
+    }
+
+    /// Checks the agent at `position` at `time`, returning what happened since the last check. The first check starts
+    /// the clock on the first goal.
+    pub fn update(
+        &mut self,
+        agent: AgentId,
+        position: glam::Vec2,
+        time: SceneTime,
+    ) -> Vec<MissionEvent> {
+        let event = |kind| MissionEvent { agent, time, kind };
+        let mut events = Vec::new();
+
+        if self.status != MissionStatus::Active {
+            return events;
+        }
+        let (start, since) = *self.leg_start.get_or_insert((position, time));
+
+        // Several goals can be reached in one update if they overlap.
+        while let Some(goal) = self.goals.get(self.current)
+            && goal.contains(position)
+        {
+            events.push(event(MissionEventKind::Reached { goal: self.current }));
+            self.current += 1;
+            self.leg_start = Some((position, time));
+            self.off_route = false;
+        }
+        if self.current == self.goals.len() {
+            self.status = MissionStatus::Completed;
+            events.push(event(MissionEventKind::Completed));
+            return events;
+        }
+        if !events.is_empty() {
+            return events;
+        }
+
+        if self
+            .timeout
+            .is_some_and(|timeout| time.0 - since.0 > timeout)
+        {
+            self.status = MissionStatus::TimedOut;
+            events.push(event(MissionEventKind::TimedOut { goal: self.current }));
+            return events;
+        }
+
+        if let Some(max_deviation) = self.max_deviation {
+            let end = self.goals[self.current].center();
+            let along = end - start;
+            let t = ((position - start).dot(along) / along.length_squared().max(f32::EPSILON))
+                .clamp(0., 1.);
+            let distance = position.distance(start + along * t);
+
+            if distance > max_deviation && !self.off_route {
+                events.push(event(MissionEventKind::OffRoute {
+                    goal: self.current,
+                    distance,
+                }));
+            }
+            self.off_route = distance > max_deviation;
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        math::Box2D,
+        scene::mission::{Goal, Mission, MissionEventKind, MissionStatus},
+        testing,
+    };
+
+    #[test]
+    fn test_mission_goals() {
+        let mut scene = Scene2D::from_pixels([20, 20], &[255; 400]).unwrap();
+        let mut agent = Agent2D::default();
+        agent.state.heading = glam::Vec2::X;
+        agent.mission = Some(Mission {
+            max_deviation: Some(1.),
+            ..Mission::new(vec![
+                Goal::Waypoint {
+                    position: glam::vec2(2., 0.),
+                    tolerance: 0.5,
+                },
+                Goal::Region(Box2D {
+                    min: glam::vec2(4., -1.),
+                    max: glam::vec2(5., 1.),
+                }),
+            ])
+        });
+        let id = scene.add_agent(agent);
+
+        // Driven by hand at 2 m/s, straight along the route.
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            scene.agents.get_mut(&id).unwrap().state.velocity = 2.;
+            scene.update(0.1);
+            events.extend(scene.drain_mission_events());
+        }
+
+        assert_eq!(
+            events.iter().map(|e| e.kind).collect::<Vec<_>>(),
+            [
+                MissionEventKind::Reached { goal: 0 },
+                MissionEventKind::Reached { goal: 1 },
+                MissionEventKind::Completed
+            ]
+        );
+        assert!(events.iter().all(|e| e.agent == id));
+        let mission = scene.agents[&id].mission.as_ref().unwrap();
+        assert_eq!(mission.status(), MissionStatus::Completed);
+        assert_eq!(mission.current(), 2);
+    }
+
+    #[test]
+    fn test_mission_timeout() {
+        let mut scene = Scene2D::from_pixels([20, 20], &[255; 400]).unwrap();
+        let slow = scene.add_agent(Agent2D {
+            mission: Some(Mission {
+                timeout: Some(0.95),
+                ..Mission::waypoints([glam::vec2(-5., -5.)], 0.5)
+            }),
+            ..Default::default()
+        });
+
+        // The clock starts at the first update.
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            scene.update(0.1);
+            events.extend(scene.drain_mission_events());
+        }
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].agent, slow);
+        assert_eq!(events[0].kind, MissionEventKind::TimedOut { goal: 0 });
+        assert!((events[0].time.0 - 1.1).abs() < 1e-3, "{:?}", events[0]);
+    }
+
+    #[test]
+    fn test_mission_off_route() {
+        // A goal off to the side, with the agent pushed away from the route to it and back.
+        let id = testing::open_room().add_agent(Agent2D::default());
+        let mut mission = Mission {
+            max_deviation: Some(1.),
+            ..Mission::waypoints([glam::vec2(0., 10.)], 0.5)
+        };
+        let at = |t: f32| crate::scene::SceneTime(t);
+        assert!(mission.update(id, glam::Vec2::ZERO, at(0.)).is_empty());
+        let events = mission.update(id, glam::vec2(2., 3.), at(1.));
+        assert_eq!(
+            events[0].kind,
+            MissionEventKind::OffRoute {
+                goal: 0,
+                distance: 2.
+            }
+        );
+        assert!(mission.update(id, glam::vec2(3., 4.), at(2.)).is_empty());
+        assert!(mission.update(id, glam::vec2(0., 5.), at(3.)).is_empty());
+        assert_eq!(mission.update(id, glam::vec2(-2., 6.), at(4.)).len(), 1);
+        assert_eq!(mission.current_goal(), Some(&mission.goals[0]));
+    }
+}
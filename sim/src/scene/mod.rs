@@ -1,39 +1,172 @@
 use std::sync::Arc;
 
 use rayon::prelude::*;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
     Agent2D,
-    math::Box2D,
-    scene::{occupancy_map::OccupancyMap, scene_loop::Scene2DLoop},
+    agent::{Agent2DConfig, Agent2DState},
+    config::{ConfigError, Validate},
+    controller::ControlContext,
+    localization::{LocalizerContext, Odometry, PoseSource},
+    logging::{AgentLogger, LogRecord},
+    math::{Box2D, ConvexPolygon, OrientedBox2D},
+    rng,
+    scene::{
+        crowd::Crowd,
+        events::{EventBus, SceneEvent},
+        mission::MissionEvent,
+        obstacles::{DynamicObstacle, ObstacleBody, ObstacleId},
+        occupancy_map::{Contact, ObjectTag},
+        source::ObstacleSource,
+        surface::SurfaceMap,
+        tiles::TiledWorld,
+        vector_map::VectorMap,
+    },
+    sim_config::{AgentCollisionMode, CollisionMode, SimConfig},
+    trailer::Trailer,
 };
 
 lazy_static::lazy_static! {
     pub static ref FUTURES_THREAD_POOL: futures::executor::ThreadPool = futures::executor::ThreadPool::new().unwrap();
 }
 
+pub mod analysis;
+pub mod builder;
+#[cfg(feature = "serde")]
+pub mod checkpoint;
+pub mod contours;
+pub mod crowd;
+pub mod events;
+pub mod generate;
+pub mod history;
+pub mod mission;
+pub mod obstacles;
 pub mod occupancy_map;
+pub mod ros_map;
 pub mod scene_loop;
+pub mod source;
+pub mod spawn;
+pub mod surface;
+#[cfg(feature = "svg")]
+pub mod svg;
+pub mod tiles;
+pub mod vector_map;
+
+pub use builder::{OccupancySource, Scene2DBuilder};
+pub use history::{Scene2DSnapshot, SceneHistory, SharedState};
+pub use occupancy_map::{BoundaryPolicy, OccupancyMap, OutOfBoundsAction};
+pub use scene_loop::Scene2DLoop;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct SceneTime(f32);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SceneTime(pub f32);
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AgentId(u64);
 
+impl std::fmt::Display for AgentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "agent{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LandmarkId(pub usize);
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BeaconId(pub usize);
+
 #[derive(Debug, Clone)]
 pub struct Scene2D {
     pub agents: FxHashMap<AgentId, Agent2D>,
     pub time: SceneTime,
     pub occupancy_map: Arc<OccupancyMap>,
+    pub landmarks: Arc<Vec<glam::Vec2>>,
+    /// Fixed radio transmitters that [BeaconRanger2D](crate::sensors::BeaconRanger2D)s measure ranges to.
+    pub beacons: Arc<Vec<glam::Vec2>>,
+    /// Moving obstacles, indexed by [ObstacleId].
+    pub obstacles: Arc<Vec<DynamicObstacle>>,
+    /// Pedestrians, which agents see as obstacles numbered on from the last of `obstacles`.
+    pub crowd: Crowd,
     pub scene_loop: Arc<Scene2DLoop>,
+    /// When set, the scene lives in an unbounded tiled world and `occupancy_map` is left empty.
+    pub tiles: Option<Arc<TiledWorld>>,
+    /// When set, the walls are these segments and `occupancy_map` is left empty.
+    pub vector_map: Option<Arc<VectorMap>>,
+    /// When set, what the ground is made of under the agents. Otherwise it grips perfectly.
+    pub surfaces: Option<Arc<SurfaceMap>>,
+    /// Updates agents one at a time in id order on the calling thread instead of in parallel. Together with a seeded
+    /// [Scene2DLoop], runs then repeat bit for bit whatever the size of the thread pool.
+    pub ordered: bool,
+    config: SimConfig,
+    /// Built for [SimConfig::threads].
+    pool: Option<Arc<rayon::ThreadPool>>,
+    /// Seconds given to [Scene2D::update] but not yet simulated, under [SimConfig::physics_dt].
+    banked: f32,
+    next_agent_id: u64,
+    out_of_bounds: FxHashSet<AgentId>,
+    out_of_bounds_events: Vec<OutOfBoundsEvent>,
+    mission_events: Vec<MissionEvent>,
+    collision_events: Vec<CollisionEvent>,
+    agent_collision_events: Vec<AgentCollisionEvent>,
+    events: EventBus,
+    logs: Vec<LogRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutOfBoundsEvent {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    pub position: glam::Vec2,
+}
+
+/// An agent driven into a wall or obstacle, reported under every [CollisionMode] but [CollisionMode::Ignore].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionEvent {
+    pub agent: AgentId,
+    pub time: SceneTime,
+    /// `None` for the map's walls.
+    pub obstacle: Option<ObstacleId>,
+    /// Where the agent met the wall.
+    pub point: glam::Vec2,
+    /// Out of the wall, into free space.
+    pub normal: glam::Vec2,
+}
+
+/// Two agents' footprints overlapping after an update, reported under every [AgentCollisionMode] but
+/// [AgentCollisionMode::Ignore].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgentCollisionEvent {
+    /// Lower id first.
+    pub agents: [AgentId; 2],
+    pub time: SceneTime,
+    /// Middle of the overlap.
+    pub point: glam::Vec2,
+    /// From the first agent towards the second.
+    pub normal: glam::Vec2,
 }
 
 #[derive(Debug)]
 pub struct Scene2DState {
     pub time: SceneTime,
     pub occupancy_map: Arc<OccupancyMap>,
+    pub landmarks: Arc<Vec<glam::Vec2>>,
+    pub beacons: Arc<Vec<glam::Vec2>>,
+    /// Agent outlines at the start of the current step, followed by the footprints of any trailers they tow.
+    pub agents: Arc<Vec<(AgentId, ConvexPolygon)>>,
+    /// World-frame velocity of each agent and trailer, in the same order as `agents`.
+    pub agent_velocities: Arc<Vec<glam::Vec2>>,
+    /// Where each obstacle stands at `time`, indexed by [ObstacleId], followed by the pedestrians of the
+    /// [crowd](Scene2D::crowd).
+    pub obstacles: Arc<Vec<ObstacleBody>>,
+    pub tiles: Option<Arc<TiledWorld>>,
+    pub vector_map: Option<Arc<VectorMap>>,
+    pub surfaces: Option<Arc<SurfaceMap>>,
+    /// Agent whose sensors are looking, so rays pass through its own body. Set for each agent the
+    /// [Scene2DLoop] senses; `None` sees every agent.
+    pub viewer: Option<AgentId>,
 }
 
 impl Clone for Scene2DState {
@@ -41,52 +174,794 @@ impl Clone for Scene2DState {
         Self {
             time: self.time,
             occupancy_map: Arc::clone(&self.occupancy_map),
+            landmarks: Arc::clone(&self.landmarks),
+            beacons: Arc::clone(&self.beacons),
+            agents: Arc::clone(&self.agents),
+            agent_velocities: Arc::clone(&self.agent_velocities),
+            obstacles: Arc::clone(&self.obstacles),
+            tiles: self.tiles.as_ref().map(Arc::clone),
+            vector_map: self.vector_map.as_ref().map(Arc::clone),
+            surfaces: self.surfaces.as_ref().map(Arc::clone),
+            viewer: self.viewer,
         }
     }
 }
 
+/// What a ray cast through the scene ended on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HitTag {
+    Map(ObjectTag),
+    Agent(AgentId),
+    Obstacle(ObstacleId),
+}
+
+impl Scene2DState {
+    /// The state as `agent`'s sensors see it, looking out through its own body.
+    pub fn viewed_by(mut self, agent: AgentId) -> Self {
+        self.viewer = Some(agent);
+        self
+    }
+
+    /// The agents' footprints, leaving out the body of `viewer` though not the trailers it tows.
+    pub fn agents_seen_by(
+        &self,
+        viewer: Option<AgentId>,
+    ) -> impl Iterator<Item = (usize, AgentId, &ConvexPolygon)> {
+        // Each agent's body comes before its trailers.
+        let mut own_body = viewer;
+        self.agents
+            .iter()
+            .enumerate()
+            .filter(move |(_, (id, _))| {
+                let own = own_body == Some(*id);
+                if own {
+                    own_body = None;
+                }
+                !own
+            })
+            .map(|(i, (id, footprint))| (i, *id, footprint))
+    }
+
+    /// Casts against the footprints of the agents, passing through the body of `viewer` so sensors never see the body
+    /// they are mounted on.
+    pub fn cast_rays_agents(
+        &self,
+        pos: glam::Vec2,
+        dir: glam::Vec2,
+        viewer: Option<AgentId>,
+    ) -> Option<(f32, AgentId)> {
+        self.agents_seen_by(viewer)
+            .filter_map(|(_, id, footprint)| Some((footprint.cast_ray(pos, dir)?, id)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// The static walls: the tiles in a tiled world, the segments of a vector map, or else the occupancy map.
+    pub fn obstacle_source(&self) -> &dyn ObstacleSource {
+        obstacle_source(&self.occupancy_map, &self.tiles, &self.vector_map)
+    }
+
+    /// Casts against the static walls.
+    pub fn cast_rays_map(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        self.obstacle_source().cast_rays_tagged(pos, dir)
+    }
+
+    pub fn cast_rays_obstacles(
+        &self,
+        pos: glam::Vec2,
+        dir: glam::Vec2,
+    ) -> Option<(f32, ObstacleId)> {
+        self.obstacles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, body)| Some((body.cast_ray(pos, dir)?, ObstacleId(i))))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// Casts against the static map, the other agents and the obstacles, returning the nearest hit.
+    pub fn cast_rays(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, HitTag)> {
+        self.nearest_hit(pos, dir, self.cast_rays_map(pos, dir))
+    }
+
+    /// [cast_rays](Self::cast_rays) for many rays from `pos`, sharing the walk through the static walls.
+    pub fn cast_rays_batch(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, HitTag)>> {
+        self.obstacle_source()
+            .cast_rays_batch_tagged(pos, dirs)
+            .into_iter()
+            .zip(dirs)
+            .map(|(map_hit, &dir)| self.nearest_hit(pos, dir, map_hit))
+            .collect()
+    }
+
+    /// The nearer of `map_hit` and whatever the ray meets among the agents and obstacles.
+    fn nearest_hit(
+        &self,
+        pos: glam::Vec2,
+        dir: glam::Vec2,
+        map_hit: Option<(f32, ObjectTag)>,
+    ) -> Option<(f32, HitTag)> {
+        let map_hit = map_hit.map(|(t, tag)| (t, HitTag::Map(tag)));
+        let agent_hit = self
+            .cast_rays_agents(pos, dir, self.viewer)
+            .map(|(t, id)| (t, HitTag::Agent(id)));
+        let obstacle_hit = self
+            .cast_rays_obstacles(pos, dir)
+            .map(|(t, id)| (t, HitTag::Obstacle(id)));
+
+        [map_hit, agent_hit, obstacle_hit]
+            .into_iter()
+            .flatten()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    /// The deepest overlap of `footprint` with the map's walls or an obstacle, and the obstacle if it was one.
+    pub fn contact(&self, footprint: &OrientedBox2D) -> Option<(Contact, Option<ObstacleId>)> {
+        let wall = self.obstacle_source().contact(footprint).map(|c| (c, None));
+
+        self.obstacles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, body)| Some((body.contact(footprint)?, Some(ObstacleId(i)))))
+            .chain(wall)
+            .max_by(|a, b| a.0.depth.total_cmp(&b.0.depth))
+    }
+
+    /// [contact](Self::contact) for a convex outline. Obstacles are checked against its bounding box.
+    pub fn contact_polygon(
+        &self,
+        footprint: &ConvexPolygon,
+    ) -> Option<(Contact, Option<ObstacleId>)> {
+        let wall = self
+            .obstacle_source()
+            .contact_polygon(footprint)
+            .map(|c| (c, None));
+        let bounds = OrientedBox2D::from(footprint.get_box());
+
+        self.obstacles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, body)| Some((body.contact(&bounds)?, Some(ObstacleId(i)))))
+            .chain(wall)
+            .max_by(|a, b| a.0.depth.total_cmp(&b.0.depth))
+    }
+}
+
 impl Scene2D {
-    pub fn from_pixels(size: [usize; 2], pixels: &[u8]) -> Result<Self, Scene2DError> {
+    /// A scene of the image `pixels`, black for walls and white for free space, set up by `config`.
+    pub fn new(size: [usize; 2], pixels: &[u8], config: SimConfig) -> Result<Self, Scene2DError> {
         // Invert because white is free space and black is occupied space.
         let pixels = pixels.iter().map(|&i| i <= 127).collect();
-        let occupancy_map = OccupancyMap::from_pixels(glam::USizeVec2::from(size), pixels)?;
+        let occupancy_map = OccupancyMap::from_pixels_with_boundary(
+            glam::USizeVec2::from(size),
+            pixels,
+            config.boundary,
+        )?;
+
+        Self::with_config(occupancy_map, config)
+    }
+
+    pub fn from_pixels(size: [usize; 2], pixels: &[u8]) -> Result<Self, Scene2DError> {
+        Self::new(size, pixels, SimConfig::default())
+    }
+
+    pub fn from_pixels_with_boundary(
+        size: [usize; 2],
+        pixels: &[u8],
+        boundary: BoundaryPolicy,
+    ) -> Result<Self, Scene2DError> {
+        Self::new(
+            size,
+            pixels,
+            SimConfig {
+                boundary,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn from_occupancy_map(occupancy_map: OccupancyMap) -> Self {
+        Self::with_config(occupancy_map, SimConfig::default())
+            .expect("The default config is valid and needs no thread pool")
+    }
+
+    /// A scene of `occupancy_map` set up by `config`. The map keeps its own boundary policy.
+    pub fn with_config(
+        mut occupancy_map: OccupancyMap,
+        mut config: SimConfig,
+    ) -> Result<Self, Scene2DError> {
+        config.validate()?;
+        config.boundary = occupancy_map.boundary;
+        occupancy_map.ray_caster = config.ray_caster;
+        let pool = match config.threads {
+            Some(threads) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()?,
+            )),
+            None => None,
+        };
 
         let scene_loop = Arc::new(Scene2DLoop::default());
+        scene_loop.set_seed(config.seed);
 
         Ok(Self {
             agents: FxHashMap::default(),
             time: SceneTime(0.),
             occupancy_map: Arc::new(occupancy_map),
+            landmarks: Arc::new(Vec::new()),
+            beacons: Arc::new(Vec::new()),
+            obstacles: Arc::new(Vec::new()),
+            crowd: Crowd::default(),
             scene_loop,
+            tiles: None,
+            vector_map: None,
+            surfaces: None,
+            ordered: config.seed.is_some(),
+            config,
+            pool,
+            banked: 0.,
+            next_agent_id: 0,
+            out_of_bounds: FxHashSet::default(),
+            out_of_bounds_events: Vec::new(),
+            mission_events: Vec::new(),
+            collision_events: Vec::new(),
+            agent_collision_events: Vec::new(),
+            events: EventBus::default(),
+            logs: Vec::new(),
+        })
+    }
+
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    /// A scene without a fixed map, where occupancy comes from `tiles` around the agents.
+    pub fn tiled(tiles: TiledWorld) -> Self {
+        Self {
+            tiles: Some(Arc::new(tiles)),
+            ..Self::from_occupancy_map(OccupancyMap::empty())
+        }
+    }
+
+    /// A scene whose walls are the segments of `map`, in an unbounded world. The config's
+    /// [ray_caster](SimConfig::ray_caster) has no effect here.
+    pub fn from_vector_map(map: VectorMap, config: SimConfig) -> Result<Self, Scene2DError> {
+        if config.ray_caster != occupancy_map::RayCaster::default() {
+            log::warn!("Vector maps ignore the {:?} ray caster", config.ray_caster);
+        }
+
+        Ok(Self {
+            vector_map: Some(Arc::new(map)),
+            ..Self::with_config(OccupancyMap::empty(), config)?
         })
     }
 
+    /// The static walls: the tiles in a tiled world, the segments of a vector map, or else the occupancy map.
+    pub fn obstacle_source(&self) -> &dyn ObstacleSource {
+        obstacle_source(&self.occupancy_map, &self.tiles, &self.vector_map)
+    }
+
     pub fn state(&self) -> Scene2DState {
         Scene2DState {
             time: self.time,
             occupancy_map: Arc::clone(&self.occupancy_map),
+            landmarks: Arc::clone(&self.landmarks),
+            beacons: Arc::clone(&self.beacons),
+            agents: Arc::new(
+                self.agents
+                    .iter()
+                    .flat_map(|(&id, agent)| agent.footprints().map(move |f| (id, f)))
+                    .collect(),
+            ),
+            agent_velocities: Arc::new(
+                self.agents
+                    .values()
+                    .flat_map(|agent| {
+                        let speed = agent.state.velocity;
+                        std::iter::once(agent.state.heading * speed)
+                            .chain(agent.trailers.iter().map(move |t| t.pose.heading * speed))
+                    })
+                    .collect(),
+            ),
+            obstacles: Arc::new(
+                self.obstacles
+                    .iter()
+                    .map(|o| o.body_at(self.time))
+                    .chain(self.crowd.bodies())
+                    .collect(),
+            ),
+            tiles: self.tiles.as_ref().map(Arc::clone),
+            vector_map: self.vector_map.as_ref().map(Arc::clone),
+            surfaces: self.surfaces.as_ref().map(Arc::clone),
+            viewer: None,
         }
     }
 
+    /// Advances the scene by [SimConfig::dt].
+    pub fn step(&mut self) {
+        self.update(self.config.dt);
+    }
+
+    /// Advances the scene by `dt`, or under [SimConfig::physics_dt] by as many whole physics steps as fit in the time
+    /// banked so far. Controllers and localizers run once per update either way.
     pub fn update(&mut self, dt: f32) {
+        let (dt, substeps) = match self.config.physics_dt {
+            None => (dt, 1),
+            Some(physics_dt) => {
+                self.banked += dt;
+                // Nudged so a whole number of steps isn't lost to rounding.
+                let substeps = (self.banked / physics_dt + 1e-3).floor() as u32;
+                self.banked = (self.banked - substeps as f32 * physics_dt).max(0.);
+                if substeps == 0 {
+                    return;
+                }
+                (substeps as f32 * physics_dt, substeps)
+            }
+        };
         self.time.0 += dt;
+
+        if let Some(tiles) = &self.tiles {
+            tiles.ensure_loaded_around(
+                self.agents.values().map(|a| a.state.position),
+                tiles.tile_size as f32,
+            );
+        }
         let state = self.state();
         let scene_loop = Arc::clone(&self.scene_loop);
+        let seed = scene_loop.seed();
+        let collision = self.config.collision;
 
-        self.agents.par_iter_mut().for_each_init(|| state.clone(), |state, (id, agent)| {
-            agent.update(dt);
-            scene_loop.update_state(*id, agent.config, agent.state, state.clone());
-        });
+        let step = |state: &Scene2DState, id: &AgentId, agent: &mut Agent2D| {
+            let seed = seed.map(|seed| rng::derive_seed(seed, (*id, state.time.0.to_bits())));
+            rng::with_seed(seed, || {
+                let localizer_log = AgentLogger::new(*id, state.time, "localizer");
+                let controller_log = AgentLogger::new(*id, state.time, "controller");
+
+                if let Some(localizer) = &agent.localizer {
+                    let estimate = localizer.lock().update(&LocalizerContext {
+                        agent: *id,
+                        time: state.time,
+                        dt,
+                        config: &agent.config,
+                        odometry: Odometry {
+                            velocity: agent.state.velocity,
+                            beta: agent.state.beta,
+                        },
+                        scene_loop: &scene_loop,
+                        map: agent.prior_map.as_ref().unwrap_or(&state.occupancy_map),
+                        log: &localizer_log,
+                    });
+                    agent.estimate = estimate.or(agent.estimate);
+                }
+
+                if let Some(controller) = &agent.controller
+                    && agent.replay.is_none()
+                {
+                    let believed = match (agent.pose_source, &agent.estimate) {
+                        (PoseSource::Estimated, Some(estimate)) => {
+                            agent.state.with_pose(estimate.pose)
+                        }
+                        _ => agent.state,
+                    };
+
+                    let mut input = controller.lock().control(&ControlContext {
+                        agent: *id,
+                        time: state.time,
+                        dt,
+                        config: &agent.config,
+                        state: &believed,
+                        estimate: agent.estimate.as_ref(),
+                        scene_loop: &scene_loop,
+                        log: &controller_log,
+                    });
+
+                    if let Some(safety) = &mut agent.safety {
+                        input = safety.filter(*id, &agent.config, &agent.state, input, state);
+                    }
+                    agent.command(input);
+                }
+
+                let before = agent.state;
+                let trailers_before = agent.trailers.clone();
+                if agent.replay.is_some() {
+                    agent.replay_to(state.time);
+                } else {
+                    for _ in 0..substeps {
+                        agent.update(dt / substeps as f32, state.surfaces.as_deref());
+                    }
+                }
+                if state.occupancy_map.boundary == BoundaryPolicy::Wrap {
+                    agent.state.position = state.occupancy_map.wrap(agent.state.position);
+                }
+                // Charge spent on a step that is then undone stays spent.
+                let charge = agent.state.state_of_charge;
+                let collision = match collision {
+                    _ if agent.replay.is_some() => None,
+                    CollisionMode::Ignore => None,
+                    mode => resolve_collision(state, mode, agent, &before, &trailers_before),
+                }
+                .map(|(contact, obstacle)| CollisionEvent {
+                    agent: *id,
+                    time: state.time,
+                    obstacle,
+                    point: contact.point,
+                    normal: contact.normal,
+                });
+
+                if let Some(cost) =
+                    scene_loop.update_state(*id, agent.config, agent.state, state.clone())
+                {
+                    agent.sensing_cost.step = cost;
+                    agent.sensing_cost.total += cost;
+                }
+
+                agent.state.state_of_charge = charge;
+                let depleted = (before.state_of_charge > 0. && charge <= 0.).then_some(*id);
+
+                let mut logs = localizer_log.into_records();
+                logs.extend(controller_log.into_records());
+                (logs, collision, depleted)
+            })
+        };
+
+        let results = if self.ordered {
+            let mut agents = self.agents.iter_mut().collect::<Vec<_>>();
+            agents.sort_by_key(|(id, _)| **id);
+            agents
+                .into_iter()
+                .map(|(id, agent)| step(&state, id, agent))
+                .collect::<Vec<_>>()
+        } else {
+            let agents = &mut self.agents;
+            let mut parallel = || {
+                agents
+                    .par_iter_mut()
+                    .map_init(
+                        || state.clone(),
+                        |state, (id, agent)| step(state, id, agent),
+                    )
+                    .collect::<Vec<_>>()
+            };
+            match &self.pool {
+                Some(pool) => pool.install(parallel),
+                None => parallel(),
+            }
+        };
+        let mut logs = Vec::new();
+        let mut collisions = Vec::new();
+        let mut depleted = Vec::new();
+        for (agent_logs, collision, empty) in results {
+            logs.extend(agent_logs);
+            collisions.extend(collision);
+            depleted.extend(empty);
+        }
+        self.logs = logs;
+        // Stable, so each agent's records stay in the order they were logged.
+        self.logs.sort_by_key(|r| r.agent);
+
+        for event in collisions {
+            log::debug!("{:?} hit a wall at {}", event.agent, event.point);
+            self.collision_events.push(event);
+            self.events.emit(SceneEvent::Collision(event));
+        }
+
+        depleted.sort();
+        for agent in depleted {
+            log::info!("{agent:?} ran out of battery");
+            self.events.emit(SceneEvent::BatteryDepleted {
+                agent,
+                time: self.time,
+            });
+        }
+
+        if !self.crowd.is_empty() {
+            let obstacles = &state.obstacles[..self.obstacles.len()];
+            self.crowd
+                .step(dt, state.obstacle_source(), obstacles, &state.agents);
+        }
+
+        if let AgentCollisionMode::Report | AgentCollisionMode::Impulse { .. } =
+            self.config.agent_collision
+        {
+            self.handle_agent_collisions();
+        }
+
+        if let BoundaryPolicy::Open(action) = self.occupancy_map.boundary
+            && self.tiles.is_none()
+            && self.vector_map.is_none()
+        {
+            self.handle_out_of_bounds(action);
+        }
+
+        for (&id, agent) in &mut self.agents {
+            if let Some(mission) = &mut agent.mission {
+                let events = mission.update(id, agent.state.position, self.time);
+                for &event in &events {
+                    log::info!("{id:?} mission: {:?}", event.kind);
+                    self.events.emit(SceneEvent::Mission(event));
+                }
+                self.mission_events.extend(events);
+            }
+        }
+
+        // Taken either way, so they don't pile up while nobody is listening.
+        let delivered = self.scene_loop.take_delivered();
+        if !self.events.is_empty() {
+            for (agent, topic, stamp) in delivered {
+                self.events.emit(SceneEvent::MeasurementReady {
+                    agent,
+                    topic,
+                    time: self.time,
+                    stamp,
+                });
+            }
+        }
+    }
+
+    fn handle_agent_collisions(&mut self) {
+        let mut ids = self.agents.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        // Each agent and each of its trailers, as the agent's index and the body's place in its train.
+        let bodies = ids
+            .iter()
+            .enumerate()
+            .flat_map(|(i, id)| (0..=self.agents[id].trailers.len()).map(move |k| (i, k)))
+            .collect::<Vec<_>>();
+        let footprint = |agent: &Agent2D, k: usize| match k {
+            0 => agent.footprint_polygon(),
+            k => agent.trailers[k - 1].footprint().into(),
+        };
+        let boxes = bodies
+            .iter()
+            .map(|&(i, k)| footprint(&self.agents[&ids[i]], k).get_box())
+            .collect::<Vec<_>>();
+
+        // Sweep along x so only bodies whose bounding boxes overlap are compared. A train never collides with itself.
+        let mut by_x = (0..bodies.len()).collect::<Vec<_>>();
+        by_x.sort_by(|&a, &b| boxes[a].min.x.total_cmp(&boxes[b].min.x));
+        let mut pairs = Vec::new();
+        for (k, &m) in by_x.iter().enumerate() {
+            for &n in &by_x[k + 1..] {
+                if boxes[n].min.x > boxes[m].max.x {
+                    break;
+                }
+                if bodies[m].0 != bodies[n].0 && boxes[m].intersects(&boxes[n]) {
+                    pairs.push((m.min(n), m.max(n)));
+                }
+            }
+        }
+        pairs.sort();
+
+        for (m, n) in pairs {
+            let ((i, ka), (j, kb)) = (bodies[m], bodies[n]);
+            let (a, b) = (&self.agents[&ids[i]], &self.agents[&ids[j]]);
+            // Earlier pairs may have pushed either agent.
+            let (fa, fb) = (footprint(a, ka), footprint(b, kb));
+            let Some((normal, depth)) = fa.penetration(&fb) else {
+                continue;
+            };
+
+            let inside = fb
+                .corners()
+                .iter()
+                .copied()
+                .filter(|&c| fa.contains(c))
+                .chain(fa.corners().iter().copied().filter(|&c| fb.contains(c)))
+                .collect::<Vec<_>>();
+            let point = match inside.len() {
+                0 => fa.centroid().midpoint(fb.centroid()),
+                n => inside.iter().sum::<glam::Vec2>() / n as f32,
+            };
+            log::debug!("{:?} and {:?} collided at {point}", ids[i], ids[j]);
+            let event = AgentCollisionEvent {
+                agents: [ids[i], ids[j]],
+                time: self.time,
+                point,
+                normal,
+            };
+            self.agent_collision_events.push(event);
+            self.events.emit(SceneEvent::AgentCollision(event));
+
+            if let AgentCollisionMode::Impulse { restitution } = self.config.agent_collision {
+                // Each agent keeps its share of the exchange in what it is free to move in: along its heading, and
+                // sideways and turning if its dynamics keep those of their own. Trailers don't turn the agent.
+                let free = |agent: &Agent2D, k: usize| {
+                    let dynamics = agent.config.dynamics;
+                    let spin = match k == 0 && dynamics.spins() {
+                        true => 1. / agent.config.yaw_inertia(),
+                        false => 0.,
+                    };
+                    (1. / agent.config.mass, dynamics.slides(), spin)
+                };
+                let ((wa, slides_a, ia), (wb, slides_b, ib)) = (free(a, ka), free(b, kb));
+                let (mut a, mut b) = (a.state, b.state);
+                let body_velocity = |state: &Agent2DState, slides: bool| {
+                    let sideways = if slides { state.lateral_velocity } else { 0. };
+                    state.heading * state.velocity + state.heading.perp() * sideways
+                };
+                let (mut va, mut vb) = (body_velocity(&a, slides_a), body_velocity(&b, slides_b));
+                let (ra, rb) = (point - a.position, point - b.position);
+                let (la, lb) = (ra.perp_dot(normal), rb.perp_dot(normal));
+
+                // Only the turning the exchange can change counts towards how fast the contact closes.
+                let turning = |state: &Agent2DState, r: glam::Vec2, spin: f32| match spin > 0. {
+                    true => r.perp() * state.yaw_rate,
+                    false => glam::Vec2::ZERO,
+                };
+                let closing = (vb + turning(&b, rb, ib) - va - turning(&a, ra, ia)).dot(normal);
+                if closing < 0. {
+                    let impulse =
+                        -(1. + restitution) * closing / (wa + wb + ia * la * la + ib * lb * lb);
+                    va -= normal * impulse * wa;
+                    vb += normal * impulse * wb;
+                    a.yaw_rate -= la * impulse * ia;
+                    b.yaw_rate += lb * impulse * ib;
+                }
+                a.velocity = va.dot(a.heading);
+                b.velocity = vb.dot(b.heading);
+                if slides_a {
+                    a.lateral_velocity = va.dot(a.heading.perp());
+                }
+                if slides_b {
+                    b.lateral_velocity = vb.dot(b.heading.perp());
+                }
+                a.position -= normal * depth * wa / (wa + wb);
+                b.position += normal * depth * wb / (wa + wb);
+
+                self.agents.get_mut(&ids[i]).unwrap().state = a;
+                self.agents.get_mut(&ids[j]).unwrap().state = b;
+            }
+        }
+    }
+
+    fn handle_out_of_bounds(&mut self, action: OutOfBoundsAction) {
+        let mut removed = Vec::new();
+
+        for (&id, agent) in &mut self.agents {
+            if self.occupancy_map.is_valid_vec2(agent.state.position) {
+                self.out_of_bounds.remove(&id);
+                continue;
+            }
+
+            if self.out_of_bounds.insert(id) {
+                log::info!("{id:?} left the map at {}", agent.state.position);
+                let event = OutOfBoundsEvent {
+                    agent: id,
+                    time: self.time,
+                    position: agent.state.position,
+                };
+                self.out_of_bounds_events.push(event);
+                self.events.emit(SceneEvent::OutOfBounds(event));
+            }
+
+            match action {
+                OutOfBoundsAction::Report => {}
+                OutOfBoundsAction::Freeze => {
+                    agent.state.velocity = 0.;
+                    agent.state.lateral_velocity = 0.;
+                    agent.state.torque = 0.;
+                }
+                OutOfBoundsAction::Remove => removed.push(id),
+            }
+        }
+
+        for id in removed {
+            self.remove_agent(id);
+        }
+    }
+
+    /// What controllers and localizers logged during the latest update, by agent.
+    pub fn logs(&self) -> &[LogRecord] {
+        &self.logs
+    }
+
+    /// Takes the out-of-bounds events recorded since the last call.
+    pub fn drain_out_of_bounds_events(&mut self) -> Vec<OutOfBoundsEvent> {
+        std::mem::take(&mut self.out_of_bounds_events)
+    }
+
+    /// Takes the mission events recorded since the last call, in no particular order between agents.
+    pub fn drain_mission_events(&mut self) -> Vec<MissionEvent> {
+        std::mem::take(&mut self.mission_events)
+    }
+
+    /// Takes the collision events recorded since the last call, in no particular order between agents.
+    pub fn drain_collision_events(&mut self) -> Vec<CollisionEvent> {
+        std::mem::take(&mut self.collision_events)
+    }
+
+    /// Takes the agent collision events recorded since the last call. Each update's come in order of the agents' ids.
+    pub fn drain_agent_collision_events(&mut self) -> Vec<AgentCollisionEvent> {
+        std::mem::take(&mut self.agent_collision_events)
+    }
+
+    /// Switches strict deterministic mode on with `seed`, or off with `None`: agents are updated in
+    /// [order](Self::ordered) and sensing is [seeded](Scene2DLoop::set_seed) and synchronous.
+    pub fn set_deterministic(&mut self, seed: Option<u64>) {
+        self.config.seed = seed;
+        self.ordered = seed.is_some();
+        self.scene_loop.set_seed(seed);
     }
 
     pub fn add_agent(&mut self, agent: Agent2D) -> AgentId {
-        let id = AgentId(self.agents.len() as u64);
+        let id = AgentId(self.next_agent_id);
+        self.next_agent_id += 1;
+        if let Some(localizer) = &agent.localizer {
+            localizer.lock().reset(agent.state.pose());
+        }
         self.scene_loop.insert_agent(id, &agent);
         self.agents.insert(id, agent);
+        self.events.emit(SceneEvent::AgentSpawned {
+            agent: id,
+            time: self.time,
+        });
 
         id
     }
 
+    /// The agent [named](crate::agent::AgentLabel::name) `name`, the lowest id first if several share it.
+    pub fn agent_named(&self, name: &str) -> Option<AgentId> {
+        self.agents
+            .iter()
+            .filter(|(_, agent)| agent.label.name.as_deref() == Some(name))
+            .map(|(&id, _)| id)
+            .min()
+    }
+
+    /// Agents whose [metadata](crate::agent::AgentLabel::metadata) sets `key` to `value`, in id order.
+    pub fn agents_tagged(&self, key: &str, value: &str) -> Vec<AgentId> {
+        let mut ids = self
+            .agents
+            .iter()
+            .filter(|(_, agent)| agent.label.metadata.get(key).is_some_and(|v| v == value))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        ids.sort();
+
+        ids
+    }
+
+    pub fn remove_agent(&mut self, id: AgentId) -> Option<Agent2D> {
+        self.scene_loop.remove_agent(id);
+        self.out_of_bounds.remove(&id);
+        let agent = self.agents.remove(&id)?;
+        self.events.emit(SceneEvent::AgentRemoved {
+            agent: id,
+            time: self.time,
+        });
+
+        Some(agent)
+    }
+
+    pub fn add_landmark(&mut self, position: glam::Vec2) -> LandmarkId {
+        let landmarks = Arc::make_mut(&mut self.landmarks);
+        landmarks.push(position);
+
+        LandmarkId(landmarks.len() - 1)
+    }
+
+    pub fn add_beacon(&mut self, position: glam::Vec2) -> BeaconId {
+        let beacons = Arc::make_mut(&mut self.beacons);
+        beacons.push(position);
+
+        BeaconId(beacons.len() - 1)
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: DynamicObstacle) -> ObstacleId {
+        let obstacles = Arc::make_mut(&mut self.obstacles);
+        obstacles.push(obstacle);
+
+        ObstacleId(obstacles.len() - 1)
+    }
+
+    /// Edits the map while the scene runs, e.g. to open a door, as [OccupancyMap::set_cells] does.
+    pub fn set_cells(&mut self, cells: &[(glam::USizeVec2, bool)]) -> Result<(), Scene2DError> {
+        Arc::make_mut(&mut self.occupancy_map).set_cells(cells)
+    }
+
     #[inline]
     pub fn in_bounds_vec2(&self, loc: glam::Vec2) -> bool {
         self.occupancy_map.is_valid_vec2(loc)
@@ -109,7 +984,7 @@ impl Scene2D {
 
     #[inline]
     pub fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
-        self.occupancy_map.is_occupied_vec2(loc)
+        self.obstacle_source().is_occupied_vec2(loc)
     }
 
     #[inline]
@@ -118,8 +993,156 @@ impl Scene2D {
     }
 }
 
+fn obstacle_source<'a>(
+    occupancy_map: &'a OccupancyMap,
+    tiles: &'a Option<Arc<TiledWorld>>,
+    vector_map: &'a Option<Arc<VectorMap>>,
+) -> &'a dyn ObstacleSource {
+    match (tiles, vector_map) {
+        (Some(tiles), _) => tiles.as_ref(),
+        (None, Some(vector_map)) => vector_map.as_ref(),
+        (None, None) => occupancy_map,
+    }
+}
+
+/// Applies `mode` to an agent that has just moved from `before`, returning the wall or obstacle it was driven into, if
+/// any. Only moves that take the footprint further into something than it started count, so obstacles moving into a
+/// standing agent don't.
+///
+/// Trailers driven into something stop the whole train where it was, whatever the mode.
+fn resolve_collision(
+    scene: &Scene2DState,
+    mode: CollisionMode,
+    agent: &mut Agent2D,
+    before: &Agent2DState,
+    trailers_before: &[Trailer],
+) -> Option<(Contact, Option<ObstacleId>)> {
+    let trailer_hit = agent
+        .trailers
+        .iter()
+        .zip(trailers_before)
+        .find_map(|(now, then)| {
+            let start = scene
+                .contact(&then.footprint())
+                .map_or(0., |(c, _)| c.depth);
+            scene
+                .contact(&now.footprint())
+                .filter(|(c, _)| c.depth > start + 1e-4)
+        });
+    if let Some(hit) = trailer_hit {
+        if mode != CollisionMode::Report {
+            agent.state = Agent2DState {
+                velocity: 0.,
+                lateral_velocity: 0.,
+                ..*before
+            };
+            agent.trailers = trailers_before.to_vec();
+        }
+        return Some(hit);
+    }
+
+    let contact = |config: &Agent2DConfig, state: &Agent2DState| match config.shape {
+        Some(_) => scene.contact_polygon(&config.footprint_polygon(state)),
+        None => scene.contact(&config.footprint(state)),
+    };
+    let depth = |state: &Agent2DState| contact(&agent.config, state).map_or(0., |(c, _)| c.depth);
+    let start = depth(before) + 1e-4;
+    let (contact, obstacle) =
+        contact(&agent.config, &agent.state).filter(|(c, _)| c.depth > start)?;
+
+    match mode {
+        CollisionMode::Ignore | CollisionMode::Report => {}
+        CollisionMode::Stop => {
+            agent.state = *before;
+            agent.state.velocity = 0.;
+            agent.state.lateral_velocity = 0.;
+            agent.trailers = trailers_before.to_vec();
+        }
+        CollisionMode::Slide => {
+            let moved = agent.state.position - before.position;
+            let into = moved.dot(contact.normal).min(0.);
+            let kept = moved - contact.normal * into;
+
+            let mut slid = agent.state;
+            slid.position = before.position + kept;
+            agent.state = if depth(&slid) > start {
+                Agent2DState {
+                    velocity: 0.,
+                    lateral_velocity: 0.,
+                    ..*before
+                }
+            } else {
+                slid
+            };
+        }
+    }
+
+    Some((contact, obstacle))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Scene2DError {
     #[error("Pixel Size Mismatch: Got {0} pixels but have shape ({width}, {height})", width = .1[0], height = .1[1])]
     PixelSizeMismatch(usize, [usize; 2]),
+
+    #[error("Map has no pixels: shape ({width}, {height})", width = .0[0], height = .0[1])]
+    EmptyMap([usize; 2]),
+
+    #[error("Cell ({x}, {y}) lies outside the map", x = .0[0], y = .0[1])]
+    CellOutOfBounds([usize; 2]),
+
+    #[error("Invalid sim config: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Failed to build the thread pool: {0}")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+
+    #[error("No occupancy source given to the scene builder")]
+    NoOccupancy,
+
+    #[error("Spawn at ({x}, {y}) is blocked by a wall, an obstacle or another agent", x = .0.x, y = .0.y)]
+    SpawnBlocked(glam::Vec2),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D,
+        scene::{HitTag, Scene2DState},
+        testing,
+        trailer::TrailerConfig,
+    };
+
+    #[test]
+    fn test_viewer() {
+        let mut scene = testing::open_room();
+        let mut agent = Agent2D::default();
+        agent.hitch(TrailerConfig::default());
+        let me = scene.add_agent(agent);
+        // Pressed up against the front, over the first agent's centre.
+        let mut neighbour = Agent2D::default();
+        neighbour.state.position = glam::vec2(0., 0.2);
+        let neighbour = scene.add_agent(neighbour);
+
+        let state = scene.state();
+        let seen = |state: &Scene2DState, from, dir| state.cast_rays(from, dir).map(|(_, tag)| tag);
+        let front = glam::Vec2::ZERO;
+        assert_eq!(seen(&state, front, glam::Vec2::Y), Some(HitTag::Agent(me)));
+
+        // Looking out through its own body, but not its trailer.
+        let state = state.viewed_by(me);
+        assert_eq!(
+            seen(&state, front, glam::Vec2::Y),
+            Some(HitTag::Agent(neighbour))
+        );
+        let back = glam::vec2(0., -0.1);
+        assert_eq!(
+            seen(&state, back, glam::Vec2::NEG_Y),
+            Some(HitTag::Agent(me))
+        );
+        assert!(matches!(
+            seen(&state, back, glam::Vec2::X),
+            Some(HitTag::Map(_))
+        ));
+    }
 }
@@ -0,0 +1,366 @@
+//! Obstacles that move through the scene on scripted paths, such as people, other vehicles and doors. Lidars and other
+//! ray-cast sensors see them and agents collide with them, but they go their own way whatever is in it.
+
+use std::sync::Arc;
+
+use crate::{
+    math::{LineSegment, OrientedBox2D, Pose2D, intersect_ray_line_segment},
+    scene::{SceneTime, occupancy_map::Contact},
+};
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ObstacleId(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObstacleShape {
+    Disc {
+        radius: f32,
+    },
+    /// Convex and counter-clockwise, in the obstacle's frame with x along its heading.
+    Polygon(Vec<glam::Vec2>),
+}
+
+#[derive(Clone)]
+pub enum ObstaclePath {
+    Fixed(Pose2D),
+    /// Followed at `speed` in m/s from the first point, facing the way it moves. Loops back round to the first point
+    /// when `looped`, otherwise stops at the last.
+    Waypoints {
+        points: Vec<glam::Vec2>,
+        speed: f32,
+        looped: bool,
+    },
+    /// The pose at each scene time.
+    Script(Arc<dyn Fn(SceneTime) -> Pose2D + Send + Sync>),
+}
+
+impl std::fmt::Debug for ObstaclePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fixed(pose) => f.debug_tuple("Fixed").field(pose).finish(),
+            Self::Waypoints {
+                points,
+                speed,
+                looped,
+            } => f
+                .debug_struct("Waypoints")
+                .field("points", points)
+                .field("speed", speed)
+                .field("looped", looped)
+                .finish(),
+            Self::Script(_) => f.write_str("Script"),
+        }
+    }
+}
+
+impl ObstaclePath {
+    pub fn pose_at(&self, time: SceneTime) -> Pose2D {
+        let (points, speed, looped) = match self {
+            Self::Fixed(pose) => return *pose,
+            Self::Script(script) => return script(time),
+            Self::Waypoints {
+                points,
+                speed,
+                looped,
+            } => (points, *speed, *looped),
+        };
+
+        let mut legs = points
+            .windows(2)
+            .map(|w| LineSegment(w[0], w[1]))
+            .collect::<Vec<_>>();
+        if looped && let (Some(&first), Some(&last)) = (points.first(), points.last()) {
+            legs.push(LineSegment(last, first));
+        }
+        legs.retain(|leg| leg.0 != leg.1);
+        let total = legs.iter().map(|leg| leg.0.distance(leg.1)).sum::<f32>();
+        let Some(&last) = legs.last() else {
+            return Pose2D {
+                position: points.first().copied().unwrap_or_default(),
+                ..Default::default()
+            };
+        };
+
+        let travelled = (speed * time.0).max(0.);
+        let mut along = if looped {
+            travelled.rem_euclid(total)
+        } else {
+            travelled.min(total)
+        };
+        for leg in &legs {
+            let length = leg.0.distance(leg.1);
+            let heading = (leg.1 - leg.0) / length;
+            if along < length {
+                return Pose2D {
+                    position: leg.0 + heading * along,
+                    heading,
+                };
+            }
+            along -= length;
+        }
+
+        // Only reached at the very end of an open path.
+        Pose2D {
+            position: last.1,
+            heading: (last.1 - last.0).normalize(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamicObstacle {
+    pub shape: ObstacleShape,
+    pub path: ObstaclePath,
+}
+
+impl DynamicObstacle {
+    /// Where the obstacle stands at `time`.
+    pub fn body_at(&self, time: SceneTime) -> ObstacleBody {
+        let pose = self.path.pose_at(time);
+
+        match &self.shape {
+            ObstacleShape::Disc { radius } => ObstacleBody::Disc {
+                center: pose.position,
+                radius: *radius,
+            },
+            ObstacleShape::Polygon(points) => {
+                ObstacleBody::Polygon(points.iter().map(|&p| pose.transform_point(p)).collect())
+            }
+        }
+    }
+}
+
+/// An obstacle's shape in the world frame at one instant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObstacleBody {
+    Disc { center: glam::Vec2, radius: f32 },
+    Polygon(Vec<glam::Vec2>),
+}
+
+impl ObstacleBody {
+    fn edges(points: &[glam::Vec2]) -> impl Iterator<Item = LineSegment> + '_ {
+        points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(&a, &b)| LineSegment(a, b))
+    }
+
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        match self {
+            Self::Disc { center, radius } => point.distance_squared(*center) <= radius * radius,
+            Self::Polygon(points) => {
+                points.len() >= 3
+                    && Self::edges(points).all(|e| (e.1 - e.0).perp_dot(point - e.0) >= 0.)
+            }
+        }
+    }
+
+    /// Distance along `dir` to the obstacle's outline. Rays starting inside never hit it.
+    pub fn cast_ray(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
+        if self.contains(pos) {
+            return None;
+        }
+
+        match self {
+            Self::Disc { center, radius } => {
+                let offset = pos - *center;
+                let (a, b, c) = (
+                    dir.length_squared(),
+                    offset.dot(dir),
+                    offset.length_squared() - radius * radius,
+                );
+                let discriminant = b * b - a * c;
+                if discriminant < 0. {
+                    return None;
+                }
+                let t = (-b - discriminant.sqrt()) / a;
+                (t > f32::EPSILON).then_some(t)
+            }
+            Self::Polygon(points) => Self::edges(points)
+                .filter_map(|e| intersect_ray_line_segment(pos, dir, &e))
+                .min_by(|a, b| a.total_cmp(b)),
+        }
+    }
+
+    /// How far `footprint` overlaps the obstacle, with the normal pointing out of the obstacle towards it.
+    pub fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact> {
+        match self {
+            Self::Disc { center, radius } => {
+                let local = footprint.to_local(*center - footprint.center);
+                let nearest = local.clamp(-footprint.half_extent, footprint.half_extent);
+                if local == nearest {
+                    // The centre is inside the footprint, so push straight apart.
+                    return Some(Contact {
+                        point: *center,
+                        normal: (footprint.center - *center).normalize_or(glam::Vec2::Y),
+                        depth: *radius,
+                    });
+                }
+
+                let point = footprint.center + footprint.heading.rotate(nearest);
+                let distance = point.distance(*center);
+                (distance < *radius).then(|| Contact {
+                    point,
+                    normal: (point - *center) / distance,
+                    depth: radius - distance,
+                })
+            }
+            Self::Polygon(points) if points.len() >= 3 => {
+                let corners = footprint.corners();
+                let project = |vertices: &[glam::Vec2], axis: glam::Vec2| {
+                    vertices
+                        .iter()
+                        .map(|v| v.dot(axis))
+                        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                            (lo.min(p), hi.max(p))
+                        })
+                };
+                let centroid = points.iter().sum::<glam::Vec2>() / points.len() as f32;
+                let between = footprint.center - centroid;
+
+                let mut best: Option<(glam::Vec2, f32)> = None;
+                let axes = [footprint.heading, footprint.heading.perp()]
+                    .into_iter()
+                    .chain(Self::edges(points).map(|e| e.normal()));
+                for axis in axes.filter(|a| *a != glam::Vec2::ZERO) {
+                    let (a_lo, a_hi) = project(points, axis);
+                    let (b_lo, b_hi) = project(&corners, axis);
+                    if a_lo > b_hi || b_lo > a_hi {
+                        return None;
+                    }
+
+                    let depth = (a_hi - b_lo).min(b_hi - a_lo);
+                    if best.is_none_or(|(_, d)| depth < d) {
+                        let axis = if between.dot(axis) < 0. { -axis } else { axis };
+                        best = Some((axis, depth));
+                    }
+                }
+                let (normal, depth) = best?;
+
+                let inside = corners
+                    .into_iter()
+                    .filter(|&c| self.contains(c))
+                    .chain(points.iter().copied().filter(|&p| footprint.contains(p)))
+                    .collect::<Vec<_>>();
+                let point = match inside.len() {
+                    0 => centroid.midpoint(footprint.center),
+                    n => inside.iter().sum::<glam::Vec2>() / n as f32,
+                };
+
+                Some(Contact {
+                    point,
+                    normal,
+                    depth,
+                })
+            }
+            Self::Polygon(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        math::Pose2D,
+        scene::{
+            HitTag, SceneTime,
+            obstacles::{DynamicObstacle, ObstacleId, ObstaclePath, ObstacleShape},
+        },
+        sim_config::{CollisionMode, SimConfig},
+    };
+
+    #[test]
+    fn test_obstacle_paths() {
+        let square = [(0., 0.), (2., 0.), (2., 2.), (0., 2.)].map(|(x, y)| glam::vec2(x, y));
+        let looped = ObstaclePath::Waypoints {
+            points: square.to_vec(),
+            speed: 1.,
+            looped: true,
+        };
+        assert_eq!(looped.pose_at(SceneTime(3.)).position, glam::vec2(2., 1.));
+        assert_eq!(looped.pose_at(SceneTime(3.)).heading, glam::Vec2::Y);
+        assert_eq!(looped.pose_at(SceneTime(9.)).position, glam::vec2(1., 0.));
+        let open = ObstaclePath::Waypoints {
+            points: square.to_vec(),
+            speed: 1.,
+            looped: false,
+        };
+        assert_eq!(open.pose_at(SceneTime(9.)).position, glam::vec2(0., 2.));
+    }
+
+    /// A pillar 3 metres up from the middle of an open 10 by 10 map, and a door swinging round a hinge on its left.
+    fn pillar_and_door() -> (Scene2D, ObstacleId, ObstacleId) {
+        let config = SimConfig {
+            seed: Some(2),
+            collision: CollisionMode::Stop,
+            ..Default::default()
+        };
+        let mut scene = Scene2D::new([10, 10], &[255; 100], config).unwrap();
+        let pillar = scene.add_obstacle(DynamicObstacle {
+            shape: ObstacleShape::Disc { radius: 0.5 },
+            path: ObstaclePath::Fixed(Pose2D::new(glam::vec2(0., 3.), 0.)),
+        });
+        // A door swinging across the left of the map, well out of the agent's way.
+        let door = scene.add_obstacle(DynamicObstacle {
+            shape: ObstacleShape::Polygon(vec![
+                glam::vec2(0., -0.1),
+                glam::vec2(1., -0.1),
+                glam::vec2(1., 0.1),
+                glam::vec2(0., 0.1),
+            ]),
+            path: ObstaclePath::Script(std::sync::Arc::new(|t: SceneTime| {
+                Pose2D::new(glam::vec2(-4., 0.), t.0)
+            })),
+        });
+
+        (scene, pillar, door)
+    }
+
+    #[test]
+    fn test_obstacle_rays() {
+        let (scene, pillar, door) = pillar_and_door();
+        let state = scene.state();
+        let (t, tag) = state.cast_rays(glam::Vec2::ZERO, glam::Vec2::Y).unwrap();
+        assert!((t - 2.5).abs() < 1e-4, "{t}");
+        assert_eq!(tag, HitTag::Obstacle(pillar));
+        let (t, tag) = state
+            .cast_rays(glam::vec2(-3.5, -2.), glam::Vec2::Y)
+            .unwrap();
+        assert!((t - 1.9).abs() < 1e-4, "{t}");
+        assert_eq!(tag, HitTag::Obstacle(door));
+        // Rays from inside an obstacle pass out of it.
+        assert_eq!(
+            state.cast_rays_obstacles(glam::vec2(0., 3.), glam::Vec2::X),
+            None
+        );
+    }
+
+    #[test]
+    fn test_obstacle_collisions() {
+        let (mut scene, pillar, _) = pillar_and_door();
+        let mut agent = Agent2D::default();
+        agent.state.velocity = 1.;
+        let id = scene.add_agent(agent);
+        for _ in 0..200 {
+            scene.step();
+        }
+
+        let events = scene.drain_collision_events();
+        assert!(!events.is_empty());
+        assert!(
+            events
+                .iter()
+                .all(|e| e.agent == id && e.obstacle == Some(pillar))
+        );
+        assert!((events[0].normal - glam::Vec2::NEG_Y).length() < 1e-3);
+        let agent = &scene.agents[&id];
+        assert_eq!(agent.state.velocity, 0.);
+        assert!(agent.state.position.y < 3.);
+
+        // The door has swung round since.
+        let state = scene.state();
+        let door_now = state.cast_rays_obstacles(glam::vec2(-3.5, -2.), glam::Vec2::Y);
+        assert!(door_now.is_none_or(|(t, _)| (t - 1.9).abs() > 1e-2));
+    }
+}
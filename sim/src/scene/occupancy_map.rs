@@ -1,19 +1,93 @@
-use std::collections::VecDeque;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-use rustc_hash::FxHashSet;
-
-use crate::{bvh::{BVH, Direction}, math::{Box2D, LineSegment, intersect_ray_box, intersect_ray_line_segment}, scene::Scene2DError};
+use crate::{bvh::{BVH, Direction}, config, math::{Box2D, ConvexPolygon, LineSegment, OrientedBox2D, clip_line_segment_box, intersect_ray_line_segment, ray_box_entry}, scene::Scene2DError};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct ObjectTag(u64);
+pub struct ObjectTag(pub u64);
+
+impl ObjectTag {
+    /// Tag of the walls added around the map by [BoundaryPolicy::Solid].
+    pub const MAP_EDGE: Self = ObjectTag(u64::MAX);
+}
+
+/// What lies beyond the edge of the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BoundaryPolicy {
+    /// The map is enclosed by walls that sensors can see.
+    #[default]
+    Solid,
+    /// Leaving one edge re-enters from the opposite one.
+    Wrap,
+    /// Everything outside the map is free space.
+    Open(OutOfBoundsAction),
+}
+
+/// How rays find the walls of an [OccupancyMap].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RayCaster {
+    /// Search the boundary segments through their [BVH], fastest on large, open maps.
+    #[default]
+    Bvh,
+    /// Step from cell to cell along the ray with [OccupancyMap::cast_ray_dda], which can win on small or cluttered
+    /// maps where walls are never far.
+    Dda,
+}
+
+/// What happens to an agent that leaves the map under [BoundaryPolicy::Open].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutOfBoundsAction {
+    /// Only record an [OutOfBoundsEvent](crate::scene::OutOfBoundsEvent).
+    #[default]
+    Report,
+    /// Record the event and stop the agent where it is.
+    Freeze,
+    /// Record the event and remove the agent from the scene.
+    Remove,
+}
+
+/// Where a footprint presses into a wall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    /// Middle of the part of the wall inside the footprint.
+    pub point: glam::Vec2,
+    /// Out of the wall, into free space.
+    pub normal: glam::Vec2,
+    /// How far the footprint reaches past the wall along `-normal`, in metres.
+    pub depth: f32,
+}
+
+/// Where one object of an [OccupancyMap] lies and how big it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectInfo {
+    pub tag: ObjectTag,
+    /// Around all of its cells, in the world.
+    pub bounds: Box2D,
+    pub cells: usize,
+    /// In square metres.
+    pub area: f32,
+}
 
 #[derive(Debug, Clone)]
 pub struct OccupancyMap {
     pub size: glam::USizeVec2,
     pub pixels: Vec<bool>,
     pub objects: Vec<Option<ObjectTag>>,
+    /// Edges between occupied and free space, wound so the occupied side is on the right. Their
+    /// [normals](LineSegment::normal) therefore point into free space. The sides of neighbouring cells along a
+    /// straight wall are merged into one segment.
     pub boundaries: Vec<LineSegment>,
+    /// The object each entry of `boundaries` belongs to.
+    pub boundary_tags: Vec<ObjectTag>,
     pub bvh: BVH,
+    pub boundary: BoundaryPolicy,
+    /// Metres per cell.
+    pub resolution: f32,
+    /// Where the centre of the map lies in the world.
+    pub origin: glam::Vec2,
+    pub ray_caster: RayCaster,
 }
 
 #[inline]
@@ -41,10 +115,101 @@ fn boundary_direction(
     }
 }
 
+/// The boundaries along grid line `k`, between rows `k - 1` and `k`, or between columns when `vertical`. Runs of
+/// neighbouring cell sides are merged, so a straight wall has one segment per side however long it is.
+fn line_boundaries(
+    size: glam::USizeVec2,
+    pixels: &[bool],
+    objects: &[Option<ObjectTag>],
+    vertical: bool,
+    k: usize,
+) -> Vec<(LineSegment, ObjectTag)> {
+    let cell = |along: usize, across: usize| {
+        if vertical {
+            glam::usizevec2(across, along)
+        } else {
+            glam::usizevec2(along, across)
+        }
+    };
+    let (length, [before, after]) = if vertical {
+        (size.y, [Direction::East, Direction::West])
+    } else {
+        (size.x, [Direction::South, Direction::North])
+    };
+
+    // The occupied cell whose side lies on the line at `i`, if only one of the two is occupied.
+    let side = |i: usize| {
+        let (a, b) = (cell(i, k - 1), cell(i, k));
+        match (pixels[a.x + a.y * size.x], pixels[b.x + b.y * size.x]) {
+            (true, false) => Some((a, before)),
+            (false, true) => Some((b, after)),
+            _ => None,
+        }
+    };
+
+    let mut segments = Vec::new();
+    let mut i = 0;
+    while i < length {
+        let Some((first, direction)) = side(i) else {
+            i += 1;
+            continue;
+        };
+        let tag = objects[first.x + first.y * size.x].expect("Occupied cells are tagged");
+
+        let mut last = first;
+        i += 1;
+        while i < length
+            && let Some((next, d)) = side(i)
+            && d == direction
+            && objects[next.x + next.y * size.x] == Some(tag)
+        {
+            last = next;
+            i += 1;
+        }
+
+        let (start, end) = (
+            boundary_direction(size, first, direction),
+            boundary_direction(size, last, direction),
+        );
+        // North and east sides run along the line the way cells are counted, south and west ones against it.
+        let segment = match direction {
+            Direction::North | Direction::East => LineSegment(start.0, end.1),
+            Direction::South | Direction::West => LineSegment(end.0, start.1),
+        };
+        segments.push((segment, tag));
+    }
+
+    segments
+}
+
 impl OccupancyMap {
+    /// Size of the map in metres.
+    #[inline]
+    pub fn extent(&self) -> glam::Vec2 {
+        self.size.as_vec2() * self.resolution
+    }
+
+    /// The area the map covers in the world.
+    #[inline]
+    pub fn bounds(&self) -> Box2D {
+        Box2D {
+            min: self.origin - self.extent() / 2.,
+            max: self.origin + self.extent() / 2.,
+        }
+    }
+
+    /// `loc` in cells from the centre of the map.
+    #[inline]
+    fn to_cells(&self, loc: glam::Vec2) -> glam::Vec2 {
+        (loc - self.origin) / self.resolution
+    }
+
     #[inline]
     pub fn is_valid_vec2(&self, loc: glam::Vec2) -> bool {
-        loc.abs().cmplt(self.size.as_vec2() / 2.).all()
+        self.to_cells(loc)
+            .abs()
+            .cmplt(self.size.as_vec2() / 2.)
+            .all()
     }
 
     #[inline]
@@ -57,18 +222,25 @@ impl OccupancyMap {
         const FLIP_HORIZONTAL: glam::Vec2 = glam::Vec2::new(-1., 1.);
         let origin_corner = (self.size.as_vec2() / 2.) * FLIP_HORIZONTAL;
 
-        ((origin_corner - loc) * FLIP_HORIZONTAL)
+        ((origin_corner - self.to_cells(loc)) * FLIP_HORIZONTAL)
             .floor()
             .as_i64vec2()
     }
 
     #[inline]
     pub fn get_box(&self, loc: glam::USizeVec2) -> Box2D {
+        self.cell_box(loc.as_i64vec2())
+    }
+
+    /// Like [OccupancyMap::get_box], but for cells that may lie outside the map.
+    #[inline]
+    pub fn cell_box(&self, loc: glam::I64Vec2) -> Box2D {
         const FLIP_HORIZONTAL: glam::Vec2 = glam::Vec2::new(-1., 1.);
 
         let origin_corner = (self.size.as_vec2() / 2.) * FLIP_HORIZONTAL;
-        let top_left = origin_corner - loc.as_vec2() * FLIP_HORIZONTAL;
-        let bottom_right = top_left + glam::vec2(1., -1.);
+        let top_left =
+            (origin_corner - loc.as_vec2() * FLIP_HORIZONTAL) * self.resolution + self.origin;
+        let bottom_right = top_left + glam::vec2(1., -1.) * self.resolution;
 
         Box2D {
             min: top_left.min(bottom_right),
@@ -78,9 +250,14 @@ impl OccupancyMap {
 
     #[inline]
     pub fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        let loc = match self.boundary {
+            BoundaryPolicy::Wrap => self.wrap(loc),
+            _ => loc,
+        };
+
         if !self.is_valid_vec2(loc) {
             log::trace!("Out of bounds: {loc}");
-            return true;
+            return !matches!(self.boundary, BoundaryPolicy::Open(_));
         }
 
         let loc = self.translate(loc).as_usizevec2();
@@ -88,6 +265,29 @@ impl OccupancyMap {
         self.pixels[loc[0] + loc[1] * self.size.x]
     }
 
+    /// Maps `loc` back into the map as if the world were a torus.
+    #[inline]
+    pub fn wrap(&self, loc: glam::Vec2) -> glam::Vec2 {
+        let extent = self.extent();
+
+        (loc - self.origin + extent / 2.).rem_euclid(extent) - extent / 2. + self.origin
+    }
+
+    /// Like [OccupancyMap::is_occupied], but resolves cells outside the map through the [BoundaryPolicy].
+    #[inline]
+    pub fn is_occupied_cell(&self, loc: glam::I64Vec2) -> bool {
+        let size = self.size.as_i64vec2();
+        if loc.cmpge(glam::I64Vec2::ZERO).all() && loc.cmplt(size).all() {
+            return self.is_occupied(loc.as_usizevec2());
+        }
+
+        match self.boundary {
+            BoundaryPolicy::Solid => true,
+            BoundaryPolicy::Wrap => self.is_occupied(loc.rem_euclid(size).as_usizevec2()),
+            BoundaryPolicy::Open(_) => false,
+        }
+    }
+
     #[inline]
     pub fn is_occupied(&self, loc: glam::USizeVec2) -> bool {
         if self.is_valid(loc) {
@@ -97,14 +297,71 @@ impl OccupancyMap {
         }
     }
 
+    /// Whether `footprint` overlaps any occupied cell. Leaving the map counts as an overlap.
+    pub fn overlaps(&self, footprint: &OrientedBox2D) -> bool {
+        self.overlapping_cells(footprint).next().is_some()
+    }
+
+    /// Boxes of the occupied cells overlapped by `footprint`, including cells outside the map that the
+    /// [BoundaryPolicy] treats as occupied.
+    pub fn overlapping_cells(&self, footprint: &OrientedBox2D) -> impl Iterator<Item = Box2D> + '_ {
+        let bx = footprint.get_box();
+        let a = self.translate(bx.min);
+        let b = self.translate(bx.max);
+        let (lo, hi) = (a.min(b), a.max(b));
+        let footprint = *footprint;
+
+        (lo.y..=hi.y)
+            .flat_map(move |y| (lo.x..=hi.x).map(move |x| glam::i64vec2(x, y)))
+            .filter(|&cell| self.is_occupied_cell(cell))
+            .map(|cell| self.cell_box(cell))
+            .filter(move |cell| footprint.intersects(&OrientedBox2D::from(*cell)))
+    }
+
+    /// Whether `footprint` overlaps any occupied cell, like [overlaps](Self::overlaps).
+    pub fn overlaps_polygon(&self, footprint: &ConvexPolygon) -> bool {
+        self.overlapping_cells(&footprint.get_box().into())
+            .any(|cell| footprint.intersects(&OrientedBox2D::from(cell).into()))
+    }
+
+    /// A map with no cells and nothing beyond its edges, for scenes whose walls come from elsewhere.
+    pub fn empty() -> Self {
+        Self {
+            size: glam::USizeVec2::ZERO,
+            pixels: Vec::new(),
+            objects: Vec::new(),
+            boundaries: Vec::new(),
+            boundary_tags: Vec::new(),
+            bvh: BVH::new(std::iter::empty()),
+            boundary: BoundaryPolicy::Open(OutOfBoundsAction::Report),
+            resolution: 1.,
+            origin: glam::Vec2::ZERO,
+            ray_caster: RayCaster::default(),
+        }
+    }
+
     pub fn from_pixels(size: glam::USizeVec2, pixels: Vec<bool>) -> Result<OccupancyMap, Scene2DError> {
+        Self::from_pixels_with_boundary(size, pixels, BoundaryPolicy::default())
+    }
+
+    pub fn from_pixels_with_boundary(
+        size: glam::USizeVec2,
+        pixels: Vec<bool>,
+        boundary: BoundaryPolicy,
+    ) -> Result<OccupancyMap, Scene2DError> {
         let [width, height] = size.to_array();
         let expected_count = size[0] * size[1];
         let pixels_len = pixels.len();
 
+        if width == 0 || height == 0 {
+            return Err(Scene2DError::EmptyMap(size.into()));
+        }
+        if expected_count != pixels_len {
+            return Err(Scene2DError::PixelSizeMismatch(pixels_len, size.into()));
+        }
+
         let mut objects = vec![None; pixels_len];
         let mut visited = FxHashSet::<glam::USizeVec2>::default();
-        let mut boundaries = Vec::new();
         let mut tmp_nodes = vec![];
 
         let mut object_count = 0;
@@ -129,88 +386,1007 @@ impl OccupancyMap {
                 let mut try_add = |node: glam::USizeVec2| {
                     let k = node.x + node.y * width;
 
-                    if visited.contains(&node) {
-                        return false;
-                    }
-
-                    if !pixels[k] {
-                        true
-                    } else {
+                    if !visited.contains(&node) && pixels[k] {
                         objects[k] = Some(object);
                         tmp_nodes.push(node);
-                        false
                     }
                 };
 
-                if node.x > 0 && try_add(node - glam::USizeVec2::X) {
-                    boundaries.push(boundary_direction(size, node, Direction::West));
+                if node.x > 0 {
+                    try_add(node - glam::USizeVec2::X);
                 }
-
-                if node.x < width - 1 && try_add(node + glam::USizeVec2::X) {
-                    boundaries.push(boundary_direction(size, node, Direction::East));
+                if node.x < width - 1 {
+                    try_add(node + glam::USizeVec2::X);
                 }
-
-                if node.y > 0 && try_add(node - glam::USizeVec2::Y) {
-                    boundaries.push(boundary_direction(size, node, Direction::North));
+                if node.y > 0 {
+                    try_add(node - glam::USizeVec2::Y);
                 }
-
-                if node.y < height - 1 && try_add(node + glam::USizeVec2::Y) {
-                    boundaries.push(boundary_direction(size, node, Direction::South));
+                if node.y < height - 1 {
+                    try_add(node + glam::USizeVec2::Y);
                 }
             }
         }
 
+        let (mut boundaries, mut boundary_tags): (Vec<_>, Vec<_>) = (1..height)
+            .flat_map(|k| line_boundaries(size, &pixels, &objects, false, k))
+            .chain((1..width).flat_map(|k| line_boundaries(size, &pixels, &objects, true, k)))
+            .unzip();
+
+        if boundary == BoundaryPolicy::Solid {
+            let half = size.as_vec2() / 2.;
+            let corners = [
+                glam::vec2(half.x, half.y),
+                glam::vec2(-half.x, half.y),
+                glam::vec2(-half.x, -half.y),
+                glam::vec2(half.x, -half.y),
+            ];
+
+            // Counter-clockwise around the map so the occupied (right-hand) side of each wall faces outward, matching
+            // the cell boundaries.
+            for i in 0..4 {
+                boundaries.push(LineSegment(corners[i], corners[(i + 1) % 4]));
+                boundary_tags.push(ObjectTag::MAP_EDGE);
+            }
+        }
+
         let bvh = BVH::new(boundaries.iter());
 
-        if expected_count == pixels_len {
-            Ok(Self {
-                pixels,
-                size,
-                objects,
-                boundaries,
-                bvh,
-            })
-        } else {
-            Err(Scene2DError::PixelSizeMismatch(pixels_len, size.into()))
+        Ok(Self {
+            pixels,
+            size,
+            objects,
+            boundaries,
+            boundary_tags,
+            bvh,
+            boundary,
+            resolution: 1.,
+            origin: glam::Vec2::ZERO,
+            ray_caster: RayCaster::default(),
+        })
+    }
+
+    /// Rescales the map to `resolution` metres per cell, centred on `origin`, e.g. for a map of a real site. Maps are
+    /// built with one metre cells centred on the world's origin.
+    pub fn with_resolution(
+        mut self,
+        resolution: f32,
+        origin: glam::Vec2,
+    ) -> Result<Self, Scene2DError> {
+        config::positive("resolution", resolution)?;
+        config::finite("origin.x", origin.x)?;
+        config::finite("origin.y", origin.y)?;
+
+        let (scale, old_origin) = (resolution / self.resolution, self.origin);
+        let rescale = |p: glam::Vec2| (p - old_origin) * scale + origin;
+        for segment in &mut self.boundaries {
+            *segment = LineSegment(rescale(segment.0), rescale(segment.1));
         }
+        self.bvh = BVH::new(self.boundaries.iter());
+        self.resolution = resolution;
+        self.origin = origin;
+
+        Ok(self)
     }
 
-    pub fn cast_rays(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
-        let BVH { box_map, root } = &self.bvh;
+    /// Black for occupied cells and white for free ones, one pixel each with the first row at the top, so the map can
+    /// be saved and loaded back as a track.
+    pub fn to_image(&self) -> image::GrayImage {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|&occupied| if occupied { 0 } else { 255 })
+            .collect();
+
+        image::GrayImage::from_raw(self.size.x as u32, self.size.y as u32, pixels)
+            .expect("The map has one cell per pixel")
+    }
 
-        let mut queue = VecDeque::new();
-        queue.push_back(*root);
+    /// Sets each of `cells` to occupied or free. Only the object tags of the cells around those that changed, and the
+    /// boundaries along their rows and columns, are redone and the BVH is patched rather than rebuilt, so small edits
+    /// such as opening a door stay cheap on large maps. Objects the edit joins or splits are retagged.
+    pub fn set_cells(&mut self, cells: &[(glam::USizeVec2, bool)]) -> Result<(), Scene2DError> {
+        if let Some(&(cell, _)) = cells.iter().find(|(cell, _)| !self.is_valid(*cell)) {
+            return Err(Scene2DError::CellOutOfBounds(cell.into()));
+        }
 
-        let mut min = f32::INFINITY;
+        let size = self.size;
+        let index = |cell: glam::USizeVec2| cell.x + cell.y * size.x;
+        let neighbours = |cell: glam::USizeVec2| {
+            [
+                (cell.x > 0).then(|| cell - glam::USizeVec2::X),
+                (cell.x + 1 < size.x).then(|| cell + glam::USizeVec2::X),
+                (cell.y > 0).then(|| cell - glam::USizeVec2::Y),
+                (cell.y + 1 < size.y).then(|| cell + glam::USizeVec2::Y),
+            ]
+            .into_iter()
+            .flatten()
+        };
 
-        while let Some(node_id) = queue.pop_front() {
-            let Some(node) = box_map.get(&node_id) else {
+        let mut affected = FxHashSet::default();
+        for &(cell, occupied) in cells {
+            let i = index(cell);
+            if self.pixels[i] == occupied {
                 continue;
-            };
+            }
+            self.pixels[i] = occupied;
+            if !occupied {
+                self.objects[i] = None;
+            }
+            affected.insert(cell);
+            affected.extend(neighbours(cell));
+        }
+        if affected.is_empty() {
+            return Ok(());
+        }
+
+        // Retag every object touching the edit. Each keeps the lowest of its old tags that no other has taken, so
+        // untouched objects and the larger part of a split one keep theirs.
+        let mut next_tag = self
+            .objects
+            .iter()
+            .flatten()
+            .map(|t| t.0 + 1)
+            .max()
+            .unwrap_or(0);
+        let mut taken = FxHashSet::default();
+        let mut seen = FxHashSet::default();
+        let mut redo = affected.clone();
+        let mut seeds = affected.iter().copied().collect::<Vec<_>>();
+        seeds.sort_by_key(|&cell| index(cell));
+        for seed in seeds {
+            if !self.pixels[index(seed)] || !seen.insert(seed) {
+                continue;
+            }
 
-            if intersect_ray_box(pos, dir, node.rect).is_some() {
-                if let Some(children) = &node.children {
-                    for child in children {
-                        queue.push_back(*child);
+            let mut component = vec![seed];
+            let mut stack = vec![seed];
+            while let Some(cell) = stack.pop() {
+                for n in neighbours(cell) {
+                    if self.pixels[index(n)] && seen.insert(n) {
+                        component.push(n);
+                        stack.push(n);
                     }
                 }
+            }
 
-                if let Some(elements) = &node.elements {
-                    for &indices in elements {
-                        if let Some(small) =
-                            intersect_ray_line_segment(pos, dir, &self.boundaries[indices])
-                        {
-                            min = min.min(small);
-                        }
-                    }
+            let mut old_tags = component
+                .iter()
+                .filter_map(|&cell| self.objects[index(cell)])
+                .filter(|tag| !taken.contains(tag))
+                .collect::<Vec<_>>();
+            old_tags.sort_by_key(|tag| tag.0);
+            let tag = old_tags.first().copied().unwrap_or_else(|| {
+                next_tag += 1;
+                ObjectTag(next_tag - 1)
+            });
+            taken.insert(tag);
+            for cell in component {
+                if self.objects[index(cell)] != Some(tag) {
+                    self.objects[index(cell)] = Some(tag);
+                    redo.insert(cell);
                 }
             }
         }
 
-        if min != f32::INFINITY {
-            Some(min)
-        } else {
-            None
+        // Merged boundaries may run far from the edit, so whole grid lines beside the redone cells are redone.
+        let mut lines = FxHashSet::default();
+        for &cell in &redo {
+            for (vertical, k) in [(false, cell.y), (true, cell.x)] {
+                let count = if vertical { size.x } else { size.y };
+                lines.extend(
+                    [k, k + 1]
+                        .into_iter()
+                        .filter(|&k| k > 0 && k < count)
+                        .map(|k| (vertical, k)),
+                );
+            }
+        }
+        let mut lines = lines.into_iter().collect::<Vec<_>>();
+        lines.sort_unstable();
+
+        let to_world = |p: glam::Vec2| p * self.resolution + self.origin;
+        let bounds = self.bounds();
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        for (vertical, k) in lines {
+            let on_line = |segment: &LineSegment| {
+                let (a, b) = (segment.0 - self.origin, segment.1 - self.origin);
+                let offset = if vertical {
+                    glam::vec2(a.x, b.x) / self.resolution + size.x as f32 / 2.
+                } else {
+                    size.y as f32 / 2. - glam::vec2(a.y, b.y) / self.resolution
+                };
+                (offset - glam::Vec2::splat(k as f32)).abs().max_element() < 0.25
+            };
+            let line = if vertical {
+                let x = to_world(glam::vec2(k as f32 - size.x as f32 / 2., 0.)).x;
+                Box2D {
+                    min: glam::vec2(x, bounds.min.y),
+                    max: glam::vec2(x, bounds.max.y),
+                }
+            } else {
+                let y = to_world(glam::vec2(0., size.y as f32 / 2. - k as f32)).y;
+                Box2D {
+                    min: glam::vec2(bounds.min.x, y),
+                    max: glam::vec2(bounds.max.x, y),
+                }
+            };
+
+            removed.extend(self.boundaries_near(line).into_iter().filter(|&i| {
+                self.boundary_tags[i] != ObjectTag::MAP_EDGE && on_line(&self.boundaries[i])
+            }));
+            added.extend(
+                line_boundaries(size, &self.pixels, &self.objects, vertical, k)
+                    .into_iter()
+                    .map(|(LineSegment(a, b), tag)| (LineSegment(to_world(a), to_world(b)), tag)),
+            );
+        }
+        removed.sort_unstable();
+        removed.dedup();
+
+        // Patching the BVH one segment at a time only pays while the edit is small next to the map.
+        let patch = removed.len() + added.len() <= self.boundaries.len() / 4;
+        if patch {
+            for &i in &removed {
+                self.bvh.remove(i, self.boundaries[i].get_box());
+            }
+        }
+
+        // New segments take the places of removed ones first, then the rest are closed up or appended.
+        let mut added = added.into_iter();
+        let mut removed = removed.into_iter();
+        let mut leftover = Vec::new();
+        for slot in removed.by_ref() {
+            let Some((segment, tag)) = added.next() else {
+                leftover.push(slot);
+                break;
+            };
+            self.boundaries[slot] = segment;
+            self.boundary_tags[slot] = tag;
+            if patch {
+                self.bvh.insert(slot, segment.get_box());
+            }
         }
+        leftover.extend(removed);
+        for slot in leftover.into_iter().rev() {
+            let last = self.boundaries.len() - 1;
+            self.boundaries.swap_remove(slot);
+            self.boundary_tags.swap_remove(slot);
+            if patch && slot != last {
+                self.bvh
+                    .relabel(last, slot, self.boundaries[slot].get_box());
+            }
+        }
+        for (segment, tag) in added {
+            self.boundaries.push(segment);
+            self.boundary_tags.push(tag);
+            if patch {
+                self.bvh
+                    .insert(self.boundaries.len() - 1, segment.get_box());
+            }
+        }
+
+        if !patch {
+            self.bvh = BVH::new(self.boundaries.iter());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the straight line from `from` to `to` stays in free space, crossing no wall.
+    pub fn segment_clear(&self, from: glam::Vec2, to: glam::Vec2) -> bool {
+        if self.is_occupied_vec2(from) || self.is_occupied_vec2(to) {
+            return false;
+        }
+
+        let offset = to - from;
+        let length = offset.length();
+        length == 0.
+            || self
+                .cast_rays(from, offset / length)
+                .is_none_or(|t| t >= length)
+    }
+
+    /// Indices of the boundary segments whose bounding boxes meet `bx`.
+    pub fn boundaries_near(&self, bx: Box2D) -> Vec<usize> {
+        let mut found = self.bvh.query(bx);
+        found.retain(|&i| self.boundaries[i].get_box().intersects(&bx));
+
+        found
+    }
+
+    /// The wall `footprint` presses furthest into, judged against the boundary segments crossing it. Of walls facing
+    /// the footprint's centre, the one it could back out of soonest is taken, so a footprint pushed into a corner is
+    /// pushed out along the nearer face.
+    pub fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact> {
+        let corners = footprint.corners();
+
+        self.boundaries_near(footprint.get_box())
+            .into_iter()
+            .filter_map(|i| {
+                let segment = self.boundaries[i];
+                let local = LineSegment(
+                    footprint.to_local(segment.0 - footprint.center),
+                    footprint.to_local(segment.1 - footprint.center),
+                );
+                let (t0, t1) = clip_line_segment_box(&local, footprint.local_box())?;
+                let along = segment.1 - segment.0;
+                let normal = segment.normal();
+                let depth = corners
+                    .iter()
+                    .map(|&c| (segment.0 - c).dot(normal))
+                    .fold(0f32, f32::max);
+                let facing = (footprint.center - segment.0).dot(normal) > 0.;
+
+                Some((
+                    facing,
+                    (t1 - t0) * along.length(),
+                    Contact {
+                        point: segment.0 + along * (t0 + t1) / 2.,
+                        normal,
+                        depth,
+                    },
+                ))
+            })
+            .filter(|(_, length, _)| *length > 0.)
+            .min_by(|a, b| {
+                b.0.cmp(&a.0)
+                    .then(a.2.depth.total_cmp(&b.2.depth))
+                    .then(b.1.total_cmp(&a.1))
+            })
+            .map(|(_, _, contact)| contact)
+    }
+
+    /// [contact](Self::contact) for a convex outline.
+    pub fn contact_polygon(&self, footprint: &ConvexPolygon) -> Option<Contact> {
+        let center = footprint.centroid();
+
+        self.boundaries_near(footprint.get_box())
+            .into_iter()
+            .filter_map(|i| {
+                let segment = self.boundaries[i];
+                let (t0, t1) = footprint.clip_segment(&segment)?;
+                let along = segment.1 - segment.0;
+                let normal = segment.normal();
+                let depth = footprint
+                    .corners()
+                    .iter()
+                    .map(|&c| (segment.0 - c).dot(normal))
+                    .fold(0f32, f32::max);
+                let facing = (center - segment.0).dot(normal) > 0.;
+
+                Some((
+                    facing,
+                    (t1 - t0) * along.length(),
+                    Contact {
+                        point: segment.0 + along * (t0 + t1) / 2.,
+                        normal,
+                        depth,
+                    },
+                ))
+            })
+            .filter(|(_, length, _)| *length > 0.)
+            .min_by(|a, b| {
+                b.0.cmp(&a.0)
+                    .then(a.2.depth.total_cmp(&b.2.depth))
+                    .then(b.1.total_cmp(&a.1))
+            })
+            .map(|(_, _, contact)| contact)
+    }
+
+    pub fn cast_rays(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
+        self.cast_rays_tagged(pos, dir).map(|(t, _)| t)
+    }
+
+    /// Every object on the map, in tag order.
+    pub fn object_infos(&self) -> Vec<ObjectInfo> {
+        let mut infos = FxHashMap::<ObjectTag, ObjectInfo>::default();
+        for (i, tag) in self.objects.iter().enumerate() {
+            let Some(tag) = *tag else {
+                continue;
+            };
+
+            let bx = self.get_box(glam::usizevec2(i % self.size.x, i / self.size.x));
+            let info = infos.entry(tag).or_insert(ObjectInfo {
+                tag,
+                bounds: bx,
+                cells: 0,
+                area: 0.,
+            });
+            info.bounds = info.bounds.encase(&bx);
+            info.cells += 1;
+            info.area += self.resolution * self.resolution;
+        }
+
+        let mut infos = infos.into_values().collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.tag.0);
+        infos
+    }
+
+    /// The object tagged `tag`, if there is one.
+    pub fn object(&self, tag: ObjectTag) -> Option<ObjectInfo> {
+        self.object_infos().into_iter().find(|info| info.tag == tag)
+    }
+
+    /// The object nearest `loc` and how far away its surface is, zero from inside it. The walls around the map under
+    /// [BoundaryPolicy::Solid] don't count.
+    pub fn nearest_object(&self, loc: glam::Vec2) -> Option<(ObjectTag, f32)> {
+        if self.is_valid_vec2(loc) {
+            let cell = self.translate(loc);
+            if let Some(tag) = self.objects[cell.x as usize + cell.y as usize * self.size.x] {
+                return Some((tag, 0.));
+            }
+        }
+
+        self.bvh
+            .nearest(loc, |i| {
+                (self.boundary_tags[i] != ObjectTag::MAP_EDGE)
+                    .then(|| self.boundaries[i].closest_point(loc).distance(loc))
+            })
+            .map(|(distance, i)| (self.boundary_tags[i], distance))
+    }
+
+    /// Index of the boundary segment nearest `loc` and how far away it is.
+    pub fn nearest_boundary(&self, loc: glam::Vec2) -> Option<(usize, f32)> {
+        self.bvh
+            .nearest(loc, |i| {
+                Some(self.boundaries[i].closest_point(loc).distance(loc))
+            })
+            .map(|(distance, i)| (i, distance))
+    }
+
+    /// Indices of the boundary segments `segment` crosses.
+    pub fn boundaries_crossing(&self, segment: &LineSegment) -> Vec<usize> {
+        let offset = segment.1 - segment.0;
+        let mut found = self.bvh.query_segment(segment);
+        found.retain(|&i| {
+            intersect_ray_line_segment(segment.0, offset, &self.boundaries[i])
+                .is_some_and(|t| t <= 1.)
+        });
+
+        found
+    }
+
+    /// Like [OccupancyMap::cast_rays], but also reports the [ObjectTag] of the boundary that was hit.
+    pub fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        if self.ray_caster == RayCaster::Dda {
+            return self.cast_ray_dda(pos, dir);
+        }
+
+        self.bvh
+            .cast_ray(pos, dir, |i| {
+                intersect_ray_line_segment(pos, dir, &self.boundaries[i])
+            })
+            .map(|(t, i)| (t, self.boundary_tags[i]))
+    }
+
+    /// [OccupancyMap::cast_rays] for many rays from `pos` at once, sharing the walk through the BVH.
+    pub fn cast_rays_batch(&self, pos: glam::Vec2, dirs: &[glam::Vec2]) -> Vec<Option<f32>> {
+        self.cast_rays_batch_tagged(pos, dirs)
+            .into_iter()
+            .map(|hit| hit.map(|(t, _)| t))
+            .collect()
+    }
+
+    /// Like [OccupancyMap::cast_rays_batch], but also reports the [ObjectTag] of the boundary each ray hit.
+    pub fn cast_rays_batch_tagged(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, ObjectTag)>> {
+        if self.ray_caster == RayCaster::Dda {
+            return dirs
+                .iter()
+                .map(|&dir| self.cast_ray_dda(pos, dir))
+                .collect();
+        }
+
+        self.bvh
+            .cast_ray_batch(pos, dirs, |i, ray| {
+                intersect_ray_line_segment(pos, dirs[ray], &self.boundaries[i])
+            })
+            .into_iter()
+            .map(|hit| hit.map(|(t, i)| (t, self.boundary_tags[i])))
+            .collect()
+    }
+
+    /// Like [OccupancyMap::cast_rays_tagged], but marching cell by cell along the ray (Amanatides and Woo's DDA) to
+    /// the first side between an occupied and a free cell, or to where it leaves a map walled in by
+    /// [BoundaryPolicy::Solid]. Cells along the edge of a map without walls stop rays coming in from outside, though
+    /// the BVH has no boundaries there.
+    pub fn cast_ray_dda(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        let solid = self.boundary == BoundaryPolicy::Solid;
+        let size = self.size.as_i64vec2();
+        let index = |cell: glam::I64Vec2| cell.x as usize + cell.y as usize * self.size.x;
+
+        // In cells, with columns counted rightwards and rows downwards from the map's top left corner.
+        let cells = self.to_cells(pos);
+        let start = glam::vec2(cells.x + size.x as f32 / 2., size.y as f32 / 2. - cells.y);
+        let step_dir = glam::vec2(dir.x, -dir.y) / self.resolution;
+        let grid = Box2D {
+            min: glam::Vec2::ZERO,
+            max: size.as_vec2(),
+        };
+
+        let mut t = ray_box_entry(start, step_dir, grid)?;
+        if t > 0. && solid {
+            return Some((t, ObjectTag::MAP_EDGE));
+        }
+        let mut cell = (start + step_dir * t)
+            .floor()
+            .as_i64vec2()
+            .clamp(glam::I64Vec2::ZERO, size - 1);
+        // Whether the cell the ray comes from is occupied. Outside counts as free, so a ray coming in stops at the
+        // first occupied cell.
+        let mut occupied = t == 0. && self.pixels[index(cell)];
+        let mut last = cell;
+
+        let step = glam::i64vec2(
+            if step_dir.x < 0. { -1 } else { 1 },
+            if step_dir.y < 0. { -1 } else { 1 },
+        );
+        let axis_t = |p: f32, d: f32, cell: i64, s: i64| {
+            if d == 0. {
+                f32::INFINITY
+            } else {
+                ((cell + (s > 0) as i64) as f32 - p) / d
+            }
+        };
+        let mut t_max = glam::vec2(
+            axis_t(start.x, step_dir.x, cell.x, step.x),
+            axis_t(start.y, step_dir.y, cell.y, step.y),
+        );
+        let t_delta = (1. / step_dir).abs();
+
+        loop {
+            let here = self.pixels[index(cell)];
+            if here != occupied && t > f32::EPSILON {
+                let wall = if here { cell } else { last };
+                let tag = self.objects[index(wall)].expect("Occupied cells are tagged");
+                return Some((t, tag));
+            }
+            occupied = here;
+            last = cell;
+
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t = t_max.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t = t_max.y;
+                t_max.y += t_delta.y;
+            }
+
+            if cell.cmplt(glam::I64Vec2::ZERO).any() || cell.cmpge(size).any() {
+                return solid.then_some((t, ObjectTag::MAP_EDGE));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        Scene2D,
+        math::LineSegment,
+        scene::{
+            Scene2DError,
+            occupancy_map::{
+                BoundaryPolicy, ObjectTag, OccupancyMap, OutOfBoundsAction, RayCaster,
+            },
+        },
+        sim_config::SimConfig,
+    };
+
+    #[test]
+    fn test_boundary_normals() {
+        #[rustfmt::skip]
+        let pixels = vec![
+            false, false, false, false,
+            false, true,  true,  false,
+            false, true,  false, false,
+            false, false, false, false,
+        ];
+        let map = OccupancyMap::from_pixels(glam::usizevec2(4, 4), pixels).unwrap();
+
+        // The L's long sides are each one segment.
+        assert_eq!(map.boundaries.len(), 6 + 4);
+        for boundary in &map.boundaries {
+            let normal = boundary.normal();
+            assert!((normal.length() - 1.).abs() < 1e-6);
+            assert!(!map.is_occupied_vec2(boundary.midpoint() + normal * 0.25));
+            assert!(map.is_occupied_vec2(boundary.midpoint() - normal * 0.25));
+        }
+
+        let image = map.to_image();
+        assert_eq!(image.get_pixel(1, 2).0, [0]);
+        assert_eq!(image.get_pixel(2, 2).0, [255]);
+        let pixels = image.pixels().map(|p| p.0[0] <= 127).collect::<Vec<_>>();
+        assert_eq!(pixels, map.pixels);
+    }
+
+    #[test]
+    fn test_merged_boundaries() {
+        // A long wall along the top, then a post and a two cell block on one line below it with a gap between them.
+        #[rustfmt::skip]
+        let map = OccupancyMap::from_ascii(&[
+            "........",
+            ".######.",
+            "........",
+            "........",
+            "........",
+            "..#..##.",
+            "........",
+            "........",
+        ]);
+        let wall = map.objects[8 + 1].unwrap();
+        let block = map.objects[5 * 8 + 5].unwrap();
+        let segments = |tag: ObjectTag| {
+            map.boundaries
+                .iter()
+                .zip(&map.boundary_tags)
+                .filter(|&(_, &t)| t == tag)
+                .map(|(s, _)| (s.0, s.1))
+                .collect::<Vec<_>>()
+        };
+
+        // Each object has one segment per side, wound with the object on the right.
+        assert_eq!(map.boundaries.len(), 4 + 4 + 4 + 4);
+        let wall_segments = segments(wall);
+        assert_eq!(wall_segments.len(), 4);
+        for segment in [
+            (glam::vec2(-3., 3.), glam::vec2(3., 3.)),
+            (glam::vec2(3., 3.), glam::vec2(3., 2.)),
+            (glam::vec2(3., 2.), glam::vec2(-3., 2.)),
+            (glam::vec2(-3., 2.), glam::vec2(-3., 3.)),
+        ] {
+            assert!(wall_segments.contains(&segment), "{segment:?}");
+        }
+        // The block's top isn't merged across the gap with the post's.
+        assert!(segments(block).contains(&(glam::vec2(1., -1.), glam::vec2(3., -1.))));
+
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(0.5, 0.), glam::Vec2::Y),
+            Some((2., wall))
+        );
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(-3.5, 2.5), glam::Vec2::X),
+            Some((0.5, wall))
+        );
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(2., 0.), glam::Vec2::NEG_Y),
+            Some((1., block))
+        );
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(0., 0.), glam::Vec2::NEG_Y),
+            Some((4., ObjectTag::MAP_EDGE))
+        );
+    }
+
+    #[test]
+    fn test_degenerate_maps() {
+        let size = glam::usizevec2(8, 4);
+
+        // Without any obstacles, rays only stop at the edge of the map.
+        let free = OccupancyMap::from_pixels(size, vec![false; 32]).unwrap();
+        assert_eq!(
+            free.cast_rays_tagged(glam::Vec2::ZERO, glam::Vec2::X),
+            Some((4., ObjectTag::MAP_EDGE))
+        );
+        assert_eq!(free.cast_rays(glam::Vec2::ZERO, glam::Vec2::NEG_Y), Some(2.));
+
+        let open = BoundaryPolicy::Open(OutOfBoundsAction::Report);
+        let free = OccupancyMap::from_pixels_with_boundary(size, vec![false; 32], open).unwrap();
+        assert_eq!(free.cast_rays(glam::Vec2::ZERO, glam::Vec2::X), None);
+
+        let full = OccupancyMap::from_pixels(size, vec![true; 32]).unwrap();
+        assert!(full.is_occupied_vec2(glam::Vec2::ZERO));
+        assert_eq!(full.cast_rays(glam::vec2(1., 0.), glam::Vec2::X), Some(3.));
+
+        let row = OccupancyMap::from_pixels(glam::usizevec2(8, 1), vec![false; 8]).unwrap();
+        assert_eq!(row.cast_rays(glam::Vec2::ZERO, glam::Vec2::NEG_X), Some(4.));
+        assert_eq!(row.cast_rays(glam::Vec2::ZERO, glam::Vec2::Y), Some(0.5));
+
+        // A single wall cell in a one pixel tall, open map.
+        let mut pixels = vec![false; 8];
+        pixels[6] = true;
+        let row =
+            OccupancyMap::from_pixels_with_boundary(glam::usizevec2(8, 1), pixels, open).unwrap();
+        assert_eq!(row.cast_rays(glam::Vec2::ZERO, glam::Vec2::X), Some(2.));
+        assert_eq!(row.cast_rays(glam::Vec2::ZERO, glam::Vec2::NEG_X), None);
+
+        assert!(matches!(
+            OccupancyMap::from_pixels(glam::usizevec2(0, 4), Vec::new()),
+            Err(Scene2DError::EmptyMap(_))
+        ));
+        assert!(matches!(
+            OccupancyMap::from_pixels(size, vec![true; 33]),
+            Err(Scene2DError::PixelSizeMismatch(33, _))
+        ));
+    }
+
+    #[test]
+    fn test_resolution_and_origin() {
+        let mut pixels = vec![false; 16];
+        pixels[1 + 4] = true;
+        let map = OccupancyMap::from_pixels(glam::usizevec2(4, 4), pixels.clone())
+            .unwrap()
+            .with_resolution(0.5, glam::vec2(10., -2.))
+            .unwrap();
+
+        assert_eq!(map.extent(), glam::vec2(2., 2.));
+        assert_eq!(map.bounds().min, glam::vec2(9., -3.));
+        let cell = map.get_box(glam::usizevec2(1, 1));
+        assert_eq!(
+            (cell.min, cell.max),
+            (glam::vec2(9.5, -2.), glam::vec2(10., -1.5))
+        );
+        assert_eq!(map.translate(glam::vec2(9.75, -1.75)), glam::i64vec2(1, 1));
+        assert!(map.is_occupied_vec2(glam::vec2(9.75, -1.75)));
+        assert!(!map.is_occupied_vec2(glam::vec2(10.25, -1.75)));
+        assert!(map.is_occupied_vec2(glam::vec2(8.9, -2.)));
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(10.5, -1.75), glam::Vec2::NEG_X),
+            Some((0.5, ObjectTag(0)))
+        );
+        assert_eq!(
+            map.cast_rays_tagged(glam::vec2(10.5, -1.75), glam::Vec2::X),
+            Some((0.5, ObjectTag::MAP_EDGE))
+        );
+        for boundary in &map.boundaries {
+            let normal = boundary.normal();
+            assert!(!map.is_occupied_vec2(boundary.midpoint() + normal * 0.1));
+            assert!(map.is_occupied_vec2(boundary.midpoint() - normal * 0.1));
+        }
+        let esdf = map.to_esdf(1.);
+        assert!((esdf.distance(glam::vec2(10.25, -1.75)) - 0.25).abs() < 1e-5);
+
+        let wrapped = OccupancyMap::from_pixels_with_boundary(
+            glam::usizevec2(4, 4),
+            pixels,
+            BoundaryPolicy::Wrap,
+        )
+        .unwrap()
+        .with_resolution(0.5, glam::vec2(10., -2.))
+        .unwrap();
+        assert_eq!(
+            wrapped.wrap(glam::vec2(11.25, -1.75)),
+            glam::vec2(9.25, -1.75)
+        );
+        assert!(matches!(
+            wrapped.with_resolution(0., glam::Vec2::ZERO),
+            Err(Scene2DError::Config(_))
+        ));
+    }
+
+    /// Width and height of [edit_map].
+    const W: usize = 40;
+
+    /// Two walls with a gap between them and a post below, amid enough pillars for edits to be patched in. Returned
+    /// with its pixels, for [check_edits] to compare against.
+    fn edit_map() -> (OccupancyMap, Vec<bool>) {
+        let mut pixels = vec![false; W * W];
+        pixels[4 * W + 2..4 * W + 5].fill(true);
+        pixels[4 * W + 6..4 * W + 9].fill(true);
+        for row in 7..11 {
+            pixels[row * W + 3] = true;
+        }
+        for row in (16..W).step_by(3) {
+            for col in (0..W).step_by(3) {
+                pixels[row * W + col] = true;
+            }
+        }
+        let map = OccupancyMap::from_pixels(glam::usizevec2(W, W), pixels.clone())
+            .unwrap()
+            .with_resolution(0.5, glam::vec2(1., 1.))
+            .unwrap();
+
+        (map, pixels)
+    }
+
+    /// Checks that an edited map matches one built from scratch out of `pixels`.
+    fn check_edits(map: &OccupancyMap, pixels: &[bool]) {
+        let fresh = OccupancyMap::from_pixels(glam::usizevec2(W, W), pixels.to_vec())
+            .unwrap()
+            .with_resolution(0.5, glam::vec2(1., 1.))
+            .unwrap();
+        assert_eq!(map.pixels, fresh.pixels);
+        assert_eq!(map.boundaries.len(), fresh.boundaries.len());
+
+        // Cells are grouped into the same objects, whatever their tags.
+        let pairs = map
+            .objects
+            .iter()
+            .zip(&fresh.objects)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(pairs.iter().all(|(a, b)| a.is_some() == b.is_some()));
+        assert_eq!(
+            pairs.len(),
+            fresh
+                .objects
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        );
+        for (segment, &tag) in map.boundaries.iter().zip(&map.boundary_tags) {
+            if tag != ObjectTag::MAP_EDGE {
+                let cell = map.translate(segment.midpoint() - segment.normal() * 0.25);
+                assert_eq!(
+                    map.objects[cell.x as usize + cell.y as usize * W],
+                    Some(tag)
+                );
+            }
+        }
+
+        for i in 0..72 {
+            let dir = glam::Vec2::from_angle(i as f32 * std::f32::consts::TAU / 72.);
+            for from in [
+                glam::vec2(-5., 8.),
+                glam::vec2(-6.8, 9.3),
+                glam::vec2(-4., 6.1),
+            ] {
+                let (t, _) = map.cast_rays_tagged(from, dir).unwrap();
+                let (expected, _) = fresh.cast_rays_tagged(from, dir).unwrap();
+                assert!(
+                    (t - expected).abs() < 1e-5,
+                    "{from} {dir}: {t} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_cells_join() {
+        // Closing the gap joins the walls into one object.
+        let (mut map, mut pixels) = edit_map();
+        map.set_cells(&[(glam::usizevec2(5, 4), true)]).unwrap();
+        pixels[4 * W + 5] = true;
+        check_edits(&map, &pixels);
+        assert_eq!(map.objects[4 * W + 2], map.objects[4 * W + 8]);
+    }
+
+    #[test]
+    fn test_set_cells_split() {
+        let (mut map, mut pixels) = edit_map();
+        map.set_cells(&[(glam::usizevec2(5, 4), true)]).unwrap();
+        pixels[4 * W + 5] = true;
+
+        // Opening the gap again splits the walls, and the post below is knocked through.
+        let edits = [
+            (glam::usizevec2(5, 4), false),
+            (glam::usizevec2(3, 8), false),
+        ];
+        map.set_cells(&edits).unwrap();
+        pixels[4 * W + 5] = false;
+        pixels[8 * W + 3] = false;
+        check_edits(&map, &pixels);
+        assert_ne!(map.objects[4 * W + 2], map.objects[4 * W + 8]);
+        assert_ne!(map.objects[7 * W + 3], map.objects[9 * W + 3]);
+    }
+
+    #[test]
+    fn test_set_cells_pillars() {
+        // A new pillar adds boundaries and knocking one down takes them away.
+        let (mut map, mut pixels) = edit_map();
+        map.set_cells(&[(glam::usizevec2(20, 5), true)]).unwrap();
+        pixels[5 * W + 20] = true;
+        check_edits(&map, &pixels);
+        map.set_cells(&[(glam::usizevec2(0, 16), false)]).unwrap();
+        pixels[16 * W] = false;
+        check_edits(&map, &pixels);
+    }
+
+    #[test]
+    fn test_set_cells_unchanged() {
+        // Setting cells to what they already are changes nothing.
+        let (mut map, _) = edit_map();
+        let endpoints = |map: &OccupancyMap| {
+            map.boundaries
+                .iter()
+                .map(|s| (s.0, s.1))
+                .collect::<Vec<_>>()
+        };
+        let before = endpoints(&map);
+        map.set_cells(&[(glam::usizevec2(0, 0), false)]).unwrap();
+        assert_eq!(endpoints(&map), before);
+    }
+
+    #[test]
+    fn test_set_cells_out_of_bounds() {
+        let (mut map, _) = edit_map();
+        assert!(matches!(
+            map.set_cells(&[(glam::usizevec2(W, 0), true)]),
+            Err(Scene2DError::CellOutOfBounds([W, 0]))
+        ));
+    }
+
+    #[test]
+    fn test_object_queries() {
+        // A 2x2 block and a lone cell, on a map of 0.5 m cells.
+        let mut pixels = vec![false; 48];
+        for i in [9, 10, 17, 18, 38] {
+            pixels[i] = true;
+        }
+        let map = OccupancyMap::from_pixels(glam::usizevec2(8, 6), pixels)
+            .unwrap()
+            .with_resolution(0.5, glam::Vec2::ZERO)
+            .unwrap();
+
+        let infos = map.object_infos();
+        assert_eq!(infos.len(), 2);
+        let (block, lone) = (infos[0], infos[1]);
+        assert_eq!((block.cells, lone.cells), (4, 1));
+        assert_eq!((block.area, lone.area), (1., 0.25));
+        assert_eq!(
+            (block.bounds.min, block.bounds.max),
+            (glam::vec2(-1.5, 0.), glam::vec2(-0.5, 1.))
+        );
+        assert_eq!(map.object(lone.tag), Some(lone));
+        assert_eq!(map.object(ObjectTag(7)), None);
+
+        assert_eq!(
+            map.nearest_object(glam::vec2(-1., 0.5)),
+            Some((block.tag, 0.))
+        );
+        assert_eq!(
+            map.nearest_object(glam::vec2(0., 0.5)),
+            Some((block.tag, 0.5))
+        );
+        assert_eq!(
+            map.nearest_object(glam::vec2(2., -0.75)),
+            Some((lone.tag, 0.5))
+        );
+        // Off the map, past the walls around it.
+        let (tag, distance) = map.nearest_object(glam::vec2(10., -0.75)).unwrap();
+        assert_eq!(tag, lone.tag);
+        assert!((distance - 8.5).abs() < 1e-5);
+
+        let (i, distance) = map.nearest_boundary(glam::vec2(0., 0.5)).unwrap();
+        assert_eq!((map.boundary_tags[i], distance), (block.tag, 0.5));
+        // Through the block's left and right sides.
+        let across = LineSegment(glam::vec2(-1.75, 0.25), glam::vec2(0., 0.25));
+        assert_eq!(map.boundaries_crossing(&across).len(), 2);
+
+        let free = OccupancyMap::from_pixels(glam::usizevec2(8, 6), vec![false; 48]).unwrap();
+        assert!(free.object_infos().is_empty());
+        assert_eq!(free.nearest_object(glam::Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn test_dda() {
+        // Scattered cells, off-centre and at half a metre each, with rays from inside and outside the map.
+        let mut rng = StdRng::seed_from_u64(11);
+        let size = glam::usizevec2(30, 20);
+        let pixels = (0..600).map(|_| rng.random_bool(0.3)).collect();
+        let map = OccupancyMap::from_pixels(size, pixels)
+            .unwrap()
+            .with_resolution(0.5, glam::vec2(1., -2.))
+            .unwrap();
+
+        for _ in 0..2000 {
+            let pos = glam::vec2(rng.random_range(-8. ..10.), rng.random_range(-8. ..4.));
+            let dir = glam::Vec2::from_angle(rng.random_range(0. ..std::f32::consts::TAU));
+            let (bvh, dda) = (map.cast_rays_tagged(pos, dir), map.cast_ray_dda(pos, dir));
+            match (bvh, dda) {
+                (Some(bvh), Some(dda)) => {
+                    assert!((bvh.0 - dda.0).abs() < 1e-3, "{pos} {dir}: {bvh:?} {dda:?}");
+                    assert_eq!(bvh.1, dda.1, "{pos} {dir}");
+                }
+                _ => assert_eq!(bvh, dda, "{pos} {dir}"),
+            }
+        }
+        assert_eq!(map.cast_ray_dda(glam::vec2(20., 0.), glam::Vec2::X), None);
+
+        let config = SimConfig {
+            ray_caster: RayCaster::Dda,
+            ..Default::default()
+        };
+        let scene = Scene2D::new([4, 4], &[255; 16], config).unwrap();
+        assert_eq!(scene.occupancy_map.ray_caster, RayCaster::Dda);
+        let map = &scene.occupancy_map;
+        assert_eq!(map.cast_rays(glam::Vec2::ZERO, glam::Vec2::X), Some(2.));
     }
 }
@@ -0,0 +1,259 @@
+//! Maps saved by ROS `map_server` and `map_saver`: a YAML file giving the resolution, origin and thresholds, next to a
+//! PGM or PNG image of the map. They are resampled into the simulator's 1 m cells.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    math::Pose2D,
+    scene::{Scene2DError, occupancy_map::OccupancyMap},
+};
+
+/// How pixel values are read as occupancy, as in `map_server`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RosMapMode {
+    #[default]
+    Trinary,
+    Scale,
+    /// Pixel values are occupancy percentages, with anything over 100 unknown.
+    Raw,
+}
+
+/// The contents of a map's YAML file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RosMapMeta {
+    /// Relative to the YAML file.
+    pub image: PathBuf,
+    /// Metres per pixel.
+    pub resolution: f32,
+    /// Pose of the image's lower-left pixel in the map frame.
+    pub origin: Pose2D,
+    pub negate: bool,
+    pub occupied_thresh: f32,
+    pub free_thresh: f32,
+    pub mode: RosMapMode,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RosMapError {
+    #[error("Failed to read the map: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to load the map image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("Missing {0:?} in the map YAML")]
+    MissingKey(&'static str),
+
+    #[error("Invalid {key:?} in the map YAML: {value:?}")]
+    InvalidValue { key: String, value: String },
+
+    #[error("Invalid map: {0}")]
+    Map(#[from] Scene2DError),
+}
+
+impl RosMapMeta {
+    /// Reads the flat `key: value` YAML that `map_saver` writes.
+    pub fn parse(yaml: &str) -> Result<Self, RosMapError> {
+        let mut values = Vec::new();
+        for line in yaml.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                values.push((key.trim(), value));
+            }
+        }
+
+        let get = |key: &'static str| {
+            values
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|&(_, v)| v)
+                .ok_or(RosMapError::MissingKey(key))
+        };
+        let invalid = |key: &str, value: &str| RosMapError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+        let number = |key: &'static str| {
+            let value = get(key)?;
+            value
+                .parse::<f32>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| invalid(key, value))
+        };
+
+        let resolution = number("resolution")?;
+        if resolution <= 0. {
+            return Err(invalid("resolution", get("resolution")?));
+        }
+
+        let origin = get("origin")?;
+        let parts = origin
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split(',')
+            .map(|p| p.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("origin", origin))?;
+        let &[x, y, yaw] = parts.as_slice() else {
+            return Err(invalid("origin", origin));
+        };
+
+        let negate = match get("negate") {
+            Ok("0" | "false") | Err(_) => false,
+            Ok("1" | "true") => true,
+            Ok(value) => return Err(invalid("negate", value)),
+        };
+        let mode = match get("mode") {
+            Ok("trinary") | Err(_) => RosMapMode::Trinary,
+            Ok("scale") => RosMapMode::Scale,
+            Ok("raw") => RosMapMode::Raw,
+            Ok(value) => return Err(invalid("mode", value)),
+        };
+
+        let occupied_thresh = number("occupied_thresh")?;
+        let free_thresh = number("free_thresh")?;
+        if !(0. ..=1.).contains(&free_thresh) || !(free_thresh..=1.).contains(&occupied_thresh) {
+            return Err(invalid("occupied_thresh", get("occupied_thresh")?));
+        }
+
+        Ok(Self {
+            image: get("image")?.into(),
+            resolution,
+            origin: Pose2D::new(glam::vec2(x, y), yaw),
+            negate,
+            occupied_thresh,
+            free_thresh,
+            mode,
+        })
+    }
+
+    /// Whether a pixel is anything but known free space.
+    fn blocked(&self, pixel: u8) -> bool {
+        let p = match self.mode {
+            RosMapMode::Raw if pixel > 100 => return true,
+            RosMapMode::Raw => pixel as f32 / 100.,
+            RosMapMode::Trinary | RosMapMode::Scale if self.negate => pixel as f32 / 255.,
+            RosMapMode::Trinary | RosMapMode::Scale => (255 - pixel) as f32 / 255.,
+        };
+
+        p >= self.free_thresh
+    }
+}
+
+/// A ROS map resampled into 1 m cells. Cells holding any occupied or unknown pixel are occupied, so thin walls and
+/// the unexplored space around the map stay solid.
+#[derive(Debug, Clone)]
+pub struct RosMap {
+    pub map: OccupancyMap,
+    pub meta: RosMapMeta,
+}
+
+impl RosMap {
+    /// Loads the YAML file at `path` and the image it names.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RosMapError> {
+        let path = path.as_ref();
+        let meta = RosMapMeta::parse(&std::fs::read_to_string(path)?)?;
+        let image_path = path.parent().unwrap_or(Path::new("")).join(&meta.image);
+        let image = image::open(image_path)?.to_luma8();
+
+        Self::from_image(meta, &image)
+    }
+
+    pub fn from_image(meta: RosMapMeta, image: &image::GrayImage) -> Result<Self, RosMapError> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let resolution = meta.resolution as f64;
+        let size = [width, height].map(|n| (n as f64 * resolution - 1e-6).ceil().max(1.) as usize);
+        // Pixels overlapping `[cell, cell + 1)` metres from the lower-left corner along an axis `n` pixels long.
+        let span = |cell: usize, n: usize| {
+            let start = (cell as f64 / resolution + 1e-6).floor() as usize;
+            let end = ((cell + 1) as f64 / resolution - 1e-6).ceil() as usize;
+            start.min(n)..end.min(n)
+        };
+
+        let pixels = (0..size[0] * size[1])
+            .map(|i| {
+                let (col, row) = (i % size[0], i / size[0]);
+                let rows = span(size[1] - 1 - row, height);
+                span(col, width).any(|x| {
+                    rows.clone().any(|y| {
+                        meta.blocked(image.get_pixel(x as u32, (height - 1 - y) as u32).0[0])
+                    })
+                })
+            })
+            .collect();
+        let map = OccupancyMap::from_pixels(glam::USizeVec2::from(size), pixels)?;
+
+        Ok(Self { map, meta })
+    }
+
+    /// Where a point in the ROS map frame lies in a scene of [RosMap::map].
+    pub fn to_scene(&self, point: glam::Vec2) -> glam::Vec2 {
+        self.meta.origin.inverse().transform_point(point) + self.map.bounds().min
+    }
+
+    /// Where a point in a scene of [RosMap::map] lies in the ROS map frame.
+    pub fn from_scene(&self, point: glam::Vec2) -> glam::Vec2 {
+        self.meta
+            .origin
+            .transform_point(point - self.map.bounds().min)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::ros_map::{RosMap, RosMapError, RosMapMeta, RosMapMode};
+
+    #[test]
+    fn test_ros_map() {
+        let yaml = "
+image: \"lab.pgm\"  # saved by map_saver
+resolution: 0.100000
+origin: [-2.0, -1.0, 0.0]
+negate: 0
+occupied_thresh: 0.65
+free_thresh: 0.196
+";
+        let meta = RosMapMeta::parse(yaml).unwrap();
+        assert_eq!(meta.image, std::path::Path::new("lab.pgm"));
+        assert_eq!(meta.mode, RosMapMode::Trinary);
+        assert_eq!(meta.origin.position, glam::vec2(-2., -1.));
+        assert!(matches!(
+            RosMapMeta::parse("image: lab.pgm\nresolution: 0.1"),
+            Err(RosMapError::MissingKey("origin"))
+        ));
+        assert!(matches!(
+            RosMapMeta::parse(&yaml.replace("0.100000", "-1")),
+            Err(RosMapError::InvalidValue { .. })
+        ));
+
+        // 4 m by 2 m of free space, with one occupied pixel and a block of unknown ones in the top right.
+        let mut image = image::GrayImage::from_pixel(40, 20, image::Luma([254]));
+        image.put_pixel(15, 12, image::Luma([0]));
+        for (x, y) in (30..40).flat_map(|x| (0..10).map(move |y| (x, y))) {
+            image.put_pixel(x, y, image::Luma([205]));
+        }
+
+        let dir = std::env::temp_dir().join(format!("ros_map_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image.save(dir.join("lab.pgm")).unwrap();
+        std::fs::write(dir.join("lab.yaml"), yaml).unwrap();
+        let ros = RosMap::load(dir.join("lab.yaml")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ros.map.size, glam::USizeVec2::new(4, 2));
+        let occupied = ros.map.pixels.iter().filter(|&&p| p).count();
+        assert_eq!(occupied, 2);
+        // The occupied pixel lies 1.55 m right and 0.75 m up from the origin.
+        let wall = ros.to_scene(glam::vec2(-2. + 1.55, -1. + 0.75));
+        assert!((wall - glam::vec2(-0.45, -0.25)).length() < 1e-5);
+        assert!(ros.map.is_occupied_vec2(wall));
+        assert!(ros.map.is_occupied_vec2(ros.to_scene(glam::vec2(1.5, 0.5))));
+        assert!(
+            !ros.map
+                .is_occupied_vec2(ros.to_scene(glam::vec2(-1.5, -0.5)))
+        );
+        assert!((ros.from_scene(wall) - glam::vec2(-0.45, -0.25)).length() < 1e-5);
+    }
+}
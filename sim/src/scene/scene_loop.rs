@@ -1,18 +1,50 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use dashmap::DashMap;
 use parking_lot::RwLock;
+use rand_distr::{Distribution, Normal};
+use rustc_hash::FxHashMap;
 
 use crate::{
-    Agent2D, Lidar2D,
-    agent::{Agent2DConfig, Agent2DMeasurements, Agent2DState},
-    scene::{AgentId, Scene2DState},
-    sensors::{Sensor2D, TimeStamped},
+    Agent2D,
+    agent::{Agent2DConfig, Agent2DState},
+    rng,
+    scene::{AgentId, Scene2DState, SceneTime},
+    sensors::{
+        AnyMeasurement, DynSensor2D, MeasurementMeta, SensorClock, TimeStamped, TopicDescriptor,
+    },
 };
 
+/// Measurements kept per topic unless changed with [Scene2DLoop::set_history_len].
+pub const DEFAULT_HISTORY_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+/// Every topic's schedule and buffered measurements at one instant, taken with [Scene2DLoop::snapshot_measurements].
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementSnapshot {
+    topics: FxHashMap<(AgentId, String), TopicSnapshot>,
+}
+
+#[derive(Debug, Clone)]
+struct TopicSnapshot {
+    next_due: SceneTime,
+    pending: VecDeque<InFlight>,
+    history: VecDeque<TimeStamped<AnyMeasurement>>,
+}
+
 #[derive(Default, Debug)]
 pub struct Scene2DLoop {
     workers: DashMap<AgentId, AgentWorker>,
+    next_subscription: AtomicU64,
+    seed: RwLock<Option<u64>>,
 }
 
 impl Scene2DLoop {
@@ -22,89 +54,779 @@ impl Scene2DLoop {
 
     pub fn insert_agent(&self, agent_id: AgentId, agent: &Agent2D) {
         if !self.contains_agent(agent_id) {
-            self.workers.insert(
-                agent_id,
-                AgentWorker {
-                    lidar: SensorWorker {
-                        lidar: Arc::clone(&agent.sensors.lidar),
-                        worker: RwLock::new(None),
-                        last_measurement: RwLock::new(None),
-                    },
-                },
-            );
+            let topics = agent
+                .sensors
+                .iter()
+                .map(|entry| {
+                    let mut topic =
+                        SensorWorker::new(entry.name.clone(), Arc::clone(&entry.sensor));
+                    topic.rate = entry.rate.filter(|r| *r > 0.);
+                    topic.latency = entry.latency;
+                    topic.jitter = entry.jitter;
+                    topic.clock = entry.clock;
+                    topic.cost = entry.cost;
+                    topic
+                })
+                .collect();
+
+            self.workers.insert(agent_id, AgentWorker { topics });
         }
     }
 
+    /// Points the agent's topics at the sensors of the same names in `agent`, e.g. after its sensors were replaced by
+    /// copies. Schedules, buffers and subscribers are kept.
+    pub fn set_sensors(&self, agent_id: AgentId, agent: &Agent2D) {
+        let Some(mut worker) = self.workers.get_mut(&agent_id) else {
+            return;
+        };
+
+        for topic in &mut worker.topics {
+            if let Some(entry) = agent.sensors.get(&topic.name) {
+                topic.sensor = Arc::clone(&entry.sensor);
+            }
+        }
+    }
+
+    pub fn remove_agent(&self, agent: AgentId) -> bool {
+        self.workers.remove(&agent).is_some()
+    }
+
+    /// Senses whichever of the agent's sensors are due, returning what they cost or `None` for an unknown agent.
     pub fn update_state(
         &self,
         agent: AgentId,
         config: Agent2DConfig,
         state: Agent2DState,
         scene_state: Scene2DState,
-    ) -> bool {
-        if let Some(worker) = self.workers.get(&agent) {
-            worker.update_state(config, state, scene_state);
+    ) -> Option<f32> {
+        let worker = self.workers.get(&agent)?;
+        let seed = self.seed().map(|seed| rng::derive_seed(seed, agent));
+
+        Some(worker.update_state(config, state, scene_state.viewed_by(agent), seed))
+    }
+
+    /// With a seed, sensors are sensed on the thread updating the scene rather than in the background, and draw their
+    /// noise from streams seeded by `seed`, the agent, the topic and the scene time, so runs repeat exactly. Zero
+    /// latency measurements are then delivered in the update they were sensed in.
+    pub fn set_seed(&self, seed: Option<u64>) {
+        *self.seed.write() = seed;
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        *self.seed.read()
+    }
+
+    /// Lists the topics published by `agent`.
+    pub fn topics(&self, agent: AgentId) -> Vec<TopicDescriptor> {
+        self.workers
+            .get(&agent)
+            .map(|worker| worker.topics.iter().map(|t| t.descriptor()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Lists the topics published by every agent in the loop.
+    pub fn all_topics(&self) -> Vec<(AgentId, TopicDescriptor)> {
+        self.workers
+            .iter()
+            .flat_map(|worker| {
+                let id = *worker.key();
+                worker
+                    .topics
+                    .iter()
+                    .map(|t| (id, t.descriptor()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Makes every rate-limited topic due on the next update, e.g. after the scene time jumped backwards.
+    pub fn reschedule(&self) {
+        for worker in self.workers.iter() {
+            for topic in &worker.topics {
+                *topic.next_due.write() = SceneTime(0.);
+            }
+        }
+    }
+
+    /// Drops every buffered measurement, e.g. after the scene time jumped backwards.
+    pub fn clear_measurements(&self) {
+        for worker in self.workers.iter() {
+            for topic in &worker.topics {
+                topic.history.write().clear();
+                topic.pending.write().clear();
+                topic.delivered.write().clear();
+            }
+        }
+    }
+
+    /// Copies what every topic has delivered and has waiting to deliver, and when it next senses. Readings still being
+    /// taken on other threads are left out.
+    pub fn snapshot_measurements(&self) -> MeasurementSnapshot {
+        let mut topics = FxHashMap::default();
+        for worker in self.workers.iter() {
+            for topic in &worker.topics {
+                topics.insert(
+                    (*worker.key(), topic.name.clone()),
+                    TopicSnapshot {
+                        next_due: *topic.next_due.read(),
+                        pending: topic.pending.read().clone(),
+                        history: topic.history.read().clone(),
+                    },
+                );
+            }
+        }
+
+        MeasurementSnapshot { topics }
+    }
+
+    /// Puts back what [Scene2DLoop::snapshot_measurements] copied, dropping any reading in progress. Topics the
+    /// snapshot doesn't know are cleared and made due.
+    pub fn restore_measurements(&self, snapshot: &MeasurementSnapshot) {
+        for worker in self.workers.iter() {
+            for topic in &worker.topics {
+                topic.worker.write().take();
+                topic.delivered.write().clear();
+                match snapshot.topics.get(&(*worker.key(), topic.name.clone())) {
+                    Some(saved) => {
+                        *topic.next_due.write() = saved.next_due;
+                        *topic.pending.write() = saved.pending.clone();
+                        *topic.history.write() = saved.history.clone();
+                    }
+                    None => {
+                        *topic.next_due.write() = SceneTime(0.);
+                        topic.pending.write().clear();
+                        topic.history.write().clear();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the topics that delivered measurements since the last call, with the stamp of each measurement.
+    pub fn take_delivered(&self) -> Vec<(AgentId, String, SceneTime)> {
+        let mut delivered = Vec::new();
+        for worker in self.workers.iter() {
+            for topic in &worker.topics {
+                delivered.extend(
+                    topic
+                        .delivered
+                        .write()
+                        .drain(..)
+                        .map(|time| (*worker.key(), topic.name.clone(), time)),
+                );
+            }
+        }
+
+        delivered
+    }
+
+    /// Sets how many measurements of `topic` are kept, at least one.
+    pub fn set_history_len(&self, agent: AgentId, topic: &str, len: usize) -> bool {
+        let Some(mut worker) = self.workers.get_mut(&agent) else {
+            return false;
+        };
+
+        match worker.topics.iter_mut().find(|t| t.name == topic) {
+            Some(topic) => {
+                topic.history_len = len.max(1);
+                let mut history = topic.history.write();
+                while history.len() > topic.history_len {
+                    history.pop_front();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets how often `topic` is sensed, in Hz. `None` senses on every scene update.
+    pub fn set_rate(&self, agent: AgentId, topic: &str, rate: Option<f32>) -> bool {
+        let Some(mut worker) = self.workers.get_mut(&agent) else {
+            return false;
+        };
 
-            true
-        } else {
-            false
+        match worker.topics.iter_mut().find(|t| t.name == topic) {
+            Some(topic) => {
+                topic.rate = rate.filter(|r| *r > 0.);
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn query(&self, agent: AgentId) -> Option<Agent2DMeasurements> {
-        Some(self.workers.get(&agent)?.query())
+    pub fn query_topic(&self, agent: AgentId, topic: &str) -> Option<TimeStamped<AnyMeasurement>> {
+        self.workers.get(&agent)?.topic(topic)?.latest()
+    }
+
+    pub fn query_topic_as<T: Send + Sync + 'static>(
+        &self,
+        agent: AgentId,
+        topic: &str,
+    ) -> Option<TimeStamped<Arc<T>>> {
+        self.query_topic(agent, topic)?.downcast()
+    }
+
+    /// Returns a channel receiving every new measurement on `topic`, or `None` if there is no such topic. Dropping
+    /// the receiver ends the subscription.
+    pub fn subscribe(&self, agent: AgentId, topic: &str) -> Option<Receiver> {
+        let (snd, rcv) = flume::unbounded();
+        self.add_subscriber(agent, topic, Subscriber::Channel(snd))?;
+
+        Some(rcv)
+    }
+
+    /// Calls `callback` with every new measurement on `topic`. Callbacks run on the thread updating the scene, so
+    /// they should be quick and must not subscribe or unsubscribe themselves.
+    pub fn on_measurement<F>(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        callback: F,
+    ) -> Option<SubscriptionId>
+    where
+        F: Fn(&TimeStamped<AnyMeasurement>) + Send + Sync + 'static,
+    {
+        self.add_subscriber(agent, topic, Subscriber::Callback(Box::new(callback)))
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.workers.iter().any(|worker| {
+            worker.topics.iter().any(|topic| {
+                let mut subscribers = topic.subscribers.write();
+                let len = subscribers.len();
+                subscribers.retain(|(sub, _)| *sub != id);
+
+                subscribers.len() != len
+            })
+        })
+    }
+
+    fn add_subscriber(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        subscriber: Subscriber,
+    ) -> Option<SubscriptionId> {
+        let worker = self.workers.get(&agent)?;
+        let topic = worker.topic(topic)?;
+
+        let id = SubscriptionId(self.next_subscription.fetch_add(1, Ordering::Relaxed));
+        topic.subscribers.write().push((id, subscriber));
+
+        Some(id)
+    }
+
+    /// Buffered measurements stamped strictly after `time`, oldest first.
+    pub fn query_since(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        time: SceneTime,
+    ) -> Vec<TimeStamped<AnyMeasurement>> {
+        self.workers
+            .get(&agent)
+            .and_then(|worker| {
+                let history = worker.topic(topic)?.history.read();
+                Some(
+                    history
+                        .iter()
+                        .filter(|m| m.time.0 > time.0)
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn query_since_as<T: Send + Sync + 'static>(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        time: SceneTime,
+    ) -> Vec<TimeStamped<Arc<T>>> {
+        self.query_since(agent, topic, time)
+            .into_iter()
+            .filter_map(|m| m.downcast())
+            .collect()
+    }
+
+    /// Up to `k` of the most recent measurements, oldest first.
+    pub fn latest_n(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        k: usize,
+    ) -> Vec<TimeStamped<AnyMeasurement>> {
+        self.workers
+            .get(&agent)
+            .and_then(|worker| {
+                let history = worker.topic(topic)?.history.read();
+                Some(
+                    history
+                        .iter()
+                        .skip(history.len().saturating_sub(k))
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn latest_n_as<T: Send + Sync + 'static>(
+        &self,
+        agent: AgentId,
+        topic: &str,
+        k: usize,
+    ) -> Vec<TimeStamped<Arc<T>>> {
+        self.latest_n(agent, topic, k)
+            .into_iter()
+            .filter_map(|m| m.downcast())
+            .collect()
     }
 }
 
 #[derive(Debug)]
 pub struct AgentWorker {
-    lidar: SensorWorker<Lidar2D>,
+    topics: Vec<SensorWorker>,
 }
 
 impl AgentWorker {
-    fn query(&self) -> Agent2DMeasurements {
-        Agent2DMeasurements {
-            lidar: self.lidar.last_measurement.read().clone(),
-        }
+    fn topic(&self, name: &str) -> Option<&SensorWorker> {
+        self.topics.iter().find(|t| t.name == name)
     }
 
-    fn update_state(&self, config: Agent2DConfig, state: Agent2DState, scene_state: Scene2DState) {
-        self.lidar.update_state(config, state, scene_state);
+    fn update_state(
+        &self,
+        config: Agent2DConfig,
+        state: Agent2DState,
+        scene_state: Scene2DState,
+        seed: Option<u64>,
+    ) -> f32 {
+        self.topics
+            .iter()
+            .map(|topic| topic.update_state(config, state, scene_state.clone(), seed))
+            .sum()
     }
 }
 
-type Receiver<S> = flume::Receiver<TimeStamped<<S as Sensor2D>::SensorType>>;
+type Receiver = flume::Receiver<TimeStamped<AnyMeasurement>>;
+
+/// A measurement along with the scene time it becomes visible.
+type InFlight = (SceneTime, TimeStamped<AnyMeasurement>);
+
+type Callback = Box<dyn Fn(&TimeStamped<AnyMeasurement>) + Send + Sync>;
+
+enum Subscriber {
+    Channel(flume::Sender<TimeStamped<AnyMeasurement>>),
+    Callback(Callback),
+}
+
+impl std::fmt::Debug for Subscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channel(_) => f.write_str("Channel"),
+            Self::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl Subscriber {
+    /// Returns `false` once the subscriber has gone away.
+    fn deliver(&self, measurement: &TimeStamped<AnyMeasurement>) -> bool {
+        match self {
+            Self::Channel(snd) => snd.send(measurement.clone()).is_ok(),
+            Self::Callback(callback) => {
+                callback(measurement);
+                true
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
-pub struct SensorWorker<S: Sensor2D> {
-    lidar: Arc<RwLock<S>>,
-    worker: RwLock<Option<Receiver<S>>>,
-    last_measurement: RwLock<Option<TimeStamped<S::SensorType>>>,
+pub struct SensorWorker {
+    name: String,
+    sensor: Arc<RwLock<dyn DynSensor2D>>,
+    /// Sensing rate in Hz, or `None` to sense on every update.
+    rate: Option<f32>,
+    /// Delay between sensing and delivery in seconds.
+    latency: f32,
+    /// Standard deviation of the error on measurement timestamps in seconds.
+    jitter: f32,
+    clock: SensorClock,
+    /// Charged per reading instead of the sensor's own cost.
+    cost: Option<f32>,
+    next_due: RwLock<SceneTime>,
+    worker: RwLock<Option<flume::Receiver<InFlight>>>,
+    /// Sensed but not delivered yet, in order of delivery.
+    pending: RwLock<VecDeque<InFlight>>,
+    history_len: usize,
+    /// Oldest first.
+    history: RwLock<VecDeque<TimeStamped<AnyMeasurement>>>,
+    /// Stamps of the measurements delivered since [Scene2DLoop::take_delivered] last looked.
+    delivered: RwLock<Vec<SceneTime>>,
+    subscribers: RwLock<Vec<(SubscriptionId, Subscriber)>>,
 }
 
-impl<S: Sensor2D + Send + Sync + 'static> SensorWorker<S> {
-    fn update_state(&self, config: Agent2DConfig, state: Agent2DState, scene_state: Scene2DState) where S::SensorType: Send + 'static {
-        if let Some(rcv) = &*self.worker.read() {
-            let rcvd = rcv.try_recv();
-
-            if let Err(e) = rcvd
-                && e == flume::TryRecvError::Empty
-            {
-                return;
-            } else if let Ok(measurement) = rcvd {
-                self.last_measurement.write().replace(measurement);
+impl SensorWorker {
+    pub fn new(name: impl Into<String>, sensor: Arc<RwLock<dyn DynSensor2D>>) -> Self {
+        Self {
+            name: name.into(),
+            sensor,
+            rate: None,
+            latency: 0.,
+            jitter: 0.,
+            clock: SensorClock::default(),
+            cost: None,
+            next_due: RwLock::new(SceneTime(0.)),
+            worker: RwLock::new(None),
+            pending: RwLock::new(VecDeque::new()),
+            history_len: DEFAULT_HISTORY_LEN,
+            history: RwLock::new(VecDeque::new()),
+            delivered: RwLock::new(Vec::new()),
+            subscribers: RwLock::new(Vec::new()),
+        }
+    }
+
+    fn descriptor(&self) -> TopicDescriptor {
+        TopicDescriptor {
+            name: self.name.clone(),
+            type_name: self.sensor.read().type_name(),
+            rate: self.rate,
+            meta: MeasurementMeta {
+                latency: self.latency,
+                jitter: self.jitter,
+                clock: self.clock,
+                ..self.sensor.read().measurement_meta()
+            },
+        }
+    }
+
+    /// Moves measurements whose latency has elapsed into the history and out to subscribers.
+    fn deliver_due(&self, now: SceneTime) {
+        let mut pending = self.pending.write();
+        while pending.front().is_some_and(|(due, _)| due.0 <= now.0) {
+            let Some((_, measurement)) = pending.pop_front() else {
+                break;
+            };
+
+            self.subscribers
+                .write()
+                .retain(|(_, subscriber)| subscriber.deliver(&measurement));
+
+            self.delivered.write().push(measurement.time);
+            let mut history = self.history.write();
+            if history.len() >= self.history_len {
+                history.pop_front();
             }
+            history.push_back(measurement);
+        }
+    }
+
+    fn latest(&self) -> Option<TimeStamped<AnyMeasurement>> {
+        self.history.read().back().cloned()
+    }
+
+    /// Starts a reading if one is due, returning its cost.
+    fn update_state(
+        &self,
+        config: Agent2DConfig,
+        state: Agent2DState,
+        scene_state: Scene2DState,
+        seed: Option<u64>,
+    ) -> f32 {
+        let busy = match &*self.worker.read() {
+            Some(rcv) => match rcv.try_recv() {
+                Ok(in_flight) => {
+                    self.pending.write().push_back(in_flight);
+                    false
+                }
+                Err(e) => e == flume::TryRecvError::Empty,
+            },
+            None => false,
+        };
+
+        self.deliver_due(scene_state.time);
+        if busy {
+            return 0.;
+        }
+
+        if let Some(rate) = self.rate {
+            let now = scene_state.time.0;
+            let mut next_due = self.next_due.write();
+            if now < next_due.0 {
+                return 0.;
+            }
+
+            // Keep to the schedule unless we've fallen a whole period behind, e.g. after a long frame.
+            let next = next_due.0 + rate.recip();
+            next_due.0 = if next <= now {
+                now + rate.recip()
+            } else {
+                next
+            };
+        }
+
+        let now = scene_state.time;
+        let cost = self.cost.unwrap_or_else(|| self.sensor.read().cost());
+        let sensor = Arc::clone(&self.sensor);
+        let (latency, jitter, clock) = (self.latency, self.jitter, self.clock);
+        let sense = move || {
+            let mut m = sensor.write().sense_any(config, state, scene_state)?;
+            let due = SceneTime(m.time.0 + latency);
+            m.meta.latency = latency;
+            m.meta.jitter = jitter;
+            m.meta.clock = clock;
+            m.meta.true_time = Some(m.time);
+            m.time = clock.read(m.time);
+            m.time.0 += Normal::new(0., jitter)
+                .map(|n| n.sample(&mut rng::rng()))
+                .unwrap_or(0.);
+
+            Some((due, m))
+        };
+
+        if let Some(seed) = seed {
+            let seed = rng::derive_seed(seed, (&self.name, now.0.to_bits()));
+            if let Some(in_flight) = rng::with_seed(Some(seed), sense) {
+                self.pending.write().push_back(in_flight);
+            }
+            self.deliver_due(now);
+            return cost;
         }
 
-        let lidar = Arc::clone(&self.lidar);
         let (snd, rcv) = flume::bounded(1);
         rayon::spawn(move || {
-            let measurement = lidar.read().sense(config, state, scene_state);
-            if let Some(m) = measurement {
-                let _ = snd.send(m);
+            if let Some(in_flight) = sense() {
+                let _ = snd.send(in_flight);
             }
         });
 
         self.worker.write().replace(rcv);
+
+        cost
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Scene2D,
+        scene::{AgentId, SceneTime, scene_loop::Scene2DLoop},
+        sensors::{AnyMeasurement, BumperSensed, CompassSensed, SensorClock, TimeStamped},
+        testing,
+    };
+
+    #[test]
+    fn test_latency() {
+        // Sensing synchronously, on steps exact in f32, so delivery is decided by scene time alone.
+        let mut scene = testing::open_room();
+        let mut agent = testing::bumper_agent();
+        agent.sensors.get_mut("bumper").unwrap().latency = 0.5;
+        let id = scene.add_agent(agent);
+
+        for _ in 0..4 {
+            scene.update(0.125);
+        }
+        assert!(scene.scene_loop.query_topic(id, "bumper").is_none());
+
+        // Sensed in the first update, at 0.125, and due half a second later.
+        scene.update(0.125);
+        let measurement = scene.scene_loop.query_topic(id, "bumper").unwrap();
+        assert_eq!(measurement.time, SceneTime(0.125));
+        assert_eq!(measurement.meta.latency, 0.5);
+
+        for _ in 0..5 {
+            scene.update(0.125);
+        }
+        let measurement = scene.scene_loop.query_topic(id, "bumper").unwrap();
+        assert_eq!(measurement.time.0, scene.time.0 - 0.5);
+    }
+
+    #[test]
+    fn test_sensor_clock() {
+        let mut scene = testing::open_room();
+        let mut agent = testing::bumper_agent();
+        let clock = SensorClock {
+            offset: 2.,
+            drift: 0.1,
+        };
+        agent.sensors.get_mut("bumper").unwrap().clock = clock;
+        let id = scene.add_agent(agent);
+
+        // Without latency, each update delivers what it sensed.
+        for _ in 0..4 {
+            scene.update(0.25);
+        }
+        let measurement = scene.scene_loop.query_topic(id, "bumper").unwrap();
+        assert_eq!(measurement.meta.true_time, Some(SceneTime(1.)));
+        assert!((measurement.time.0 - 3.1).abs() < 1e-6);
+        assert_eq!(measurement.meta.clock, clock);
+    }
+
+    /// A bumper that kept its last 3 of 5 readings, taken every quarter second.
+    fn bumper_history() -> (Arc<Scene2DLoop>, AgentId) {
+        let mut scene = testing::open_room();
+        let id = scene.add_agent(testing::bumper_agent());
+        let scene_loop = Arc::clone(&scene.scene_loop);
+        assert!(scene_loop.set_history_len(id, "bumper", 3));
+
+        for _ in 0..5 {
+            scene.update(0.25);
+        }
+
+        (scene_loop, id)
+    }
+
+    fn times(ms: &[TimeStamped<AnyMeasurement>]) -> Vec<f32> {
+        ms.iter().map(|m| m.time.0).collect()
+    }
+
+    #[test]
+    fn test_history_latest_n() {
+        // Full, so the two oldest were evicted.
+        let (scene_loop, id) = bumper_history();
+        assert_eq!(
+            times(&scene_loop.latest_n(id, "bumper", 10)),
+            [0.75, 1., 1.25]
+        );
+        assert_eq!(times(&scene_loop.latest_n(id, "bumper", 2)), [1., 1.25]);
+        assert!(scene_loop.latest_n(id, "bumper", 0).is_empty());
+    }
+
+    #[test]
+    fn test_history_since() {
+        // Strictly after the given time.
+        let (scene_loop, id) = bumper_history();
+        assert_eq!(
+            times(&scene_loop.query_since(id, "bumper", SceneTime(1.))),
+            [1.25]
+        );
+        assert_eq!(
+            times(&scene_loop.query_since(id, "bumper", SceneTime(0.))),
+            [0.75, 1., 1.25]
+        );
+        assert!(
+            scene_loop
+                .query_since(id, "bumper", SceneTime(1.25))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_history_typed() {
+        let (scene_loop, id) = bumper_history();
+        assert_eq!(
+            scene_loop
+                .latest_n_as::<BumperSensed>(id, "bumper", 2)
+                .len(),
+            2
+        );
+        assert_eq!(
+            scene_loop
+                .query_since_as::<BumperSensed>(id, "bumper", SceneTime(0.))
+                .len(),
+            3
+        );
+        assert!(
+            scene_loop
+                .latest_n_as::<CompassSensed>(id, "bumper", 2)
+                .is_empty()
+        );
+        assert!(
+            scene_loop
+                .query_since_as::<CompassSensed>(id, "bumper", SceneTime(0.))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_history_missing_topic() {
+        let (scene_loop, id) = bumper_history();
+        assert!(scene_loop.latest_n(id, "missing", 2).is_empty());
+        assert!(
+            scene_loop
+                .query_since(id, "missing", SceneTime(0.))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_history_shrink() {
+        // Shrinking drops the oldest.
+        let (scene_loop, id) = bumper_history();
+        assert!(scene_loop.set_history_len(id, "bumper", 1));
+        assert_eq!(times(&scene_loop.latest_n(id, "bumper", 10)), [1.25]);
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let mut scene = testing::open_room();
+        let id = scene.add_agent(testing::bumper_agent());
+        let scene_loop = Arc::clone(&scene.scene_loop);
+
+        assert!(scene_loop.subscribe(id, "missing").is_none());
+        assert!(scene_loop.on_measurement(id, "missing", |_| {}).is_none());
+
+        let channel = scene_loop.subscribe(id, "bumper").unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let calls = Arc::clone(&calls);
+            scene_loop
+                .on_measurement(id, "bumper", move |m| calls.lock().push(m.time))
+                .unwrap()
+        };
+
+        for _ in 0..3 {
+            scene.update(0.25);
+        }
+        let received = channel.try_iter().map(|m| m.time).collect::<Vec<_>>();
+        assert_eq!(received, [SceneTime(0.25), SceneTime(0.5), SceneTime(0.75)]);
+        assert_eq!(*calls.lock(), received);
+
+        assert!(scene_loop.unsubscribe(callback));
+        assert!(!scene_loop.unsubscribe(callback));
+        scene.update(0.25);
+        assert_eq!(calls.lock().len(), 3);
+        assert_eq!(channel.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut scene = testing::open_room();
+        let mut agent = testing::bumper_agent();
+        agent.sensors.get_mut("bumper").unwrap().rate = Some(5.);
+        let id = scene.add_agent(agent);
+        let sensed = scene.scene_loop.subscribe(id, "bumper").unwrap();
+        let run = |scene: &mut Scene2D, updates: usize| {
+            for _ in 0..updates {
+                scene.update(0.0625);
+            }
+            sensed.try_iter().count()
+        };
+
+        // Due straight away, then 5 Hz over a second of 16 Hz updates.
+        assert_eq!(run(&mut scene, 1), 1);
+        assert_eq!(run(&mut scene, 16), 5);
+
+        // A jump forwards senses once rather than making up every missed reading, then keeps to the rate from there.
+        scene.time.0 += 10.;
+        assert_eq!(run(&mut scene, 1), 1);
+        assert_eq!(run(&mut scene, 3), 0);
+        assert_eq!(run(&mut scene, 1), 1);
+
+        // A jump backwards waits for the old schedule unless rescheduled.
+        scene.time.0 = 1.;
+        assert_eq!(run(&mut scene, 3), 0);
+        scene.scene_loop.reschedule();
+        assert_eq!(run(&mut scene, 1), 1);
+
+        assert!(scene.scene_loop.set_rate(id, "bumper", None));
+        assert_eq!(run(&mut scene, 4), 4);
+        assert!(!scene.scene_loop.set_rate(id, "missing", None));
     }
 }
@@ -0,0 +1,119 @@
+use crate::{
+    math::{ConvexPolygon, OrientedBox2D},
+    scene::{
+        occupancy_map::{Contact, ObjectTag, OccupancyMap},
+        tiles::TiledWorld,
+        vector_map::VectorMap,
+    },
+};
+
+/// The static walls of a scene, whatever they are made of, for sensors and collisions to query.
+pub trait ObstacleSource: std::fmt::Debug + Send + Sync {
+    /// Distance along `dir` to the nearest wall and the object it belongs to.
+    fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)>;
+
+    /// [cast_rays_tagged](Self::cast_rays_tagged) for many rays from `pos`, one at a time unless the source can share
+    /// the work between them.
+    fn cast_rays_batch_tagged(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, ObjectTag)>> {
+        dirs.iter()
+            .map(|&dir| self.cast_rays_tagged(pos, dir))
+            .collect()
+    }
+
+    fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool;
+
+    /// The wall `footprint` presses furthest into, if the source can tell.
+    fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact>;
+
+    /// [contact](Self::contact) for a convex outline, judged against its bounding box unless the source can do better.
+    fn contact_polygon(&self, footprint: &ConvexPolygon) -> Option<Contact> {
+        self.contact(&footprint.get_box().into())
+    }
+
+    fn overlaps(&self, footprint: &OrientedBox2D) -> bool {
+        self.contact(footprint).is_some()
+    }
+
+    /// [overlaps](Self::overlaps) for a convex outline, judged against its bounding box unless the source can do
+    /// better.
+    fn overlaps_polygon(&self, footprint: &ConvexPolygon) -> bool {
+        self.overlaps(&footprint.get_box().into())
+    }
+}
+
+impl ObstacleSource for OccupancyMap {
+    fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        OccupancyMap::cast_rays_tagged(self, pos, dir)
+    }
+
+    fn cast_rays_batch_tagged(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, ObjectTag)>> {
+        OccupancyMap::cast_rays_batch_tagged(self, pos, dirs)
+    }
+
+    fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        OccupancyMap::is_occupied_vec2(self, loc)
+    }
+
+    fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact> {
+        OccupancyMap::contact(self, footprint)
+    }
+
+    fn contact_polygon(&self, footprint: &ConvexPolygon) -> Option<Contact> {
+        OccupancyMap::contact_polygon(self, footprint)
+    }
+
+    /// Also counts leaving a map with walls around it.
+    fn overlaps(&self, footprint: &OrientedBox2D) -> bool {
+        OccupancyMap::overlaps(self, footprint)
+    }
+
+    fn overlaps_polygon(&self, footprint: &ConvexPolygon) -> bool {
+        OccupancyMap::overlaps_polygon(self, footprint)
+    }
+}
+
+impl ObstacleSource for VectorMap {
+    fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        VectorMap::cast_rays_tagged(self, pos, dir)
+    }
+
+    fn cast_rays_batch_tagged(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, ObjectTag)>> {
+        VectorMap::cast_rays_batch_tagged(self, pos, dirs)
+    }
+
+    fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        VectorMap::is_occupied_vec2(self, loc)
+    }
+
+    fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact> {
+        VectorMap::contact(self, footprint)
+    }
+}
+
+impl ObstacleSource for TiledWorld {
+    /// Tiles are built independently, so their object tags are not meaningful across the world.
+    fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        self.cast_rays(pos, dir).map(|t| (t, ObjectTag::MAP_EDGE))
+    }
+
+    fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        TiledWorld::is_occupied_vec2(self, loc)
+    }
+
+    /// Tiles don't take part in collisions.
+    fn contact(&self, _footprint: &OrientedBox2D) -> Option<Contact> {
+        None
+    }
+}
@@ -0,0 +1,106 @@
+//! Finding room for agents: checking a spawn is clear and sampling poses that are.
+
+use rand::Rng;
+
+use crate::{
+    Agent2D,
+    math::{ConvexPolygon, OrientedBox2D, Pose2D},
+    scene::{AgentId, Scene2D, Scene2DError},
+};
+
+/// Poses [find_free_pose](Scene2D::find_free_pose) tries before giving up.
+const MAX_SAMPLES: usize = 1000;
+
+impl Scene2D {
+    /// Whether `footprint` is clear of the walls, the obstacles and pedestrians where they stand now and every agent
+    /// and trailer. Obstacles and pedestrians are checked against its bounding box.
+    pub fn is_clear(&self, footprint: &ConvexPolygon) -> bool {
+        let bounds = OrientedBox2D::from(footprint.get_box());
+
+        !self.obstacle_source().overlaps_polygon(footprint)
+            && self
+                .obstacles
+                .iter()
+                .map(|o| o.body_at(self.time))
+                .chain(self.crowd.bodies())
+                .all(|body| body.contact(&bounds).is_none())
+            && self
+                .agents
+                .values()
+                .flat_map(Agent2D::footprints)
+                .all(|other| !other.intersects(footprint))
+    }
+
+    /// Samples poses uniformly over the map until `footprint`, given in the agent's frame, is
+    /// [clear](Self::is_clear) there. `None` if no clear pose turns up.
+    pub fn find_free_pose(&self, rng: &mut impl Rng, footprint: &ConvexPolygon) -> Option<Pose2D> {
+        let bounds = self.occupancy_map.bounds();
+        if bounds.size().min_element() <= 0. {
+            return None;
+        }
+
+        (0..MAX_SAMPLES).find_map(|_| {
+            let pose = Pose2D::new(
+                glam::vec2(
+                    rng.random_range(bounds.min.x..bounds.max.x),
+                    rng.random_range(bounds.min.y..bounds.max.y),
+                ),
+                rng.random_range(-std::f32::consts::PI..std::f32::consts::PI),
+            );
+
+            self.is_clear(&footprint.transformed(&pose)).then_some(pose)
+        })
+    }
+
+    /// [add_agent](Self::add_agent), unless the agent or any of its trailers would start overlapping something.
+    pub fn try_add_agent(&mut self, agent: Agent2D) -> Result<AgentId, Scene2DError> {
+        if !agent
+            .footprints()
+            .all(|footprint| self.is_clear(&footprint))
+        {
+            return Err(Scene2DError::SpawnBlocked(agent.state.position));
+        }
+
+        Ok(self.add_agent(agent))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use crate::{
+        Agent2D, Scene2D,
+        math::Pose2D,
+        scene::{Scene2DError, generate},
+    };
+
+    #[test]
+    fn test_spawn() {
+        let size = [48, 48];
+        let mut scene = Scene2D::from_pixels(size, &generate::rooms(size, [3, 3], 1, 4)).unwrap();
+
+        // Straddling the wall between two rooms.
+        let mut agent = Agent2D::default();
+        agent.state = agent.state.with_pose(Pose2D::new(glam::vec2(8.5, 4.5), 0.));
+        assert!(matches!(
+            scene.try_add_agent(agent.clone()),
+            Err(Scene2DError::SpawnBlocked(_))
+        ));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let outline = agent.config.outline();
+        let mut ids = Vec::new();
+        for _ in 0..10 {
+            let pose = scene.find_free_pose(&mut rng, &outline).unwrap();
+            agent.state = agent.state.with_pose(pose);
+            ids.push(scene.try_add_agent(agent.clone()).unwrap());
+        }
+
+        // None of them overlap each other or the walls.
+        for &id in &ids {
+            let footprint = scene.remove_agent(id).unwrap().footprint_polygon();
+            assert!(scene.is_clear(&footprint));
+        }
+    }
+}
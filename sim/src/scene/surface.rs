@@ -0,0 +1,250 @@
+//! What the ground is made of, cell by cell, for ice patches, gravel traps and changes from carpet to tile. Agents
+//! consult the surface under them on every update, see [Agent2D::update](crate::Agent2D::update).
+
+use crate::{
+    agent::Agent2DState,
+    config::{self, ConfigError, Validate},
+    math::Box2D,
+    scene::occupancy_map::OccupancyMap,
+};
+
+/// Acceleration due to gravity in m/s².
+pub(crate) const GRAVITY: f32 = 9.81;
+
+/// How the ground holds an agent back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Surface {
+    /// Coefficient of friction between the tyres and the ground. Speeding up, slowing down and turning are each limited
+    /// to `friction` g, past which the tyres slip.
+    pub friction: f32,
+    /// Coefficient of rolling resistance, slowing a rolling agent by `rolling_resistance` g.
+    pub rolling_resistance: f32,
+}
+
+impl Default for Surface {
+    /// Dry tarmac.
+    fn default() -> Self {
+        Self {
+            friction: 1.,
+            rolling_resistance: 0.,
+        }
+    }
+}
+
+impl Validate for Surface {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("friction", self.friction)?;
+        config::non_negative("rolling_resistance", self.rolling_resistance)
+    }
+}
+
+impl Surface {
+    pub const ICE: Self = Self {
+        friction: 0.1,
+        rolling_resistance: 0.01,
+    };
+    pub const GRAVEL: Self = Self {
+        friction: 0.6,
+        rolling_resistance: 0.2,
+    };
+    pub const CARPET: Self = Self {
+        friction: 0.8,
+        rolling_resistance: 0.05,
+    };
+
+    /// Limits `next`, integrated from `state` over `dt` on ideal ground, to what this surface allows.
+    pub fn constrain(&self, state: &Agent2DState, next: &mut Agent2DState, dt: f32) {
+        let grip = self.friction * GRAVITY * dt;
+        next.velocity = state.velocity + (next.velocity - state.velocity).clamp(-grip, grip);
+
+        let rolling = (self.rolling_resistance * GRAVITY * dt).min(next.velocity.abs());
+        next.velocity -= rolling * next.velocity.signum();
+
+        // Turning at speed v through an angle θ in dt takes a sideways acceleration of v θ / dt.
+        let speed = state.velocity.abs();
+        if speed > 0. {
+            let turn = state.heading.angle_to(next.heading);
+            let max_turn = grip / speed;
+            if turn.abs() > max_turn {
+                next.heading =
+                    glam::Vec2::from_angle(max_turn.copysign(turn)).rotate(state.heading);
+            }
+        }
+    }
+}
+
+/// A [Surface] for every cell of a grid, laid out like an [OccupancyMap]'s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SurfaceMap {
+    pub size: glam::USizeVec2,
+    /// Metres per cell.
+    pub resolution: f32,
+    /// Where the centre of the grid lies in the world.
+    pub origin: glam::Vec2,
+    /// Row-major with the first row at the top, like [OccupancyMap::pixels].
+    pub cells: Vec<Surface>,
+    /// Beyond the grid.
+    pub outside: Surface,
+}
+
+impl Validate for SurfaceMap {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("resolution", self.resolution)?;
+        config::within(
+            "cells",
+            self.cells.len() as f32,
+            self.cells.len() == self.size.x * self.size.y,
+            "one per cell",
+        )?;
+        self.outside.validate().map_err(|e| e.in_field("outside"))?;
+        self.cells.iter().enumerate().try_for_each(|(i, cell)| {
+            cell.validate()
+                .map_err(|e| e.in_field(&format!("cells[{i}]")))
+        })
+    }
+}
+
+impl SurfaceMap {
+    /// Covers `map` cell for cell, all of `surface`.
+    pub fn new(map: &OccupancyMap, surface: Surface) -> Self {
+        Self {
+            size: map.size,
+            resolution: map.resolution,
+            origin: map.origin,
+            cells: vec![surface; map.size.x * map.size.y],
+            outside: surface,
+        }
+    }
+
+    /// Index of the cell containing `loc`, if it lies on the grid.
+    fn index(&self, loc: glam::Vec2) -> Option<usize> {
+        let cells = (loc - self.origin) / self.resolution;
+        let half = self.size.as_vec2() / 2.;
+        let cell = glam::vec2(cells.x + half.x, half.y - cells.y)
+            .floor()
+            .as_i64vec2();
+
+        (cell.cmpge(glam::I64Vec2::ZERO).all() && cell.cmplt(self.size.as_i64vec2()).all())
+            .then(|| cell.x as usize + cell.y as usize * self.size.x)
+    }
+
+    /// The surface at `loc`.
+    pub fn at(&self, loc: glam::Vec2) -> Surface {
+        self.index(loc).map_or(self.outside, |i| self.cells[i])
+    }
+
+    /// Lays `surface` over every cell whose centre lies in `area`.
+    pub fn fill(&mut self, area: Box2D, surface: Surface) {
+        let half = self.size.as_vec2() / 2.;
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            let (col, row) = ((i % self.size.x) as f32, (i / self.size.x) as f32);
+            let center =
+                glam::vec2(col + 0.5 - half.x, half.y - row - 0.5) * self.resolution + self.origin;
+            if area.contains(center) {
+                *cell = surface;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        math::Box2D,
+        scene::{
+            OccupancyMap,
+            surface::{Surface, SurfaceMap},
+        },
+    };
+
+    /// The left half of a 10 by 10 metre map, where the surface is changed.
+    const PATCH: Box2D = Box2D {
+        min: glam::vec2(-5., 0.),
+        max: glam::vec2(0., 10.),
+    };
+
+    /// A map of 0.5 m cells with `surface` over [PATCH] and the default elsewhere.
+    fn patched(surface: Surface) -> (OccupancyMap, SurfaceMap) {
+        let map = OccupancyMap::from_pixels(glam::usizevec2(20, 20), vec![false; 400])
+            .unwrap()
+            .with_resolution(0.5, glam::vec2(0., 5.))
+            .unwrap();
+        let mut surfaces = SurfaceMap::new(&map, Surface::default());
+        surfaces.fill(PATCH, surface);
+
+        (map, surfaces)
+    }
+
+    #[test]
+    fn test_surface_lookup() {
+        let (_, surfaces) = patched(Surface::ICE);
+        assert_eq!(surfaces.at(glam::vec2(-0.1, 5.)), Surface::ICE);
+        assert_eq!(surfaces.at(glam::vec2(0.1, 5.)), Surface::default());
+        assert_eq!(surfaces.at(glam::vec2(-0.1, -5.)), Surface::default());
+    }
+
+    #[test]
+    fn test_surface_traction() {
+        // Flooring it on ice spins the wheels, while on tarmac the agent pulls away.
+        let (_, surfaces) = patched(Surface::ICE);
+        let accelerate = |position: glam::Vec2| {
+            let mut agent = Agent2D::default();
+            agent.state.position = position;
+            agent.state.torque = agent.config.torque_range.1;
+            agent.update(0.1, Some(&surfaces));
+            agent.state.velocity
+        };
+        let on_ice = accelerate(glam::vec2(-2., 5.));
+        assert!(
+            (on_ice - (0.1 - 0.01) * 9.81 * 0.1).abs() < 1e-4,
+            "{on_ice}"
+        );
+        assert!(accelerate(glam::vec2(2., 5.)) > on_ice);
+    }
+
+    #[test]
+    fn test_surface_rolling_resistance() {
+        // Gravel brings a coasting agent to a stop, and never sends it backwards.
+        let (_, surfaces) = patched(Surface::GRAVEL);
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(-2., 5.);
+        agent.state.velocity = 1.;
+        for _ in 0..20 {
+            agent.update(0.1, Some(&surfaces));
+        }
+        assert_eq!(agent.state.velocity, 0.);
+    }
+
+    #[test]
+    fn test_surface_grip() {
+        // On ice, a hard turn at speed is cut down to what the tyres can hold.
+        let (_, surfaces) = patched(Surface::ICE);
+        let turn = |surfaces: Option<&SurfaceMap>| {
+            let mut agent = Agent2D::default();
+            agent.state.position = glam::vec2(-2., 5.);
+            agent.state.velocity = 5.;
+            agent.state.beta = agent.config.beta_range.1;
+            agent.update(0.1, surfaces);
+            glam::Vec2::Y.angle_to(agent.state.heading).abs()
+        };
+        assert!((turn(Some(&surfaces)) - 0.1 * 9.81 * 0.1 / 5.).abs() < 1e-4);
+        assert!(turn(None) > turn(Some(&surfaces)));
+    }
+
+    #[test]
+    fn test_surface_scene() {
+        // The scene hands its surfaces to the agents it updates.
+        let (map, surfaces) = patched(Surface::ICE);
+        let mut scene = Scene2D::from_occupancy_map(map);
+        scene.surfaces = Some(std::sync::Arc::new(surfaces));
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(-2., 5.);
+        agent.state.velocity = 1.;
+        let id = scene.add_agent(agent);
+        scene.step();
+        assert!(scene.agents[&id].state.velocity < 1.);
+    }
+}
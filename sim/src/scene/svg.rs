@@ -0,0 +1,204 @@
+//! Walls read straight from an SVG drawing, such as a track drawn in Inkscape, keeping its precision rather than going
+//! through a raster image first. Lines, polylines, polygons, rects and paths all become walls, with curves flattened
+//! into short segments.
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::LineSegment,
+    scene::{occupancy_map::ObjectTag, vector_map::VectorMap},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgImport {
+    /// Metres per SVG user unit, after the document's `viewBox` is applied.
+    pub scale: f32,
+    /// Furthest a flattened curve may stray from the original, in metres.
+    pub tolerance: f32,
+    /// As for [VectorMap::thickness].
+    pub thickness: f32,
+}
+
+impl Default for SvgImport {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            tolerance: 0.01,
+            thickness: 0.,
+        }
+    }
+}
+
+impl Validate for SvgImport {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("scale", self.scale)?;
+        config::positive("tolerance", self.tolerance)?;
+        config::non_negative("thickness", self.thickness)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SvgError {
+    #[error("Failed to parse the SVG: {0}")]
+    Parse(#[from] usvg::Error),
+
+    #[error("Invalid SVG import: {0}")]
+    Config(#[from] ConfigError),
+}
+
+/// Appends the walls of every visible path under `group`, tagging each path as the next object after `paths`.
+fn collect_walls(
+    group: &usvg::Group,
+    to_world: &impl Fn(usvg::tiny_skia_path::Point) -> glam::Vec2,
+    tolerance: f32,
+    walls: &mut Vec<(LineSegment, ObjectTag)>,
+    paths: &mut u64,
+) {
+    for node in group.children() {
+        let path = match node {
+            usvg::Node::Group(group) => {
+                collect_walls(group, to_world, tolerance, walls, paths);
+                continue;
+            }
+            usvg::Node::Path(path) if path.is_visible() => path,
+            _ => continue,
+        };
+        let Some(data) = path.data().clone().transform(path.abs_transform()) else {
+            continue;
+        };
+
+        let tag = ObjectTag(*paths);
+        *paths += 1;
+        let (mut start, mut current) = (glam::Vec2::ZERO, glam::Vec2::ZERO);
+        let mut points = Vec::new();
+        for segment in data.segments() {
+            use usvg::tiny_skia_path::PathSegment;
+
+            points.clear();
+            match segment {
+                PathSegment::MoveTo(p) => {
+                    start = to_world(p);
+                    current = start;
+                    continue;
+                }
+                PathSegment::LineTo(p) => points.push(to_world(p)),
+                PathSegment::QuadTo(c, p) => {
+                    let (c, p) = (to_world(c), to_world(p));
+                    // Split into n chords, a quadratic strays at most |a - 2c + b| / 4n² from them, and a cubic
+                    // 3/4 of its larger such term over n².
+                    let n = ((current - 2. * c + p).length() / (4. * tolerance))
+                        .sqrt()
+                        .ceil()
+                        .max(1.);
+                    points.extend((1..=n as usize).map(|i| {
+                        let t = i as f32 / n;
+                        current * (1. - t).powi(2) + c * 2. * t * (1. - t) + p * t * t
+                    }));
+                }
+                PathSegment::CubicTo(c0, c1, p) => {
+                    let (c0, c1, p) = (to_world(c0), to_world(c1), to_world(p));
+                    let bend = (current - 2. * c0 + c1)
+                        .length()
+                        .max((c0 - 2. * c1 + p).length());
+                    let n = (0.75 * bend / tolerance).sqrt().ceil().max(1.);
+                    points.extend((1..=n as usize).map(|i| {
+                        let t = i as f32 / n;
+                        let s = 1. - t;
+                        current * s.powi(3)
+                            + c0 * 3. * s * s * t
+                            + c1 * 3. * s * t * t
+                            + p * t.powi(3)
+                    }));
+                }
+                PathSegment::Close => points.push(start),
+            }
+
+            for &next in &points {
+                if next != current {
+                    walls.push((LineSegment(current, next), tag));
+                }
+                current = next;
+            }
+        }
+    }
+}
+
+impl VectorMap {
+    /// Reads walls from the SVG document `data`, centred on the origin with y flipped to point up, as when the drawing
+    /// is rendered into a map image. Every path is an object of its own for [ObjectTag]s. Fills are ignored, so a
+    /// filled shape is only walled around its outline.
+    pub fn from_svg(data: &[u8], import: &SvgImport) -> Result<Self, SvgError> {
+        import.validate()?;
+
+        let tree = usvg::Tree::from_data(data, &usvg::Options::default())?;
+        let half = glam::vec2(tree.size().width(), tree.size().height()) / 2.;
+        let to_world =
+            |p: usvg::tiny_skia_path::Point| glam::vec2(p.x - half.x, half.y - p.y) * import.scale;
+
+        let mut walls = Vec::new();
+        collect_walls(tree.root(), &to_world, import.tolerance, &mut walls, &mut 0);
+
+        Ok(Self::with_tags(walls, import.thickness))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        occupancy_map::ObjectTag,
+        svg::{SvgError, SvgImport},
+        vector_map::VectorMap,
+    };
+
+    #[test]
+    fn test_svg_import() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50" viewBox="0 0 100 50">
+            <g stroke="black" fill="none">
+                <rect x="10" y="10" width="80" height="30"/>
+                <circle cx="50" cy="25" r="5"/>
+                <line x1="10" y1="45" x2="90" y2="45" visibility="hidden"/>
+                <g transform="translate(0 2)"><line x1="10" y1="45" x2="90" y2="45"/></g>
+            </g>
+        </svg>"#;
+        let import = SvgImport {
+            scale: 0.1,
+            ..Default::default()
+        };
+        let map = VectorMap::from_svg(svg, &import).unwrap();
+
+        let bounds = map.bounds().unwrap();
+        assert!((bounds.min - glam::vec2(-4., -2.2)).length() < 1e-4);
+        assert!((bounds.max - glam::vec2(4., 1.5)).length() < 1e-4);
+        let (t, tag) = map
+            .cast_rays_tagged(glam::vec2(0., 1.), glam::Vec2::X)
+            .unwrap();
+        assert!((t - 4.).abs() < 1e-4 && tag == ObjectTag(0));
+        let (t, tag) = map
+            .cast_rays_tagged(glam::vec2(0., -1.7), glam::Vec2::NEG_Y)
+            .unwrap();
+        assert!((t - 0.5).abs() < 1e-4 && tag == ObjectTag(2));
+
+        // The circle is flattened to within the tolerance all the way round.
+        for i in 0..36 {
+            let dir = glam::Vec2::from_angle(i as f32 * std::f32::consts::TAU / 36.);
+            let (t, tag) = map.cast_rays_tagged(glam::Vec2::ZERO, dir).unwrap();
+            assert_eq!(tag, ObjectTag(1));
+            assert!(
+                (0.5 - import.tolerance - 1e-4..=0.5 + 1e-4).contains(&t),
+                "{t}"
+            );
+        }
+
+        assert!(matches!(
+            VectorMap::from_svg(b"<svg", &import),
+            Err(SvgError::Parse(_))
+        ));
+        let import = SvgImport {
+            tolerance: 0.,
+            ..import
+        };
+        assert!(matches!(
+            VectorMap::from_svg(svg, &import),
+            Err(SvgError::Config(_))
+        ));
+    }
+}
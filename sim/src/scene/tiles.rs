@@ -0,0 +1,353 @@
+use std::{path::PathBuf, sync::Arc};
+
+use parking_lot::Mutex;
+use rustc_hash::FxHashMap;
+
+use crate::scene::occupancy_map::{BoundaryPolicy, OccupancyMap, OutOfBoundsAction};
+
+/// Index of a square tile. Tile `(i, j)` covers `[i, i + 1) * tile_size` by `[j, j + 1) * tile_size` in world units.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct TileCoord(pub i64, pub i64);
+
+/// Produces the occupancy of tiles on demand.
+///
+/// Pixels are row-major with the first row at the top (largest y) of the tile, matching
+/// [OccupancyMap::from_pixels].
+pub trait TileSource: std::fmt::Debug + Send + Sync {
+    /// Returns `tile_size * tile_size` pixels, or `None` if the tile is entirely free.
+    fn load(&self, coord: TileCoord, tile_size: usize) -> Option<Vec<bool>>;
+}
+
+/// Procedurally generated tiles.
+pub struct FnTileSource<F>(pub F);
+
+impl<F> std::fmt::Debug for FnTileSource<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FnTileSource")
+    }
+}
+
+impl<F> TileSource for FnTileSource<F>
+where
+    F: Fn(TileCoord, usize) -> Option<Vec<bool>> + Send + Sync,
+{
+    fn load(&self, coord: TileCoord, tile_size: usize) -> Option<Vec<bool>> {
+        (self.0)(coord, tile_size)
+    }
+}
+
+/// Tiles stored as `{i}_{j}.png` images in a directory. Missing files are free space.
+#[derive(Debug, Clone)]
+pub struct ImageDirTileSource {
+    pub directory: PathBuf,
+    pub threshold: u8,
+}
+
+impl TileSource for ImageDirTileSource {
+    fn load(&self, TileCoord(i, j): TileCoord, tile_size: usize) -> Option<Vec<bool>> {
+        let path = self.directory.join(format!("{i}_{j}.png"));
+        let image = image::open(&path).ok()?.to_luma8();
+
+        if image.width() as usize != tile_size || image.height() as usize != tile_size {
+            log::warn!(
+                "Tile {path:?} is {}x{}, expected {tile_size}x{tile_size}",
+                image.width(),
+                image.height()
+            );
+            return None;
+        }
+
+        // Invert because white is free space and black is occupied space.
+        Some(
+            image
+                .into_vec()
+                .into_iter()
+                .map(|p| p <= self.threshold)
+                .collect(),
+        )
+    }
+}
+
+/// One large map image, such as a whole warehouse, cut into tiles as they are needed. Pixels are kept one bit each, so
+/// a 10k by 10k image takes 12.5 MB rather than the gigabytes a single [OccupancyMap] of it would. The image is centred
+/// on the origin, to the nearest cell.
+#[derive(Debug, Clone)]
+pub struct ImageTileSource {
+    width: usize,
+    height: usize,
+    /// Row-major with the first row at the top, a set bit for each occupied pixel.
+    bits: Vec<u64>,
+    /// Whether everything beyond the image is a wall rather than free space.
+    pub outside_occupied: bool,
+}
+
+impl ImageTileSource {
+    /// Pixels no brighter than `threshold` are walls.
+    pub fn new(image: &image::GrayImage, threshold: u8) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mut bits = vec![0u64; (width * height).div_ceil(64)];
+        for (i, pixel) in image.pixels().enumerate() {
+            if pixel.0[0] <= threshold {
+                bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            bits,
+            outside_occupied: false,
+        }
+    }
+
+    /// Decodes the image at `path`, keeping only its packed pixels.
+    pub fn open(path: impl AsRef<std::path::Path>, threshold: u8) -> image::ImageResult<Self> {
+        Ok(Self::new(&image::open(path)?.to_luma8(), threshold))
+    }
+
+    /// Whether the cell with lower left corner `(x, y)` in the world is a wall.
+    fn occupied(&self, x: i64, y: i64) -> bool {
+        let col = x + (self.width / 2) as i64;
+        let row = (self.height - self.height / 2) as i64 - 1 - y;
+        if col < 0 || row < 0 || col >= self.width as i64 || row >= self.height as i64 {
+            return self.outside_occupied;
+        }
+
+        let i = row as usize * self.width + col as usize;
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+}
+
+impl TileSource for ImageTileSource {
+    fn load(&self, TileCoord(i, j): TileCoord, tile_size: usize) -> Option<Vec<bool>> {
+        let size = tile_size as i64;
+        let pixels = (0..tile_size * tile_size)
+            .map(|k| {
+                let (col, row) = ((k % tile_size) as i64, (k / tile_size) as i64);
+                self.occupied(i * size + col, (j + 1) * size - 1 - row)
+            })
+            .collect::<Vec<_>>();
+
+        pixels.contains(&true).then_some(pixels)
+    }
+}
+
+#[derive(Debug, Default)]
+struct TileCache {
+    tiles: FxHashMap<TileCoord, (Option<Arc<OccupancyMap>>, u64)>,
+    tick: u64,
+}
+
+/// A world made of tiles that are generated or loaded around the agents as needed, each with its own BVH. Only the
+/// `capacity` most recently used tiles are kept in memory.
+#[derive(Debug)]
+pub struct TiledWorld {
+    pub tile_size: usize,
+    pub capacity: usize,
+    /// Rays give up after crossing this many tiles without a hit.
+    pub max_ray_tiles: usize,
+    source: Box<dyn TileSource>,
+    cache: Mutex<TileCache>,
+}
+
+impl TiledWorld {
+    pub fn new(tile_size: usize, capacity: usize, source: impl TileSource + 'static) -> Self {
+        Self {
+            tile_size: tile_size.max(1),
+            capacity: capacity.max(1),
+            max_ray_tiles: 8,
+            source: Box::new(source),
+            cache: Mutex::new(TileCache::default()),
+        }
+    }
+
+    #[inline]
+    pub fn tile_of(&self, loc: glam::Vec2) -> TileCoord {
+        let t = (loc / self.tile_size as f32).floor();
+
+        TileCoord(t.x as i64, t.y as i64)
+    }
+
+    #[inline]
+    pub fn tile_center(&self, TileCoord(i, j): TileCoord) -> glam::Vec2 {
+        (glam::vec2(i as f32, j as f32) + 0.5) * self.tile_size as f32
+    }
+
+    pub fn loaded_tiles(&self) -> usize {
+        self.cache.lock().tiles.len()
+    }
+
+    /// Returns the tile, loading it if needed. `None` means the tile is free space.
+    pub fn tile(&self, coord: TileCoord) -> Option<Arc<OccupancyMap>> {
+        {
+            let mut cache = self.cache.lock();
+            cache.tick += 1;
+            let tick = cache.tick;
+
+            if let Some((tile, last_used)) = cache.tiles.get_mut(&coord) {
+                *last_used = tick;
+                return tile.clone();
+            }
+        }
+
+        // Load outside the lock so slow sources don't stall other sensors.
+        let tile = self.source.load(coord, self.tile_size).and_then(|pixels| {
+            let size = glam::USizeVec2::splat(self.tile_size);
+            let boundary = BoundaryPolicy::Open(OutOfBoundsAction::Report);
+
+            match OccupancyMap::from_pixels_with_boundary(size, pixels, boundary) {
+                Ok(map) => Some(Arc::new(map)),
+                Err(e) => {
+                    log::warn!("Failed to build tile {coord:?}: {e}");
+                    None
+                }
+            }
+        });
+
+        let mut cache = self.cache.lock();
+        let tick = cache.tick;
+        cache.tiles.insert(coord, (tile.clone(), tick));
+
+        while cache.tiles.len() > self.capacity {
+            let Some(oldest) = cache
+                .tiles
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&coord, _)| coord)
+            else {
+                break;
+            };
+            cache.tiles.remove(&oldest);
+        }
+
+        tile
+    }
+
+    /// Loads every tile within `radius` of each position.
+    pub fn ensure_loaded_around(
+        &self,
+        positions: impl IntoIterator<Item = glam::Vec2>,
+        radius: f32,
+    ) {
+        for position in positions {
+            let TileCoord(i0, j0) = self.tile_of(position - radius);
+            let TileCoord(i1, j1) = self.tile_of(position + radius);
+
+            for j in j0..=j1 {
+                for i in i0..=i1 {
+                    self.tile(TileCoord(i, j));
+                }
+            }
+        }
+    }
+
+    pub fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        let coord = self.tile_of(loc);
+
+        self.tile(coord)
+            .is_some_and(|tile| tile.is_occupied_vec2(loc - self.tile_center(coord)))
+    }
+
+    /// Marches the ray through the tile grid, casting against each tile's BVH in turn.
+    pub fn cast_rays(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
+        let size = self.tile_size as f32;
+        let TileCoord(mut i, mut j) = self.tile_of(pos);
+
+        let step = glam::i64vec2(
+            if dir.x < 0. { -1 } else { 1 },
+            if dir.y < 0. { -1 } else { 1 },
+        );
+        let axis_t = |p: f32, d: f32, cell: i64, s: i64| {
+            if d == 0. {
+                f32::INFINITY
+            } else {
+                ((cell + (s > 0) as i64) as f32 * size - p) / d
+            }
+        };
+        let mut t_max = glam::vec2(
+            axis_t(pos.x, dir.x, i, step.x),
+            axis_t(pos.y, dir.y, j, step.y),
+        );
+        let t_delta = (size / dir).abs();
+        let mut t_enter = 0f32;
+
+        for _ in 0..self.max_ray_tiles {
+            let coord = TileCoord(i, j);
+            if let Some(tile) = self.tile(coord) {
+                let local = pos - self.tile_center(coord);
+
+                // Tiles have no walls along their edges, so a ray entering straight into an occupied edge cell
+                // would otherwise pass through it.
+                if t_enter > 0. && tile.is_occupied_vec2(local + dir * (t_enter + 1e-3)) {
+                    return Some(t_enter);
+                }
+
+                if let Some(t) = tile.cast_rays(local, dir) {
+                    return Some(t);
+                }
+            }
+
+            if t_max.x < t_max.y {
+                i += step.x;
+                t_enter = t_max.x;
+                t_max.x += t_delta.x;
+            } else {
+                j += step.y;
+                t_enter = t_max.y;
+                t_max.y += t_delta.y;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scene::{
+        occupancy_map::OccupancyMap,
+        tiles::{FnTileSource, ImageTileSource, TileCoord, TiledWorld},
+    };
+
+    #[test]
+    fn test_cast_across_tiles() {
+        let world = TiledWorld::new(
+            4,
+            4,
+            FnTileSource(|TileCoord(i, _), size| (i == 1).then(|| vec![true; size * size])),
+        );
+
+        let t = world.cast_rays(glam::vec2(0.5, 2.), glam::Vec2::X).unwrap();
+        assert!((t - 3.5).abs() < 1e-4);
+        assert_eq!(world.cast_rays(glam::vec2(0.5, 2.), glam::Vec2::NEG_X), None);
+        assert!(world.is_occupied_vec2(glam::vec2(5., 1.)));
+        assert!(world.loaded_tiles() <= 4);
+    }
+
+    #[test]
+    fn test_image_tiles() {
+        // Tiles cut from an image give the same world as a map of the whole image.
+        let image = image::GrayImage::from_fn(10, 6, |x, y| {
+            image::Luma([if (x * 7 + y * 3) % 5 == 0 { 0 } else { 255 }])
+        });
+        let pixels = image.pixels().map(|p| p.0[0] <= 127).collect();
+        let map = OccupancyMap::from_pixels(glam::usizevec2(10, 6), pixels).unwrap();
+        let world = TiledWorld::new(4, 6, ImageTileSource::new(&image, 127));
+
+        for (x, y) in (-5..5).flat_map(|x| (-3..3).map(move |y| (x, y))) {
+            let center = glam::vec2(x as f32 + 0.5, y as f32 + 0.5);
+            assert_eq!(
+                world.is_occupied_vec2(center),
+                map.is_occupied_vec2(center),
+                "{center}"
+            );
+        }
+        assert!(!world.is_occupied_vec2(glam::vec2(-5.5, 0.5)));
+        assert!(world.tile(TileCoord(5, 5)).is_none());
+
+        let mut walled = ImageTileSource::new(&image, 127);
+        walled.outside_occupied = true;
+        let world = TiledWorld::new(4, 6, walled);
+        assert!(world.is_occupied_vec2(glam::vec2(-5.5, 0.5)));
+    }
+}
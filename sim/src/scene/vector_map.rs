@@ -0,0 +1,258 @@
+//! Maps made directly of wall segments, such as CAD floorplans, which keep their precision and stay small however large
+//! the site, where rasterizing them into 1 m cells would not.
+
+use crate::{
+    bvh::BVH,
+    math::{Box2D, LineSegment, OrientedBox2D, clip_line_segment_box, intersect_ray_line_segment},
+    scene::{
+        Scene2DError,
+        occupancy_map::{BoundaryPolicy, Contact, ObjectTag, OccupancyMap},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct VectorMap {
+    pub walls: Vec<LineSegment>,
+    /// The object each entry of `walls` belongs to.
+    pub wall_tags: Vec<ObjectTag>,
+    /// How thick walls are in metres, centred on their segments, for occupancy and collisions. Rays stop at the
+    /// segments themselves.
+    pub thickness: f32,
+    pub bvh: BVH,
+}
+
+impl VectorMap {
+    /// Each wall is an object of its own, tagged with its index.
+    pub fn new(walls: impl IntoIterator<Item = LineSegment>, thickness: f32) -> Self {
+        Self::with_tags(
+            walls
+                .into_iter()
+                .enumerate()
+                .map(|(i, wall)| (wall, ObjectTag(i as u64))),
+            thickness,
+        )
+    }
+
+    pub fn with_tags(
+        walls: impl IntoIterator<Item = (LineSegment, ObjectTag)>,
+        thickness: f32,
+    ) -> Self {
+        let (walls, wall_tags): (Vec<_>, Vec<_>) = walls.into_iter().unzip();
+        let bvh = BVH::new(walls.iter());
+
+        Self {
+            walls,
+            wall_tags,
+            thickness: thickness.max(0.),
+            bvh,
+        }
+    }
+
+    /// The walls along `points`, back round to the first when `closed`.
+    pub fn polyline(points: &[glam::Vec2], closed: bool) -> Vec<LineSegment> {
+        let mut walls = points
+            .windows(2)
+            .map(|w| LineSegment(w[0], w[1]))
+            .collect::<Vec<_>>();
+        if closed && let [first, .., last] = points {
+            walls.push(LineSegment(*last, *first));
+        }
+
+        walls
+    }
+
+    /// Around every wall, or `None` without any.
+    pub fn bounds(&self) -> Option<Box2D> {
+        self.walls
+            .iter()
+            .map(|w| w.get_box())
+            .reduce(|a, b| a.encase(&b))
+    }
+
+    /// Indices of the walls that, with their thickness, may meet `bx`.
+    pub fn walls_near(&self, bx: Box2D) -> Vec<usize> {
+        let margin = glam::Vec2::splat(self.thickness / 2.);
+        let bx = Box2D {
+            min: bx.min - margin,
+            max: bx.max + margin,
+        };
+        let mut found = self.bvh.query(bx);
+        found.retain(|&i| self.walls[i].get_box().intersects(&bx));
+
+        found
+    }
+
+    /// The point of wall `i`'s centre line nearest `loc`.
+    fn nearest_on_wall(&self, i: usize, loc: glam::Vec2) -> glam::Vec2 {
+        self.walls[i].closest_point(loc)
+    }
+
+    /// The solid part of wall `i`.
+    fn wall_box(&self, i: usize) -> OrientedBox2D {
+        let wall = self.walls[i];
+        let along = wall.1 - wall.0;
+
+        OrientedBox2D {
+            center: wall.midpoint(),
+            half_extent: glam::vec2(along.length(), self.thickness) / 2.,
+            heading: along.normalize_or(glam::Vec2::X),
+        }
+    }
+
+    pub fn is_occupied_vec2(&self, loc: glam::Vec2) -> bool {
+        self.walls_near(Box2D { min: loc, max: loc })
+            .into_iter()
+            .any(|i| self.nearest_on_wall(i, loc).distance(loc) <= self.thickness / 2.)
+    }
+
+    pub fn cast_rays(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<f32> {
+        self.cast_rays_tagged(pos, dir).map(|(t, _)| t)
+    }
+
+    pub fn cast_rays_tagged(&self, pos: glam::Vec2, dir: glam::Vec2) -> Option<(f32, ObjectTag)> {
+        self.bvh
+            .cast_ray(pos, dir, |i| {
+                intersect_ray_line_segment(pos, dir, &self.walls[i])
+            })
+            .map(|(t, i)| (t, self.wall_tags[i]))
+    }
+
+    /// [VectorMap::cast_rays_tagged] for many rays from `pos` at once, sharing the walk through the BVH.
+    pub fn cast_rays_batch_tagged(
+        &self,
+        pos: glam::Vec2,
+        dirs: &[glam::Vec2],
+    ) -> Vec<Option<(f32, ObjectTag)>> {
+        self.bvh
+            .cast_ray_batch(pos, dirs, |i, ray| {
+                intersect_ray_line_segment(pos, dirs[ray], &self.walls[i])
+            })
+            .into_iter()
+            .map(|hit| hit.map(|(t, i)| (t, self.wall_tags[i])))
+            .collect()
+    }
+
+    /// Every wall `footprint` overlaps, with normals pointing from the wall towards the footprint.
+    pub fn contacts(&self, footprint: &OrientedBox2D) -> Vec<Contact> {
+        self.walls_near(footprint.get_box())
+            .into_iter()
+            .filter_map(|i| {
+                let (normal, depth) = self.wall_box(i).penetration(footprint)?;
+                let wall = self.walls[i];
+                let local = LineSegment(
+                    footprint.to_local(wall.0 - footprint.center),
+                    footprint.to_local(wall.1 - footprint.center),
+                );
+                // Where the wall's centre line crosses the footprint, or its nearest point for a thick wall that only
+                // grazes it.
+                let point = match clip_line_segment_box(&local, footprint.local_box()) {
+                    Some((t0, t1)) => wall.0 + (wall.1 - wall.0) * (t0 + t1) / 2.,
+                    None => self.nearest_on_wall(i, footprint.center),
+                };
+
+                Some(Contact {
+                    point,
+                    normal,
+                    depth,
+                })
+            })
+            .collect()
+    }
+
+    /// The wall `footprint` presses furthest into.
+    pub fn contact(&self, footprint: &OrientedBox2D) -> Option<Contact> {
+        self.contacts(footprint)
+            .into_iter()
+            .max_by(|a, b| a.depth.total_cmp(&b.depth))
+    }
+
+    /// An occupancy grid `size` cells across, for planners and localizers that need one. Every cell a wall reaches
+    /// into is occupied.
+    pub fn rasterize(
+        &self,
+        size: [usize; 2],
+        boundary: BoundaryPolicy,
+    ) -> Result<OccupancyMap, Scene2DError> {
+        let half = glam::vec2(size[0] as f32, size[1] as f32) / 2.;
+        let margin = glam::Vec2::splat(self.thickness / 2.);
+        let pixels = (0..size[0] * size[1])
+            .map(|i| {
+                let (col, row) = ((i % size[0]) as f32, (i / size[0]) as f32);
+                let cell = Box2D {
+                    min: glam::vec2(col - half.x, half.y - row - 1.) - margin,
+                    max: glam::vec2(col + 1. - half.x, half.y - row) + margin,
+                };
+                self.walls_near(cell)
+                    .into_iter()
+                    .any(|w| clip_line_segment_box(&self.walls[w], cell).is_some())
+            })
+            .collect();
+
+        OccupancyMap::from_pixels_with_boundary(glam::USizeVec2::from(size), pixels, boundary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Lidar2D, Scene2D,
+        math::LineSegment,
+        scene::{BoundaryPolicy, HitTag, occupancy_map::ObjectTag, vector_map::VectorMap},
+        sensors::{Sensor2D, lidar::Lidar2DSensed},
+        sim_config::{CollisionMode, SimConfig},
+    };
+
+    #[test]
+    fn test_vector_map() {
+        // A closed room with a short wall across the middle.
+        let corners = [(4., 4.), (-4., 4.), (-4., -4.), (4., -4.)].map(|(x, y)| glam::vec2(x, y));
+        let mut walls = VectorMap::polyline(&corners, true);
+        walls.push(LineSegment(glam::vec2(-1., 1.), glam::vec2(1., 1.)));
+        let map = VectorMap::new(walls, 0.2);
+
+        assert_eq!(
+            map.cast_rays_tagged(glam::Vec2::ZERO, glam::Vec2::Y),
+            Some((1., ObjectTag(4)))
+        );
+        assert_eq!(map.cast_rays(glam::Vec2::ZERO, glam::Vec2::X), Some(4.));
+        assert!(map.is_occupied_vec2(glam::vec2(0.5, 1.05)));
+        assert!(!map.is_occupied_vec2(glam::vec2(0.5, 1.2)));
+
+        let grid = map.rasterize([10, 10], BoundaryPolicy::Solid).unwrap();
+        assert!(grid.is_occupied_vec2(glam::vec2(0.5, 1.5)));
+        assert!(grid.is_occupied_vec2(glam::vec2(0.5, 0.5)));
+        assert!(!grid.is_occupied_vec2(glam::vec2(0.5, -0.5)));
+        assert!(grid.is_occupied_vec2(glam::vec2(-4.2, 0.)));
+
+        let config = SimConfig {
+            seed: Some(5),
+            collision: CollisionMode::Stop,
+            ..Default::default()
+        };
+        let mut scene = Scene2D::from_vector_map(map, config).unwrap();
+        let state = scene.state();
+        assert_eq!(
+            state.cast_rays(glam::vec2(0., -2.), glam::Vec2::Y),
+            Some((3., HitTag::Map(ObjectTag(4))))
+        );
+
+        let mut agent = Agent2D::default();
+        agent.sensors.insert(Lidar2D::TOPIC, Lidar2D::regular(36));
+        agent.state.velocity = 1.;
+        let id = scene.add_agent(agent);
+        for _ in 0..100 {
+            scene.step();
+        }
+
+        let events = scene.drain_collision_events();
+        assert!(!events.is_empty());
+        assert!((events[0].normal - glam::Vec2::NEG_Y).length() < 1e-3);
+        assert!(scene.agents[&id].state.position.y < 1.);
+        assert!(scene.out_of_bounds.is_empty());
+        let scan = scene
+            .scene_loop
+            .query_topic_as::<Lidar2DSensed>(id, Lidar2D::TOPIC)
+            .unwrap();
+        assert_eq!(scan.state.points.len(), 36);
+    }
+}
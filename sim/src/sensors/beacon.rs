@@ -0,0 +1,178 @@
+use rand_distr::{Distribution, Exp, Normal};
+use smallvec::{SmallVec, smallvec};
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    rng,
+    scene::{BeaconId, Scene2DState},
+    sensors::{Sensor2D, TimeStamped},
+};
+
+/// Time-of-flight ranging to the scene's [beacons](crate::Scene2D::add_beacon), like UWB anchors: a range to each
+/// beacon in reach but no bearing.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BeaconRanger2D {
+    /// In metres.
+    pub max_range: f32,
+    /// Standard deviation of the range in metres.
+    pub noise: f32,
+    /// Mean extra range in metres when a wall blocks the line of sight, as the signal then arrives late through or
+    /// around it. Exponentially distributed, so ranges through walls are always too long. `None` to drop beacons
+    /// behind walls instead.
+    pub nlos_bias: Option<f32>,
+}
+
+impl Default for BeaconRanger2D {
+    fn default() -> Self {
+        Self {
+            max_range: 30.,
+            noise: 0.1,
+            nlos_bias: Some(0.5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeaconRange {
+    pub id: BeaconId,
+    pub range: f32,
+    /// Whether a wall blocked the line of sight. Ground truth for evaluating estimators; a real sensor can only guess.
+    pub occluded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeaconSensed(pub Vec<BeaconRange>);
+
+impl Validate for BeaconRanger2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::non_negative("max_range", self.max_range)?;
+        config::finite("noise", self.noise)?;
+        config::non_negative("noise", self.noise)?;
+        if let Some(bias) = self.nlos_bias {
+            config::finite("nlos_bias", bias)?;
+            config::positive("nlos_bias", bias)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sensor2D for BeaconRanger2D {
+    type SensorType = BeaconSensed;
+
+    const TOPIC: &'static str = "beacons";
+
+    fn sigma(&self) -> SmallVec<[f32; 2]> {
+        smallvec![self.noise]
+    }
+
+    fn sense(
+        &mut self,
+        _agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        // Beacons mounted on a wall are not blocked by the wall they sit on.
+        const OCCLUSION_TOLERANCE: f32 = 1e-2;
+
+        let mut rng = rng::rng();
+        let noise = Normal::new(0., self.noise).ok();
+        let delay = self.nlos_bias.and_then(|bias| Exp::new(bias.recip()).ok());
+
+        let readings = scene
+            .beacons
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &beacon)| {
+                let disp = beacon - agent_state.position;
+                let range = disp.length();
+                if range > self.max_range {
+                    return None;
+                }
+
+                let occluded = range > OCCLUSION_TOLERANCE
+                    && scene
+                        .cast_rays_map(agent_state.position, disp / range)
+                        .is_some_and(|(hit, _)| hit < range - OCCLUSION_TOLERANCE);
+                let extra = match (occluded, &delay) {
+                    (false, _) => 0.,
+                    (true, Some(delay)) => delay.sample(&mut rng),
+                    (true, None) => return None,
+                };
+
+                let error = noise.map_or(0., |n| n.sample(&mut rng));
+                Some(BeaconRange {
+                    id: BeaconId(i),
+                    range: (range + extra + error).max(0.),
+                    occluded,
+                })
+            })
+            .collect();
+
+        Some(TimeStamped {
+            time: scene.time,
+            state: BeaconSensed(readings),
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D, rng,
+        scene::BeaconId,
+        sensors::{BeaconRanger2D, Sensor2D},
+    };
+
+    #[test]
+    fn test_beacon_ranging() {
+        // A wall down the middle of the map, from x = -1 to 1.
+        let mut pixels = [255; 64];
+        for row in 0..8 {
+            pixels[row * 8 + 3] = 0;
+            pixels[row * 8 + 4] = 0;
+        }
+        let mut scene = Scene2D::from_pixels([8, 8], &pixels).unwrap();
+        let near = scene.add_beacon(glam::vec2(-2.5, 3.));
+        let behind = scene.add_beacon(glam::vec2(2.5, -3.));
+        scene.add_beacon(glam::vec2(-2.5, -50.));
+
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(-2.5, -3.);
+        let mut ranger = BeaconRanger2D {
+            noise: 0.,
+            ..Default::default()
+        };
+
+        let sense = |ranger: &mut BeaconRanger2D| {
+            rng::with_seed(Some(1), || {
+                ranger
+                    .sense(agent.config, agent.state, scene.state())
+                    .unwrap()
+                    .state
+                    .0
+            })
+        };
+
+        // The far beacon is out of range and the one behind the wall reads long.
+        let readings = sense(&mut ranger);
+        assert_eq!(
+            readings.iter().map(|r| r.id).collect::<Vec<_>>(),
+            [near, behind]
+        );
+        assert_eq!(readings[0].range, 6.);
+        assert!(!readings[0].occluded);
+        assert!(
+            readings[1].occluded && readings[1].range > 5.,
+            "{readings:?}"
+        );
+
+        ranger.nlos_bias = None;
+        let readings = sense(&mut ranger);
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].id, BeaconId(0));
+    }
+}
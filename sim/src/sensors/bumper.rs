@@ -0,0 +1,174 @@
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{ConfigError, Validate},
+    math::OrientedBox2D,
+    scene::Scene2DState,
+    sensors::{Sensor2D, TimeStamped},
+};
+
+/// Contact sensor covering the agent's footprint rectangle.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bumper2D;
+
+/// Which sides of the footprint are touching something. Front is along the heading, left is counter-clockwise of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BumperSensed {
+    pub front: bool,
+    pub back: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Validate for Bumper2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        Ok(())
+    }
+}
+
+impl BumperSensed {
+    pub fn contact(&self) -> bool {
+        self.front || self.back || self.left || self.right
+    }
+
+    fn touch(&mut self, footprint: &OrientedBox2D, point: glam::Vec2) {
+        let local = footprint.to_local(point - footprint.center) / footprint.half_extent;
+
+        if local.x.abs() >= local.y.abs() {
+            if local.x >= 0. {
+                self.front = true;
+            } else {
+                self.back = true;
+            }
+        } else if local.y >= 0. {
+            self.left = true;
+        } else {
+            self.right = true;
+        }
+    }
+}
+
+impl Sensor2D for Bumper2D {
+    type SensorType = BumperSensed;
+
+    const TOPIC: &'static str = "bumper";
+
+    fn feature_len(&self) -> usize {
+        4
+    }
+
+    fn features(&self, measurement: &Self::SensorType, out: &mut Vec<f32>) {
+        let BumperSensed {
+            front,
+            back,
+            left,
+            right,
+        } = *measurement;
+        out.extend([front, back, left, right].map(|b| b as u8 as f32));
+    }
+
+    fn sense(
+        &mut self,
+        agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        let footprint = agent_config.footprint(&agent_state);
+        let mut sensed = BumperSensed::default();
+
+        for cell in scene.occupancy_map.overlapping_cells(&footprint) {
+            sensed.touch(&footprint, cell.centroid());
+        }
+        if let Some(vector_map) = &scene.vector_map {
+            for contact in vector_map.contacts(&footprint) {
+                sensed.touch(&footprint, contact.point);
+            }
+        }
+
+        let outline = agent_config.footprint_polygon(&agent_state);
+        for (_, _, other) in scene.agents_seen_by(scene.viewer) {
+            if outline.intersects(other) {
+                sensed.touch(&footprint, other.centroid());
+            }
+        }
+
+        Some(TimeStamped {
+            time: scene.time,
+            state: sensed,
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        sensors::{Sensor2D, bumper::Bumper2D, bumper::BumperSensed},
+    };
+
+    /// Only `side` of the bumper is touching.
+    fn only(side: fn(&mut BumperSensed) -> &mut bool) -> BumperSensed {
+        let mut sensed = BumperSensed::default();
+        *side(&mut sensed) = true;
+        sensed
+    }
+
+    #[test]
+    fn test_bumper_walls() {
+        let mut pixels = [255; 64];
+        pixels[4 * 8 + 4] = 0;
+        let scene = Scene2D::from_pixels([8, 8], &pixels).unwrap();
+        let wall = scene
+            .occupancy_map
+            .get_box(glam::usizevec2(4, 4))
+            .centroid();
+
+        // Facing along x, with the wall cell just overlapping one side of the 0.5 × 0.25 footprint.
+        let sense = |scene: &Scene2D, position: glam::Vec2| {
+            let mut agent = Agent2D::default();
+            agent.state.position = position;
+            agent.state.heading = glam::Vec2::X;
+            Bumper2D
+                .sense(agent.config, agent.state, scene.state())
+                .unwrap()
+                .state
+        };
+        assert_eq!(
+            sense(&scene, wall - glam::vec2(0.7, 0.)),
+            only(|s| &mut s.front)
+        );
+        assert_eq!(
+            sense(&scene, wall + glam::vec2(0.7, 0.)),
+            only(|s| &mut s.back)
+        );
+        assert_eq!(
+            sense(&scene, wall - glam::vec2(0., 0.575)),
+            only(|s| &mut s.left)
+        );
+        assert_eq!(
+            sense(&scene, wall + glam::vec2(0., 0.575)),
+            only(|s| &mut s.right)
+        );
+        assert!(!sense(&scene, wall - glam::vec2(1., 1.)).contact());
+    }
+
+    #[test]
+    fn test_bumper_agents() {
+        // Another agent pushed so far in that it covers this one's centre still touches.
+        let mut scene = Scene2D::from_pixels([8, 8], &[255; 64]).unwrap();
+        let mut me = Agent2D::default();
+        me.state.position = glam::vec2(-2.5, -2.5);
+        me.state.heading = glam::Vec2::X;
+        let mut other = me.clone();
+        other.state.position.x += 0.2;
+        let (config, state) = (me.config, me.state);
+        let me = scene.add_agent(me);
+        scene.add_agent(other);
+        let sensed = Bumper2D
+            .sense(config, state, scene.state().viewed_by(me))
+            .unwrap()
+            .state;
+        assert_eq!(sensed, only(|s| &mut s.front));
+    }
+}
@@ -0,0 +1,259 @@
+use rand_distr::{Distribution, Normal};
+use smallvec::{SmallVec, smallvec};
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    math::Box2D,
+    rng,
+    scene::{Scene2DState, SceneTime},
+    sensors::{Sensor2D, TimeStamped},
+};
+
+/// A region where the magnetic field is distorted, e.g. near steel structures or motors.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MagneticDisturbance {
+    pub region: Box2D,
+    /// Peak heading error in radians.
+    pub amplitude: f32,
+    /// Period of the oscillation in seconds. Zero gives a constant offset of `amplitude`.
+    pub period: f32,
+}
+
+impl MagneticDisturbance {
+    fn offset(&self, position: glam::Vec2, time: f32) -> f32 {
+        if !self.region.contains(position) {
+            0.
+        } else if self.period > 0. {
+            self.amplitude * (std::f32::consts::TAU * time / self.period).sin()
+        } else {
+            self.amplitude
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compass2D {
+    /// Constant offset between magnetic and true north in radians.
+    pub declination: f32,
+    /// Constant offset of this particular sensor in radians.
+    pub bias: f32,
+    /// Standard deviation of the white noise on each reading in radians.
+    pub noise: f32,
+    /// How fast the bias drifts, as the standard deviation of a random walk in radians per √s.
+    pub bias_walk: f32,
+    pub disturbances: Vec<MagneticDisturbance>,
+    /// Bias accumulated by the random walk so far, and when it was last advanced.
+    drift: (f32, Option<SceneTime>),
+}
+
+impl Compass2D {
+    /// Bias currently added to readings, including drift.
+    pub fn current_bias(&self) -> f32 {
+        self.bias + self.drift.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompassSensed {
+    /// Measured heading in radians, counter-clockwise from the x-axis and wrapped to `[-PI, PI]`.
+    pub heading: f32,
+}
+
+impl Validate for MagneticDisturbance {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::ordered("region.x", (self.region.min.x, self.region.max.x))?;
+        config::ordered("region.y", (self.region.min.y, self.region.max.y))?;
+        config::finite("amplitude", self.amplitude)?;
+        config::finite("period", self.period)?;
+        config::non_negative("period", self.period)
+    }
+}
+
+impl Validate for Compass2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("declination", self.declination)?;
+        config::finite("bias", self.bias)?;
+        config::finite("noise", self.noise)?;
+        config::non_negative("noise", self.noise)?;
+        config::finite("bias_walk", self.bias_walk)?;
+        config::non_negative("bias_walk", self.bias_walk)?;
+
+        self.disturbances.iter().enumerate().try_for_each(|(i, d)| {
+            d.validate()
+                .map_err(|e| e.in_field(&format!("disturbances[{i}]")))
+        })
+    }
+}
+
+impl Sensor2D for Compass2D {
+    type SensorType = CompassSensed;
+
+    const TOPIC: &'static str = "compass";
+
+    fn sigma(&self) -> SmallVec<[f32; 2]> {
+        smallvec![self.noise]
+    }
+
+    fn feature_len(&self) -> usize {
+        2
+    }
+
+    /// `(cos, sin)` of the heading, which unlike the angle is continuous.
+    fn features(&self, measurement: &Self::SensorType, out: &mut Vec<f32>) {
+        let (sin, cos) = measurement.heading.sin_cos();
+        out.extend([cos, sin]);
+    }
+
+    fn sense(
+        &mut self,
+        _agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        let sample = |std_dev: f32| {
+            Normal::new(0., std_dev)
+                .map(|n| n.sample(&mut rng::rng()))
+                .unwrap_or(0.)
+        };
+        let noise = sample(self.noise);
+
+        // Time running backwards, e.g. the scene clock set back without restoring a snapshot, doesn't undo the drift.
+        let (drift, last_time) = &mut self.drift;
+        let dt = last_time.map_or(0., |t| (scene.time.0 - t.0).max(0.));
+        *drift += sample(self.bias_walk * dt.sqrt());
+        *last_time = Some(scene.time);
+
+        let disturbance: f32 = self
+            .disturbances
+            .iter()
+            .map(|d| d.offset(agent_state.position, scene.time.0))
+            .sum();
+
+        let heading = agent_state.heading.to_angle()
+            + self.declination
+            + self.current_bias()
+            + disturbance
+            + noise;
+
+        Some(TimeStamped {
+            time: scene.time,
+            state: CompassSensed {
+                heading: glam::Vec2::from_angle(heading).to_angle(),
+            },
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use crate::{
+        Agent2D,
+        math::Box2D,
+        scene::SceneTime,
+        sensors::{Sensor2D, compass::Compass2D, compass::MagneticDisturbance},
+        testing,
+    };
+
+    /// Heading read by `compass` for an agent at `position` facing `heading` in an open room at `time`.
+    fn sense(compass: &mut Compass2D, position: glam::Vec2, heading: f32, time: f32) -> f32 {
+        let mut agent = Agent2D::default();
+        agent.state.position = position;
+        agent.state.heading = glam::Vec2::from_angle(heading);
+        let mut state = testing::open_room().state();
+        state.time = SceneTime(time);
+        compass
+            .sense(agent.config, agent.state, state)
+            .unwrap()
+            .state
+            .heading
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    /// Away from any [disturbance](disturbed).
+    const OUTSIDE: glam::Vec2 = glam::vec2(-2., -2.);
+
+    /// Offset by 0.3 in all, and disturbed by a constant 0.05 over (0, 0) to (1, 1) and by up to 0.4 with a period of
+    /// 2 seconds over (2, 0) to (3, 1).
+    fn disturbed() -> Compass2D {
+        Compass2D {
+            declination: 0.1,
+            bias: 0.2,
+            disturbances: vec![
+                MagneticDisturbance {
+                    region: Box2D {
+                        min: glam::vec2(0., 0.),
+                        max: glam::vec2(1., 1.),
+                    },
+                    amplitude: 0.05,
+                    period: 0.,
+                },
+                MagneticDisturbance {
+                    region: Box2D {
+                        min: glam::vec2(2., 0.),
+                        max: glam::vec2(3., 1.),
+                    },
+                    amplitude: 0.4,
+                    period: 2.,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compass_offsets() {
+        assert!(close(sense(&mut disturbed(), OUTSIDE, 0.3, 0.), 0.6));
+    }
+
+    #[test]
+    fn test_compass_disturbances() {
+        // A constant disturbance, then one oscillating at its peak a quarter period in.
+        let mut compass = disturbed();
+        assert!(close(
+            sense(&mut compass, glam::vec2(0.5, 0.5), 0.3, 0.),
+            0.65
+        ));
+        assert!(close(
+            sense(&mut compass, glam::vec2(2.5, 0.5), 0.3, 0.5),
+            1.
+        ));
+        assert!(close(
+            sense(&mut compass, glam::vec2(2.5, 0.5), 0.3, 1.5),
+            0.2
+        ));
+    }
+
+    #[test]
+    fn test_compass_wrap() {
+        // Past π wraps round to the negative side.
+        let mut compass = disturbed();
+        assert!(close(sense(&mut compass, OUTSIDE, 3., 0.), 3.3 - 2. * PI));
+        assert!(close(sense(&mut compass, OUTSIDE, -3., 0.), -2.7));
+    }
+
+    #[test]
+    fn test_compass_drift() {
+        // Drift only moves on with time, and not back when time runs backwards.
+        let mut compass = Compass2D {
+            bias_walk: 0.1,
+            ..Default::default()
+        };
+        sense(&mut compass, OUTSIDE, 0., 0.);
+        sense(&mut compass, OUTSIDE, 0., 10.);
+        let drifted = compass.current_bias();
+        assert_ne!(drifted, 0.);
+        sense(&mut compass, OUTSIDE, 0., 5.);
+        assert_eq!(compass.current_bias(), drifted);
+        sense(&mut compass, OUTSIDE, 0., 5.);
+        assert_eq!(compass.current_bias(), drifted);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    scene::{LandmarkId, Scene2DState},
+    sensors::{Sensor2D, TimeStamped},
+};
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LandmarkSensor2D {
+    /// Full field of view in radians, centred on the agent heading.
+    pub fov: f32,
+    /// In metres.
+    pub max_range: f32,
+}
+
+impl Default for LandmarkSensor2D {
+    fn default() -> Self {
+        Self {
+            fov: std::f32::consts::TAU,
+            max_range: f32::INFINITY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeBearing {
+    pub id: LandmarkId,
+    pub range: f32,
+    /// Angle from the agent heading to the landmark, counter-clockwise in radians.
+    pub bearing: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LandmarkSensed(pub Vec<RangeBearing>);
+
+impl Validate for LandmarkSensor2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::within(
+            "fov",
+            self.fov,
+            self.fov > 0. && self.fov <= std::f32::consts::TAU,
+            "(0, 2π]",
+        )?;
+        config::non_negative("max_range", self.max_range)
+    }
+}
+
+impl Sensor2D for LandmarkSensor2D {
+    type SensorType = LandmarkSensed;
+
+    const TOPIC: &'static str = "landmarks";
+
+    fn sense(
+        &mut self,
+        _agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        // Landmarks sitting on a wall should not be occluded by the wall they sit on.
+        const OCCLUSION_TOLERANCE: f32 = 1e-2;
+
+        let readings = scene
+            .landmarks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &landmark)| {
+                let disp = landmark - agent_state.position;
+                let range = disp.length();
+
+                if range > self.max_range || range < f32::EPSILON {
+                    return None;
+                }
+
+                let bearing = agent_state.heading.angle_to(disp);
+                if bearing.abs() > self.fov / 2. {
+                    return None;
+                }
+
+                let dir = disp / range;
+                if let Some((hit, _)) = scene.cast_rays_map(agent_state.position, dir)
+                    && hit < range - OCCLUSION_TOLERANCE
+                {
+                    return None;
+                }
+
+                Some(RangeBearing {
+                    id: LandmarkId(i),
+                    range,
+                    bearing,
+                })
+            })
+            .collect();
+
+        Some(TimeStamped {
+            time: scene.time,
+            state: LandmarkSensed(readings),
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_PI_4;
+
+    use crate::{
+        Agent2D, Scene2D,
+        scene::LandmarkId,
+        sensors::{
+            Sensor2D,
+            landmark::{LandmarkSensor2D, RangeBearing},
+        },
+    };
+
+    /// Seen from the middle of a 10 by 10 map facing +x, through ±1 radian out to 4 metres, with a wall cell spanning
+    /// (1, -2) to (2, -1).
+    fn sense(landmarks: &[glam::Vec2]) -> Vec<RangeBearing> {
+        let mut pixels = [255; 100];
+        pixels[6 * 10 + 6] = 0;
+        let mut scene = Scene2D::from_pixels([10, 10], &pixels).unwrap();
+        for &landmark in landmarks {
+            scene.add_landmark(landmark);
+        }
+
+        let mut agent = Agent2D::default();
+        agent.state.heading = glam::Vec2::X;
+        let mut sensor = LandmarkSensor2D {
+            fov: 2.,
+            max_range: 4.,
+        };
+        sensor
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap()
+            .state
+            .0
+    }
+
+    #[test]
+    fn test_landmark_range_bearing() {
+        let readings = sense(&[glam::vec2(3., 0.), glam::vec2(2., 2.)]);
+
+        assert_eq!(readings.len(), 2);
+        assert_eq!(
+            readings[0],
+            RangeBearing {
+                id: LandmarkId(0),
+                range: 3.,
+                bearing: 0.,
+            }
+        );
+        assert_eq!(readings[1].id, LandmarkId(1));
+        assert!((readings[1].range - 8f32.sqrt()).abs() < 1e-6);
+        assert!((readings[1].bearing - FRAC_PI_4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_landmark_fov_and_range() {
+        // Behind, just outside the field of view, and in view but too far away.
+        let outside = [
+            glam::vec2(-2., 0.),
+            glam::Vec2::from_angle(1.1) * 2.,
+            glam::vec2(3.5, -2.5),
+        ];
+        assert!(sense(&outside).is_empty());
+
+        let inside = sense(&[glam::Vec2::from_angle(0.9) * 2., glam::vec2(3.9, 0.)]);
+        assert_eq!(inside.iter().map(|r| r.id.0).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn test_landmark_occlusion() {
+        // The first is hidden behind the wall cell, the second sits on its face.
+        let readings = sense(&[glam::vec2(2.5, -2.5), glam::vec2(1., -1.)]);
+
+        assert_eq!(
+            readings.iter().map(|r| r.id).collect::<Vec<_>>(),
+            [LandmarkId(1)]
+        );
+    }
+}
@@ -1,14 +1,34 @@
 use crate::{
     agent::{Agent2DConfig, Agent2DState},
-    scene::Scene2DState,
+    config::{self, ConfigError, Validate},
+    math::Pose2D,
+    rng,
+    scene::{HitTag, Scene2DState, SceneTime},
     sensors::{Sensor2D, TimeStamped},
 };
+use rand_distr::{Distribution, Normal};
 use rayon::prelude::*;
 use zerocopy::{ByteEq, ByteHash, Immutable, IntoBytes};
 
+/// Rays cast together through the map by one thread.
+const BATCH_RAYS: usize = 256;
+
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Lidar2D {
+    /// Ray directions relative to the agent heading.
     pub directions: Vec<glam::Vec2>,
+    /// Whether to report what every ray hit alongside the points.
+    pub semantic: bool,
+    /// Time taken to sweep through `directions`, in seconds. Non-zero values cast each ray from the pose the agent
+    /// had when that ray fired, interpolated between this scan and the previous one, which distorts the scan the way
+    /// a real spinning lidar's is.
+    pub scan_duration: f32,
+    /// Standard deviation of the error on each reported [time offset](Lidar2DSensed::time_offsets), in seconds. The
+    /// rays still fire on schedule.
+    pub ray_jitter: f32,
+    /// Scene time and pose of the previous scan.
+    last: Option<(SceneTime, Pose2D)>,
 }
 
 impl Lidar2D {
@@ -18,7 +38,10 @@ impl Lidar2D {
             directions.push(glam::Vec2::from_angle(angle));
         }
 
-        Lidar2D { directions }
+        Lidar2D {
+            directions,
+            ..Default::default()
+        }
     }
 
     pub fn set_regular(&mut self, n: usize) {
@@ -33,6 +56,20 @@ impl Lidar2D {
     }
 }
 
+impl Validate for Lidar2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::finite("scan_duration", self.scan_duration)?;
+        config::non_negative("scan_duration", self.scan_duration)?;
+        config::finite("ray_jitter", self.ray_jitter)?;
+        config::non_negative("ray_jitter", self.ray_jitter)?;
+
+        self.directions
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, &d)| config::direction(&format!("directions[{i}]"), d))
+    }
+}
+
 // #[inline]
 // pub fn intersect_ray_line_segment(
 //     pos: glam::Vec2,
@@ -79,11 +116,36 @@ impl std::ops::DerefMut for HashVec2 {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Lidar2DSensed(pub Vec<glam::Vec2>);
+pub struct Lidar2DSensed {
+    /// Range along each of the lidar's directions, infinite where the ray hit nothing.
+    pub ranges: Vec<f32>,
+    /// World-frame hit points of the rays that hit something.
+    pub points: Vec<glam::Vec2>,
+    /// One tag per point, present when the lidar is [semantic](Lidar2D::semantic).
+    pub tags: Option<Vec<HitTag>>,
+    /// When each ray fired relative to the measurement time, in seconds, one per direction. Present when the lidar
+    /// has a [scan duration](Lidar2D::scan_duration) or [ray jitter](Lidar2D::ray_jitter).
+    pub time_offsets: Option<Vec<f32>>,
+}
 
 impl Sensor2D for Lidar2D {
     type SensorType = Lidar2DSensed;
 
+    const TOPIC: &'static str = "lidar";
+
+    fn cost(&self) -> f32 {
+        self.directions.len() as f32
+    }
+
+    fn feature_len(&self) -> usize {
+        self.directions.len()
+    }
+
+    /// Inverse ranges, so rays that hit nothing read as zero.
+    fn features(&self, measurement: &Self::SensorType, out: &mut Vec<f32>) {
+        out.extend(measurement.ranges.iter().map(|r| r.recip()));
+    }
+
     // fn sense(&mut self, agent: &Agent2D, scene: &Scene2D) -> Self::SensorType {
     //     log::info!("Sensing surroundings with Lidar");
     //     let start = std::time::Instant::now();
@@ -208,7 +270,7 @@ impl Sensor2D for Lidar2D {
     // }
 
     fn sense(
-        &self,
+        &mut self,
         _agent_config: Agent2DConfig,
         agent_state: Agent2DState,
         scene: Scene2DState,
@@ -216,31 +278,88 @@ impl Sensor2D for Lidar2D {
         log::info!("Sensing surroundings with Lidar");
         let start = std::time::Instant::now();
 
-        let loc = scene.occupancy_map.translate(agent_state.position);
-
-        if loc.cmplt(glam::I64Vec2::ZERO).any()
-            || scene.occupancy_map.is_occupied(loc.as_usizevec2())
+        if scene
+            .obstacle_source()
+            .is_occupied_vec2(agent_state.position)
         {
             return None;
         }
 
-        let results: Vec<glam::Vec2> = self
-            .directions
-            .par_iter()
-            .flat_map(|&dir| {
-                let world_dir = agent_state.heading.rotate(dir);
-                scene
-                    .occupancy_map
-                    .cast_rays(agent_state.position, world_dir)
-                    .map(|i| world_dir * i + agent_state.position)
-            })
+        // The last ray fires at the measurement time, the first `scan_duration` before it.
+        let n = self.directions.len();
+        let time_offsets = (self.scan_duration > 0. || self.ray_jitter > 0.).then(|| {
+            (0..n)
+                .map(|i| -self.scan_duration * (n - 1 - i) as f32 / (n - 1).max(1) as f32)
+                .collect::<Vec<_>>()
+        });
+
+        let pose = agent_state.pose();
+        let previous = self.last.replace((scene.time, pose));
+        let pose_at = |offset: f32| match previous {
+            Some((time, last)) if scene.time.0 > time.0 => {
+                last.interpolate(&pose, (1. + offset / (scene.time.0 - time.0)).max(0.))
+            }
+            _ => pose,
+        };
+
+        let hits: Vec<_> = match &time_offsets {
+            // Every ray leaves from the same pose, so neighbouring rays can share the walk through the map.
+            None => self
+                .directions
+                .par_chunks(BATCH_RAYS)
+                .flat_map_iter(|dirs| {
+                    let world_dirs = dirs
+                        .iter()
+                        .map(|&dir| pose.heading.rotate(dir))
+                        .collect::<Vec<_>>();
+                    scene
+                        .cast_rays_batch(pose.position, &world_dirs)
+                        .into_iter()
+                        .zip(world_dirs)
+                        .map(|(hit, dir)| hit.map(|(i, tag)| (i, dir * i + pose.position, tag)))
+                })
+                .collect(),
+            Some(offsets) => self
+                .directions
+                .par_iter()
+                .enumerate()
+                .map(|(i, &dir)| {
+                    let pose = pose_at(offsets[i]);
+                    let world_dir = pose.heading.rotate(dir);
+                    scene
+                        .cast_rays(pose.position, world_dir)
+                        .map(|(i, tag)| (i, world_dir * i + pose.position, tag))
+                })
+                .collect(),
+        };
+
+        let ranges = hits
+            .iter()
+            .map(|hit| hit.map_or(f32::INFINITY, |(i, _, _)| i))
             .collect();
+        let (points, tags): (Vec<glam::Vec2>, Vec<HitTag>) = hits
+            .into_iter()
+            .flatten()
+            .map(|(_, point, tag)| (point, tag))
+            .unzip();
 
         // log::debug!("{results:?}");
 
         let sensed = TimeStamped {
             time: scene.time,
-            state: Lidar2DSensed(results),
+            state: Lidar2DSensed {
+                ranges,
+                points,
+                tags: self.semantic.then_some(tags),
+                time_offsets: time_offsets.map(|offsets| {
+                    let noise = Normal::new(0., self.ray_jitter).ok();
+                    offsets
+                        .into_iter()
+                        .map(|t| t + noise.map_or(0., |n| n.sample(&mut rng::rng())))
+                        .collect()
+                }),
+            },
+            meta: Default::default(),
         };
 
         log::info!(
@@ -273,3 +392,134 @@ impl Sensor2D for Lidar2D {
 //
 //     visited.insert(neighbor);
 // }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        scene::{HitTag, OccupancyMap, SceneTime, occupancy_map::ObjectTag},
+        sensors::{Sensor2D, lidar::Lidar2D},
+    };
+
+    /// An 8 by 8 map with a wall spanning (2, -1) to (3, 1) and a block spanning (0, -3) to (1, -2).
+    fn two_objects() -> OccupancyMap {
+        OccupancyMap::from_ascii(&[
+            "........", "........", "........", "......#.", "......#.", "........", "....#...",
+            "........",
+        ])
+    }
+
+    /// Looking along +x, +y, -x and -y from (0.5, 0.5).
+    fn axis_lidar(semantic: bool) -> (Agent2D, Lidar2D) {
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(0.5, 0.5);
+        agent.state.heading = glam::Vec2::X;
+        let mut lidar = Lidar2D {
+            semantic,
+            ..Default::default()
+        };
+        lidar.update_directions(vec![
+            glam::Vec2::X,
+            glam::Vec2::Y,
+            glam::Vec2::NEG_X,
+            glam::Vec2::NEG_Y,
+        ]);
+        (agent, lidar)
+    }
+
+    #[test]
+    fn test_lidar_tags() {
+        let map = two_objects();
+        let wall = map.objects[3 * 8 + 6].unwrap();
+        let block = map.objects[6 * 8 + 4].unwrap();
+        assert_ne!(wall, block);
+        let scene = Scene2D::from_occupancy_map(map);
+
+        let (agent, mut lidar) = axis_lidar(true);
+        let sensed = lidar
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap()
+            .state;
+
+        assert_eq!(sensed.ranges, vec![1.5, 3.5, 4.5, 2.5]);
+        assert_eq!(
+            sensed.points,
+            vec![
+                glam::vec2(2., 0.5),
+                glam::vec2(0.5, 4.),
+                glam::vec2(-4., 0.5),
+                glam::vec2(0.5, -2.),
+            ]
+        );
+        assert_eq!(
+            sensed.tags,
+            Some(vec![
+                HitTag::Map(wall),
+                HitTag::Map(ObjectTag::MAP_EDGE),
+                HitTag::Map(ObjectTag::MAP_EDGE),
+                HitTag::Map(block),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lidar_tags_off() {
+        let scene = Scene2D::from_occupancy_map(two_objects());
+
+        let (agent, mut lidar) = axis_lidar(false);
+        let sensed = lidar
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap()
+            .state;
+
+        assert_eq!(sensed.ranges, vec![1.5, 3.5, 4.5, 2.5]);
+        assert_eq!(sensed.tags, None);
+    }
+
+    /// Scans once from (0.5, 0.5) at time 0 and again from (-0.5, 0.5) at time 0.1, returning the second scan.
+    fn scan_while_moving(lidar: &mut Lidar2D) -> super::Lidar2DSensed {
+        let scene = Scene2D::from_occupancy_map(two_objects());
+        let (mut agent, _) = axis_lidar(false);
+        lidar
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap();
+
+        agent.state.position = glam::vec2(-0.5, 0.5);
+        let mut state = scene.state();
+        state.time = SceneTime(0.1);
+        lidar.sense(agent.config, agent.state, state).unwrap().state
+    }
+
+    #[test]
+    fn test_lidar_motion_distortion() {
+        // Three rays along +x, sweeping over the whole move from one scan to the next.
+        let mut lidar = Lidar2D {
+            scan_duration: 0.1,
+            ..Default::default()
+        };
+        lidar.update_directions(vec![glam::Vec2::X; 3]);
+        let sensed = scan_while_moving(&mut lidar);
+
+        assert_eq!(sensed.time_offsets, Some(vec![-0.1, -0.05, 0.]));
+        // The first ray leaves from the previous pose and the last from the current one.
+        assert_eq!(sensed.ranges, vec![1.5, 2., 2.5]);
+        assert_eq!(sensed.points, vec![glam::vec2(2., 0.5); 3]);
+    }
+
+    #[test]
+    fn test_lidar_no_distortion() {
+        let mut lidar = Lidar2D::regular(90);
+        let sensed = scan_while_moving(&mut lidar);
+        assert_eq!(sensed.time_offsets, None);
+
+        // The same as a first scan from where the agent ended up.
+        let scene = Scene2D::from_occupancy_map(two_objects());
+        let (mut agent, _) = axis_lidar(false);
+        agent.state.position = glam::vec2(-0.5, 0.5);
+        let still = Lidar2D::regular(90)
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap()
+            .state;
+        assert_eq!(sensed, still);
+    }
+}
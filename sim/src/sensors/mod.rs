@@ -1,23 +1,224 @@
+use std::{any::Any, sync::Arc};
+
+use parking_lot::RwLock;
+use smallvec::SmallVec;
+
 use crate::{
     agent::{Agent2DConfig, Agent2DState},
+    config::Validate,
     scene::{Scene2DState, SceneTime},
 };
 
+pub mod beacon;
+pub mod bumper;
+pub mod compass;
+pub mod landmark;
 pub mod lidar;
+pub mod radar;
+pub mod sonar;
+
+pub use beacon::{BeaconRange, BeaconRanger2D, BeaconSensed};
+pub use bumper::{Bumper2D, BumperSensed};
+pub use compass::{Compass2D, CompassSensed, MagneticDisturbance};
+pub use landmark::{LandmarkSensed, LandmarkSensor2D, RangeBearing};
+pub use lidar::{Lidar2D, Lidar2DSensed};
+pub use radar::{Radar2D, RadarDetection, RadarSensed};
+pub use sonar::{Sonar2D, SonarSensed};
+
+/// Noise and timing a sensor is configured with, so estimators can be set up from the same numbers as the
+/// simulation rather than repeating them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeasurementMeta {
+    /// Standard deviation of each component of the measurement in its own units, e.g. `[heading]` for a compass.
+    /// Empty for noise-free sensors.
+    pub sigma: SmallVec<[f32; 2]>,
+    /// Delay between sensing and delivery in seconds.
+    pub latency: f32,
+    /// Standard deviation of the error on [TimeStamped::time] in seconds.
+    pub jitter: f32,
+    pub clock: SensorClock,
+    /// Scene time the measurement was actually taken, whereas [TimeStamped::time] is what the sensor reported. Kept
+    /// for evaluating time synchronization; a real sensor can't know it. `None` in [TopicDescriptor]s.
+    pub true_time: Option<SceneTime>,
+}
+
+/// Budget units an agent has spent on sensing, e.g. rays cast. See [Sensor2D::cost].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensingCost {
+    /// Spent during the latest scene update.
+    pub step: f32,
+    /// Spent since the agent was added to the scene.
+    pub total: f32,
+}
+
+/// The clock a sensor stamps its measurements with, which runs apart from scene time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorClock {
+    /// Reading of the clock at scene time zero, in seconds.
+    pub offset: f32,
+    /// Fraction by which the clock runs fast, e.g. `1e-4` gains 0.1 ms every second.
+    pub drift: f32,
+}
 
-#[derive(Debug, Clone, Copy)]
+impl SensorClock {
+    /// What the clock reads at scene time `time`.
+    pub fn read(&self, time: SceneTime) -> SceneTime {
+        SceneTime(self.offset + time.0 * (1. + self.drift))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TimeStamped<T> {
     pub time: SceneTime,
     pub state: T,
+    /// Filled in by the [Scene2DLoop](crate::scene::scene_loop::Scene2DLoop); sensors may leave it default.
+    pub meta: MeasurementMeta,
+}
+
+pub type AnyMeasurement = Arc<dyn Any + Send + Sync>;
+
+impl TimeStamped<AnyMeasurement> {
+    pub fn downcast<T: Send + Sync + 'static>(&self) -> Option<TimeStamped<Arc<T>>> {
+        Some(TimeStamped {
+            time: self.time,
+            state: Arc::clone(&self.state).downcast::<T>().ok()?,
+            meta: self.meta.clone(),
+        })
+    }
+}
+
+/// Stable name and measurement type of a sensor stream published by a [Scene2DLoop](crate::scene::scene_loop::Scene2DLoop).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicDescriptor {
+    pub name: String,
+    pub type_name: &'static str,
+    /// Configured sensing rate in Hz, `None` if sensed every update.
+    pub rate: Option<f32>,
+    pub meta: MeasurementMeta,
 }
 
 pub trait Sensor2D {
     type SensorType;
 
+    /// Default topic name the sensor's measurements are published under.
+    const TOPIC: &'static str;
+
+    /// Takes `&mut self` so sensors can carry state between readings, e.g. a drifting bias. Each sensor is sensed by
+    /// one job at a time, but agents cloned from each other share their sensors and so their state.
     fn sense(
-        &self,
+        &mut self,
         agent_config: Agent2DConfig,
         agent_state: Agent2DState,
         scene: Scene2DState,
     ) -> Option<TimeStamped<Self::SensorType>>;
+
+    /// Configured standard deviations, see [MeasurementMeta::sigma].
+    fn sigma(&self) -> SmallVec<[f32; 2]> {
+        SmallVec::new()
+    }
+
+    /// What taking one reading costs, in whatever units a power or compute budget is kept in. One unless a sensor
+    /// says otherwise; sensors that cast rays charge one per ray.
+    fn cost(&self) -> f32 {
+        1.
+    }
+
+    /// Length of the vector written by [Sensor2D::features]. Zero for sensors that can't be flattened into a
+    /// fixed-size observation.
+    fn feature_len(&self) -> usize {
+        0
+    }
+
+    /// Flattens a measurement into `feature_len` numbers for learning agents. Features must not reveal the true
+    /// pose, e.g. ranges rather than world-frame points.
+    fn features(&self, _measurement: &Self::SensorType, _out: &mut Vec<f32>) {}
+}
+
+/// Object-safe counterpart of [Sensor2D], used wherever sensors are stored without knowing their type.
+pub trait DynSensor2D: std::fmt::Debug + Send + Sync + Validate {
+    fn type_name(&self) -> &'static str;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Metadata attached to this sensor's measurements, without latency or jitter.
+    fn measurement_meta(&self) -> MeasurementMeta;
+
+    fn cost(&self) -> f32;
+
+    fn feature_len(&self) -> usize;
+
+    /// Appends the features of `measurement`, or nothing if it isn't this sensor's measurement type.
+    fn features_any(&self, measurement: &AnyMeasurement, out: &mut Vec<f32>);
+
+    fn sense_any(
+        &mut self,
+        agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<AnyMeasurement>>;
+
+    /// A copy of the sensor and whatever state it carries, sharing nothing with this one.
+    fn clone_dyn(&self) -> Arc<RwLock<dyn DynSensor2D>>;
+}
+
+impl<S> DynSensor2D for S
+where
+    S: Sensor2D + Validate + Clone + std::fmt::Debug + Send + Sync + 'static,
+    S::SensorType: Send + Sync + 'static,
+{
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<S::SensorType>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn measurement_meta(&self) -> MeasurementMeta {
+        MeasurementMeta {
+            sigma: self.sigma(),
+            ..Default::default()
+        }
+    }
+
+    fn cost(&self) -> f32 {
+        Sensor2D::cost(self)
+    }
+
+    fn feature_len(&self) -> usize {
+        Sensor2D::feature_len(self)
+    }
+
+    fn features_any(&self, measurement: &AnyMeasurement, out: &mut Vec<f32>) {
+        if let Some(measurement) = measurement.downcast_ref::<S::SensorType>() {
+            self.features(measurement, out);
+        }
+    }
+
+    fn sense_any(
+        &mut self,
+        agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<AnyMeasurement>> {
+        let TimeStamped { time, state, .. } = self.sense(agent_config, agent_state, scene)?;
+
+        Some(TimeStamped {
+            time,
+            state: Arc::new(state),
+            meta: self.measurement_meta(),
+        })
+    }
+
+    fn clone_dyn(&self) -> Arc<RwLock<dyn DynSensor2D>> {
+        Arc::new(RwLock::new(self.clone()))
+    }
 }
@@ -0,0 +1,223 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Poisson};
+use smallvec::{SmallVec, smallvec};
+
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    rng,
+    scene::{AgentId, HitTag, Scene2DState},
+    sensors::{Sensor2D, TimeStamped},
+};
+
+/// Doppler radar that detects other agents, reporting where they are and how fast they approach or recede.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Radar2D {
+    /// Full field of view in radians, centred on the agent heading.
+    pub fov: f32,
+    /// In metres. Must be finite when `false_alarm_rate` is non-zero.
+    pub max_range: f32,
+    /// Standard deviation of the range in metres.
+    pub range_noise: f32,
+    /// Standard deviation of the bearing in radians.
+    pub bearing_noise: f32,
+    /// Standard deviation of the radial velocity in m/s.
+    pub velocity_noise: f32,
+    /// Mean number of spurious detections per scan, spread uniformly over the field of view.
+    pub false_alarm_rate: f32,
+}
+
+impl Default for Radar2D {
+    fn default() -> Self {
+        Self {
+            fov: std::f32::consts::FRAC_PI_2,
+            max_range: 50.,
+            range_noise: 0.,
+            bearing_noise: 0.,
+            velocity_noise: 0.,
+            false_alarm_rate: 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadarDetection {
+    pub range: f32,
+    /// Angle from the agent heading to the detection, counter-clockwise in radians.
+    pub bearing: f32,
+    /// Rate at which the range grows in m/s, i.e. negative when closing in.
+    pub radial_velocity: f32,
+    /// The agent that caused the detection, `None` for false alarms. Ground truth for evaluating trackers; a real
+    /// radar doesn't report it.
+    pub source: Option<AgentId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadarSensed(pub Vec<RadarDetection>);
+
+impl Validate for Radar2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::within(
+            "fov",
+            self.fov,
+            self.fov > 0. && self.fov <= std::f32::consts::TAU,
+            "(0, 2π]",
+        )?;
+        config::non_negative("max_range", self.max_range)?;
+        for (field, noise) in [
+            ("range_noise", self.range_noise),
+            ("bearing_noise", self.bearing_noise),
+            ("velocity_noise", self.velocity_noise),
+            ("false_alarm_rate", self.false_alarm_rate),
+        ] {
+            config::finite(field, noise)?;
+            config::non_negative(field, noise)?;
+        }
+
+        if self.false_alarm_rate > 0. {
+            config::finite("max_range", self.max_range)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sensor2D for Radar2D {
+    type SensorType = RadarSensed;
+
+    const TOPIC: &'static str = "radar";
+
+    fn sigma(&self) -> SmallVec<[f32; 2]> {
+        smallvec![self.range_noise, self.bearing_noise, self.velocity_noise]
+    }
+
+    fn sense(
+        &mut self,
+        _agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        let sample = |std_dev: f32| {
+            Normal::new(0., std_dev)
+                .map(|n| n.sample(&mut rng::rng()))
+                .unwrap_or(0.)
+        };
+        let own_velocity = agent_state.heading * agent_state.velocity;
+
+        let mut detections = scene
+            .agents_seen_by(scene.viewer)
+            .filter_map(|(i, id, footprint)| {
+                let velocity = scene.agent_velocities[i];
+                let disp = footprint.centroid() - agent_state.position;
+                let range = disp.length();
+                if range > self.max_range || range < f32::EPSILON {
+                    return None;
+                }
+
+                let bearing = agent_state.heading.angle_to(disp);
+                if bearing.abs() > self.fov / 2. {
+                    return None;
+                }
+
+                // Only agents with a clear line of sight to their centre are seen.
+                let dir = disp / range;
+                match scene.cast_rays(agent_state.position, dir) {
+                    Some((_, HitTag::Agent(hit))) if hit == id => {}
+                    _ => return None,
+                }
+
+                Some(RadarDetection {
+                    range,
+                    bearing,
+                    radial_velocity: (velocity - own_velocity).dot(dir),
+                    source: Some(id),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for detection in &mut detections {
+            detection.range = (detection.range + sample(self.range_noise)).max(0.);
+            detection.bearing += sample(self.bearing_noise);
+            detection.radial_velocity += sample(self.velocity_noise);
+        }
+
+        // False alarms look like stationary clutter.
+        let mut rng = rng::rng();
+        let false_alarms = Poisson::new(self.false_alarm_rate)
+            .map(|p| p.sample(&mut rng) as usize)
+            .unwrap_or(0);
+        detections.extend((0..false_alarms).map(|_| {
+            let bearing = rng.random_range(-self.fov / 2. ..=self.fov / 2.);
+            let dir = glam::Vec2::from_angle(bearing).rotate(agent_state.heading);
+
+            RadarDetection {
+                range: rng.random_range(0. ..=self.max_range),
+                bearing,
+                radial_velocity: -own_velocity.dot(dir) + sample(self.velocity_noise),
+                source: None,
+            }
+        }));
+
+        Some(TimeStamped {
+            time: scene.time,
+            state: RadarSensed(detections),
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        sensors::{Sensor2D, radar::Radar2D},
+    };
+
+    #[test]
+    fn test_radar() {
+        // A wall ahead and to the left, two to three metres out.
+        let mut pixels = [255; 100];
+        pixels[2 * 10..2 * 10 + 4].fill(0);
+        let mut scene = Scene2D::from_pixels([10, 10], &pixels).unwrap();
+
+        let mut radar = Agent2D::default();
+        radar.state.heading = glam::Vec2::Y;
+        let (config, state) = (radar.config, radar.state);
+        let me = scene.add_agent(radar);
+        let target = |scene: &mut Scene2D, position: glam::Vec2, velocity: f32| {
+            let mut agent = Agent2D::default();
+            agent.state.position = position;
+            agent.state.heading = glam::Vec2::NEG_Y;
+            agent.state.velocity = velocity;
+            scene.add_agent(agent)
+        };
+        let closing = target(&mut scene, glam::vec2(0., 3.), 2.);
+        let receding = target(&mut scene, glam::vec2(1., 2.), -1.);
+        // Behind the wall, and outside the field of view.
+        let hidden = target(&mut scene, glam::vec2(-3.5, 3.5), 0.);
+        let aside = target(&mut scene, glam::vec2(2., 0.), 0.);
+
+        let sense = |scene: &Scene2D| {
+            Radar2D::default()
+                .sense(config, state, scene.state().viewed_by(me))
+                .unwrap()
+                .state
+                .0
+        };
+        let detections = sense(&scene);
+        let detection = |id| detections.iter().find(|d| d.source == Some(id));
+        assert!(detection(me).is_none());
+        assert!(detection(hidden).is_none());
+        assert!(detection(aside).is_none());
+        assert!((detection(closing).unwrap().radial_velocity + 2.).abs() < 1e-4);
+        assert!(detection(receding).unwrap().radial_velocity > 0.);
+        assert_eq!(detections.len(), 2);
+
+        // Still seen once close enough to cover the radar itself.
+        scene.agents.get_mut(&closing).unwrap().state.position = glam::vec2(0., 0.2);
+        let detections = sense(&scene);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].source, Some(closing));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{
+    agent::{Agent2DConfig, Agent2DState},
+    config::{self, ConfigError, Validate},
+    scene::Scene2DState,
+    sensors::{Sensor2D, TimeStamped},
+};
+
+/// Ultrasonic range finder. Reports a single reading: the nearest return anywhere inside its cone.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sonar2D {
+    /// Mounting direction relative to the agent heading.
+    pub direction: glam::Vec2,
+    /// Full opening angle of the beam in radians.
+    pub cone: f32,
+    /// Readings closer than this are reported as `min_range`, mimicking the transducer's ring-down blind zone.
+    pub min_range: f32,
+    /// In metres, like `min_range`. Reported when there is no echo, so an infinite range reports infinity then.
+    pub max_range: f32,
+    /// Number of rays used to sample the cone.
+    pub rays: usize,
+}
+
+impl Default for Sonar2D {
+    fn default() -> Self {
+        Self {
+            direction: glam::Vec2::X,
+            cone: 30f32.to_radians(),
+            min_range: 0.,
+            max_range: 4.,
+            rays: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SonarSensed {
+    /// Reported range, `max_range` when there was no echo.
+    pub range: f32,
+    pub echo: bool,
+}
+
+impl Validate for Sonar2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::direction("direction", self.direction)?;
+        config::within(
+            "cone",
+            self.cone,
+            (0. ..=std::f32::consts::TAU).contains(&self.cone),
+            "[0, 2π]",
+        )?;
+        config::non_negative("min_range", self.min_range)?;
+        config::finite("min_range", self.min_range)?;
+        config::non_negative("max_range", self.max_range)?;
+        config::ordered("range", (self.min_range, self.max_range))?;
+        config::at_least("rays", self.rays, 1)
+    }
+}
+
+impl Sensor2D for Sonar2D {
+    type SensorType = SonarSensed;
+
+    const TOPIC: &'static str = "sonar";
+
+    fn cost(&self) -> f32 {
+        self.rays as f32
+    }
+
+    fn feature_len(&self) -> usize {
+        2
+    }
+
+    /// The inverse range and whether there was an echo.
+    fn features(&self, measurement: &Self::SensorType, out: &mut Vec<f32>) {
+        out.push(if measurement.echo {
+            measurement.range.recip()
+        } else {
+            0.
+        });
+        out.push(measurement.echo as u8 as f32);
+    }
+
+    fn sense(
+        &mut self,
+        _agent_config: Agent2DConfig,
+        agent_state: Agent2DState,
+        scene: Scene2DState,
+    ) -> Option<TimeStamped<Self::SensorType>> {
+        let center = agent_state.heading.rotate(self.direction);
+        let rays = self.rays.max(1);
+
+        let nearest = (0..rays)
+            .map(|i| {
+                let offset = if rays == 1 {
+                    0.
+                } else {
+                    self.cone * (i as f32 / (rays - 1) as f32 - 0.5)
+                };
+
+                glam::Vec2::from_angle(offset).rotate(center)
+            })
+            .filter_map(|dir| scene.cast_rays(agent_state.position, dir).map(|(t, _)| t))
+            .filter(|&t| t <= self.max_range)
+            .min_by(f32::total_cmp);
+
+        let state = match nearest {
+            Some(range) => SonarSensed {
+                range: range.max(self.min_range),
+                echo: true,
+            },
+            None => SonarSensed {
+                range: self.max_range,
+                echo: false,
+            },
+        };
+
+        Some(TimeStamped {
+            time: scene.time,
+            state,
+            meta: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        sensors::{Sensor2D, sonar::Sonar2D, sonar::SonarSensed},
+    };
+
+    /// What `sonar` reads 5.5 m from a wall ahead, with a block 1.5 m out just off the beam's axis.
+    fn sense(mut sonar: Sonar2D) -> SonarSensed {
+        let mut pixels = [255; 100];
+        for row in 0..10 {
+            pixels[row * 10 + 8] = 0;
+        }
+        pixels[4 * 10 + 4] = 0;
+        let scene = Scene2D::from_pixels([10, 10], &pixels).unwrap();
+
+        let mut agent = Agent2D::default();
+        agent.state.position = glam::vec2(-2.5, -0.2);
+        agent.state.heading = glam::Vec2::X;
+        sonar
+            .sense(agent.config, agent.state, scene.state())
+            .unwrap()
+            .state
+    }
+
+    fn wide() -> Sonar2D {
+        Sonar2D {
+            max_range: 10.,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sonar_cone() {
+        // The nearest return anywhere in the cone, not just along its axis.
+        let SonarSensed { range, echo } = sense(wide());
+        assert!(echo && (1.5..1.56).contains(&range), "{range}");
+        let narrow = Sonar2D {
+            cone: 0.,
+            rays: 1,
+            ..wide()
+        };
+        assert_eq!(
+            sense(narrow),
+            SonarSensed {
+                range: 5.5,
+                echo: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_sonar_min_range() {
+        let blind = Sonar2D {
+            min_range: 2.,
+            ..wide()
+        };
+        assert_eq!(
+            sense(blind),
+            SonarSensed {
+                range: 2.,
+                echo: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_sonar_max_range() {
+        let short = Sonar2D {
+            max_range: 1.,
+            ..wide()
+        };
+        assert_eq!(
+            sense(short),
+            SonarSensed {
+                range: 1.,
+                echo: false
+            }
+        );
+        // The default range falls short of the wall.
+        assert_eq!(
+            sense(Sonar2D {
+                cone: 0.,
+                rays: 1,
+                ..Default::default()
+            }),
+            SonarSensed {
+                range: 4.,
+                echo: false
+            }
+        );
+    }
+}
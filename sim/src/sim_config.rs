@@ -0,0 +1,468 @@
+//! Settings for a whole simulation, given to [Scene2D::new](crate::Scene2D::new) in one place rather than set piece
+//! by piece after the scene is built.
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    plugin::{ParamValue, PluginError, PluginParams},
+    scene::occupancy_map::{BoundaryPolicy, RayCaster},
+};
+
+/// What happens to an agent that drives into a wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CollisionMode {
+    /// Agents drive through walls, leaving it to controllers and the
+    /// [safety supervisor](crate::safety::SafetySupervisor) to keep them out.
+    #[default]
+    Ignore,
+    /// Agents drive through walls, but each step that takes one further into a wall is reported as a
+    /// [CollisionEvent](crate::scene::CollisionEvent).
+    Report,
+    /// A step that would take an agent's footprint further into a wall is undone and the agent stopped. Agents that
+    /// start overlapping a wall are free to drive out of it.
+    Stop,
+    /// Like [CollisionMode::Stop], but the agent keeps the part of each step's motion along the wall and its speed, so
+    /// it scrapes along walls it meets at an angle.
+    Slide,
+}
+
+/// What happens when agents' footprints overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AgentCollisionMode {
+    /// Agents pass through each other.
+    #[default]
+    Ignore,
+    /// Agents pass through each other, but each step they overlap is reported as an
+    /// [AgentCollisionEvent](crate::scene::AgentCollisionEvent).
+    Report,
+    /// Overlapping agents are pushed apart and trade momentum along the contact normal, keeping `restitution` of
+    /// their closing speed: 0 for a dead stop, 1 for a perfect bounce. Reported like [AgentCollisionMode::Report].
+    ///
+    /// Agents take the exchange along their headings, and also sideways and as spin if their dynamics
+    /// [slide](crate::dynamics::DynamicsModel::slides) and [spin](crate::dynamics::DynamicsModel::spins). Whatever
+    /// the wheels hold them against is lost to the ground, so momentum is only conserved between agents free to
+    /// take it.
+    Impulse { restitution: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimConfig {
+    /// Seconds of scene time per [step](crate::Scene2D::step).
+    pub dt: f32,
+    /// Fixed step agent dynamics are integrated with, however long the updates the scene is given. Time is banked
+    /// between updates and spent a whole number of steps at a time, so runs don't depend on the caller's frame rate.
+    /// `None` integrates once per update.
+    pub physics_dt: Option<f32>,
+    /// Threads agents are updated on, or `None` to share rayon's global pool.
+    pub threads: Option<usize>,
+    /// Runs the scene in strict [deterministic mode](crate::Scene2D::set_deterministic) with this seed.
+    pub seed: Option<u64>,
+    /// Used when the scene builds its own map. Maps handed to the scene keep their own policy.
+    pub boundary: BoundaryPolicy,
+    pub collision: CollisionMode,
+    pub agent_collision: AgentCollisionMode,
+    /// Seconds of scene time a [SceneHistory](crate::scene::SceneHistory) keeps.
+    pub history: f32,
+    /// How rays are cast through the scene's occupancy map. Only scenes built on an [OccupancyMap] use it; the tiles
+    /// of a [TiledWorld] and the segments of a [VectorMap] are always searched through their BVHs.
+    ///
+    /// [OccupancyMap]: crate::scene::OccupancyMap
+    /// [TiledWorld]: crate::scene::tiles::TiledWorld
+    /// [VectorMap]: crate::scene::vector_map::VectorMap
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ray_caster: RayCaster,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            dt: 1. / 60.,
+            physics_dt: None,
+            threads: None,
+            seed: None,
+            boundary: BoundaryPolicy::default(),
+            collision: CollisionMode::default(),
+            agent_collision: AgentCollisionMode::default(),
+            history: 30.,
+            ray_caster: RayCaster::default(),
+        }
+    }
+}
+
+impl Validate for SimConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("dt", self.dt)?;
+        if let Some(physics_dt) = self.physics_dt {
+            config::positive("physics_dt", physics_dt)?;
+        }
+        if let Some(threads) = self.threads {
+            config::at_least("threads", threads, 1)?;
+        }
+        if let AgentCollisionMode::Impulse { restitution } = self.agent_collision {
+            config::within(
+                "agent_collision.restitution",
+                restitution,
+                (0. ..=1.).contains(&restitution),
+                "[0, 1]",
+            )?;
+        }
+        config::finite("history", self.history)?;
+        config::non_negative("history", self.history)
+    }
+}
+
+impl SimConfig {
+    /// Reads the `sim` section of a scenario. The boundary policy is left at its default, as scenarios give it with
+    /// the map.
+    pub fn from_params(params: &PluginParams) -> Result<Self, PluginError> {
+        let default = Self::default();
+        let optional = |key: &str| match params.get(key) {
+            Some(_) => params.usize_or(key, 0).map(Some),
+            None => Ok(None),
+        };
+
+        let config = Self {
+            dt: params.f32_or("dt", default.dt)?,
+            physics_dt: match params.get("physics_dt") {
+                Some(_) => Some(params.f32_or("physics_dt", 0.)?),
+                None => None,
+            },
+            threads: optional("threads")?,
+            seed: optional("seed")?.map(|seed| seed as u64),
+            boundary: default.boundary,
+            collision: match params.get("collision") {
+                None => default.collision,
+                Some(ParamValue::String(mode)) if mode == "ignore" => CollisionMode::Ignore,
+                Some(ParamValue::String(mode)) if mode == "report" => CollisionMode::Report,
+                Some(ParamValue::String(mode)) if mode == "stop" => CollisionMode::Stop,
+                Some(ParamValue::String(mode)) if mode == "slide" => CollisionMode::Slide,
+                Some(_) => {
+                    return Err(PluginError::InvalidParam(
+                        "collision".to_string(),
+                        "\"ignore\", \"report\", \"stop\" or \"slide\"",
+                    ));
+                }
+            },
+            agent_collision: match params.get("agent_collision") {
+                None => default.agent_collision,
+                Some(ParamValue::String(mode)) if mode == "ignore" => AgentCollisionMode::Ignore,
+                Some(ParamValue::String(mode)) if mode == "report" => AgentCollisionMode::Report,
+                Some(ParamValue::String(mode)) if mode == "impulse" => {
+                    AgentCollisionMode::Impulse {
+                        restitution: params.f32_or("restitution", 0.2)?,
+                    }
+                }
+                Some(_) => {
+                    return Err(PluginError::InvalidParam(
+                        "agent_collision".to_string(),
+                        "\"ignore\", \"report\" or \"impulse\"",
+                    ));
+                }
+            },
+            history: params.f32_or("history", default.history)?,
+            ray_caster: match params.get("ray_caster") {
+                None => default.ray_caster,
+                Some(ParamValue::String(caster)) if caster == "bvh" => RayCaster::Bvh,
+                Some(ParamValue::String(caster)) if caster == "dda" => RayCaster::Dda,
+                Some(_) => {
+                    return Err(PluginError::InvalidParam(
+                        "ray_caster".to_string(),
+                        "\"bvh\" or \"dda\"",
+                    ));
+                }
+            },
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustc_hash::FxHashMap;
+
+    use crate::{
+        Agent2D, Scene2D,
+        dynamics::{DynamicsModel, Omni},
+        plugin::{ParamValue, PluginParams},
+        scene::Scene2DError,
+        sim_config::{AgentCollisionMode, CollisionMode, SimConfig},
+    };
+
+    /// A 0.1 s step on two threads with seed 7, stopping agents at walls.
+    fn stopping() -> SimConfig {
+        let params = PluginParams(FxHashMap::from_iter([
+            ("dt".to_string(), ParamValue::Number(0.1)),
+            ("threads".to_string(), ParamValue::Number(2.)),
+            ("seed".to_string(), ParamValue::Number(7.)),
+            ("collision".to_string(), ParamValue::String("stop".into())),
+        ]));
+        SimConfig::from_params(&params).unwrap()
+    }
+
+    /// A wall across the top two rows of a 10x10 map.
+    fn top_wall() -> [u8; 100] {
+        let mut pixels = [255; 100];
+        pixels[..20].fill(0);
+        pixels
+    }
+
+    #[test]
+    fn test_sim_config() {
+        let config = stopping();
+        assert_eq!(config.dt, 0.1);
+        assert_eq!(config.threads, Some(2));
+        assert_eq!(config.seed, Some(7));
+        assert_eq!(config.collision, CollisionMode::Stop);
+        assert_eq!(config.history, SimConfig::default().history);
+    }
+
+    #[test]
+    fn test_sim_config_scene() {
+        // An agent heading for the wall.
+        let mut scene = Scene2D::new([10, 10], &top_wall(), stopping()).unwrap();
+        assert!(scene.ordered);
+        assert_eq!(scene.scene_loop.seed(), Some(7));
+        let mut agent = Agent2D::default();
+        agent.state.velocity = 2.;
+        let id = scene.add_agent(agent);
+        for _ in 0..30 {
+            scene.step();
+        }
+        assert!((scene.time.0 - 3.).abs() < 1e-4);
+        let agent = &scene.agents[&id];
+        assert!(!scene.occupancy_map.overlaps(&agent.footprint()));
+        assert_eq!(agent.state.velocity, 0.);
+        assert!(agent.state.position.y > 1.5, "{:?}", agent.state);
+    }
+
+    #[test]
+    fn test_sim_config_default_scene() {
+        // Without collisions the agent drives into the wall.
+        let mut scene = Scene2D::new([10, 10], &top_wall(), SimConfig::default()).unwrap();
+        let mut agent = Agent2D::default();
+        agent.state.velocity = 2.;
+        let id = scene.add_agent(agent);
+        scene.update(1.5);
+        assert!(scene.occupancy_map.overlaps(&scene.agents[&id].footprint()));
+    }
+
+    #[test]
+    fn test_sim_config_invalid() {
+        let invalid = SimConfig {
+            threads: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Scene2D::new([10, 10], &top_wall(), invalid),
+            Err(Scene2DError::Config(_))
+        ));
+        let params = PluginParams(FxHashMap::from_iter([(
+            "collision".to_string(),
+            ParamValue::String("bounce".into()),
+        )]));
+        assert!(SimConfig::from_params(&params).is_err());
+    }
+
+    #[test]
+    fn test_collision_modes() {
+        // The same wall, approached at 45°.
+        let mut pixels = [255; 100];
+        pixels[..20].fill(0);
+        let drive = |collision| {
+            let config = SimConfig {
+                collision,
+                ..Default::default()
+            };
+            let mut scene = Scene2D::new([10, 10], &pixels, config).unwrap();
+            let mut agent = Agent2D::default();
+            agent.state.heading = glam::vec2(1., 1.).normalize();
+            agent.state.position = glam::vec2(-2., 0.);
+            agent.state.velocity = 2.;
+            let id = scene.add_agent(agent);
+            let mut events = Vec::new();
+            for _ in 0..60 {
+                scene.update(0.05);
+                events.extend(scene.drain_collision_events());
+            }
+            (scene.agents[&id].state, events)
+        };
+
+        let (state, events) = drive(CollisionMode::Ignore);
+        assert!(events.is_empty());
+        assert!(state.position.y > 3., "{state:?}");
+
+        let (state, events) = drive(CollisionMode::Report);
+        assert!(state.position.y > 3., "{state:?}");
+        let first = events.first().expect("The agent hits the wall");
+        assert!((first.point.y - 3.).abs() < 1e-4, "{first:?}");
+        assert_eq!(first.normal, glam::Vec2::NEG_Y);
+
+        let (stopped, events) = drive(CollisionMode::Stop);
+        assert_eq!(events.len(), 1);
+        assert_eq!(stopped.velocity, 0.);
+        assert!(stopped.position.y < 3.);
+
+        // Sliding keeps the agent out of the wall but carries it along it.
+        let (slid, events) = drive(CollisionMode::Slide);
+        assert!(!events.is_empty());
+        assert!(slid.velocity > 0., "{slid:?}");
+        assert!(
+            slid.position.x > stopped.position.x + 0.5,
+            "{slid:?} {stopped:?}"
+        );
+        assert!(slid.position.y < 3.);
+    }
+    #[test]
+    fn test_agent_collisions() {
+        // Two agents driving head on at each other, the second twice as heavy.
+        let run = |agent_collision| {
+            let config = SimConfig {
+                agent_collision,
+                ..Default::default()
+            };
+            let mut scene = Scene2D::new([20, 20], &[255; 400], config).unwrap();
+            let mut a = Agent2D::default();
+            a.state.heading = glam::Vec2::X;
+            a.state.position = glam::vec2(-3., 0.);
+            a.state.velocity = 2.;
+            let mut b = Agent2D::default();
+            b.config.mass *= 2.;
+            b.state.heading = glam::Vec2::NEG_X;
+            b.state.position = glam::vec2(3., 0.);
+            b.state.velocity = 2.;
+            let (a, b) = (scene.add_agent(a), scene.add_agent(b));
+
+            let mut events = Vec::new();
+            for _ in 0..40 {
+                scene.update(0.05);
+                events.extend(scene.drain_agent_collision_events());
+            }
+            (
+                scene.agents[&a].state,
+                scene.agents[&b].state,
+                events,
+                [a, b],
+            )
+        };
+
+        let (a, b, events, _) = run(AgentCollisionMode::Ignore);
+        assert!(events.is_empty());
+        assert!(a.position.x > b.position.x);
+
+        let (a, b, events, ids) = run(AgentCollisionMode::Report);
+        assert!(a.position.x > b.position.x);
+        let first = events.first().expect("The agents meet");
+        assert_eq!(first.agents, ids);
+        assert!(first.normal.abs_diff_eq(glam::Vec2::X, 1e-6));
+        assert!(first.point.y.abs() < 1e-4, "{first:?}");
+
+        // A dead stop shares the momentum out, leaving both agents rolling the heavier one's way.
+        let (a, b, events, _) = run(AgentCollisionMode::Impulse { restitution: 0. });
+        assert!(!events.is_empty());
+        assert!(a.position.x < b.position.x, "{a:?} {b:?}");
+        assert!(a.velocity < 0. && b.velocity > 0., "{a:?} {b:?}");
+
+        let invalid = SimConfig {
+            agent_collision: AgentCollisionMode::Impulse { restitution: 1.5 },
+            ..Default::default()
+        };
+        assert!(matches!(
+            Scene2D::new([10, 10], &[255; 100], invalid),
+            Err(Scene2DError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_impulse_momentum() {
+        // A perfect bounce between agents of unequal mass keeps their momentum and their closing speed.
+        let bounce = |mut a: Agent2D, mut b: Agent2D| {
+            let config = SimConfig {
+                agent_collision: AgentCollisionMode::Impulse { restitution: 1. },
+                ..Default::default()
+            };
+            let mut scene = Scene2D::new([20, 20], &[255; 400], config).unwrap();
+            a.state.position = glam::vec2(-3., 0.);
+            b.state.position = glam::vec2(3., 0.);
+            b.config.mass *= 2.;
+            let world = |agent: &Agent2D| {
+                let state = agent.state;
+                state.heading * state.velocity + state.heading.perp() * state.lateral_velocity
+            };
+            let momentum =
+                |a: &Agent2D, b: &Agent2D| world(a) * a.config.mass + world(b) * b.config.mass;
+            let before = momentum(&a, &b);
+            let closing = world(&b) - world(&a);
+            let (a, b) = (scene.add_agent(a), scene.add_agent(b));
+
+            for _ in 0..40 {
+                scene.update(0.05);
+            }
+            assert!(!scene.drain_agent_collision_events().is_empty());
+            let (a, b) = (&scene.agents[&a], &scene.agents[&b]);
+            let after = momentum(a, b);
+            assert!(after.abs_diff_eq(before, 1e-4), "{before} {after}");
+            assert!((world(b) - world(a)).abs_diff_eq(-closing, 1e-4));
+        };
+
+        // Head on, along their headings.
+        let mut a = Agent2D::default();
+        a.state.heading = glam::Vec2::X;
+        a.state.velocity = 2.;
+        let mut b = Agent2D::default();
+        b.state.heading = glam::Vec2::NEG_X;
+        b.state.velocity = 2.;
+        bounce(a, b);
+
+        // Omni wheels sliding sideways into each other, which their headings alone would miss.
+        let mut a = Agent2D::default();
+        a.config.dynamics = DynamicsModel::Omni(Omni);
+        let mut b = a.clone();
+        a.state.lateral_velocity = -2.;
+        b.state.lateral_velocity = 2.;
+        bounce(a, b);
+    }
+
+    #[test]
+    fn test_physics_substeps() {
+        // A turning agent, driven for a second at different frame rates.
+        let drive = |physics_dt, frames: usize| {
+            let config = SimConfig {
+                physics_dt,
+                ..Default::default()
+            };
+            let mut scene = Scene2D::new([20, 20], &[255; 400], config).unwrap();
+            let mut agent = Agent2D::default();
+            agent.state.velocity = 3.;
+            agent.state.beta = 0.4;
+            let id = scene.add_agent(agent);
+            for _ in 0..frames {
+                scene.update(1. / frames as f32);
+            }
+            (scene.time.0, scene.agents[&id].state.position)
+        };
+
+        let (time, slow) = drive(Some(1e-3), 24);
+        assert!((time - 1.).abs() < 1e-3, "{time}");
+        let (_, fast) = drive(Some(1e-3), 144);
+        assert!(slow.distance(fast) < 1e-3, "{slow} {fast}");
+
+        let (_, slow) = drive(None, 24);
+        let (_, fast) = drive(None, 144);
+        assert!(slow.distance(fast) > 1e-2, "{slow} {fast}");
+
+        // Updates shorter than a physics step are banked until there's a whole one.
+        let config = SimConfig {
+            physics_dt: Some(0.1),
+            ..Default::default()
+        };
+        let mut scene = Scene2D::new([10, 10], &[255; 100], config).unwrap();
+        scene.update(0.06);
+        assert_eq!(scene.time.0, 0.);
+        scene.update(0.06);
+        assert!((scene.time.0 - 0.1).abs() < 1e-6);
+    }
+}
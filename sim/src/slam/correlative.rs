@@ -0,0 +1,276 @@
+use crate::{
+    config::{self, ConfigError, Validate},
+    mapping::LikelihoodField,
+    math::Pose2D,
+    scene::occupancy_map::OccupancyMap,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorrelativeConfig {
+    /// Cell size of the finest search grid, in metres. Translations are searched at this step.
+    pub resolution: f32,
+    /// How quickly the score of a point falls off with its distance to the nearest wall, in metres.
+    pub sigma: f32,
+    /// Number of coarser grids used to bound the search, each halving the resolution of the last.
+    pub depth: usize,
+}
+
+impl Default for CorrelativeConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 0.25,
+            sigma: 0.25,
+            depth: 3,
+        }
+    }
+}
+
+impl Validate for CorrelativeConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("resolution", self.resolution)?;
+        config::positive("sigma", self.sigma)?;
+        config::within("depth", self.depth as f32, self.depth <= 16, "at most 16")
+    }
+}
+
+/// Region around the initial pose that is searched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchWindow {
+    /// Half the width of the square of positions searched, in metres.
+    pub linear: f32,
+    /// Largest rotation searched either way, in radians.
+    pub angular: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanMatch {
+    pub pose: Pose2D,
+    /// Mean score of the scan points, from 0 when none are near a wall to 1 when all lie on one.
+    pub score: f32,
+}
+
+/// Matches scans against a fixed [OccupancyMap] by scoring every pose in a search window, which finds the best pose
+/// even from a poor initial guess. Multi-resolution branch and bound keeps the exhaustive search cheap.
+#[derive(Debug, Clone)]
+pub struct CorrelativeScanMatcher {
+    config: CorrelativeConfig,
+    /// World position of the lower corner of cell `(0, 0)`.
+    origin: glam::Vec2,
+    size: glam::IVec2,
+    /// Level `k` holds, for every cell, the best score of the `2^k` by `2^k` block of finest cells starting there.
+    levels: Vec<Vec<f32>>,
+}
+
+impl CorrelativeScanMatcher {
+    pub fn new(map: &OccupancyMap, config: CorrelativeConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        let field = LikelihoodField::new(map, config.resolution);
+        let (origin, size) = (field.origin(), field.size());
+        let index = |i: i32, j: i32| (i + j * size.x) as usize;
+
+        let finest = field
+            .distances()
+            .iter()
+            .map(|d| (-0.5 * (d / config.sigma).powi(2)).exp())
+            .collect::<Vec<_>>();
+
+        let mut levels = vec![finest];
+        for k in 1..=config.depth {
+            let half = 1 << (k - 1);
+            let previous = &levels[k - 1];
+            let get = |i: i32, j: i32| {
+                if i < size.x && j < size.y {
+                    previous[index(i, j)]
+                } else {
+                    0.
+                }
+            };
+
+            let level = (0..size.y)
+                .flat_map(|j| (0..size.x).map(move |i| (i, j)))
+                .map(|(i, j)| {
+                    get(i, j)
+                        .max(get(i + half, j))
+                        .max(get(i, j + half))
+                        .max(get(i + half, j + half))
+                })
+                .collect();
+            levels.push(level);
+        }
+
+        Ok(Self {
+            config,
+            origin,
+            size,
+            levels,
+        })
+    }
+
+    pub fn config(&self) -> &CorrelativeConfig {
+        &self.config
+    }
+
+    /// Finds the pose within `window` of `initial_pose` at which the sensor-frame points of `scan` best line up with
+    /// the walls, e.g. from [scan_points](crate::slam::icp::scan_points). `None` if the scan has no points.
+    pub fn match_scan(
+        &self,
+        scan: &[glam::Vec2],
+        initial_pose: Pose2D,
+        window: SearchWindow,
+    ) -> Option<ScanMatch> {
+        let max_range = scan.iter().map(|p| p.length()).fold(0., f32::max);
+        if scan.is_empty() || max_range <= 0. {
+            return None;
+        }
+
+        // Step the rotation so the furthest point moves by about a cell.
+        let angular = window.angular.max(0.);
+        let angle_step = (self.config.resolution / max_range).min(angular.max(f32::EPSILON));
+        let rotations = (angular / angle_step).ceil() as i32;
+        let reach = (window.linear.max(0.) / self.config.resolution).ceil() as i32;
+
+        // Cells of the scan points at each rotation, before any translation.
+        let rotated = (-rotations..=rotations)
+            .map(|r| {
+                let angle = initial_pose.angle() + r as f32 * angle_step;
+                let heading = glam::Vec2::from_angle(angle);
+                let cells = scan
+                    .iter()
+                    .map(|&p| {
+                        let world = initial_pose.position + heading.rotate(p);
+                        ((world - self.origin) / self.config.resolution)
+                            .floor()
+                            .as_ivec2()
+                    })
+                    .collect::<Vec<_>>();
+
+                (angle, cells)
+            })
+            .collect::<Vec<_>>();
+
+        let score = |rotation: usize, offset: glam::IVec2, level: usize| {
+            let grid = &self.levels[level];
+            let total: f32 = rotated[rotation]
+                .1
+                .iter()
+                .map(|&cell| cell + offset)
+                .filter(|cell| cell.cmpge(glam::IVec2::ZERO).all() && cell.cmplt(self.size).all())
+                .map(|cell| grid[(cell.x + cell.y * self.size.x) as usize])
+                .sum();
+
+            total / scan.len() as f32
+        };
+
+        let depth = self.config.depth;
+        let top = 1 << depth;
+        let mut candidates = Vec::new();
+        for rotation in 0..rotated.len() {
+            for y in (-reach..=reach).step_by(top) {
+                for x in (-reach..=reach).step_by(top) {
+                    let offset = glam::ivec2(x, y);
+                    candidates.push((score(rotation, offset, depth), rotation, offset, depth));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Depth first through the most promising candidates, skipping any that can't beat the best leaf so far.
+        let mut best: Option<(f32, usize, glam::IVec2)> = None;
+        while let Some((bound, rotation, offset, level)) = candidates.pop() {
+            if best.is_some_and(|(score, _, _)| bound <= score) {
+                continue;
+            }
+            if level == 0 {
+                best = Some((bound, rotation, offset));
+                continue;
+            }
+
+            let half = 1 << (level - 1);
+            let mut children = [(0, 0), (half, 0), (0, half), (half, half)]
+                .into_iter()
+                .map(|(x, y)| offset + glam::ivec2(x, y))
+                .filter(|child| child.cmple(glam::IVec2::splat(reach)).all())
+                .map(|child| {
+                    (
+                        score(rotation, child, level - 1),
+                        rotation,
+                        child,
+                        level - 1,
+                    )
+                })
+                .collect::<Vec<_>>();
+            children.sort_by(|a, b| a.0.total_cmp(&b.0));
+            candidates.extend(children);
+        }
+
+        best.map(|(score, rotation, offset)| ScanMatch {
+            pose: Pose2D::new(
+                initial_pose.position + offset.as_vec2() * self.config.resolution,
+                rotated[rotation].0,
+            ),
+            score,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::Pose2D,
+        scene::{generate, occupancy_map::OccupancyMap},
+        slam::correlative::{CorrelativeConfig, CorrelativeScanMatcher, SearchWindow},
+    };
+
+    #[test]
+    fn test_correlative_matching() {
+        let size = glam::usizevec2(24, 24);
+        let pixels = generate::rooms(size.to_array(), [2, 2], 1, 4)
+            .into_iter()
+            .map(|p| p <= 127)
+            .collect();
+        let map = OccupancyMap::from_pixels(size, pixels).unwrap();
+
+        let truth = Pose2D::new(glam::vec2(-4.5, -3.), 0.4);
+        let scan = (0..90)
+            .map(|i| glam::Vec2::from_angle(i as f32 * std::f32::consts::TAU / 90.))
+            .filter_map(|dir| Some(dir * map.cast_rays(truth.position, truth.heading.rotate(dir))?))
+            .collect::<Vec<_>>();
+
+        let matcher = CorrelativeScanMatcher::new(&map, CorrelativeConfig::default()).unwrap();
+        let initial = Pose2D::new(glam::vec2(-3.8, -3.6), 0.2);
+        let found = matcher
+            .match_scan(
+                &scan,
+                initial,
+                SearchWindow {
+                    linear: 1.5,
+                    angular: 0.4,
+                },
+            )
+            .unwrap();
+
+        assert!(
+            found.pose.position.distance(truth.position) <= 0.25,
+            "{found:?}"
+        );
+        assert!(
+            (found.pose.angle() - truth.angle()).abs() <= 0.05,
+            "{found:?}"
+        );
+        assert!(found.score > 0.8);
+
+        assert!(
+            matcher
+                .match_scan(
+                    &[],
+                    initial,
+                    SearchWindow {
+                        linear: 1.,
+                        angular: 0.
+                    }
+                )
+                .is_none()
+        );
+    }
+}
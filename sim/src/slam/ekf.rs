@@ -0,0 +1,479 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rand_distr::{Distribution, Normal};
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    localization::{Localizer, LocalizerContext, PoseEstimate, outer},
+    math::Pose2D,
+    rng,
+    scene::SceneTime,
+    sensors::landmark::{LandmarkSensed, RangeBearing},
+};
+
+/// EKF-SLAM over point landmarks: a joint Gaussian over the agent pose and every landmark seen so far, predicted with
+/// noisy odometry and corrected with range-bearing observations.
+///
+/// The landmark ids the sensor reports are ground truth, so they are ignored. Each observation is associated with the
+/// nearest landmark by Mahalanobis distance, and starts a new landmark when no known one could explain it.
+#[derive(Debug, Clone)]
+pub struct EkfSlam2D {
+    /// Standard deviation of the velocity reading, relative to the velocity.
+    pub velocity_noise: f32,
+    /// Standard deviation of the steering reading in radians.
+    pub beta_noise: f32,
+    /// Standard deviation of observed ranges in metres.
+    pub range_noise: f32,
+    /// Standard deviation of observed bearings in radians.
+    pub bearing_noise: f32,
+    /// Squared Mahalanobis distance within which an observation is matched to a landmark. The default lets through 99%
+    /// of true matches.
+    pub association_gate: f32,
+    /// Squared Mahalanobis distance from every landmark beyond which an observation starts a new one. Observations
+    /// between the two gates are ambiguous and dropped.
+    pub new_landmark_gate: f32,
+    pub landmark_topic: String,
+    /// `[x, y, angle, landmark 0 x, landmark 0 y, ...]`.
+    mean: Vec<f32>,
+    /// Row-major, `mean.len()` square.
+    covariance: Vec<f32>,
+    last_observation: Option<SceneTime>,
+}
+
+impl Default for EkfSlam2D {
+    fn default() -> Self {
+        Self {
+            velocity_noise: 0.1,
+            beta_noise: 0.05,
+            range_noise: 0.1,
+            bearing_noise: 0.02,
+            association_gate: 9.21,
+            new_landmark_gate: 25.,
+            landmark_topic: "landmarks".to_string(),
+            mean: Vec::new(),
+            covariance: Vec::new(),
+            last_observation: None,
+        }
+    }
+}
+
+impl Validate for EkfSlam2D {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (field, value) in [
+            ("velocity_noise", self.velocity_noise),
+            ("beta_noise", self.beta_noise),
+        ] {
+            config::finite(field, value)?;
+            config::non_negative(field, value)?;
+        }
+        config::positive("range_noise", self.range_noise)?;
+        config::positive("bearing_noise", self.bearing_noise)?;
+        config::positive("association_gate", self.association_gate)?;
+        config::within(
+            "new_landmark_gate",
+            self.new_landmark_gate,
+            self.new_landmark_gate >= self.association_gate,
+            "at least association_gate",
+        )
+    }
+}
+
+/// Linearized observation of one landmark from the current mean.
+struct Predicted {
+    landmark: usize,
+    /// Observation minus prediction, as `(range, bearing)`.
+    innovation: glam::Vec2,
+    /// Jacobian of the observation with respect to the pose, one row per component.
+    pose_jacobian: [glam::Vec3; 2],
+    /// Jacobian of the observation with respect to the landmark.
+    landmark_jacobian: glam::Mat2,
+    /// Innovation covariance.
+    covariance: glam::Mat2,
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    glam::Vec2::from_angle(angle).to_angle()
+}
+
+/// `A M A^T` for the 2 by 3 matrix `A` with rows `a`.
+fn project(a: [glam::Vec3; 2], m: glam::Mat3) -> glam::Mat2 {
+    let entry = |r: usize, c: usize| a[r].dot(m * a[c]);
+    glam::mat2(
+        glam::vec2(entry(0, 0), entry(1, 0)),
+        glam::vec2(entry(0, 1), entry(1, 1)),
+    )
+}
+
+impl EkfSlam2D {
+    fn dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn pose(&self) -> Option<Pose2D> {
+        (self.dim() >= 3).then(|| Pose2D::new(glam::vec2(self.mean[0], self.mean[1]), self.mean[2]))
+    }
+
+    pub fn landmark_count(&self) -> usize {
+        self.dim().saturating_sub(3) / 2
+    }
+
+    /// Estimated position of the `i`th landmark found and its covariance. Landmarks are numbered in the order they
+    /// were first seen, which has nothing to do with the scene's [LandmarkId](crate::scene::LandmarkId)s.
+    pub fn landmark(&self, i: usize) -> Option<(glam::Vec2, glam::Mat2)> {
+        if i >= self.landmark_count() {
+            return None;
+        }
+
+        let j = 3 + 2 * i;
+        let position = glam::vec2(self.mean[j], self.mean[j + 1]);
+        let covariance = glam::mat2(
+            glam::vec2(self.p(j, j), self.p(j + 1, j)),
+            glam::vec2(self.p(j, j + 1), self.p(j + 1, j + 1)),
+        );
+
+        Some((position, covariance))
+    }
+
+    /// Covariance of the pose, as `(x, y, angle)`.
+    pub fn pose_covariance(&self) -> glam::Mat3 {
+        if self.dim() < 3 {
+            return glam::Mat3::ZERO;
+        }
+
+        let col = |c: usize| glam::vec3(self.p(0, c), self.p(1, c), self.p(2, c));
+        glam::Mat3::from_cols(col(0), col(1), col(2))
+    }
+
+    fn p(&self, row: usize, col: usize) -> f32 {
+        self.covariance[row * self.dim() + col]
+    }
+
+    fn estimate(&self, time: SceneTime) -> Option<PoseEstimate> {
+        Some(PoseEstimate {
+            time,
+            pose: self.pose()?,
+            covariance: self.pose_covariance(),
+        })
+    }
+
+    fn predict(&mut self, ctx: &LocalizerContext) {
+        let sample = |std_dev: f32| {
+            Normal::new(0., std_dev)
+                .map(|n| n.sample(&mut rng::rng()))
+                .unwrap_or(0.)
+        };
+        let velocity_std = self.velocity_noise * ctx.odometry.velocity.abs();
+        let velocity = ctx.odometry.velocity + sample(velocity_std);
+        let beta = ctx.odometry.beta + sample(self.beta_noise);
+
+        let (dt, length, n) = (ctx.dt, ctx.config.length, self.dim());
+        let (sin, cos) = self.mean[2].sin_cos();
+
+        // Only the pose moves, so only the pose rows and columns of the covariance change.
+        let jacobian = glam::Mat3::from_cols(
+            glam::Vec3::X,
+            glam::Vec3::Y,
+            glam::vec3(-velocity * sin * dt, velocity * cos * dt, 1.),
+        );
+        let d_velocity = glam::vec3(cos * dt, sin * dt, beta.tan() / length * dt);
+        let d_beta = glam::vec3(0., 0., velocity / (length * beta.cos().powi(2)) * dt);
+
+        let pose_covariance = jacobian * self.pose_covariance() * jacobian.transpose()
+            + outer(d_velocity) * velocity_std.powi(2)
+            + outer(d_beta) * self.beta_noise.powi(2);
+        for col in 3..n {
+            let cross = jacobian * glam::vec3(self.p(0, col), self.p(1, col), self.p(2, col));
+            for row in 0..3 {
+                self.covariance[row * n + col] = cross[row];
+                self.covariance[col * n + row] = cross[row];
+            }
+        }
+        for row in 0..3 {
+            for col in 0..3 {
+                self.covariance[row * n + col] = pose_covariance.col(col)[row];
+            }
+        }
+
+        self.mean[0] += velocity * cos * dt;
+        self.mean[1] += velocity * sin * dt;
+        self.mean[2] = wrap_angle(self.mean[2] + velocity * beta.tan() / length * dt);
+    }
+
+    fn noise(&self) -> glam::Mat2 {
+        glam::Mat2::from_diagonal(glam::vec2(
+            self.range_noise.powi(2),
+            self.bearing_noise.powi(2),
+        ))
+    }
+
+    fn predict_observation(
+        &self,
+        landmark: usize,
+        observation: &RangeBearing,
+    ) -> Option<Predicted> {
+        let j = 3 + 2 * landmark;
+        let delta = glam::vec2(self.mean[j] - self.mean[0], self.mean[j + 1] - self.mean[1]);
+        let q = delta.length_squared();
+        if q < f32::EPSILON {
+            return None;
+        }
+        let r = q.sqrt();
+
+        let innovation = glam::vec2(
+            observation.range - r,
+            wrap_angle(observation.bearing - (delta.y.atan2(delta.x) - self.mean[2])),
+        );
+        let pose_jacobian = [
+            glam::vec3(-delta.x / r, -delta.y / r, 0.),
+            glam::vec3(delta.y / q, -delta.x / q, -1.),
+        ];
+        // Rows of the landmark Jacobian, stored as columns of the transpose.
+        let landmark_rows = [
+            glam::vec2(delta.x / r, delta.y / r),
+            glam::vec2(-delta.y / q, delta.x / q),
+        ];
+
+        // H P H^T, summed over the pose and landmark blocks of H.
+        let p_rm = |k: usize| glam::vec3(self.p(0, j + k), self.p(1, j + k), self.p(2, j + k));
+        let entry = |r: usize, c: usize| {
+            pose_jacobian[r].dot(p_rm(0)) * landmark_rows[c].x
+                + pose_jacobian[r].dot(p_rm(1)) * landmark_rows[c].y
+        };
+        let cross = glam::mat2(
+            glam::vec2(entry(0, 0), entry(1, 0)),
+            glam::vec2(entry(0, 1), entry(1, 1)),
+        );
+        let landmark_covariance = glam::mat2(
+            glam::vec2(self.p(j, j), self.p(j + 1, j)),
+            glam::vec2(self.p(j, j + 1), self.p(j + 1, j + 1)),
+        );
+        let landmark_jacobian = glam::Mat2::from_cols(
+            glam::vec2(landmark_rows[0].x, landmark_rows[1].x),
+            glam::vec2(landmark_rows[0].y, landmark_rows[1].y),
+        );
+        let covariance = project(pose_jacobian, self.pose_covariance())
+            + cross
+            + cross.transpose()
+            + landmark_jacobian * landmark_covariance * landmark_jacobian.transpose()
+            + self.noise();
+
+        Some(Predicted {
+            landmark,
+            innovation,
+            pose_jacobian,
+            landmark_jacobian,
+            covariance,
+        })
+    }
+
+    fn correct(&mut self, predicted: &Predicted) {
+        let n = self.dim();
+        let j = 3 + 2 * predicted.landmark;
+        let s_inverse = predicted.covariance.inverse();
+
+        // P H^T, one row per state component.
+        let p_ht = (0..n)
+            .map(|i| {
+                let pose = glam::vec3(self.p(i, 0), self.p(i, 1), self.p(i, 2));
+                let landmark = glam::vec2(self.p(i, j), self.p(i, j + 1));
+                glam::vec2(
+                    predicted.pose_jacobian[0].dot(pose)
+                        + predicted.landmark_jacobian.row(0).dot(landmark),
+                    predicted.pose_jacobian[1].dot(pose)
+                        + predicted.landmark_jacobian.row(1).dot(landmark),
+                )
+            })
+            .collect::<Vec<_>>();
+        let gain = p_ht
+            .iter()
+            .map(|&row| s_inverse.transpose() * row)
+            .collect::<Vec<_>>();
+
+        for (m, k) in self.mean.iter_mut().zip(&gain) {
+            *m += k.dot(predicted.innovation);
+        }
+        self.mean[2] = wrap_angle(self.mean[2]);
+
+        // P - K H P, where H P is the transpose of P H^T.
+        for (row, k) in self.covariance.chunks_exact_mut(n).zip(&gain) {
+            for (p, hp) in row.iter_mut().zip(&p_ht) {
+                *p -= k.dot(*hp);
+            }
+        }
+        // Keep the covariance symmetric despite rounding.
+        for row in 0..n {
+            for col in row + 1..n {
+                let mean = (self.covariance[row * n + col] + self.covariance[col * n + row]) / 2.;
+                self.covariance[row * n + col] = mean;
+                self.covariance[col * n + row] = mean;
+            }
+        }
+    }
+
+    fn add_landmark(&mut self, observation: &RangeBearing) {
+        let n = self.dim();
+        let angle = self.mean[2] + observation.bearing;
+        let (sin, cos) = angle.sin_cos();
+        let range = observation.range;
+
+        // Jacobians of the landmark position with respect to the pose and to the observation.
+        let pose_jacobian = [
+            glam::vec3(1., 0., -range * sin),
+            glam::vec3(0., 1., range * cos),
+        ];
+        let observation_jacobian =
+            glam::mat2(glam::vec2(cos, sin), glam::vec2(-range * sin, range * cos));
+
+        let cross = (0..n)
+            .map(|i| {
+                let pose = glam::vec3(self.p(0, i), self.p(1, i), self.p(2, i));
+                glam::vec2(pose_jacobian[0].dot(pose), pose_jacobian[1].dot(pose))
+            })
+            .collect::<Vec<_>>();
+        let landmark_covariance = project(pose_jacobian, self.pose_covariance())
+            + observation_jacobian * self.noise() * observation_jacobian.transpose();
+
+        let m = n + 2;
+        let mut covariance = vec![0.; m * m];
+        for row in 0..n {
+            covariance[row * m..row * m + n]
+                .copy_from_slice(&self.covariance[row * n..(row + 1) * n]);
+            covariance[row * m + n] = cross[row].x;
+            covariance[row * m + n + 1] = cross[row].y;
+        }
+        for k in 0..2 {
+            for col in 0..n {
+                covariance[(n + k) * m + col] = cross[col][k];
+            }
+            covariance[(n + k) * m + n] = landmark_covariance.col(0)[k];
+            covariance[(n + k) * m + n + 1] = landmark_covariance.col(1)[k];
+        }
+
+        self.covariance = covariance;
+        self.mean
+            .extend([self.mean[0] + range * cos, self.mean[1] + range * sin]);
+    }
+
+    fn observe(&mut self, observation: &RangeBearing) {
+        let nearest = (0..self.landmark_count())
+            .filter_map(|i| self.predict_observation(i, observation))
+            .map(|p| {
+                let distance = p.innovation.dot(p.covariance.inverse() * p.innovation);
+                (distance, p)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match nearest {
+            Some((distance, predicted)) if distance <= self.association_gate => {
+                self.correct(&predicted)
+            }
+            Some((distance, _)) if distance <= self.new_landmark_gate => {}
+            _ => self.add_landmark(observation),
+        }
+    }
+}
+
+impl Localizer for EkfSlam2D {
+    fn reset(&mut self, pose: Pose2D) {
+        self.mean = vec![pose.position.x, pose.position.y, pose.angle()];
+        self.covariance = vec![0.; 9];
+        self.last_observation = None;
+    }
+
+    fn update(&mut self, ctx: &LocalizerContext) -> Option<PoseEstimate> {
+        if self.dim() < 3 {
+            return None;
+        }
+
+        self.predict(ctx);
+
+        if let Some(observations) = ctx.measurement::<LandmarkSensed>(&self.landmark_topic)
+            && self
+                .last_observation
+                .is_none_or(|last| last.0 < observations.time.0)
+        {
+            self.last_observation = Some(observations.time);
+            for observation in &observations.state.0 {
+                self.observe(observation);
+            }
+        }
+
+        self.estimate(ctx.time)
+    }
+
+    fn try_clone(&self) -> Option<Arc<Mutex<dyn Localizer>>> {
+        Some(Arc::new(Mutex::new(self.clone())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        Agent2D, Scene2D,
+        localization::Localizer,
+        math::Pose2D,
+        sensors::{Sensor2D, landmark::LandmarkSensor2D},
+        slam::ekf::EkfSlam2D,
+    };
+
+    #[test]
+    fn test_ekf_slam() {
+        let mut scene = Scene2D::from_pixels([24, 24], &[255; 24 * 24]).unwrap();
+        scene.scene_loop.set_seed(Some(3));
+        let landmarks = [
+            glam::vec2(4., 0.),
+            glam::vec2(-3., 4.),
+            glam::vec2(-4., -3.),
+            glam::vec2(2., -5.),
+            glam::vec2(6., 6.),
+        ];
+        for landmark in landmarks {
+            scene.add_landmark(landmark);
+        }
+
+        let mut agent = Agent2D::default();
+        agent.state = agent.state.with_pose(Pose2D::new(glam::Vec2::ZERO, 0.));
+        agent.state.velocity = 1.;
+        agent.state.beta = 0.2;
+        agent
+            .sensors
+            .insert(LandmarkSensor2D::TOPIC, LandmarkSensor2D::default());
+        let ekf = EkfSlam2D {
+            velocity_noise: 0.05,
+            beta_noise: 0.02,
+            ..Default::default()
+        };
+        let ekf = Arc::new(Mutex::new(ekf));
+        agent.localizer = Some(Arc::clone(&ekf) as Arc<Mutex<dyn Localizer>>);
+        let id = scene.add_agent(agent);
+
+        for _ in 0..100 {
+            scene.update(0.05);
+        }
+
+        let agent = &scene.agents[&id];
+        let estimate = agent.estimate.unwrap();
+        assert!(
+            estimate.pose.position.distance(agent.state.position) < 0.3,
+            "{estimate:?} vs {:?}",
+            agent.state.pose()
+        );
+        assert!(estimate.pose.heading.angle_to(agent.state.heading).abs() < 0.1);
+
+        let ekf = ekf.lock();
+        assert_eq!(ekf.landmark_count(), landmarks.len());
+        for i in 0..ekf.landmark_count() {
+            let (position, covariance) = ekf.landmark(i).unwrap();
+            assert!(
+                landmarks.iter().any(|l| l.distance(position) < 0.3),
+                "{position}"
+            );
+            assert!(covariance.determinant() > 0.);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Lidar2D,
+    config::{self, ConfigError, Validate},
+    math::Pose2D,
+    scene::occupancy_map::OccupancyMap,
+    sensors::lidar::Lidar2DSensed,
+};
+
+/// What the distance between a source point and its match is measured to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IcpMetric {
+    /// The matched target point.
+    #[default]
+    PointToPoint,
+    /// The line through the matched target point along the surface. Converges in fewer iterations on walls.
+    PointToLine,
+}
+
+/// Iterative closest point alignment of 2D point sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Icp {
+    pub metric: IcpMetric,
+    pub max_iterations: usize,
+    /// Source points further than this from every target point are left unmatched.
+    pub max_correspondence_distance: f32,
+    /// Stop once an iteration moves the estimate by less than this, in metres and radians.
+    pub tolerance: f32,
+}
+
+impl Default for Icp {
+    fn default() -> Self {
+        Self {
+            metric: IcpMetric::default(),
+            max_iterations: 30,
+            max_correspondence_distance: 1.,
+            tolerance: 1e-4,
+        }
+    }
+}
+
+impl Validate for Icp {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("max_iterations", self.max_iterations, 1)?;
+        config::positive(
+            "max_correspondence_distance",
+            self.max_correspondence_distance,
+        )?;
+        config::non_negative("tolerance", self.tolerance)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IcpResult {
+    /// Maps source points onto the target, i.e. the pose of the source frame in the target frame.
+    pub transform: Pose2D,
+    /// Fraction of source points matched to the target at the final estimate.
+    pub fitness: f32,
+    /// Root mean square of the matched distances, under the chosen [IcpMetric].
+    pub rmse: f32,
+    pub iterations: usize,
+    /// Whether the estimate settled within [Icp::tolerance] before running out of iterations.
+    pub converged: bool,
+}
+
+/// Points to align against, with a surface normal for each.
+#[derive(Debug, Clone)]
+pub struct IcpTarget {
+    points: Vec<glam::Vec2>,
+    normals: Vec<glam::Vec2>,
+    grid: PointGrid,
+}
+
+impl IcpTarget {
+    /// Normals are estimated from each point's nearest neighbour within `spacing`, which should be a little more than
+    /// the typical distance between neighbouring points.
+    pub fn from_points(points: Vec<glam::Vec2>, spacing: f32) -> Self {
+        let grid = PointGrid::new(&points, spacing);
+        let normals = points
+            .iter()
+            .map(|&p| {
+                grid.nearest(&points, p, spacing, true)
+                    .map_or(glam::Vec2::ZERO, |(j, _)| {
+                        (points[j] - p).perp().normalize_or_zero()
+                    })
+            })
+            .collect();
+
+        Self {
+            points,
+            normals,
+            grid,
+        }
+    }
+
+    /// The middles of each cell's side along the map's [boundaries](OccupancyMap::boundaries), with their exact normals.
+    pub fn from_map(map: &OccupancyMap) -> Self {
+        let (points, normals): (Vec<_>, Vec<_>) = map
+            .boundaries
+            .iter()
+            .flat_map(|b| {
+                // Boundaries along straight walls are merged, so are split back into cell sides.
+                let sides = (b.0.distance(b.1) / map.resolution).round().max(1.);
+                (0..sides as usize).map(move |i| {
+                    let t = (i as f32 + 0.5) / sides;
+                    (b.0.lerp(b.1, t), b.normal())
+                })
+            })
+            .unzip();
+
+        Self {
+            grid: PointGrid::new(&points, 1.),
+            points,
+            normals,
+        }
+    }
+
+    pub fn points(&self) -> &[glam::Vec2] {
+        &self.points
+    }
+}
+
+/// Sensor-frame points of the rays in `scan` that hit something. `lidar` must be the sensor that took the scan.
+pub fn scan_points(lidar: &Lidar2D, scan: &Lidar2DSensed) -> Vec<glam::Vec2> {
+    lidar
+        .directions
+        .iter()
+        .zip(&scan.ranges)
+        .filter(|(_, range)| range.is_finite())
+        .map(|(&dir, &range)| dir * range)
+        .collect()
+}
+
+/// Buckets points by square cells, so a nearest neighbour search only looks at the cells within reach.
+#[derive(Debug, Clone)]
+struct PointGrid {
+    cell_size: f32,
+    cells: FxHashMap<glam::IVec2, Vec<usize>>,
+}
+
+impl PointGrid {
+    fn new(points: &[glam::Vec2], cell_size: f32) -> Self {
+        let mut grid = Self {
+            cell_size,
+            cells: FxHashMap::default(),
+        };
+        for (i, &p) in points.iter().enumerate() {
+            grid.cells.entry(grid.cell(p)).or_default().push(i);
+        }
+
+        grid
+    }
+
+    fn cell(&self, p: glam::Vec2) -> glam::IVec2 {
+        (p / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Index of and distance to the point nearest `p` within `max_distance`, optionally ignoring points at `p` itself.
+    fn nearest(
+        &self,
+        points: &[glam::Vec2],
+        p: glam::Vec2,
+        max_distance: f32,
+        exclude_coincident: bool,
+    ) -> Option<(usize, f32)> {
+        let cell = self.cell(p);
+        let reach = (max_distance / self.cell_size).ceil() as i32;
+
+        (-reach..=reach)
+            .flat_map(|y| (-reach..=reach).map(move |x| cell + glam::ivec2(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .map(|&i| (i, points[i].distance(p)))
+            .filter(|&(_, d)| d <= max_distance && !(exclude_coincident && d <= f32::EPSILON))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+impl Icp {
+    /// Aligns `source` to `target`, starting from `initial`.
+    pub fn align(
+        &self,
+        source: &[glam::Vec2],
+        target: &IcpTarget,
+        initial: Pose2D,
+    ) -> Result<IcpResult, IcpError> {
+        self.validate()?;
+
+        let min_pairs = match self.metric {
+            IcpMetric::PointToPoint => 2,
+            IcpMetric::PointToLine => 3,
+        };
+
+        let mut transform = initial;
+        let mut converged = false;
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            iterations += 1;
+
+            let pairs = self.correspondences(source, target, transform);
+            if pairs.len() < min_pairs {
+                return Err(IcpError::TooFewMatches(pairs.len()));
+            }
+
+            let delta = match self.metric {
+                IcpMetric::PointToPoint => point_to_point(&pairs),
+                IcpMetric::PointToLine => point_to_line(&pairs).ok_or(IcpError::Degenerate)?,
+            };
+            transform = delta.compose(&transform);
+
+            if delta.position.length() < self.tolerance && delta.angle().abs() < self.tolerance {
+                converged = true;
+                break;
+            }
+        }
+
+        let pairs = self.correspondences(source, target, transform);
+        let squared: f32 = pairs
+            .iter()
+            .map(|pair| match self.metric {
+                IcpMetric::PointToPoint => pair.source.distance_squared(pair.target),
+                IcpMetric::PointToLine => pair.normal.dot(pair.source - pair.target).powi(2),
+            })
+            .sum();
+
+        Ok(IcpResult {
+            transform,
+            fitness: pairs.len() as f32 / source.len().max(1) as f32,
+            rmse: (squared / pairs.len().max(1) as f32).sqrt(),
+            iterations,
+            converged,
+        })
+    }
+
+    /// Aligns two scans taken by `lidar`. The result is the pose of the sensor at `source` relative to where it was at
+    /// `target`.
+    pub fn align_scans(
+        &self,
+        lidar: &Lidar2D,
+        source: &Lidar2DSensed,
+        target: &Lidar2DSensed,
+        initial: Pose2D,
+    ) -> Result<IcpResult, IcpError> {
+        // Neighbouring rays of a lidar with n directions hit points about `range * TAU / n` apart.
+        let target = scan_points(lidar, target);
+        let spacing = target.iter().map(|p| p.length()).fold(0., f32::max) * std::f32::consts::TAU
+            / lidar.directions.len().max(1) as f32;
+        let target = IcpTarget::from_points(target, spacing.max(1e-3) * 2.);
+
+        self.align(&scan_points(lidar, source), &target, initial)
+    }
+
+    /// Pairs each source point, moved by `transform`, with its nearest target point.
+    fn correspondences(
+        &self,
+        source: &[glam::Vec2],
+        target: &IcpTarget,
+        transform: Pose2D,
+    ) -> Vec<Pair> {
+        source
+            .iter()
+            .map(|&p| transform.transform_point(p))
+            .filter_map(|p| {
+                let (i, _) = target.grid.nearest(
+                    &target.points,
+                    p,
+                    self.max_correspondence_distance,
+                    false,
+                )?;
+                let normal = target.normals[i];
+
+                (self.metric == IcpMetric::PointToPoint || normal != glam::Vec2::ZERO).then_some(
+                    Pair {
+                        source: p,
+                        target: target.points[i],
+                        normal,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pair {
+    source: glam::Vec2,
+    target: glam::Vec2,
+    normal: glam::Vec2,
+}
+
+/// Closed-form rigid transform minimizing the squared distances between pairs.
+fn point_to_point(pairs: &[Pair]) -> Pose2D {
+    let n = pairs.len() as f32;
+    let source_mean = pairs.iter().map(|p| p.source).sum::<glam::Vec2>() / n;
+    let target_mean = pairs.iter().map(|p| p.target).sum::<glam::Vec2>() / n;
+
+    let (dot, cross) = pairs.iter().fold((0., 0.), |(dot, cross), pair| {
+        let s = pair.source - source_mean;
+        let t = pair.target - target_mean;
+        (dot + s.dot(t), cross + s.perp_dot(t))
+    });
+    let heading = glam::vec2(dot, cross).normalize_or(glam::Vec2::X);
+
+    Pose2D {
+        position: target_mean - heading.rotate(source_mean),
+        heading,
+    }
+}
+
+/// One Gauss-Newton step on the distances from the source points to the lines through their targets, linearized
+/// about no rotation. `None` if the lines don't pin down the transform, e.g. they are all parallel.
+fn point_to_line(pairs: &[Pair]) -> Option<Pose2D> {
+    let (jtj, jtr) = pairs
+        .iter()
+        .fold((glam::Mat3::ZERO, glam::Vec3::ZERO), |(jtj, jtr), pair| {
+            let n = pair.normal;
+            let jacobian = glam::vec3(n.x, n.y, n.dot(pair.source.perp()));
+            let residual = n.dot(pair.source - pair.target);
+
+            (
+                jtj + glam::Mat3::from_cols(
+                    jacobian * jacobian.x,
+                    jacobian * jacobian.y,
+                    jacobian * jacobian.z,
+                ),
+                jtr + jacobian * residual,
+            )
+        });
+
+    if jtj.determinant().abs() < 1e-9 {
+        return None;
+    }
+    let step = -(jtj.inverse() * jtr);
+
+    Some(Pose2D::new(step.truncate(), step.z))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IcpError {
+    #[error("Only {0} points could be matched, too few to align")]
+    TooFewMatches(usize),
+
+    #[error("The matched points don't constrain the alignment, e.g. they all lie on one line")]
+    Degenerate,
+
+    #[error("Invalid ICP config: {0}")]
+    Config(#[from] ConfigError),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::Pose2D,
+        slam::icp::{Icp, IcpMetric, IcpTarget},
+    };
+
+    #[test]
+    fn test_icp() {
+        // The outline of a 4 by 2 room.
+        let outline = |spacing: f32| {
+            let (nx, ny) = ((4. / spacing) as usize, (2. / spacing) as usize);
+            let x = (0..nx).map(move |i| i as f32 * spacing - 2.);
+            let y = (0..ny).map(move |i| i as f32 * spacing - 1.);
+
+            x.flat_map(|x| [glam::vec2(x, -1.), glam::vec2(x, 1.)])
+                .chain(y.flat_map(|y| [glam::vec2(-2., y), glam::vec2(2., y)]))
+                .collect::<Vec<_>>()
+        };
+
+        // A real scan never samples the walls at the same places as the map, so the source is sparser.
+        let truth = Pose2D::new(glam::vec2(0.15, -0.1), 0.08);
+        let source = outline(0.1)
+            .into_iter()
+            .map(|p| truth.inverse().transform_point(p))
+            .collect::<Vec<_>>();
+        let target = outline(0.02);
+
+        for metric in [IcpMetric::PointToPoint, IcpMetric::PointToLine] {
+            let icp = Icp {
+                metric,
+                max_iterations: 50,
+                ..Default::default()
+            };
+            let target = IcpTarget::from_points(target.clone(), 0.05);
+            let result = icp.align(&source, &target, Pose2D::default()).unwrap();
+
+            assert!(result.converged, "{metric:?}: {result:?}");
+            assert!(
+                result.transform.position.distance(truth.position) < 1e-2,
+                "{metric:?}: {result:?}"
+            );
+            assert!((result.transform.angle() - truth.angle()).abs() < 1e-2);
+            assert_eq!(result.fitness, 1., "{metric:?}: {result:?}");
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::Pose2D,
+    slam::{
+        icp::{Icp, IcpError, IcpResult, IcpTarget},
+        pose_graph::NodeId,
+    },
+};
+
+/// Signature of a scan that doesn't change when the sensor turns on the spot: the share of points in each ring of
+/// range around the sensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanDescriptor(pub Vec<f32>);
+
+impl ScanDescriptor {
+    /// Half the L1 distance between the histograms, from 0 for identical descriptors to 1 for disjoint ones.
+    pub fn distance(&self, other: &Self) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f32>()
+            / 2.
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopCandidate {
+    pub node: NodeId,
+    /// [ScanDescriptor::distance] to the keyframe.
+    pub distance: f32,
+}
+
+/// Proposes loop closures by comparing the descriptor of each new keyframe scan with those of earlier ones. The
+/// proposals are only candidates: check each with [LoopClosureDetector::align] before adding it to a
+/// [PoseGraph](crate::slam::pose_graph::PoseGraph), since places that look alike are common.
+#[derive(Debug, Clone)]
+pub struct LoopClosureDetector {
+    /// Number of range rings in each descriptor.
+    pub bins: usize,
+    /// Points beyond this range, in metres, are left out of descriptors.
+    pub max_range: f32,
+    /// Largest descriptor distance proposed as a loop closure.
+    pub threshold: f32,
+    /// The most recent keyframes are skipped, since they overlap the new one without closing a loop.
+    pub skip_recent: usize,
+    pub max_candidates: usize,
+    /// Spacing of the points in keyframe scans, used to fit the lines they are aligned against.
+    pub spacing: f32,
+    keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone)]
+struct Keyframe {
+    node: NodeId,
+    descriptor: ScanDescriptor,
+    target: IcpTarget,
+}
+
+impl Default for LoopClosureDetector {
+    fn default() -> Self {
+        Self {
+            bins: 20,
+            max_range: 10.,
+            threshold: 0.15,
+            skip_recent: 10,
+            max_candidates: 3,
+            spacing: 0.2,
+            keyframes: Vec::new(),
+        }
+    }
+}
+
+impl Validate for LoopClosureDetector {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("bins", self.bins, 1)?;
+        config::positive("max_range", self.max_range)?;
+        config::finite("max_range", self.max_range)?;
+        config::within(
+            "threshold",
+            self.threshold,
+            (0. ..=1.).contains(&self.threshold),
+            "[0, 1]",
+        )?;
+        config::positive("spacing", self.spacing)
+    }
+}
+
+impl LoopClosureDetector {
+    /// Descriptor of sensor-frame scan points, e.g. from [scan_points](crate::slam::icp::scan_points).
+    pub fn descriptor(&self, scan: &[glam::Vec2]) -> ScanDescriptor {
+        let mut histogram = vec![0.; self.bins];
+        for range in scan.iter().map(|p| p.length()) {
+            if range < self.max_range {
+                histogram[(range / self.max_range * self.bins as f32) as usize] += 1.;
+            }
+        }
+
+        let total = histogram.iter().sum::<f32>();
+        if total > 0. {
+            histogram.iter_mut().for_each(|h| *h /= total);
+        }
+
+        ScanDescriptor(histogram)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Earlier keyframes that `scan` may be a revisit of, best first.
+    pub fn query(&self, scan: &[glam::Vec2]) -> Vec<LoopCandidate> {
+        let descriptor = self.descriptor(scan);
+        let searched = self.keyframes.len().saturating_sub(self.skip_recent);
+
+        let mut candidates = self.keyframes[..searched]
+            .iter()
+            .map(|keyframe| LoopCandidate {
+                node: keyframe.node,
+                distance: keyframe.descriptor.distance(&descriptor),
+            })
+            .filter(|candidate| candidate.distance <= self.threshold)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        candidates.truncate(self.max_candidates);
+
+        candidates
+    }
+
+    /// Remembers the scan taken at `node` and returns the loop closures it proposes.
+    pub fn add_keyframe(&mut self, node: NodeId, scan: &[glam::Vec2]) -> Vec<LoopCandidate> {
+        let candidates = self.query(scan);
+        self.keyframes.push(Keyframe {
+            node,
+            descriptor: self.descriptor(scan),
+            target: IcpTarget::from_points(scan.to_vec(), self.spacing),
+        });
+
+        candidates
+    }
+
+    /// Aligns `scan` against the keyframe scan of `candidate`, giving the pose of `scan` in the keyframe's frame for
+    /// [PoseGraph::add_scan_match](crate::slam::pose_graph::PoseGraph::add_scan_match). `initial` is the current guess
+    /// of that pose, e.g. from the pose graph.
+    pub fn align(
+        &self,
+        icp: &Icp,
+        candidate: &LoopCandidate,
+        scan: &[glam::Vec2],
+        initial: Pose2D,
+    ) -> Option<Result<IcpResult, IcpError>> {
+        let keyframe = self.keyframes.iter().find(|k| k.node == candidate.node)?;
+
+        Some(icp.align(scan, &keyframe.target, initial))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::Pose2D,
+        scene::{generate, occupancy_map::OccupancyMap},
+        slam::{icp::Icp, loop_closure::LoopClosureDetector, pose_graph::NodeId},
+    };
+
+    #[test]
+    fn test_loop_closure_detection() {
+        let size = glam::usizevec2(24, 24);
+        let pixels = generate::rooms(size.to_array(), [2, 2], 1, 4)
+            .into_iter()
+            .map(|p| p <= 127)
+            .collect();
+        let map = OccupancyMap::from_pixels(size, pixels).unwrap();
+        let scan = |pose: Pose2D| {
+            (0..180)
+                .map(|i| glam::Vec2::from_angle(i as f32 * std::f32::consts::TAU / 180.))
+                .filter_map(|dir| {
+                    Some(dir * map.cast_rays(pose.position, pose.heading.rotate(dir))?)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut detector = LoopClosureDetector {
+            skip_recent: 1,
+            ..Default::default()
+        };
+        let start = Pose2D::new(glam::vec2(-5.5, -5.), 0.);
+        let elsewhere = Pose2D::new(glam::vec2(4.5, 6.), 0.5);
+        assert!(detector.add_keyframe(NodeId(0), &scan(start)).is_empty());
+        detector.add_keyframe(NodeId(1), &scan(elsewhere));
+
+        // Back at the start, facing another way.
+        let revisit = Pose2D::new(start.position + glam::vec2(0.1, 0.05), 2.);
+        let revisit_scan = scan(revisit);
+        let candidates = detector.add_keyframe(NodeId(2), &revisit_scan);
+        assert_eq!(
+            candidates.first().map(|c| c.node),
+            Some(NodeId(0)),
+            "{candidates:?}"
+        );
+
+        // The recent keyframe is skipped even though it matches perfectly.
+        let candidates = detector.query(&revisit_scan);
+        assert!(candidates.iter().all(|c| c.node != NodeId(2)));
+
+        let truth = start.inverse().compose(&revisit);
+        let initial = Pose2D::new(glam::Vec2::ZERO, truth.angle() + 0.1);
+        let result = detector
+            .align(&Icp::default(), &candidates[0], &revisit_scan, initial)
+            .unwrap()
+            .unwrap();
+        assert!(
+            result.transform.position.distance(truth.position) < 0.05,
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,7 @@
+//! Building blocks for SLAM front ends, to be run against the simulator's sensor output.
+
+pub mod correlative;
+pub mod ekf;
+pub mod icp;
+pub mod loop_closure;
+pub mod pose_graph;
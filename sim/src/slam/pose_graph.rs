@@ -0,0 +1,480 @@
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::Pose2D,
+    slam::icp::IcpResult,
+};
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Odometry,
+    LoopClosure,
+}
+
+/// A measurement of where node `to` sits in the frame of node `from`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub measurement: Pose2D,
+    /// Inverse covariance of the measurement's `(x, y, angle)`.
+    pub information: glam::Mat3,
+    pub kind: EdgeKind,
+}
+
+/// Graph SLAM back end: agent poses linked by relative-pose constraints, optimized together so loop closures spread
+/// the drift of odometry over the whole trajectory.
+#[derive(Debug, Clone, Default)]
+pub struct PoseGraph {
+    nodes: Vec<Pose2D>,
+    /// Nodes the optimizer leaves where they are. The first node added is fixed, which pins down the whole graph.
+    fixed: Vec<bool>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseGraphOptimizer {
+    pub max_iterations: usize,
+    /// Stop once no pose moves by more than this in an iteration, in metres or radians.
+    pub tolerance: f32,
+    /// Starting Levenberg-Marquardt damping, relative to the diagonal of the system. Zero gives plain Gauss-Newton.
+    pub damping: f32,
+}
+
+impl Default for PoseGraphOptimizer {
+    fn default() -> Self {
+        Self {
+            max_iterations: 50,
+            tolerance: 1e-5,
+            damping: 1e-4,
+        }
+    }
+}
+
+impl Validate for PoseGraphOptimizer {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::at_least("max_iterations", self.max_iterations, 1)?;
+        config::positive("tolerance", self.tolerance)?;
+        config::finite("damping", self.damping)?;
+        config::non_negative("damping", self.damping)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptimizationReport {
+    pub iterations: usize,
+    /// Sum over the edges of the squared error weighted by the information matrix, before and after.
+    pub initial_error: f32,
+    pub final_error: f32,
+    pub converged: bool,
+}
+
+/// Residual of an edge and its Jacobians with respect to the `from` and `to` poses, as `(x, y, angle)`.
+struct Linearized {
+    error: [f64; 3],
+    from: [[f64; 3]; 3],
+    to: [[f64; 3]; 3],
+}
+
+fn wrap_angle(angle: f64) -> f64 {
+    angle.sin().atan2(angle.cos())
+}
+
+impl PoseGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, pose: Pose2D) -> NodeId {
+        self.fixed.push(self.nodes.is_empty());
+        self.nodes.push(pose);
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<Pose2D> {
+        self.nodes.get(id.0).copied()
+    }
+
+    pub fn nodes(&self) -> &[Pose2D] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Whether the optimizer may move `id`.
+    pub fn set_fixed(&mut self, id: NodeId, fixed: bool) -> Result<(), PoseGraphError> {
+        *self
+            .fixed
+            .get_mut(id.0)
+            .ok_or(PoseGraphError::UnknownNode(id))? = fixed;
+
+        Ok(())
+    }
+
+    /// Constrains `to` to sit at `measurement` in the frame of `from`.
+    pub fn add_edge(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        measurement: Pose2D,
+        information: glam::Mat3,
+        kind: EdgeKind,
+    ) -> Result<(), PoseGraphError> {
+        for id in [from, to] {
+            if id.0 >= self.nodes.len() {
+                return Err(PoseGraphError::UnknownNode(id));
+            }
+        }
+        if from == to {
+            return Err(PoseGraphError::SelfLoop(from));
+        }
+
+        self.edges.push(Edge {
+            from,
+            to,
+            measurement,
+            information,
+            kind,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_odometry(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        measurement: Pose2D,
+        information: glam::Mat3,
+    ) -> Result<(), PoseGraphError> {
+        self.add_edge(from, to, measurement, information, EdgeKind::Odometry)
+    }
+
+    /// Adds a node at `measurement` in the frame of the last node, linked to it by odometry. The first node is placed
+    /// at `measurement` itself.
+    pub fn extend(
+        &mut self,
+        measurement: Pose2D,
+        information: glam::Mat3,
+    ) -> Result<NodeId, PoseGraphError> {
+        let Some(&last) = self.nodes.last() else {
+            return Ok(self.add_node(measurement));
+        };
+
+        let from = NodeId(self.nodes.len() - 1);
+        let to = self.add_node(last.compose(&measurement));
+        self.add_odometry(from, to, measurement, information)?;
+
+        Ok(to)
+    }
+
+    pub fn add_loop_closure(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        measurement: Pose2D,
+        information: glam::Mat3,
+    ) -> Result<(), PoseGraphError> {
+        self.add_edge(from, to, measurement, information, EdgeKind::LoopClosure)
+    }
+
+    /// Adds a loop closure from aligning the scan taken at `to` (the source) against the scan taken at `from` (the
+    /// target), e.g. with [align_scans](crate::slam::icp::Icp::align_scans). Unconverged alignments are rejected.
+    pub fn add_scan_match(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        result: &IcpResult,
+        information: glam::Mat3,
+    ) -> Result<(), PoseGraphError> {
+        if !result.converged {
+            return Err(PoseGraphError::Unconverged);
+        }
+
+        self.add_loop_closure(from, to, result.transform, information)
+    }
+
+    /// Sum over the edges of the squared error weighted by the information matrix.
+    pub fn error(&self) -> f32 {
+        self.total_error(&self.nodes) as f32
+    }
+
+    fn total_error(&self, nodes: &[Pose2D]) -> f64 {
+        self.edges
+            .iter()
+            .map(|edge| {
+                let e = linearize(edge, nodes).error;
+                let omega = information(edge);
+                (0..3)
+                    .map(|r| (0..3).map(|c| e[r] * omega[r][c] * e[c]).sum::<f64>())
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+
+    /// Moves the free nodes to minimize [PoseGraph::error] with Levenberg-Marquardt.
+    pub fn optimize(
+        &mut self,
+        optimizer: &PoseGraphOptimizer,
+    ) -> Result<OptimizationReport, PoseGraphError> {
+        optimizer.validate()?;
+
+        let initial_error = self.total_error(&self.nodes);
+        let mut report = OptimizationReport {
+            iterations: 0,
+            initial_error: initial_error as f32,
+            final_error: initial_error as f32,
+            converged: false,
+        };
+        if self.edges.is_empty() {
+            report.converged = true;
+            return Ok(report);
+        }
+
+        let mut error = initial_error;
+        let mut damping = optimizer.damping as f64;
+        while report.iterations < optimizer.max_iterations {
+            report.iterations += 1;
+
+            let (mut hessian, gradient) = self.normal_equations();
+            let n = gradient.len();
+            for i in 0..n {
+                hessian[i * n + i] *= 1. + damping;
+            }
+            let step = cholesky_solve(hessian, gradient.iter().map(|g| -g).collect(), n)
+                .ok_or(PoseGraphError::Singular)?;
+
+            let nodes = self
+                .nodes
+                .iter()
+                .enumerate()
+                .map(|(i, pose)| {
+                    let d = &step[3 * i..3 * i + 3];
+                    Pose2D::new(
+                        pose.position + glam::vec2(d[0] as f32, d[1] as f32),
+                        (pose.angle() as f64 + d[2]) as f32,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let new_error = self.total_error(&nodes);
+            if new_error <= error {
+                self.nodes = nodes;
+                error = new_error;
+                damping /= 10.;
+            } else {
+                damping = (damping * 10.).max(1e-6);
+            }
+
+            if step.iter().all(|d| d.abs() < optimizer.tolerance as f64) {
+                report.converged = true;
+                break;
+            }
+        }
+
+        report.final_error = error as f32;
+        Ok(report)
+    }
+
+    /// `H` and `b` of the Gauss-Newton system `H dx = -b`, with fixed nodes held in place.
+    fn normal_equations(&self) -> (Vec<f64>, Vec<f64>) {
+        let n = 3 * self.nodes.len();
+        let mut hessian = vec![0.; n * n];
+        let mut gradient = vec![0.; n];
+
+        for edge in &self.edges {
+            let Linearized { error, from, to } = linearize(edge, &self.nodes);
+            let omega = information(edge);
+            let blocks = [(edge.from.0, from), (edge.to.0, to)];
+
+            for &(i, a) in &blocks {
+                // A^T Omega, 3 by 3.
+                let at_omega =
+                    |r: usize, c: usize| (0..3).map(|k| a[k][r] * omega[k][c]).sum::<f64>();
+                for r in 0..3 {
+                    gradient[3 * i + r] += (0..3).map(|c| at_omega(r, c) * error[c]).sum::<f64>();
+                }
+
+                for &(j, b) in &blocks {
+                    for r in 0..3 {
+                        for c in 0..3 {
+                            hessian[(3 * i + r) * n + 3 * j + c] +=
+                                (0..3).map(|k| at_omega(r, k) * b[k][c]).sum::<f64>();
+                        }
+                    }
+                }
+            }
+        }
+
+        for (i, _) in self.fixed.iter().enumerate().filter(|(_, fixed)| **fixed) {
+            for r in 3 * i..3 * i + 3 {
+                for c in 0..n {
+                    hessian[r * n + c] = 0.;
+                    hessian[c * n + r] = 0.;
+                }
+                hessian[r * n + r] = 1.;
+                gradient[r] = 0.;
+            }
+        }
+
+        (hessian, gradient)
+    }
+}
+
+/// Symmetrized, so a slightly asymmetric input can't unbalance the system.
+fn information(edge: &Edge) -> [[f64; 3]; 3] {
+    let m = edge.information;
+    let entry = |r: usize, c: usize| (m.col(c)[r] as f64 + m.col(r)[c] as f64) / 2.;
+
+    [0, 1, 2].map(|r| [0, 1, 2].map(|c| entry(r, c)))
+}
+
+fn linearize(edge: &Edge, nodes: &[Pose2D]) -> Linearized {
+    let (xi, xj, z) = (nodes[edge.from.0], nodes[edge.to.0], edge.measurement);
+    let (ti, tj) = (xi.position.as_dvec2(), xj.position.as_dvec2());
+    let (theta_i, theta_j, theta_z) = (xi.angle() as f64, xj.angle() as f64, z.angle() as f64);
+    let (si, ci) = theta_i.sin_cos();
+    let (sz, cz) = theta_z.sin_cos();
+
+    // Rotations applied as transposes: R(a)^T v = (c v.x + s v.y, -s v.x + c v.y).
+    let rotate_back =
+        |s: f64, c: f64, v: glam::DVec2| glam::dvec2(c * v.x + s * v.y, -s * v.x + c * v.y);
+    let d = tj - ti;
+    let local = rotate_back(si, ci, d);
+    let t = rotate_back(sz, cz, local - z.position.as_dvec2());
+
+    // d(R_i^T d)/d(theta_i).
+    let d_local = glam::dvec2(-si * d.x + ci * d.y, -ci * d.x - si * d.y);
+    let d_t = rotate_back(sz, cz, d_local);
+    // R_z^T R_i^T, whose columns are the images of x and y.
+    let rx = rotate_back(sz, cz, rotate_back(si, ci, glam::DVec2::X));
+    let ry = rotate_back(sz, cz, rotate_back(si, ci, glam::DVec2::Y));
+
+    Linearized {
+        error: [t.x, t.y, wrap_angle(theta_j - theta_i - theta_z)],
+        from: [[-rx.x, -ry.x, d_t.x], [-rx.y, -ry.y, d_t.y], [0., 0., -1.]],
+        to: [[rx.x, ry.x, 0.], [rx.y, ry.y, 0.], [0., 0., 1.]],
+    }
+}
+
+/// Solves `a x = b` for symmetric positive definite `a`, row-major and `n` square.
+fn cholesky_solve(mut a: Vec<f64>, mut b: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    // Lower triangle of `a` becomes L, with L L^T = a.
+    for j in 0..n {
+        let diagonal = a[j * n + j] - (0..j).map(|k| a[j * n + k].powi(2)).sum::<f64>();
+        if diagonal <= 0. || !diagonal.is_finite() {
+            return None;
+        }
+        let diagonal = diagonal.sqrt();
+        a[j * n + j] = diagonal;
+
+        for i in j + 1..n {
+            let dot = (0..j).map(|k| a[i * n + k] * a[j * n + k]).sum::<f64>();
+            a[i * n + j] = (a[i * n + j] - dot) / diagonal;
+        }
+    }
+
+    for i in 0..n {
+        let dot = (0..i).map(|k| a[i * n + k] * b[k]).sum::<f64>();
+        b[i] = (b[i] - dot) / a[i * n + i];
+    }
+    for i in (0..n).rev() {
+        let dot = (i + 1..n).map(|k| a[k * n + i] * b[k]).sum::<f64>();
+        b[i] = (b[i] - dot) / a[i * n + i];
+    }
+
+    Some(b)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PoseGraphError {
+    #[error("No node {0:?} in the pose graph")]
+    UnknownNode(NodeId),
+
+    #[error("Edge from {0:?} to itself")]
+    SelfLoop(NodeId),
+
+    #[error("The scan match didn't converge")]
+    Unconverged,
+
+    #[error("The graph doesn't constrain every free node, e.g. a node has no edges")]
+    Singular,
+
+    #[error("Invalid optimizer config: {0}")]
+    Config(#[from] ConfigError),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::Pose2D,
+        slam::pose_graph::{NodeId, PoseGraph, PoseGraphError, PoseGraphOptimizer},
+    };
+
+    #[test]
+    fn test_pose_graph() {
+        // Drive around a 4 by 4 square with odometry that turns slightly too little at every corner.
+        let information = glam::Mat3::from_diagonal(glam::vec3(100., 100., 400.));
+        let mut graph = PoseGraph::new();
+        graph.add_node(Pose2D::default());
+        let mut truth = vec![Pose2D::default()];
+        for i in 1..16 {
+            let step = if i % 4 == 0 {
+                Pose2D::new(glam::vec2(1., 0.), std::f32::consts::FRAC_PI_2)
+            } else {
+                Pose2D::new(glam::vec2(1., 0.), 0.)
+            };
+            let measured = Pose2D::new(step.position, step.angle() * 0.95);
+            truth.push(truth[i - 1].compose(&step));
+            graph.extend(measured, information).unwrap();
+        }
+
+        let last = NodeId(graph.len() - 1);
+        let closure = truth[last.0].inverse().compose(&truth[0]);
+        let drift = graph
+            .node(last)
+            .unwrap()
+            .compose(&closure)
+            .position
+            .length();
+        assert!(drift > 0.5);
+
+        graph
+            .add_loop_closure(last, NodeId(0), closure, information)
+            .unwrap();
+        let report = graph.optimize(&PoseGraphOptimizer::default()).unwrap();
+
+        assert!(report.converged, "{report:?}");
+        assert!(report.final_error < report.initial_error / 10.);
+        assert_eq!(graph.node(NodeId(0)), Some(Pose2D::default()));
+        for (node, truth) in graph.nodes().iter().zip(&truth) {
+            assert!(
+                node.position.distance(truth.position) < 0.3,
+                "{node:?} vs {truth:?}"
+            );
+        }
+
+        assert!(matches!(
+            graph.add_loop_closure(last, NodeId(99), closure, information),
+            Err(PoseGraphError::UnknownNode(NodeId(99)))
+        ));
+        graph.add_node(Pose2D::default());
+        assert!(matches!(
+            graph.optimize(&PoseGraphOptimizer::default()),
+            Err(PoseGraphError::Singular)
+        ));
+    }
+}
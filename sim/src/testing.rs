@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests.
+
+use crate::{Agent2D, Scene2D, scene::OccupancyMap, sensors::bumper::Bumper2D};
+
+impl OccupancyMap {
+    /// A map drawn one string per row, top row first, with `#` for walls.
+    pub(crate) fn from_ascii(rows: &[&str]) -> OccupancyMap {
+        let size = glam::usizevec2(rows[0].len(), rows.len());
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect();
+
+        Self::from_pixels(size, pixels).unwrap()
+    }
+}
+
+/// An empty 8 by 8 room that senses synchronously, so what an update delivers depends on scene time alone.
+pub(crate) fn open_room() -> Scene2D {
+    let mut scene = Scene2D::from_pixels([8, 8], &[255; 64]).unwrap();
+    scene.set_deterministic(Some(0));
+    scene
+}
+
+/// A default agent with a [Bumper2D] on the `"bumper"` topic, the cheapest sensor to watch deliveries with.
+pub(crate) fn bumper_agent() -> Agent2D {
+    let mut agent = Agent2D::default();
+    agent.sensors.insert("bumper", Bumper2D);
+    agent
+}
@@ -0,0 +1,184 @@
+//! Trailers towed behind an agent, such as the carts of a warehouse tugger train. Each is hitched behind the body in
+//! front of it and follows its hitch like a wheelbarrow, with its axle dragged straight towards the hitch.
+
+use crate::{
+    config::{self, ConfigError, Validate},
+    math::{OrientedBox2D, Pose2D},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrailerConfig {
+    /// From the hitch back to the axle in metres, also the length of the footprint.
+    pub length: f32,
+    /// In metres.
+    pub width: f32,
+    /// How far the hitch is behind the body towing the trailer in metres, from the centre of an agent or the axle of
+    /// a trailer.
+    pub hitch_offset: f32,
+}
+
+/// A trailer and where it stands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trailer {
+    pub config: TrailerConfig,
+    /// The middle of the axle, facing the hitch.
+    pub pose: Pose2D,
+}
+
+impl Default for TrailerConfig {
+    fn default() -> Self {
+        Self {
+            length: 0.5,
+            width: 0.25,
+            hitch_offset: 0.35,
+        }
+    }
+}
+
+impl Validate for TrailerConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        config::positive("length", self.length)?;
+        config::positive("width", self.width)?;
+        config::finite("hitch_offset", self.hitch_offset)?;
+        config::non_negative("hitch_offset", self.hitch_offset)
+    }
+}
+
+impl Trailer {
+    /// A trailer in line behind `tow`.
+    pub fn hitched_behind(tow: &Pose2D, config: TrailerConfig) -> Self {
+        let hitch = Self::hitch(tow, &config);
+
+        Self {
+            config,
+            pose: Pose2D {
+                position: hitch - tow.heading * config.length,
+                heading: tow.heading,
+            },
+        }
+    }
+
+    /// Where a trailer with `config` is hitched to `tow`.
+    pub fn hitch(tow: &Pose2D, config: &TrailerConfig) -> glam::Vec2 {
+        tow.position - tow.heading * config.hitch_offset
+    }
+
+    /// Drags the trailer after `tow`, which has moved since it was last followed.
+    pub fn follow(&mut self, tow: &Pose2D) {
+        let hitch = Self::hitch(tow, &self.config);
+        let heading = (hitch - self.pose.position).normalize_or(self.pose.heading);
+
+        self.pose = Pose2D {
+            position: hitch - heading * self.config.length,
+            heading,
+        };
+    }
+
+    pub fn footprint(&self) -> OrientedBox2D {
+        OrientedBox2D {
+            center: self.pose.position + self.pose.heading * self.config.length / 2.,
+            half_extent: glam::vec2(self.config.length, self.config.width) / 2.,
+            heading: self.pose.heading,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Agent2D, Scene2D,
+        scene::{AgentId, HitTag, OccupancyMap},
+        sim_config::{CollisionMode, SimConfig},
+        trailer::TrailerConfig,
+    };
+
+    /// A default agent towing two default trailers.
+    fn train() -> Agent2D {
+        let mut agent = Agent2D::default();
+        agent.hitch(TrailerConfig::default());
+        agent.hitch(TrailerConfig::default());
+        agent
+    }
+
+    #[test]
+    fn test_trailer_hitching() {
+        let agent = train();
+        assert_eq!(agent.trailers[0].pose.position, glam::vec2(0., -0.85));
+        assert_eq!(agent.trailers[1].pose.position, glam::vec2(0., -1.7));
+    }
+
+    #[test]
+    fn test_trailer_turning() {
+        let mut agent = train();
+
+        // Driving round a steady turn, each trailer cuts inside the body in front of it and stays hitched.
+        agent.state.velocity = 1.;
+        for _ in 0..100 {
+            agent.state.beta = 0.3;
+            agent.update(0.05, None);
+        }
+        let radius = |p: glam::Vec2| p.distance(glam::vec2(-0.5 / 0.3f32.tan(), 0.));
+        let centre = glam::vec2(-0.5 / 0.3f32.tan(), 0.);
+        assert!(
+            agent.state.position.distance(centre)
+                > agent.trailers[0].pose.position.distance(centre)
+        );
+        assert!(radius(agent.trailers[0].pose.position) > radius(agent.trailers[1].pose.position));
+        let mut tow = agent.state.pose();
+        for trailer in &agent.trailers {
+            let hitch = tow.position - tow.heading * trailer.config.hitch_offset;
+            assert!((hitch.distance(trailer.pose.position) - trailer.config.length).abs() < 1e-4);
+            tow = trailer.pose;
+        }
+    }
+
+    /// A tug facing +x with one trailer behind it, a short way ahead of a wall cell.
+    fn tug_by_wall() -> (Scene2D, AgentId) {
+        let map = OccupancyMap::from_ascii(&[
+            "........", //
+            "........", //
+            "#.......", //
+            "........", //
+            "........", //
+            "........", //
+        ]);
+        let config = SimConfig {
+            collision: CollisionMode::Stop,
+            ..Default::default()
+        };
+        let mut scene = Scene2D::with_config(map, config).unwrap();
+        let mut tug = Agent2D::default();
+        tug.state.position = glam::vec2(-1.5, 0.5);
+        tug.state.heading = glam::Vec2::X;
+        tug.hitch(TrailerConfig::default());
+        let id = scene.add_agent(tug);
+
+        (scene, id)
+    }
+
+    #[test]
+    fn test_trailer_sensed() {
+        // Trailers are bodies in the scene that sensors see.
+        let (scene, id) = tug_by_wall();
+        let state = scene.state();
+        assert_eq!(state.agents.len(), 2);
+        let hit = state.cast_rays(glam::vec2(-2.3, 2.5), glam::Vec2::NEG_Y);
+        assert_eq!(hit.map(|(_, tag)| tag), Some(HitTag::Agent(id)));
+    }
+
+    #[test]
+    fn test_trailer_collisions() {
+        // Backing the trailer into a wall stops the train.
+        let (mut scene, id) = tug_by_wall();
+        scene.agents.get_mut(&id).unwrap().state.velocity = -1.;
+        for _ in 0..20 {
+            scene.update(0.05);
+        }
+        assert!(!scene.drain_collision_events().is_empty());
+        let trailer = scene.agents[&id].trailers[0].footprint();
+        assert!(!scene.occupancy_map.overlaps(&trailer));
+        assert!(scene.agents[&id].state.position.x > -1.5 - 0.65);
+    }
+}